@@ -0,0 +1,63 @@
+//! Decides whether this crate passes everything through to the real `users`
+//! crate, or provides its own implementation.
+//!
+//! The real crate only works on Unix targets that have a passwd database
+//! and that it knows about. When one of those is being built for, this sets
+//! the `users_passthrough` cfg, which `lib.rs` dispatches on.
+//!
+//! It also sets the `users_utmpx` cfg when `libc` is available and has the
+//! utmpx functions and fields the `sessions` module reads.
+
+use std::env;
+
+
+/// Unix-family targets that don’t get the real `users` crate, either
+/// because they have no passwd database to speak of, or because this crate
+/// has a backend of its own for them.
+///
+/// Keep this in sync with the target of the `users-orig` dependency in
+/// `Cargo.toml`, which can’t be decided by a build script.
+const NO_PASSTHROUGH: &[&str] = &[
+    "aix",      // has its own backend
+    "espidf",   // no passwd database
+    "fuchsia",  // has its own backend
+    "hermit",   // no passwd database
+    "horizon",  // no passwd database
+    "illumos",  // has its own backend
+    "vita",     // no passwd database
+];
+
+/// Targets whose utmpx entries `libc` knows the user, line, and host
+/// fields of, as the operating system and environment.
+const UTMPX: &[(&str, &str)] = &[
+    ("linux",   "gnu"),
+    ("linux",   "musl"),
+    ("macos",   ""),
+    ("ios",     ""),
+    ("freebsd", ""),
+    ("illumos", ""),
+    ("solaris", ""),
+    ("aix",     ""),
+];
+
+
+fn main() {
+    println!("cargo:rustc-check-cfg=cfg(users_passthrough)");
+    println!("cargo:rustc-check-cfg=cfg(users_utmpx)");
+
+    let family = env::var("CARGO_CFG_TARGET_FAMILY").unwrap_or_default();
+    let os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    let target_env = env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default();
+    let force_stub = env::var_os("CARGO_FEATURE_FORCE_STUB").is_some();
+
+    let unix = family.split(',').any(|f| f == "unix");
+    let passthrough = unix && ! NO_PASSTHROUGH.contains(&&*os) && ! force_stub;
+    if passthrough {
+        println!("cargo:rustc-cfg=users_passthrough");
+    }
+
+    let libc = passthrough || os == "illumos" || os == "aix";
+    if libc && UTMPX.iter().any(|&(o, e)| o == os && (e.is_empty() || e == target_env)) {
+        println!("cargo:rustc-cfg=users_utmpx");
+    }
+}
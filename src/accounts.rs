@@ -0,0 +1,232 @@
+//! Telling the accounts of people apart from those of the system.
+//!
+//! Most systems give daemons and other system accounts user IDs from a low
+//! range, and people IDs from above it, up to a limit past which special
+//! accounts such as `nobody` live again. The ranges differ between
+//! platforms, and on Linux, `/etc/login.defs` says where they are:
+//!
+//! ```
+//! use users::{User, AccountExt};
+//!
+//! let root = User::new(0, "root", 0);
+//! assert!(root.is_system_account());
+//! ```
+//!
+//! The ranges can be changed for the whole process with `set_regular_uids`,
+//! for sites that hand out IDs differently.
+//!
+//! Accounts that can’t be logged into can be told apart too, by their
+//! password field, which starts with `!` or `*` for locked accounts:
+//!
+//! ```
+//! use users::{User, AccountExt};
+//! use users::os::unix::UserExt;
+//!
+//! let alice = User::new(1000, "alice", 100).with_password("!$6$hash");
+//! assert!(alice.is_locked());
+//! ```
+//!
+//! If the password is kept in the shadow database instead, as the `x` in
+//! the field says, that is only read with the `shadow` feature; otherwise
+//! the account isn’t taken to be locked. On Windows, accounts are locked if
+//! they are disabled.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::RwLock;
+
+use {User, uid_t};
+use os::unix::UserExt;
+
+
+/// The user IDs of accounts that belong to people.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct RegularUids {
+    ranges: Vec<RangeInclusive<uid_t>>,
+}
+
+impl RegularUids {
+
+    /// The IDs from `min` to `max`, inclusive, like `UID_MIN` and `UID_MAX`
+    /// in `/etc/login.defs`.
+    pub fn new(min: uid_t, max: uid_t) -> Self {
+        Self { ranges: vec![ min ..= max ] }
+    }
+
+    /// Adds the IDs from `min` to `max`, inclusive, to the regular ones.
+    pub fn with_range(mut self, min: uid_t, max: uid_t) -> Self {
+        self.ranges.push(min ..= max);
+        self
+    }
+
+    /// Reads `UID_MIN` and `UID_MAX` from a file in the `/etc/login.defs`
+    /// format, using the usual values for any that are missing.
+    pub fn from_login_defs<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::from_login_defs_str(&fs::read_to_string(path)?))
+    }
+
+    /// Reads `UID_MIN` and `UID_MAX` from text in the `/etc/login.defs`
+    /// format, using the usual values for any that are missing.
+    pub fn from_login_defs_str(contents: &str) -> Self {
+        let mut min = 1000;
+        let mut max = 60000;
+
+        for line in contents.lines() {
+            let mut words = line.split_whitespace();
+            let key = words.next();
+            match (key, words.next().and_then(|v| v.parse().ok())) {
+                (Some("UID_MIN"), Some(uid)) => min = uid,
+                (Some("UID_MAX"), Some(uid)) => max = uid,
+                _                            => {},
+            }
+        }
+
+        Self::new(min, max)
+    }
+
+    /// Whether the given user ID belongs to a person.
+    pub fn contains(&self, uid: uid_t) -> bool {
+        self.ranges.iter().any(|r| r.contains(&uid))
+    }
+}
+
+impl Default for RegularUids {
+
+    /// The regular user IDs on this platform.
+    #[cfg(target_os = "macos")]
+    fn default() -> Self {
+        Self::new(501, 0x7fff_ffff)
+    }
+
+    /// The regular user IDs on this platform: local and primary domain
+    /// accounts with relative IDs from 1000, as the `sid` module maps them.
+    #[cfg(windows)]
+    fn default() -> Self {
+        Self::new(0x30000 + 1000, 0x5ffff)
+            .with_range(0x100000 + 1000, 0x7fff_ffff)
+    }
+
+    /// The regular user IDs on this platform, from `/etc/login.defs` if
+    /// there is one.
+    #[cfg(not(any(target_os = "macos", windows)))]
+    fn default() -> Self {
+        Self::from_login_defs("/etc/login.defs").unwrap_or_else(|_| Self::new(1000, 60000))
+    }
+}
+
+
+static REGULAR_UIDS: RwLock<Option<RegularUids>> = RwLock::new(None);
+
+/// Changes which user IDs `is_system_account` takes to belong to people,
+/// for the whole process.
+pub fn set_regular_uids(uids: RegularUids) {
+    *REGULAR_UIDS.write().unwrap_or_else(|e| e.into_inner()) = Some(uids);
+}
+
+/// Returns the user IDs `is_system_account` takes to belong to people: the
+/// ones set with `set_regular_uids`, or the platform’s.
+pub fn regular_uids() -> RegularUids {
+    if let Some(ref uids) = *REGULAR_UIDS.read().unwrap_or_else(|e| e.into_inner()) {
+        return uids.clone();
+    }
+
+    REGULAR_UIDS.write().unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(RegularUids::default)
+        .clone()
+}
+
+
+/// Classifies users as system accounts or people’s.
+pub trait AccountExt {
+
+    /// Whether this is a system account, rather than one belonging to a
+    /// person, going by its user ID.
+    fn is_system_account(&self) -> bool;
+
+    /// Whether this account can’t be logged into, because it is locked,
+    /// has no password to log in with, or, on Windows, is disabled. Users
+    /// made up with `User::new` have a password of `*`, so they count as
+    /// locked.
+    fn is_locked(&self) -> bool;
+}
+
+impl AccountExt for User {
+    fn is_system_account(&self) -> bool {
+        ! regular_uids().contains(self.uid())
+    }
+
+    fn is_locked(&self) -> bool {
+        let password = self.password();
+
+        // macOS puts asterisks in every user’s field, whatever the password.
+        let locked = if password == "x" || password == "********" { shadow_locked(self) }
+                     else { is_locked_password(password) };
+
+        locked || is_disabled(self)
+    }
+}
+
+/// Whether a password field locks the account: `!` and `!!` mark locked
+/// accounts, and `*` ones that never had a password.
+pub(crate) fn is_locked_password(password: &OsStr) -> bool {
+    let password = password.to_string_lossy();
+    password.starts_with('!') || password.starts_with('*')
+}
+
+#[cfg(feature = "shadow")]
+fn shadow_locked(user: &User) -> bool {
+    ::shadow::get_shadow_by_name(user.name()).ok().flatten().map_or(false, |shadow| shadow.is_locked())
+}
+
+#[cfg(not(feature = "shadow"))]
+fn shadow_locked(_user: &User) -> bool {
+    false
+}
+
+#[cfg(all(windows, not(users_passthrough)))]
+fn is_disabled(user: &User) -> bool {
+    use os::windows::UserExt;
+    user.is_disabled()
+}
+
+#[cfg(not(all(windows, not(users_passthrough))))]
+fn is_disabled(_user: &User) -> bool {
+    false
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn login_defs() {
+        let uids = RegularUids::from_login_defs_str("# comment\nUID_MIN\t\t 500\nGID_MIN 500\nMAIL_DIR /var/mail\n");
+        assert_eq!(uids, RegularUids::new(500, 60000));
+        assert!(! uids.contains(499));
+        assert!(uids.contains(60000));
+    }
+
+    #[test]
+    fn ranges() {
+        let uids = RegularUids::new(1000, 1999).with_range(5000, 5999);
+        assert!(uids.contains(1000) && uids.contains(5999));
+        assert!(! uids.contains(2000) && ! uids.contains(65534));
+    }
+
+    #[test]
+    fn locked() {
+        assert!(User::new(1000, "alice", 100).with_password("!!").is_locked());
+        assert!(User::new(1000, "alice", 100).with_password("*LK*").is_locked());
+        assert!(! User::new(1000, "alice", 100).with_password("$6$hash").is_locked());
+        assert!(! User::new(1000, "alice", 100).with_password("").is_locked());
+    }
+
+    #[test]
+    fn root_is_system() {
+        assert!(User::new(0, "root", 0).is_system_account());
+    }
+}
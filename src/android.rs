@@ -0,0 +1,105 @@
+//! Names for Android app users.
+//!
+//! Android gives every app its own user ID, and every user profile on the
+//! device its own range of them, so most of the IDs a process or file
+//! listing comes across have no passwd entry. Tools such as `ps` show them
+//! under conventional names instead, which this module reproduces:
+//!
+//! - `u0_a36` for app 36 (UID 10036) in user profile 0;
+//! - `u10_i5` for isolated process 5 (UID 1090005) in user profile 10.
+//!
+//! When the C library has no entry for an ID in those ranges,
+//! `get_user_by_uid` returns a user with the made-up name instead, and
+//! with its own ID as the primary group, as app users have.
+
+#[cfg(target_os = "android")]
+use std::ffi::OsString;
+
+#[cfg(target_os = "android")]
+use users_orig;
+
+use {User, uid_t};
+
+
+/// The number of IDs set aside for each user profile.
+const USER_OFFSET: uid_t = 100_000;
+
+/// The range of app IDs within a user profile.
+const APP_START: uid_t = 10_000;
+const APP_END: uid_t = 19_999;
+
+/// The range of isolated process IDs within a user profile.
+const ISOLATED_START: uid_t = 90_000;
+const ISOLATED_END: uid_t = 99_999;
+
+
+/// Returns the conventional name for an app or isolated process user ID,
+/// or `None` for IDs outside those ranges.
+pub fn synthetic_name(uid: uid_t) -> Option<String> {
+    let user = uid / USER_OFFSET;
+    let app = uid % USER_OFFSET;
+
+    match app {
+        APP_START ..= APP_END            => Some(format!("u{}_a{}", user, app - APP_START)),
+        ISOLATED_START ..= ISOLATED_END  => Some(format!("u{}_i{}", user, app - ISOLATED_START)),
+        _                                => None,
+    }
+}
+
+/// Returns a user with the conventional name for the given ID, if it is
+/// an app or isolated process user ID.
+pub fn synthetic_user(uid: uid_t) -> Option<User> {
+    synthetic_name(uid).map(|name| User::new(uid, &name, uid))
+}
+
+
+/// Searches for a `User` with the given ID in the system’s user database,
+/// falling back to a synthetic app user.
+#[cfg(target_os = "android")]
+pub fn get_user_by_uid(uid: uid_t) -> Option<User> {
+    users_orig::get_user_by_uid(uid).or_else(|| synthetic_user(uid))
+}
+
+/// Returns the username of the user running the process, which may be a
+/// synthetic app user name.
+#[cfg(target_os = "android")]
+pub fn get_current_username() -> Option<OsString> {
+    get_user_by_uid(users_orig::get_current_uid()).map(|user| user.name().to_owned())
+}
+
+/// Returns the username of the effective user running the process, which
+/// may be a synthetic app user name.
+#[cfg(target_os = "android")]
+pub fn get_effective_username() -> Option<OsString> {
+    get_user_by_uid(users_orig::get_effective_uid()).map(|user| user.name().to_owned())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn app() {
+        assert_eq!(synthetic_name(10_036), Some("u0_a36".into()));
+        assert_eq!(synthetic_name(1_019_999), Some("u10_a9999".into()));
+    }
+
+    #[test]
+    fn isolated() {
+        assert_eq!(synthetic_name(1_090_005), Some("u10_i5".into()));
+    }
+
+    #[test]
+    fn system() {
+        assert_eq!(synthetic_name(0), None);
+        assert_eq!(synthetic_name(1000), None);
+        assert_eq!(synthetic_name(100_000 + 20_000), None);
+    }
+
+    #[test]
+    fn primary_group() {
+        let user = synthetic_user(10_036).unwrap();
+        assert_eq!(user.primary_group_id(), 10_036);
+    }
+}
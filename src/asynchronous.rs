@@ -0,0 +1,366 @@
+//! Asynchronous versions of the `Users` and `Groups` traits.
+//!
+//! Looking up a user can mean asking an LDAP server or a user database
+//! daemon, and an asynchronous program shouldn’t block one of its worker
+//! threads waiting for the answer. With the `async` feature, backends that
+//! talk to such services can implement [`AsyncUsers`](trait.AsyncUsers.html)
+//! and [`AsyncGroups`](trait.AsyncGroups.html) instead, and be awaited.
+//!
+//! The traits don’t depend on any runtime. Their methods return boxed
+//! futures, so they can be made into trait objects, and implemented without
+//! any macros:
+//!
+//! ```text
+//! impl AsyncUsers for Ldap {
+//!     fn get_user_by_uid<'a>(&'a self, uid: uid_t) -> LookupFuture<'a, Option<Arc<User>>> {
+//!         Box::pin(async move { self.search_uid(uid).await })
+//!     }
+//!     // ...
+//! }
+//! ```
+//!
+//! Anything implementing the synchronous traits can be used where the
+//! asynchronous ones are expected by wrapping it: in a
+//! [`Ready`](struct.Ready.html) if its answers come from memory, like a
+//! `MockUsers` table, or, with the `tokio` feature, in a
+//! [`SpawnBlocking`](struct.SpawnBlocking.html) if they might block, like
+//! the system’s own lookups.
+
+use std::ffi::OsStr;
+use std::future::{self, Future};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use {User, Group, Users, Groups, uid_t, gid_t};
+
+#[cfg(feature = "tokio")]
+use std::ffi::OsString;
+
+#[cfg(feature = "tokio")]
+use std::panic;
+
+#[cfg(feature = "tokio")]
+use std::task::{Context, Poll};
+
+#[cfg(feature = "tokio")]
+use tokio::task::{self, JoinHandle};
+
+
+/// The future returned by the methods of `AsyncUsers` and `AsyncGroups`.
+pub type LookupFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+
+/// Trait for producers of users that have to wait for the answers.
+pub trait AsyncUsers {
+
+    /// Returns a `User` if one exists for the given user ID; otherwise, returns `None`.
+    fn get_user_by_uid<'a>(&'a self, uid: uid_t) -> LookupFuture<'a, Option<Arc<User>>>;
+
+    /// Returns a `User` if one exists for the given username; otherwise, returns `None`.
+    fn get_user_by_name<'a>(&'a self, username: &'a OsStr) -> LookupFuture<'a, Option<Arc<User>>>;
+
+    /// Returns the user ID for the user running the process.
+    fn get_current_uid<'a>(&'a self) -> LookupFuture<'a, uid_t>;
+
+    /// Returns the username of the user running the process.
+    fn get_current_username<'a>(&'a self) -> LookupFuture<'a, Option<Arc<OsStr>>>;
+
+    /// Returns the effective user id.
+    fn get_effective_uid<'a>(&'a self) -> LookupFuture<'a, uid_t>;
+
+    /// Returns the effective username.
+    fn get_effective_username<'a>(&'a self) -> LookupFuture<'a, Option<Arc<OsStr>>>;
+}
+
+/// Trait for producers of groups that have to wait for the answers.
+pub trait AsyncGroups {
+
+    /// Returns a `Group` if one exists for the given group ID; otherwise, returns `None`.
+    fn get_group_by_gid<'a>(&'a self, gid: gid_t) -> LookupFuture<'a, Option<Arc<Group>>>;
+
+    /// Returns a `Group` if one exists for the given groupname; otherwise, returns `None`.
+    fn get_group_by_name<'a>(&'a self, group_name: &'a OsStr) -> LookupFuture<'a, Option<Arc<Group>>>;
+
+    /// Returns the group ID for the user running the process.
+    fn get_current_gid<'a>(&'a self) -> LookupFuture<'a, gid_t>;
+
+    /// Returns the group name of the user running the process.
+    fn get_current_groupname<'a>(&'a self) -> LookupFuture<'a, Option<Arc<OsStr>>>;
+
+    /// Returns the effective group id.
+    fn get_effective_gid<'a>(&'a self) -> LookupFuture<'a, gid_t>;
+
+    /// Returns the effective group name.
+    fn get_effective_groupname<'a>(&'a self) -> LookupFuture<'a, Option<Arc<OsStr>>>;
+}
+
+
+/// Answers asynchronous lookups from a synchronous `Users` or `Groups`
+/// implementation, straight away.
+///
+/// The lookups happen on whichever thread polls the future, so this is only
+/// suitable for implementations that answer from memory.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Ready<U>(pub U);
+
+impl<U: Users> AsyncUsers for Ready<U> {
+    fn get_user_by_uid<'a>(&'a self, uid: uid_t) -> LookupFuture<'a, Option<Arc<User>>> {
+        Box::pin(future::ready(self.0.get_user_by_uid(uid)))
+    }
+
+    fn get_user_by_name<'a>(&'a self, username: &'a OsStr) -> LookupFuture<'a, Option<Arc<User>>> {
+        Box::pin(future::ready(self.0.get_user_by_name(username)))
+    }
+
+    fn get_current_uid<'a>(&'a self) -> LookupFuture<'a, uid_t> {
+        Box::pin(future::ready(self.0.get_current_uid()))
+    }
+
+    fn get_current_username<'a>(&'a self) -> LookupFuture<'a, Option<Arc<OsStr>>> {
+        Box::pin(future::ready(self.0.get_current_username()))
+    }
+
+    fn get_effective_uid<'a>(&'a self) -> LookupFuture<'a, uid_t> {
+        Box::pin(future::ready(self.0.get_effective_uid()))
+    }
+
+    fn get_effective_username<'a>(&'a self) -> LookupFuture<'a, Option<Arc<OsStr>>> {
+        Box::pin(future::ready(self.0.get_effective_username()))
+    }
+}
+
+impl<U: Groups> AsyncGroups for Ready<U> {
+    fn get_group_by_gid<'a>(&'a self, gid: gid_t) -> LookupFuture<'a, Option<Arc<Group>>> {
+        Box::pin(future::ready(self.0.get_group_by_gid(gid)))
+    }
+
+    fn get_group_by_name<'a>(&'a self, group_name: &'a OsStr) -> LookupFuture<'a, Option<Arc<Group>>> {
+        Box::pin(future::ready(self.0.get_group_by_name(group_name)))
+    }
+
+    fn get_current_gid<'a>(&'a self) -> LookupFuture<'a, gid_t> {
+        Box::pin(future::ready(self.0.get_current_gid()))
+    }
+
+    fn get_current_groupname<'a>(&'a self) -> LookupFuture<'a, Option<Arc<OsStr>>> {
+        Box::pin(future::ready(self.0.get_current_groupname()))
+    }
+
+    fn get_effective_gid<'a>(&'a self) -> LookupFuture<'a, gid_t> {
+        Box::pin(future::ready(self.0.get_effective_gid()))
+    }
+
+    fn get_effective_groupname<'a>(&'a self) -> LookupFuture<'a, Option<Arc<OsStr>>> {
+        Box::pin(future::ready(self.0.get_effective_groupname()))
+    }
+}
+
+
+/// Answers asynchronous lookups from a synchronous `Users` or `Groups`
+/// implementation on Tokio’s blocking thread pool, with the `tokio`
+/// feature.
+///
+/// The worker threads stay free while the lookups wait on the network, so
+/// this suits the system’s own lookups, or a `UsersCache` in front of them.
+/// Lookups have to be awaited from within a Tokio runtime.
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate tokio;
+/// # extern crate users;
+/// use tokio::runtime::Builder;
+/// use users::{AsyncUsers, SpawnBlocking, SharedUsersCache};
+///
+/// # fn main() {
+/// let runtime = Builder::new_current_thread().build().unwrap();
+/// let users = SpawnBlocking::new(SharedUsersCache::new());
+/// let user = runtime.block_on(users.get_user_by_uid(1000));
+/// # }
+/// ```
+#[cfg(feature = "tokio")]
+#[derive(Debug, Default)]
+pub struct SpawnBlocking<U> {
+    inner: Arc<U>,
+}
+
+#[cfg(feature = "tokio")]
+impl<U> Clone for SpawnBlocking<U> {
+    fn clone(&self) -> Self {
+        Self { inner: Arc::clone(&self.inner) }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<U> SpawnBlocking<U> {
+
+    /// Wraps the given implementation.
+    pub fn new(inner: U) -> Self {
+        Self { inner: Arc::new(inner) }
+    }
+
+    /// Returns the wrapped implementation.
+    pub fn inner(&self) -> &U {
+        &self.inner
+    }
+}
+
+/// Runs a lookup on the blocking thread pool, once it is first polled.
+#[cfg(feature = "tokio")]
+fn spawn<'a, T, F>(f: F) -> LookupFuture<'a, T>
+where T: Send + 'static,
+      F: FnOnce() -> T + Send + Unpin + 'static,
+{
+    Box::pin(Spawned::Waiting(Some(f)))
+}
+
+/// A lookup to run on the blocking thread pool, which passes on its
+/// panics.
+#[cfg(feature = "tokio")]
+enum Spawned<T, F> {
+    Waiting(Option<F>),
+    Running(JoinHandle<T>),
+}
+
+#[cfg(feature = "tokio")]
+impl<T, F> Future for Spawned<T, F>
+where T: Send + 'static,
+      F: FnOnce() -> T + Send + Unpin + 'static,
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        if let Spawned::Waiting(ref mut f) = *self {
+            let f = f.take().expect("lookup already spawned");
+            *self = Spawned::Running(task::spawn_blocking(f));
+        }
+
+        match *self {
+            Spawned::Running(ref mut handle) => Pin::new(handle).poll(cx).map(|result| {
+                result.unwrap_or_else(|e| panic::resume_unwind(e.into_panic()))
+            }),
+            Spawned::Waiting(_) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<U: Users + Send + Sync + 'static> AsyncUsers for SpawnBlocking<U> {
+    fn get_user_by_uid<'a>(&'a self, uid: uid_t) -> LookupFuture<'a, Option<Arc<User>>> {
+        let inner = Arc::clone(&self.inner);
+        spawn(move || inner.get_user_by_uid(uid))
+    }
+
+    fn get_user_by_name<'a>(&'a self, username: &'a OsStr) -> LookupFuture<'a, Option<Arc<User>>> {
+        let (inner, username) = (Arc::clone(&self.inner), OsString::from(username));
+        spawn(move || inner.get_user_by_name(&username))
+    }
+
+    fn get_current_uid<'a>(&'a self) -> LookupFuture<'a, uid_t> {
+        let inner = Arc::clone(&self.inner);
+        spawn(move || inner.get_current_uid())
+    }
+
+    fn get_current_username<'a>(&'a self) -> LookupFuture<'a, Option<Arc<OsStr>>> {
+        let inner = Arc::clone(&self.inner);
+        spawn(move || inner.get_current_username())
+    }
+
+    fn get_effective_uid<'a>(&'a self) -> LookupFuture<'a, uid_t> {
+        let inner = Arc::clone(&self.inner);
+        spawn(move || inner.get_effective_uid())
+    }
+
+    fn get_effective_username<'a>(&'a self) -> LookupFuture<'a, Option<Arc<OsStr>>> {
+        let inner = Arc::clone(&self.inner);
+        spawn(move || inner.get_effective_username())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<U: Groups + Send + Sync + 'static> AsyncGroups for SpawnBlocking<U> {
+    fn get_group_by_gid<'a>(&'a self, gid: gid_t) -> LookupFuture<'a, Option<Arc<Group>>> {
+        let inner = Arc::clone(&self.inner);
+        spawn(move || inner.get_group_by_gid(gid))
+    }
+
+    fn get_group_by_name<'a>(&'a self, group_name: &'a OsStr) -> LookupFuture<'a, Option<Arc<Group>>> {
+        let (inner, group_name) = (Arc::clone(&self.inner), OsString::from(group_name));
+        spawn(move || inner.get_group_by_name(&group_name))
+    }
+
+    fn get_current_gid<'a>(&'a self) -> LookupFuture<'a, gid_t> {
+        let inner = Arc::clone(&self.inner);
+        spawn(move || inner.get_current_gid())
+    }
+
+    fn get_current_groupname<'a>(&'a self) -> LookupFuture<'a, Option<Arc<OsStr>>> {
+        let inner = Arc::clone(&self.inner);
+        spawn(move || inner.get_current_groupname())
+    }
+
+    fn get_effective_gid<'a>(&'a self) -> LookupFuture<'a, gid_t> {
+        let inner = Arc::clone(&self.inner);
+        spawn(move || inner.get_effective_gid())
+    }
+
+    fn get_effective_groupname<'a>(&'a self) -> LookupFuture<'a, Option<Arc<OsStr>>> {
+        let inner = Arc::clone(&self.inner);
+        spawn(move || inner.get_effective_groupname())
+    }
+}
+
+
+#[cfg(test)]
+#[cfg(feature = "mock")]
+mod test {
+    use super::*;
+    use test::mock_alice;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread::{self, Thread};
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Polls the future on this thread until it is done.
+    fn block_on<T>(mut future: LookupFuture<T>) -> T {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(value) => return value,
+                Poll::Pending      => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn ready() {
+        let users = Ready(mock_alice());
+        assert_eq!(block_on(users.get_current_username()), Some(Arc::from(OsStr::new("alice"))));
+        assert_eq!(block_on(users.get_group_by_name(OsStr::new("users"))).map(|g| g.gid()), Some(100));
+    }
+
+    #[test]
+    fn trait_objects() {
+        let users: Box<dyn AsyncUsers + Send + Sync> = Box::new(Ready(mock_alice()));
+        assert_eq!(block_on(users.get_user_by_uid(1000)).map(|u| u.uid()), Some(1000));
+    }
+
+    #[test]
+    #[cfg(feature = "tokio")]
+    fn spawn_blocking() {
+        use tokio::runtime::Builder;
+
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let users = SpawnBlocking::new(mock_alice());
+        let name = runtime.block_on(users.get_user_by_name(OsStr::new("alice")));
+        assert_eq!(name.map(|u| u.uid()), Some(1000));
+    }
+}
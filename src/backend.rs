@@ -0,0 +1,411 @@
+//! Finding out, and choosing, which backend answers the functions in this
+//! crate.
+//!
+//! On most Unix platforms, every function asks the C library, and every
+//! answer is real. Elsewhere, some or all of them are answered by the stub,
+//! and an ID of 0 or a missing name says nothing about the actual system.
+//! Applications that display users, such as in an owner column, can check
+//! [`backend_info`](fn.backend_info.html) to decide whether to show them at
+//! all, instead of printing misleading zeros.
+//!
+//! # Examples
+//!
+//! ```
+//! use users::backend_info;
+//!
+//! let info = backend_info();
+//! if info.has_real_lookups() {
+//!     println!("Owners are looked up using {:?}", info.backend());
+//! }
+//! else {
+//!     println!("No owners to show");
+//! }
+//! ```
+//!
+//! ## Custom backends
+//!
+//! An application can also supply a backend of its own with
+//! [`set_backend`](fn.set_backend.html), such as one that asks an LDAP
+//! server, or a `MockUsers` table in tests. Anything implementing both
+//! `Users` and `Groups` will do. The user and group lookups, and the
+//! current and effective IDs and names, then come from it instead, on every
+//! platform; the other functions, such as `get_user_groups` and those in
+//! `switch`, are unaffected.
+//!
+//! Tests that run in parallel should use
+//! [`test::override_backend`](test/fn.override_backend.html) instead,
+//! which does the same for the current thread only.
+//!
+//! ## Choosing at runtime
+//!
+//! The `USERS_BACKEND` environment variable can pick a backend without any
+//! code changes. It is read the first time a backend is needed, and can
+//! be one of:
+//!
+//! - `native`, the platform’s own backend, which is the default;
+//! - `stub`, the stub, answering from its configuration as the
+//!   [`Stub`](stub/struct.Stub.html) type does;
+//! - `file:` followed by a directory, such as `file:/mnt/image/etc`, whose
+//!   `passwd` and `group` files are read with a
+//!   [`FileBackend`](files/struct.FileBackend.html).
+//!
+//! That lets an operator point a backup tool at a mounted image’s users,
+//! for example. Any other value is ignored, and a backend set with
+//! `set_backend` replaces the one the variable chose.
+//!
+//! ```
+//! use users::{User, get_current_uid, set_backend, clear_backend};
+//! use users::mock::MockUsers;
+//!
+//! let mut users = MockUsers::with_current_uid(1000);
+//! users.add_user(User::new(1000, "alice", 100));
+//! set_backend(users);
+//! assert_eq!(get_current_uid(), 1000);
+//! clear_backend();
+//! ```
+
+use std::cell::RefCell;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+use std::sync::{Arc, Once, RwLock};
+
+#[cfg(not(users_passthrough))]
+use platform::Current;
+
+use super::{User, Group, Users, Groups, uid_t, gid_t};
+use files::FileBackend;
+use platform::Platform;
+use stub::Stub;
+
+
+/// The environment variable choosing a backend at runtime.
+pub const BACKEND_VAR: &str = "USERS_BACKEND";
+
+
+/// The backends that can answer the functions in this crate.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum Backend {
+
+    /// The C library’s users database, on Unix platforms.
+    Native,
+
+    /// Windows accounts, mapped to IDs through their SIDs, with the
+    /// `windows-native` feature.
+    Windows,
+
+    /// The `/etc/passwd` and `/etc/group` files, read directly, on WASI,
+    /// or those in the directory `USERS_BACKEND` names.
+    Files,
+
+    /// The single synthetic user on Fuchsia.
+    Synthetic,
+
+    /// The stub, on platforms with no backend at all, or when
+    /// `USERS_BACKEND` asks for it.
+    Stub,
+
+    /// A backend the application supplied with `set_backend`.
+    Custom,
+}
+
+/// What the active backend is, and which of its answers reflect the
+/// system the process runs on.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct BackendInfo {
+    backend: Backend,
+    configured: bool,
+    switching: bool,
+}
+
+impl BackendInfo {
+
+    /// Returns the backend answering the functions in this crate.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Returns whether looking up users and groups by ID or name, and
+    /// listing them, consults an actual users database. A custom backend is
+    /// taken at its word.
+    pub fn has_real_lookups(&self) -> bool {
+        match self.backend {
+            Backend::Native | Backend::Windows | Backend::Files | Backend::Custom  => true,
+            Backend::Synthetic | Backend::Stub                                    => false,
+        }
+    }
+
+    /// Returns whether the current and effective user and group IDs are
+    /// the ones the process actually runs as. A custom backend is taken at
+    /// its word.
+    pub fn has_real_ids(&self) -> bool {
+        match self.backend {
+            Backend::Native | Backend::Windows | Backend::Custom   => true,
+            Backend::Files | Backend::Synthetic | Backend::Stub    => false,
+        }
+    }
+
+    /// Returns whether the functions in `switch` actually change who the
+    /// process runs as, which depends on the platform and the features the
+    /// crate was built with: on Windows, it takes `windows-native`, and
+    /// switches the calling thread only. With a custom backend, which has
+    /// no say in the matter, this is false.
+    pub fn has_real_switching(&self) -> bool {
+        self.switching
+    }
+
+    /// Returns whether the stub answers from a configuration, an identity
+    /// provider or the environment, rather than with dummy values. That
+    /// is what the application asked for, if not what the system says.
+    /// Always false for backends other than the stub.
+    pub fn is_configured(&self) -> bool {
+        self.configured
+    }
+}
+
+
+/// Returns what backend answers the functions in this crate, and which of
+/// its answers are real.
+#[cfg(users_passthrough)]
+pub fn backend_info() -> BackendInfo {
+    if let Some(info) = registered_info() {
+        return info;
+    }
+
+    BackendInfo { backend: Backend::Native, configured: false, switching: true }
+}
+
+/// Returns what backend answers the functions in this crate, and which of
+/// its answers are real.
+#[cfg(not(users_passthrough))]
+pub fn backend_info() -> BackendInfo {
+    if let Some(info) = registered_info() {
+        return info;
+    }
+
+    BackendInfo {
+        backend: Current::BACKEND,
+        configured: Current::IS_STUB && ! Current::is_dummy(),
+        switching: Current::SWITCHES,
+    }
+}
+
+
+/// Describes the backend set with `set_backend` or `USERS_BACKEND`, or for
+/// the current thread with `test::override_backend`, if there is one.
+fn registered_info() -> Option<BackendInfo> {
+    from_env();
+
+    if OVERRIDE.with(|o| o.borrow().is_some()) {
+        return Some(BackendInfo { backend: Backend::Custom, configured: false, switching: false });
+    }
+
+    let registered = REGISTERED.read().unwrap_or_else(|e| e.into_inner());
+    registered.as_ref().map(|r| BackendInfo {
+        backend: r.backend,
+        configured: r.backend == Backend::Stub && ! Stub::is_dummy(),
+        switching: false,
+    })
+}
+
+
+/// The lookups of `Users` and `Groups`, in a form that can be boxed, and
+/// answering with owned values like the functions in this crate do.
+pub(crate) trait Lookup {
+    fn user_by_uid(&self, uid: uid_t) -> Option<User>;
+    fn user_by_name(&self, username: &OsStr) -> Option<User>;
+    fn group_by_gid(&self, gid: gid_t) -> Option<Group>;
+    fn group_by_name(&self, groupname: &OsStr) -> Option<Group>;
+    fn current_uid(&self) -> uid_t;
+    fn current_username(&self) -> Option<OsString>;
+    fn effective_uid(&self) -> uid_t;
+    fn effective_username(&self) -> Option<OsString>;
+    fn current_gid(&self) -> gid_t;
+    fn current_groupname(&self) -> Option<OsString>;
+    fn effective_gid(&self) -> gid_t;
+    fn effective_groupname(&self) -> Option<OsString>;
+}
+
+impl<B: Users + Groups> Lookup for B {
+    fn user_by_uid(&self, uid: uid_t) -> Option<User> {
+        self.get_user_by_uid(uid).map(|user| (*user).clone())
+    }
+
+    fn user_by_name(&self, username: &OsStr) -> Option<User> {
+        self.get_user_by_name(username).map(|user| (*user).clone())
+    }
+
+    fn group_by_gid(&self, gid: gid_t) -> Option<Group> {
+        self.get_group_by_gid(gid).map(|group| (*group).clone())
+    }
+
+    fn group_by_name(&self, groupname: &OsStr) -> Option<Group> {
+        self.get_group_by_name(groupname).map(|group| (*group).clone())
+    }
+
+    fn current_uid(&self) -> uid_t {
+        self.get_current_uid()
+    }
+
+    fn current_username(&self) -> Option<OsString> {
+        self.get_current_username().map(|name| name.to_os_string())
+    }
+
+    fn effective_uid(&self) -> uid_t {
+        self.get_effective_uid()
+    }
+
+    fn effective_username(&self) -> Option<OsString> {
+        self.get_effective_username().map(|name| name.to_os_string())
+    }
+
+    fn current_gid(&self) -> gid_t {
+        self.get_current_gid()
+    }
+
+    fn current_groupname(&self) -> Option<OsString> {
+        self.get_current_groupname().map(|name| name.to_os_string())
+    }
+
+    fn effective_gid(&self) -> gid_t {
+        self.get_effective_gid()
+    }
+
+    fn effective_groupname(&self) -> Option<OsString> {
+        self.get_effective_groupname().map(|name| name.to_os_string())
+    }
+}
+
+
+/// A backend put in place for the whole process.
+struct Registered {
+
+    /// What kind of backend it is, for `backend_info`.
+    backend: Backend,
+
+    lookup: Arc<dyn Lookup + Send + Sync>,
+}
+
+static REGISTERED: RwLock<Option<Registered>> = RwLock::new(None);
+
+static FROM_ENV: Once = Once::new();
+
+thread_local! {
+    static OVERRIDE: RefCell<Option<Box<dyn Lookup>>> = RefCell::new(None);
+}
+
+/// Makes the user and group lookups, and the current and effective IDs and
+/// names, come from the given backend, replacing any that was set before.
+pub fn set_backend<B: Users + Groups + Send + Sync + 'static>(backend: B) {
+    from_env();
+    *REGISTERED.write().unwrap_or_else(|e| e.into_inner()) = Some(Registered { backend: Backend::Custom, lookup: Arc::new(backend) });
+}
+
+/// Removes the backend set with `set_backend` or `USERS_BACKEND`, if any,
+/// going back to the platform’s own.
+pub fn clear_backend() {
+    from_env();
+    *REGISTERED.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Puts the backend `USERS_BACKEND` asks for in place, the first time
+/// this is called.
+fn from_env() {
+    FROM_ENV.call_once(|| {
+        if let Some(registered) = env::var_os(BACKEND_VAR).and_then(|value| parse_var(&value)) {
+            *REGISTERED.write().unwrap_or_else(|e| e.into_inner()) = Some(registered);
+        }
+    });
+}
+
+/// Returns the backend a value of `USERS_BACKEND` asks for, or `None` for
+/// the platform’s own.
+fn parse_var(value: &OsStr) -> Option<Registered> {
+    match value.to_str()? {
+        "stub" => Some(Registered { backend: Backend::Stub, lookup: Arc::new(Stub) }),
+        value  => value.strip_prefix("file:").map(|dir| {
+            Registered { backend: Backend::Files, lookup: Arc::new(FileBackend::in_dir(Path::new(dir))) }
+        }),
+    }
+}
+
+/// Puts the given backend in place for the current thread only, returning
+/// the one it replaces.
+pub(crate) fn swap_override(backend: Option<Box<dyn Lookup>>) -> Option<Box<dyn Lookup>> {
+    OVERRIDE.with(|o| o.replace(backend))
+}
+
+/// Returns whether a backend was set with `set_backend`, or for the
+/// current thread with `test::override_backend`.
+pub(crate) fn is_registered() -> bool {
+    from_env();
+    OVERRIDE.with(|o| o.borrow().is_some())
+        || REGISTERED.read().unwrap_or_else(|e| e.into_inner()).is_some()
+}
+
+/// Runs the given function on the backend overriding the current thread’s,
+/// or otherwise the one set with `set_backend`, if there is one.
+pub(crate) fn with_registered<T, F>(f: F) -> Option<T>
+where F: FnOnce(&dyn Lookup) -> T
+{
+    from_env();
+
+    OVERRIDE.with(|o| {
+        if let Some(ref b) = *o.borrow() {
+            return Some(f(&**b));
+        }
+
+        // The lock is let go of before the backend is called, so a backend
+        // that looks something up itself, or that takes a while while
+        // another thread waits to set one, can’t deadlock.
+        let lookup = REGISTERED.read().unwrap_or_else(|e| e.into_inner()).as_ref().map(|r| Arc::clone(&r.lookup));
+        lookup.map(|lookup| f(&*lookup))
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn native() {
+        let info = BackendInfo { backend: Backend::Native, configured: false, switching: true };
+        assert!(info.has_real_lookups());
+        assert!(info.has_real_ids());
+        assert!(info.has_real_switching());
+    }
+
+    #[test]
+    fn stub() {
+        let info = BackendInfo { backend: Backend::Stub, configured: true, switching: false };
+        assert!(! info.has_real_lookups());
+        assert!(! info.has_real_ids());
+        assert!(! info.has_real_switching());
+    }
+
+    #[test]
+    fn variable() {
+        assert_eq!(parse_var(OsStr::new("stub")).map(|r| r.backend), Some(Backend::Stub));
+        assert_eq!(parse_var(OsStr::new("file:/etc")).map(|r| r.backend), Some(Backend::Files));
+        assert!(parse_var(OsStr::new("native")).is_none());
+        assert!(parse_var(OsStr::new("ldap")).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn lookup() {
+        use mock::MockUsers;
+
+        let mut users = MockUsers::with_current_uid(1000);
+        users.add_user(User::new(1000, "alice", 100));
+        users.add_group(Group::new(1000, "alice"));
+
+        let lookup: Box<dyn Lookup> = Box::new(users);
+        assert_eq!(lookup.current_username(), Some("alice".into()));
+        assert_eq!(lookup.user_by_name(OsStr::new("alice")).map(|u| u.uid()), Some(1000));
+        assert_eq!(lookup.current_groupname(), Some("alice".into()));
+        assert!(lookup.user_by_uid(1001).is_none());
+    }
+}
@@ -36,10 +36,17 @@ use std::io;
 use std::ptr;
 use std::sync::Arc;
 
+use backend::with_registered;
+use current;
+use error::{check_name, Error};
+use listing;
+use platform::{Current, Platform, stub_used};
+
 pub type c_char = i8;
 pub type c_int = i32;
 pub type uid_t = u32;
 pub type gid_t = u32;
+pub type time_t = i64;
 
 /// Information about a particular user.
 #[derive(Clone)]
@@ -231,88 +238,684 @@ impl fmt::Debug for Group {
     }
 }
 
-/// const empty vec
-unsafe fn members(groups: *mut *mut c_char) -> Vec<OsString> {
-    return vec![];
-}
-
 
-/// const None
+/// Searches the platform’s users for one with the given ID, or the
+/// [stub configuration](stub/index.html) where there is no backend
+#[track_caller]
 pub fn get_user_by_uid(uid: uid_t) -> Option<User> {
-    None
+    if let Some(user) = with_registered(|b| b.user_by_uid(uid)) {
+        return user;
+    }
+
+    stub_used("get_user_by_uid");
+    Current::get_user_by_uid(uid)
 }
 
-/// const None
+/// Searches the platform’s users for one with the given name, or the
+/// [stub configuration](stub/index.html) where there is no backend
+///
+/// With `windows-native`, `DOMAIN\user` and `user@domain` forms are
+/// accepted too.
+#[track_caller]
 pub fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(username: &S) -> Option<User> {
-    None
+    if let Some(user) = with_registered(|b| b.user_by_name(username.as_ref())) {
+        return user;
+    }
+
+    stub_used("get_user_by_name");
+    Current::get_user_by_name(username)
 }
 
-/// const None
+/// Searches the platform’s groups for one with the given ID, or the
+/// [stub configuration](stub/index.html) where there is no backend
+#[track_caller]
 pub fn get_group_by_gid(gid: gid_t) -> Option<Group> {
-    None
+    if let Some(group) = with_registered(|b| b.group_by_gid(gid)) {
+        return group;
+    }
+
+    stub_used("get_group_by_gid");
+    Current::get_group_by_gid(gid)
 }
 
-/// const None
+/// Searches the platform’s groups for one with the given name, or the
+/// [stub configuration](stub/index.html) where there is no backend
+#[track_caller]
 pub fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(groupname: &S) -> Option<Group> {
-    None
+    if let Some(group) = with_registered(|b| b.group_by_name(groupname.as_ref())) {
+        return group;
+    }
+
+    stub_used("get_group_by_name");
+    Current::get_group_by_name(groupname)
+}
+
+/// Searches the platform’s users for one with the given ID, or the stub
+/// configuration, telling a missing user apart from one that couldn’t be
+/// looked up
+#[track_caller]
+pub fn try_get_user_by_uid(uid: uid_t) -> Result<User, Error> {
+    if let Some(user) = with_registered(|b| b.user_by_uid(uid)) {
+        return user.ok_or(Error::NotFound);
+    }
+
+    stub_used("try_get_user_by_uid");
+    if Current::is_dummy() {
+        return Err(Error::Unsupported);
+    }
+
+    Error::from_lookup(Current::try_get_user_by_uid(uid))
 }
 
-/// const 0
+/// Searches the platform’s users for one with the given name, or the stub
+/// configuration, telling a missing user apart from one that couldn’t be
+/// looked up
+#[track_caller]
+pub fn try_get_user_by_name<S: AsRef<OsStr> + ?Sized>(username: &S) -> Result<User, Error> {
+    let username = username.as_ref();
+    check_name(username)?;
+
+    if let Some(user) = with_registered(|b| b.user_by_name(username)) {
+        return user.ok_or(Error::NotFound);
+    }
+
+    stub_used("try_get_user_by_name");
+    if Current::is_dummy() {
+        return Err(Error::Unsupported);
+    }
+
+    Error::from_lookup(Current::try_get_user_by_name(username))
+}
+
+/// Searches the platform’s groups for one with the given ID, or the stub
+/// configuration, telling a missing group apart from one that couldn’t be
+/// looked up
+#[track_caller]
+pub fn try_get_group_by_gid(gid: gid_t) -> Result<Group, Error> {
+    if let Some(group) = with_registered(|b| b.group_by_gid(gid)) {
+        return group.ok_or(Error::NotFound);
+    }
+
+    stub_used("try_get_group_by_gid");
+    if Current::is_dummy() {
+        return Err(Error::Unsupported);
+    }
+
+    Error::from_lookup(Current::try_get_group_by_gid(gid))
+}
+
+/// Searches the platform’s groups for one with the given name, or the stub
+/// configuration, telling a missing group apart from one that couldn’t be
+/// looked up
+#[track_caller]
+pub fn try_get_group_by_name<S: AsRef<OsStr> + ?Sized>(groupname: &S) -> Result<Group, Error> {
+    let groupname = groupname.as_ref();
+    check_name(groupname)?;
+
+    if let Some(group) = with_registered(|b| b.group_by_name(groupname)) {
+        return group.ok_or(Error::NotFound);
+    }
+
+    stub_used("try_get_group_by_name");
+    if Current::is_dummy() {
+        return Err(Error::Unsupported);
+    }
+
+    Error::from_lookup(Current::try_get_group_by_name(groupname))
+}
+
+/// The platform’s idea of the current user ID, as looked up the first time
+/// (see `refresh_current_identity`), or the stub configuration’s (0 unless
+/// configured)
+#[track_caller]
 pub fn get_current_uid() -> uid_t {
-    0
+    if let Some(uid) = with_registered(|b| b.current_uid()) {
+        return uid;
+    }
+
+    stub_used("get_current_uid");
+    if Current::IS_STUB { Current::get_current_uid() } else { current::current_uid(Current::get_current_uid) }
 }
 
-/// const None
+/// The platform’s idea of the current username, as looked up the first time
+/// (see `refresh_current_identity`), or the stub configuration’s (None
+/// unless configured)
+#[track_caller]
 pub fn get_current_username() -> Option<OsString> {
-    None
+    if let Some(username) = with_registered(|b| b.current_username()) {
+        return username;
+    }
+
+    stub_used("get_current_username");
+    if Current::IS_STUB { Current::get_current_username() } else { current::current_username(Current::get_current_username) }
 }
 
-/// const 0
+/// The platform’s idea of the effective user ID, or the stub
+/// configuration’s current one (0 unless configured)
+#[track_caller]
 pub fn get_effective_uid() -> uid_t {
-    0
+    if let Some(uid) = with_registered(|b| b.effective_uid()) {
+        return uid;
+    }
+
+    stub_used("get_effective_uid");
+    Current::get_effective_uid()
 }
 
-/// const None
+/// The platform’s idea of the effective username, or the stub
+/// configuration’s current one (None unless configured)
+#[track_caller]
 pub fn get_effective_username() -> Option<OsString> {
-    None
+    if let Some(username) = with_registered(|b| b.effective_username()) {
+        return username;
+    }
+
+    stub_used("get_effective_username");
+    Current::get_effective_username()
 }
 
-/// const 0
+/// The platform’s idea of the current group ID, or the stub configuration’s
+/// (0 unless configured)
+#[track_caller]
 pub fn get_current_gid() -> gid_t {
-    0
+    if let Some(gid) = with_registered(|b| b.current_gid()) {
+        return gid;
+    }
+
+    stub_used("get_current_gid");
+    Current::get_current_gid()
 }
 
-/// const None
+/// The platform’s idea of the current group name, or the stub
+/// configuration’s (None unless configured)
+#[track_caller]
 pub fn get_current_groupname() -> Option<OsString> {
-    None
+    if let Some(groupname) = with_registered(|b| b.current_groupname()) {
+        return groupname;
+    }
+
+    stub_used("get_current_groupname");
+    Current::get_current_groupname()
 }
 
-/// const 0
+/// The platform’s idea of the effective group ID, or the stub
+/// configuration’s current one (0 unless configured)
+#[track_caller]
 pub fn get_effective_gid() -> gid_t {
-    0
+    if let Some(gid) = with_registered(|b| b.effective_gid()) {
+        return gid;
+    }
+
+    stub_used("get_effective_gid");
+    Current::get_effective_gid()
 }
 
-/// const None
+/// The platform’s idea of the effective group name, or the stub
+/// configuration’s current one (None unless configured)
+#[track_caller]
 pub fn get_effective_groupname() -> Option<OsString> {
-    None
+    if let Some(groupname) = with_registered(|b| b.effective_groupname()) {
+        return groupname;
+    }
+
+    stub_used("get_effective_groupname");
+    Current::get_effective_groupname()
+}
+
+/// The platform’s idea of the real, effective, and saved user IDs, or the
+/// stub configuration’s current one for all three (0 unless configured)
+///
+/// Only a few platforms can tell the saved user ID, so elsewhere it is
+/// reported as the effective one, which is what it starts out as.
+#[track_caller]
+pub fn get_resuid() -> (uid_t, uid_t, uid_t) {
+    if let Some((ruid, euid)) = with_registered(|b| (b.current_uid(), b.effective_uid())) {
+        return (ruid, euid, euid);
+    }
+
+    stub_used("get_resuid");
+    Current::get_resuid()
 }
 
-/// const Ok empty vec
+/// The platform’s idea of the real, effective, and saved group IDs, or the
+/// stub configuration’s current one for all three (0 unless configured)
+///
+/// Only a few platforms can tell the saved group ID, so elsewhere it is
+/// reported as the effective one, which is what it starts out as.
+#[track_caller]
+pub fn get_resgid() -> (gid_t, gid_t, gid_t) {
+    if let Some((rgid, egid)) = with_registered(|b| (b.current_gid(), b.effective_gid())) {
+        return (rgid, egid, egid);
+    }
+
+    stub_used("get_resgid");
+    Current::get_resgid()
+}
+
+/// The groups the process is a member of on the platform, or the stub
+/// configuration’s current user’s (Ok empty vec unless configured)
+#[track_caller]
 pub fn group_access_list() -> io::Result<Vec<Group>> {
-    Ok(vec![])
+    stub_used("group_access_list");
+
+    Current::group_access_list()
 }
 
-/// const None
+/// The groups the given user is a member of, primary group first, on the
+/// platform or in the stub configuration (None unless configured)
+#[track_caller]
 pub fn get_user_groups<S: AsRef<OsStr> + ?Sized>(username: &S, gid: gid_t) -> Option<Vec<Group>> {
-    None
+    stub_used("get_user_groups");
+
+    Current::get_user_groups(username, gid)
+}
+
+/// The IDs of the groups the given user is a member of, without looking
+/// the groups themselves up
+#[track_caller]
+pub(crate) fn get_user_group_ids(username: &OsStr, gid: gid_t) -> Option<Vec<gid_t>> {
+    stub_used("get_user_groups");
+
+    Current::get_user_group_ids(username, gid)
 }
 
-/// empty iterator
+/// Every user on the platform, or in the stub configuration (empty
+/// iterator unless configured)
+#[track_caller]
 pub unsafe fn all_users() -> impl Iterator<Item=User> {
-    std::iter::empty()
+    stub_used("all_users");
+
+    Current::all_users().into_iter()
+}
+
+/// Every user on the platform, or in the stub configuration, or the reason
+/// they can’t be listed (Unsupported error unless configured)
+#[track_caller]
+pub fn try_all_users() -> io::Result<impl Iterator<Item=io::Result<User>>> {
+    stub_used("try_all_users");
+
+    let _listing = listing::lock();
+    Current::try_all_users().map(Vec::into_iter)
+}
+
+/// Every group on the platform, or in the stub configuration (empty
+/// iterator unless configured)
+///
+/// # Safety
+///
+/// Listing groups uses state shared by the whole process, so no other
+/// thread may list groups at the same time.
+#[track_caller]
+pub unsafe fn all_groups() -> impl Iterator<Item=Group> {
+    stub_used("all_groups");
+
+    Current::all_groups().into_iter()
 }
 
 pub mod os {
-    pub type UserExtras = ();
-    pub type GroupExtras = ();
+
+    /// Extensions to users for Windows.
+    ///
+    /// Windows accounts are identified by a security identifier rather than
+    /// by a number; the user ID of a `User` is derived from it.
+    #[cfg(windows)]
+    pub mod windows {
+        use std::ffi::{OsStr, OsString};
+        use std::path::{Path, PathBuf};
+
+        use super::super::User;
+        use super::bsd;
+        use sid::Sid;
+
+        /// Windows-specific extensions for `User`s.
+        pub trait UserExt {
+
+            /// Returns the security identifier of this user’s account, if
+            /// the user was read from the system.
+            fn sid(&self) -> Option<&Sid>;
+
+            /// Sets this user’s security identifier.
+            fn with_sid(self, sid: Sid) -> Self;
+
+            /// Returns the name of the domain this user’s account belongs to:
+            /// the machine name for local accounts, or the NetBIOS name of an
+            /// Active Directory domain.
+            fn domain(&self) -> Option<&OsStr>;
+
+            /// Sets the name of this user’s domain.
+            fn with_domain<S: AsRef<OsStr> + ?Sized>(self, domain: &S) -> Self;
+
+            /// Returns the path to this user’s profile directory, such as
+            /// `C:\Users\alice`, if the user has logged on at least once.
+            fn profile_dir(&self) -> Option<&Path>;
+
+            /// Sets the path to this user’s profile directory.
+            fn with_profile_dir<S: AsRef<OsStr> + ?Sized>(self, profile_dir: &S) -> Self;
+
+            /// Returns whether this user’s account is disabled, so that they
+            /// can’t log on.
+            fn is_disabled(&self) -> bool;
+
+            /// Sets whether this user’s account is disabled.
+            fn with_disabled(self, disabled: bool) -> Self;
+        }
+
+        /// Windows-specific fields for `User`s.
+        #[derive(Clone, Default, Debug)]
+        pub struct UserExtras {
+
+            /// The security identifier of the user’s account.
+            pub sid: Option<Sid>,
+
+            /// The name of the domain the user’s account belongs to.
+            pub domain: Option<OsString>,
+
+            /// The path to the user’s profile directory.
+            pub profile_dir: Option<PathBuf>,
+
+            /// Whether the user’s account is disabled.
+            pub disabled: bool,
+
+            /// The fields of `User`s every platform has.
+            pub extras: bsd::UserExtras,
+        }
+
+        impl UserExt for User {
+            fn sid(&self) -> Option<&Sid> {
+                self.extras.sid.as_ref()
+            }
+
+            fn with_sid(mut self, sid: Sid) -> Self {
+                self.extras.sid = Some(sid);
+                self
+            }
+
+            fn domain(&self) -> Option<&OsStr> {
+                self.extras.domain.as_ref().map(OsString::as_os_str)
+            }
+
+            fn with_domain<S: AsRef<OsStr> + ?Sized>(mut self, domain: &S) -> Self {
+                self.extras.domain = Some(domain.into());
+                self
+            }
+
+            fn profile_dir(&self) -> Option<&Path> {
+                self.extras.profile_dir.as_ref().map(PathBuf::as_path)
+            }
+
+            fn with_profile_dir<S: AsRef<OsStr> + ?Sized>(mut self, profile_dir: &S) -> Self {
+                self.extras.profile_dir = Some(profile_dir.into());
+                self
+            }
+
+            fn is_disabled(&self) -> bool {
+                self.extras.disabled
+            }
+
+            fn with_disabled(mut self, disabled: bool) -> Self {
+                self.extras.disabled = disabled;
+                self
+            }
+        }
+    }
+
+    /// Extensions to users and groups as the `/etc` files describe them.
+    ///
+    /// These are the same as the real crate’s, so that code using them on
+    /// Unix compiles unchanged against the stub, on every platform. Users
+    /// that don’t come from the files get the same dummy home directory,
+    /// shell, and password as the real crate’s test users.
+    pub mod unix {
+        use std::ffi::{OsStr, OsString};
+        use std::path::{Path, PathBuf};
+
+        use super::super::{User, Group};
+
+        /// Unix-specific extensions for `User`s.
+        pub trait UserExt {
+
+            /// Returns a path to this user’s home directory.
+            fn home_dir(&self) -> &Path;
+
+            /// Sets this user value’s home directory to the given string.
+            /// Can be used to construct test users, which by default come with a
+            /// dummy home directory string.
+            fn with_home_dir<S: AsRef<OsStr> + ?Sized>(self, home_dir: &S) -> Self;
+
+            /// Returns a path to this user’s shell.
+            fn shell(&self) -> &Path;
+
+            /// Sets this user’s shell path to the given string.
+            /// Can be used to construct test users, which by default come with a
+            /// dummy shell field.
+            fn with_shell<S: AsRef<OsStr> + ?Sized>(self, shell: &S) -> Self;
+
+            /// Returns the user’s encrypted password.
+            fn password(&self) -> &OsStr;
+
+            /// Sets this user’s password to the given string.
+            /// Can be used to construct tests users, which by default come with a
+            /// dummy password field.
+            fn with_password<S: AsRef<OsStr> + ?Sized>(self, password: &S) -> Self;
+        }
+
+        /// Unix-specific extensions for `Group`s.
+        pub trait GroupExt {
+
+            /// Returns a slice of the list of users that are in this group as
+            /// their non-primary group.
+            fn members(&self) -> &[OsString];
+
+            /// Adds a new member to this group.
+            fn add_member<S: AsRef<OsStr> + ?Sized>(self, name: &S) -> Self;
+        }
+
+        /// Unix-specific fields for `User`s.
+        #[derive(Clone, Debug)]
+        pub struct UserExtras {
+
+            /// The path to the user’s home directory.
+            pub home_dir: PathBuf,
+
+            /// The path to the user’s shell.
+            pub shell: PathBuf,
+
+            /// The user’s encrypted password.
+            pub password: OsString,
+
+            /// The user’s GECOS field, which [`gecos`](../../gecos/index.html)
+            /// can make sense of.
+            pub gecos: OsString,
+        }
+
+        impl Default for UserExtras {
+            fn default() -> Self {
+                Self {
+                    home_dir: "/var/empty".into(),
+                    shell:    "/bin/false".into(),
+                    password: "*".into(),
+                    gecos:    OsString::new(),
+                }
+            }
+        }
+
+        impl User {
+            fn unix(&self) -> &UserExtras {
+                &self.bsd().extras
+            }
+
+            fn unix_mut(&mut self) -> &mut UserExtras {
+                &mut self.bsd_mut().extras
+            }
+
+            /// Returns this user’s GECOS field. The real crate doesn’t keep
+            /// one, so it isn’t part of `UserExt`.
+            pub(crate) fn gecos_field(&self) -> &OsStr {
+                &self.unix().gecos
+            }
+
+            /// Sets this user’s GECOS field.
+            pub(crate) fn with_gecos_field<S: AsRef<OsStr> + ?Sized>(mut self, gecos: &S) -> Self {
+                self.unix_mut().gecos = gecos.into();
+                self
+            }
+        }
+
+        impl UserExt for User {
+            fn home_dir(&self) -> &Path {
+                &self.unix().home_dir
+            }
+
+            fn with_home_dir<S: AsRef<OsStr> + ?Sized>(mut self, home_dir: &S) -> Self {
+                self.unix_mut().home_dir = home_dir.into();
+                self
+            }
+
+            fn shell(&self) -> &Path {
+                &self.unix().shell
+            }
+
+            fn with_shell<S: AsRef<OsStr> + ?Sized>(mut self, shell: &S) -> Self {
+                self.unix_mut().shell = shell.into();
+                self
+            }
+
+            fn password(&self) -> &OsStr {
+                &self.unix().password
+            }
+
+            fn with_password<S: AsRef<OsStr> + ?Sized>(mut self, password: &S) -> Self {
+                self.unix_mut().password = password.into();
+                self
+            }
+        }
+
+        /// Unix-specific fields for `Group`s.
+        #[derive(Clone, Default, Debug)]
+        pub struct GroupExtras {
+
+            /// Vector of usernames that are members of this group.
+            pub members: Vec<OsString>,
+        }
+
+        impl GroupExt for Group {
+            fn members(&self) -> &[OsString] {
+                &self.extras.members
+            }
+
+            fn add_member<S: AsRef<OsStr> + ?Sized>(mut self, member: &S) -> Self {
+                self.extras.members.push(member.into());
+                self
+            }
+        }
+    }
+
+    /// Extensions to users for BSD platforms.
+    ///
+    /// These are the same as the real crate’s on macOS, FreeBSD, and
+    /// DragonFly, so that code using them compiles unchanged against the
+    /// stub, on every platform. Only users read from a `master.passwd` file
+    /// have password change and expiry times; for the rest, both are 0,
+    /// meaning “never”.
+    pub mod bsd {
+        use std::ops::{Deref, DerefMut};
+
+        use super::super::{User, time_t};
+        use super::unix;
+
+        /// BSD-specific fields for `User`s.
+        #[derive(Clone, Default, Debug)]
+        pub struct UserExtras {
+
+            /// Fields specific to Unix, rather than just BSD. (This struct is
+            /// a superset, so it has to have all the other fields in it, too).
+            pub extras: unix::UserExtras,
+
+            /// Password change time.
+            pub change: time_t,
+
+            /// Password expiry time.
+            pub expire: time_t,
+        }
+
+        impl Deref for UserExtras {
+            type Target = unix::UserExtras;
+
+            fn deref(&self) -> &Self::Target {
+                &self.extras
+            }
+        }
+
+        impl DerefMut for UserExtras {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                &mut self.extras
+            }
+        }
+
+        /// BSD-specific accessors for `User`s.
+        pub trait UserExt {
+
+            /// Returns this user's password change timestamp.
+            fn password_change_time(&self) -> time_t;
+
+            /// Returns this user's password expiry timestamp.
+            fn password_expire_time(&self) -> time_t;
+        }
+
+        // On Windows, these live inside the Windows-specific fields.
+        #[cfg(windows)]
+        impl User {
+            pub(super) fn bsd(&self) -> &UserExtras {
+                &self.extras.extras
+            }
+
+            pub(super) fn bsd_mut(&mut self) -> &mut UserExtras {
+                &mut self.extras.extras
+            }
+        }
+
+        #[cfg(not(windows))]
+        impl User {
+            pub(super) fn bsd(&self) -> &UserExtras {
+                &self.extras
+            }
+
+            pub(super) fn bsd_mut(&mut self) -> &mut UserExtras {
+                &mut self.extras
+            }
+        }
+
+        impl User {
+
+            /// Sets this user’s password change and expiry times. The real
+            /// crate has no way to do this, so it isn’t public.
+            pub(crate) fn with_password_times(mut self, change: time_t, expire: time_t) -> Self {
+                let extras = self.bsd_mut();
+                extras.change = change;
+                extras.expire = expire;
+                self
+            }
+        }
+
+        impl UserExt for User {
+            fn password_change_time(&self) -> time_t {
+                self.bsd().change
+            }
+
+            fn password_expire_time(&self) -> time_t {
+                self.bsd().expire
+            }
+        }
+    }
+
+    /// Any extra fields on a `User` specific to the current platform.
+    #[cfg(windows)]
+    pub type UserExtras = windows::UserExtras;
+
+    /// Any extra fields on a `User` specific to the current platform.
+    #[cfg(not(windows))]
+    pub type UserExtras = bsd::UserExtras;
+
+    /// Any extra fields on a `Group` specific to the current platform.
+    pub type GroupExtras = unix::GroupExtras;
 }
@@ -0,0 +1,107 @@
+//! Looking up many users or groups at once.
+//!
+//! Tools that list directories resolve the owner of every file, and most
+//! files share a few owners. These functions look each ID up once, however
+//! often it occurs, and when there are many different IDs, they list every
+//! user in one pass instead of asking about each one separately:
+//!
+//! ```
+//! use users::get_users_by_uids;
+//!
+//! let owners = get_users_by_uids(&[ 0, 0, 1000, 0 ]);
+//! if let Some(root) = owners.get(&0) {
+//!     println!("root is called {:?}", root.name());
+//! }
+//! ```
+//!
+//! `UsersCache::get_groups_by_gids` does the same for groups, and keeps
+//! the groups it found for next time.
+
+use std::collections::{BTreeSet, HashMap};
+use std::hash::Hash;
+
+use {User, Group, uid_t, gid_t};
+use {get_user_by_uid, get_group_by_gid, all_users_snapshot, all_groups_snapshot};
+use backend::is_registered;
+
+
+/// How many different IDs make listing everything faster than looking
+/// each one up.
+const SCAN_THRESHOLD: usize = 32;
+
+
+/// Looks up the users with the given IDs, returning those that exist.
+///
+/// Each ID is only looked up once. With more than a few dozen different
+/// IDs, every user is listed in one pass, and only the IDs that weren’t in
+/// the list, as happens with some network users databases, are then looked
+/// up one at a time.
+pub fn get_users_by_uids(uids: &[uid_t]) -> HashMap<uid_t, User> {
+    by_ids(uids, all_users_snapshot, User::uid, get_user_by_uid)
+}
+
+/// Looks up the groups with the given IDs, returning those that exist.
+///
+/// This works the same way as `get_users_by_uids`.
+pub fn get_groups_by_gids(gids: &[gid_t]) -> HashMap<gid_t, Group> {
+    by_ids(gids, all_groups_snapshot, Group::gid, get_group_by_gid)
+}
+
+fn by_ids<K, V>(ids: &[K], list: fn() -> Vec<V>, id: fn(&V) -> K, lookup: fn(K) -> Option<V>) -> HashMap<K, V>
+where K: Ord + Hash + Copy
+{
+    let mut wanted = ids.iter().cloned().collect::<BTreeSet<_>>();
+    let mut found = HashMap::with_capacity(wanted.len());
+
+    // A backend set with `set_backend` can only be asked one at a time.
+    if wanted.len() > SCAN_THRESHOLD && ! is_registered() {
+        for value in list() {
+            if wanted.remove(&id(&value)) {
+                found.insert(id(&value), value);
+            }
+        }
+    }
+
+    for key in wanted {
+        if let Some(value) = lookup(key) {
+            found.insert(key, value);
+        }
+    }
+
+    found
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use get_current_uid;
+
+    #[test]
+    fn deduplicates() {
+        let uid = get_current_uid();
+        let users = get_users_by_uids(&[ uid, uid, uid ]);
+        assert!(users.len() <= 1);
+        assert_eq!(users.get(&uid).map(User::uid), get_user_by_uid(uid).map(|u| u.uid()));
+    }
+
+    #[test]
+    fn groups_scan_agrees() {
+        let gids = (0 .. 100).collect::<Vec<gid_t>>();
+        let groups = get_groups_by_gids(&gids);
+        for gid in gids {
+            assert_eq!(groups.get(&gid).map(|g| g.name().to_owned()),
+                       get_group_by_gid(gid).map(|g| g.name().to_owned()));
+        }
+    }
+
+    #[test]
+    fn scan_agrees() {
+        let uids = (0 .. 100).collect::<Vec<uid_t>>();
+        let users = get_users_by_uids(&uids);
+        for uid in uids {
+            assert_eq!(users.get(&uid).map(|u| u.name().to_owned()),
+                       get_user_by_uid(uid).map(|u| u.name().to_owned()));
+        }
+    }
+}
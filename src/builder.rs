@@ -0,0 +1,230 @@
+//! Builders for `User` and `Group` values with every field filled in.
+//!
+//! `User::new` only takes an ID, a name, and a primary group, and leaves the
+//! rest as dummy values. A builder, which `User::builder` starts once
+//! `UserBuilderExt` is in scope, can set the others too. `Group::builder`
+//! does the same for groups and their members:
+//!
+//! ```
+//! use users::{User, UserBuilderExt};
+//! use users::os::unix::UserExt;
+//!
+//! let user = User::builder(1000, "alice", 100)
+//!     .home_dir("/home/alice")
+//!     .shell("/bin/zsh")
+//!     .gecos("Alice Appleseed,,,")
+//!     .build();
+//!
+//! assert_eq!(user.shell(), std::path::Path::new("/bin/zsh"));
+//!
+//! use users::{Group, GroupBuilderExt};
+//! use users::os::unix::GroupExt;
+//!
+//! let group = Group::builder(10, "wheel").member("root").member("alice").build();
+//! assert_eq!(group.members().len(), 2);
+//! ```
+//!
+//! The real crate’s users have nowhere to keep a GECOS field, so with it,
+//! `gecos` is ignored.
+
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+
+use {User, Group, uid_t, gid_t};
+use os::unix::{UserExt, GroupExt};
+
+
+/// A builder for a `User`. Any field that isn’t set gets the same dummy
+/// value `User::new` gives it.
+#[derive(Clone, Debug)]
+pub struct UserBuilder {
+    uid: uid_t,
+    name: OsString,
+    primary_group: gid_t,
+    home_dir: Option<PathBuf>,
+    shell: Option<PathBuf>,
+    password: Option<OsString>,
+    gecos: Option<OsString>,
+}
+
+impl UserBuilder {
+
+    /// Starts building a user with the given ID, name, and primary group.
+    pub fn new<S: AsRef<OsStr> + ?Sized>(uid: uid_t, name: &S, primary_group: gid_t) -> Self {
+        Self {
+            uid,
+            name: name.into(),
+            primary_group,
+            home_dir: None,
+            shell: None,
+            password: None,
+            gecos: None,
+        }
+    }
+
+    /// Sets the user’s home directory.
+    pub fn home_dir<S: AsRef<OsStr> + ?Sized>(mut self, home_dir: &S) -> Self {
+        self.home_dir = Some(home_dir.into());
+        self
+    }
+
+    /// Sets the user’s shell.
+    pub fn shell<S: AsRef<OsStr> + ?Sized>(mut self, shell: &S) -> Self {
+        self.shell = Some(shell.into());
+        self
+    }
+
+    /// Sets the user’s encrypted password.
+    pub fn password<S: AsRef<OsStr> + ?Sized>(mut self, password: &S) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Sets the user’s GECOS field, which `GecosExt` can make sense of.
+    pub fn gecos<S: AsRef<OsStr> + ?Sized>(mut self, gecos: &S) -> Self {
+        self.gecos = Some(gecos.into());
+        self
+    }
+
+    /// Builds the user.
+    pub fn build(self) -> User {
+        let mut user = User::new(self.uid, &self.name, self.primary_group);
+
+        if let Some(home_dir) = self.home_dir {
+            user = user.with_home_dir(&home_dir);
+        }
+
+        if let Some(shell) = self.shell {
+            user = user.with_shell(&shell);
+        }
+
+        if let Some(password) = self.password {
+            user = user.with_password(&password);
+        }
+
+        #[cfg(not(users_passthrough))]
+        {
+            if let Some(gecos) = self.gecos {
+                user = user.with_gecos_field(&gecos);
+            }
+        }
+
+        user
+    }
+}
+
+
+/// Adds `User::builder`. The real crate’s `User` can’t be given methods
+/// of its own here, so this trait has to be in scope.
+pub trait UserBuilderExt {
+
+    /// Starts building a user with the given ID, name, and primary group.
+    fn builder<S: AsRef<OsStr> + ?Sized>(uid: uid_t, name: &S, primary_group: gid_t) -> UserBuilder;
+}
+
+impl UserBuilderExt for User {
+    fn builder<S: AsRef<OsStr> + ?Sized>(uid: uid_t, name: &S, primary_group: gid_t) -> UserBuilder {
+        UserBuilder::new(uid, name, primary_group)
+    }
+}
+
+
+/// A builder for a `Group`.
+#[derive(Clone, Debug)]
+pub struct GroupBuilder {
+    gid: gid_t,
+    name: OsString,
+    members: Vec<OsString>,
+}
+
+impl GroupBuilder {
+
+    /// Starts building a group with the given ID and name, and no members.
+    pub fn new<S: AsRef<OsStr> + ?Sized>(gid: gid_t, name: &S) -> Self {
+        Self { gid, name: name.into(), members: Vec::new() }
+    }
+
+    /// Adds a member to the group.
+    pub fn member<S: AsRef<OsStr> + ?Sized>(mut self, name: &S) -> Self {
+        self.members.push(name.into());
+        self
+    }
+
+    /// Adds every one of the given members to the group.
+    pub fn members<I>(mut self, names: I) -> Self
+    where I: IntoIterator,
+          I::Item: AsRef<OsStr>,
+    {
+        self.members.extend(names.into_iter().map(|name| name.as_ref().to_owned()));
+        self
+    }
+
+    /// Builds the group.
+    pub fn build(self) -> Group {
+        self.members.iter().fold(Group::new(self.gid, &self.name), |group, member| group.add_member(member))
+    }
+}
+
+
+/// Adds `Group::builder`, for the same reason as `UserBuilderExt`.
+pub trait GroupBuilderExt {
+
+    /// Starts building a group with the given ID and name.
+    fn builder<S: AsRef<OsStr> + ?Sized>(gid: gid_t, name: &S) -> GroupBuilder;
+}
+
+impl GroupBuilderExt for Group {
+    fn builder<S: AsRef<OsStr> + ?Sized>(gid: gid_t, name: &S) -> GroupBuilder {
+        GroupBuilder::new(gid, name)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn defaults() {
+        let user = User::builder(1000, "alice", 100).build();
+        let plain = User::new(1000, "alice", 100);
+        assert_eq!(user.home_dir(), plain.home_dir());
+        assert_eq!(user.shell(), plain.shell());
+        assert_eq!(user.password(), plain.password());
+    }
+
+    #[test]
+    fn every_field() {
+        let user = User::builder(1000, "alice", 100)
+            .home_dir("/home/alice")
+            .shell("/bin/zsh")
+            .password("$6$hash")
+            .gecos("Alice Appleseed,,,")
+            .build();
+
+        assert_eq!(user.uid(), 1000);
+        assert_eq!(user.name(), "alice");
+        assert_eq!(user.primary_group_id(), 100);
+        assert_eq!(user.home_dir(), Path::new("/home/alice"));
+        assert_eq!(user.shell(), Path::new("/bin/zsh"));
+        assert_eq!(user.password(), "$6$hash");
+    }
+
+    #[test]
+    fn group_members() {
+        let group = Group::builder(10, "wheel").member("root").members(vec![ "alice", "bob" ]).build();
+        assert_eq!(group.gid(), 10);
+        assert_eq!(group.name(), "wheel");
+        assert_eq!(group.members(), &[ OsString::from("root"), "alice".into(), "bob".into() ][..]);
+    }
+
+    #[test]
+    #[cfg(not(users_passthrough))]
+    fn gecos() {
+        use gecos::GecosExt;
+
+        let user = User::builder(1000, "alice", 100).gecos("Alice Appleseed,,,").build();
+        assert_eq!(user.full_name(), Some("Alice Appleseed".into()));
+    }
+}
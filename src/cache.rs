@@ -6,6 +6,10 @@
 //! type helps with this, providing methods that have the same name as the
 //! others in this crate, only they store the results.
 //!
+//! The cache works the same on every platform: where the stub answers the
+//! lookups, it stores the stub’s answers, so code using it compiles and runs
+//! unchanged.
+//!
 //! ## Example
 //!
 //! ```no_run
@@ -83,16 +87,86 @@
 //! the values themselves don’t count as being stored *in* the cache anymore. So
 //! it can be queried multiple times or go out of scope and the values it
 //! produces are not affected.
+//!
+//! The `RefCell`s do mean that a `UsersCache` can’t be shared between
+//! threads. For that, use a
+//! [`SharedUsersCache`](../shared_cache/struct.SharedUsersCache.html).
+//!
+//! ## Bounded caches
+//!
+//! A cache keeps every result it is given, which adds up when scanning many
+//! files on a host with a huge network users database. A cache created with
+//! [`with_capacity`](struct.UsersCache.html#method.with_capacity) instead
+//! holds a limited number of users and groups, evicting those used least
+//! recently to make room for new ones.
+//!
+//! Users and groups that weren’t found are cached too, so that scanning a
+//! directory full of files whose owners were deleted doesn’t ask a network
+//! users database over and over. A cache that shouldn’t keep them can be
+//! made with
+//! [`without_negative_caching`](struct.UsersCache.html#method.without_negative_caching).
+//!
+//! ## Snapshots
+//!
+//! With the `serde` feature, a cache’s contents can be saved as a
+//! [`CacheSnapshot`](struct.CacheSnapshot.html) and loaded back into a new
+//! cache later. A short-lived program that runs over and over, such as one
+//! that draws a shell prompt, can then keep what it looked up between runs:
+//!
+//! ```no_run
+//! # extern crate serde_json;
+//! # extern crate users;
+//! # #[cfg(feature = "serde")]
+//! # fn main() {
+//! use users::{Users, UsersCache};
+//! use users::cache::CacheSnapshot;
+//!
+//! let cache = UsersCache::new();
+//! if let Ok(json) = std::fs::read_to_string("users-cache.json") {
+//!     let snapshot: CacheSnapshot = serde_json::from_str(&json).unwrap();
+//!     cache.load_snapshot(snapshot);
+//! }
+//!
+//! let user = cache.get_user_by_uid(1000);
+//! let json = serde_json::to_string(&cache.snapshot()).unwrap();
+//! std::fs::write("users-cache.json", json).unwrap();
+//! # }
+//! # #[cfg(not(feature = "serde"))]
+//! # fn main() {}
+//! ```
+//!
+//! Users are saved with everything the `serialize` module writes, such as
+//! their home directory, shell, and GECOS field, and groups with their
+//! members. The snapshot is only as fresh as when it was taken, so a
+//! program should throw it away every so often.
+//!
+//! ## Noticing changes
+//!
+//! With the `watch-files` feature, the cache checks whether `/etc/passwd`
+//! or `/etc/group` changed, at most once a second, and forgets every user
+//! and group it looked up if they did. A long-running process then never
+//! keeps serving accounts that were deleted or renamed.
 
-use super::{uid_t, gid_t};
 use std::cell::{Cell, RefCell};
-use std::collections::hash_map::Entry::{Occupied, Vacant};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ffi::OsStr;
+use std::hash::Hash;
 use std::sync::Arc;
 
-use base::{User, Group, all_users};
-use traits::{Users, Groups};
+use {User, Group, Users, Groups, uid_t, gid_t, all_users};
+use listing;
+
+#[cfg(feature = "watch-files")]
+use watch::Watch;
+
+#[cfg(feature = "serde")]
+use std::ffi::OsString;
+
+#[cfg(feature = "serde")]
+use serde::de::value;
+
+#[cfg(feature = "serde")]
+use serialize::{UserForm, GroupForm};
 
 
 /// A producer of user and group instances that caches every result.
@@ -106,6 +180,11 @@ pub struct UsersCache {
     gid:  Cell<Option<gid_t>>,
     euid: Cell<Option<uid_t>>,
     egid: Cell<Option<gid_t>>,
+
+    policy: Policy,
+
+    #[cfg(feature = "watch-files")]
+    watch: Watch,
 }
 
 /// A kinda-bi-directional `HashMap` that associates keys to values, and
@@ -118,8 +197,64 @@ pub struct UsersCache {
 struct BiMap<K, V> {
     forward:  RefCell< HashMap<K, Option<Arc<V>>> >,
     backward: RefCell< HashMap<Arc<OsStr>, Option<K>> >,
+    recency:  RefCell< Recency<K> >,
+    name:     fn(&V) -> &OsStr,
 }
 
+/// What a cache keeps, and how much of it.
+#[derive(Clone, Copy)]
+struct Policy {
+    capacity: Option<usize>,
+    keep_missing: bool,
+}
+
+/// When each entry of a `BiMap` was last used, so the least recently used
+/// can be evicted once there are too many. Only kept for bounded caches.
+struct Recency<K> {
+    clock:     u64,
+    last_used: HashMap<Slot<K>, u64>,
+    by_age:    BTreeMap<u64, Slot<K>>,
+}
+
+/// An entry of a `BiMap`: either an ID, along with the name it was found
+/// under, or a name that wasn’t found.
+#[derive(PartialEq, Eq, Hash, Clone)]
+enum Slot<K> {
+    Id(K),
+    MissingName(Arc<OsStr>),
+}
+
+
+/// The contents of a `UsersCache`, which can be serialized and deserialized
+/// with the `serde` feature.
+///
+/// It holds the users and groups that were found, in the same form as the
+/// [`serialize`](../serialize/index.html) module writes them, and the IDs
+/// and names that weren’t, but not the current and effective IDs, which
+/// belong to the process. Users and groups with a name, path, or member
+/// that isn’t valid UTF-8 are left out.
+#[cfg(feature = "serde")]
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheSnapshot {
+    #[serde(default)]
+    users: Vec<UserForm>,
+
+    #[serde(default)]
+    groups: Vec<GroupForm>,
+
+    #[serde(default)]
+    missing_uids: Vec<uid_t>,
+
+    #[serde(default)]
+    missing_gids: Vec<gid_t>,
+
+    #[serde(default)]
+    missing_usernames: Vec<String>,
+
+    #[serde(default)]
+    missing_groupnames: Vec<String>,
+}
 
 // Default has to be impl’d manually here, because there’s no
 // Default impl on User or Group, even though those types aren’t
@@ -127,20 +262,18 @@ struct BiMap<K, V> {
 impl Default for UsersCache {
     fn default() -> Self {
         Self {
-            users: BiMap {
-                forward:  RefCell::new(HashMap::new()),
-                backward: RefCell::new(HashMap::new()),
-            },
-
-            groups: BiMap {
-                forward:  RefCell::new(HashMap::new()),
-                backward: RefCell::new(HashMap::new()),
-            },
+            users:  BiMap::new(User::name),
+            groups: BiMap::new(Group::name),
 
             uid:  Cell::new(None),
             gid:  Cell::new(None),
             euid: Cell::new(None),
             egid: Cell::new(None),
+
+            policy: Policy { capacity: None, keep_missing: true },
+
+            #[cfg(feature = "watch-files")]
+            watch: Watch::default(),
         }
     }
 }
@@ -161,13 +294,47 @@ impl UsersCache {
         Self::default()
     }
 
+    /// Creates a new empty cache that holds at most `capacity` users, and as
+    /// many groups, evicting the least recently used ones to make room.
+    /// Names that weren’t found count towards the limit too.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use users::cache::UsersCache;
+    ///
+    /// let cache = UsersCache::with_capacity(1024);
+    /// ```
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut cache = Self::default();
+        cache.policy.capacity = Some(capacity);
+        cache
+    }
+
+    /// Makes the cache not store users and groups that weren’t found, so
+    /// they are looked up every time they are asked for.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use users::cache::UsersCache;
+    ///
+    /// let cache = UsersCache::new().without_negative_caching();
+    /// ```
+    pub fn without_negative_caching(mut self) -> Self {
+        self.policy.keep_missing = false;
+        self
+    }
+
     /// Creates a new cache that contains all the users present on the system.
     ///
     /// # Safety
     ///
-    /// This is `unsafe` because we cannot prevent data races if two caches
-    /// were attempted to be initialised on different threads at the same time.
-    /// For more information, see the [`all_users` documentation](../fn.all_users.html).
+    /// This is `unsafe` because it calls `all_users`. Other caches being
+    /// initialised, and anything else in this crate listing users, wait for
+    /// it, but we cannot prevent data races with code on other threads that
+    /// calls `all_users` or `all_groups` directly. For more information, see
+    /// the [`all_users` documentation](../fn.all_users.html).
     ///
     /// # Examples
     ///
@@ -179,81 +346,342 @@ impl UsersCache {
     pub unsafe fn with_all_users() -> Self {
         let cache = Self::new();
 
+        let _listing = listing::lock();
         for user in all_users() {
-            let uid = user.uid();
-            let user_arc = Arc::new(user);
-            cache.users.forward.borrow_mut().insert(uid, Some(Arc::clone(&user_arc)));
-            cache.users.backward.borrow_mut().insert(Arc::clone(&user_arc.name_arc), Some(uid));
+            cache.users.insert(user.uid(), Some(Arc::new(user)));
         }
 
         cache
     }
-}
 
+    /// Fills the cache with all the users present on the system, in a
+    /// single pass over the users database, and with their primary groups.
+    ///
+    /// Listing a large directory otherwise looks up the owner of each file
+    /// one at a time, which is slow when every lookup asks a network users
+    /// database. Only the groups the users belong to are looked up, each
+    /// once.
+    ///
+    /// # Safety
+    ///
+    /// This is `unsafe` for the same reason as `with_all_users`: we cannot
+    /// prevent data races with other threads calling `all_users` or
+    /// `all_groups` directly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use users::{Users, UsersCache};
+    ///
+    /// let cache = UsersCache::new();
+    /// unsafe { cache.preload_all() };
+    /// let root = cache.get_user_by_uid(0);
+    /// ```
+    pub unsafe fn preload_all(&self) {
+        self.check_files();
 
-// TODO: stop using ‘Arc::from’ with entry API
-// The ‘get_*_by_name’ functions below create a new Arc before even testing if
-// the user exists in the cache, essentially creating an unnecessary Arc.
-// https://internals.rust-lang.org/t/pre-rfc-abandonning-morals-in-the-name-of-performance-the-raw-entry-api/7043/51
-// https://github.com/rust-lang/rfcs/pull/1769
+        let mut gids = BTreeSet::new();
+        let _listing = listing::lock();
+        for user in all_users() {
+            let uid = user.uid();
+            gids.insert(user.primary_group_id());
+            self.users.insert(uid, Some(Arc::new(user)));
+            self.users.used(Slot::Id(uid), self.policy);
+        }
 
+        for gid in gids {
+            self.get_group_by_gid(gid);
+        }
+    }
 
-impl Users for UsersCache {
-    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
-        let mut users_forward = self.users.forward.borrow_mut();
+    /// Returns the groups with the given IDs that exist, looking up those
+    /// that aren’t in the cache yet together, with `get_groups_by_gids`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use users::UsersCache;
+    ///
+    /// let cache = UsersCache::new();
+    /// let groups = cache.get_groups_by_gids(&[ 0, 0, 100 ]);
+    /// assert!(groups.len() <= 2);
+    /// ```
+    pub fn get_groups_by_gids(&self, gids: &[gid_t]) -> HashMap<gid_t, Arc<Group>> {
+        self.check_files();
 
-        let entry = match users_forward.entry(uid) {
-            Vacant(e) => e,
-            Occupied(e) => return e.get().as_ref().map(Arc::clone),
-        };
+        let uncached = gids.iter().cloned()
+                           .filter(|gid| ! self.groups.forward.borrow().contains_key(gid))
+                           .collect::<Vec<_>>();
+        let mut found = ::get_groups_by_gids(&uncached);
 
-        if let Some(user) = super::get_user_by_uid(uid) {
-            let newsername = Arc::clone(&user.name_arc);
-            let mut users_backward = self.users.backward.borrow_mut();
-            users_backward.insert(newsername, Some(uid));
+        gids.iter()
+            .filter_map(|&gid| self.groups.by_id(gid, self.policy, |gid| found.remove(&gid)).map(|g| (gid, g)))
+            .collect()
+    }
 
-            let user_arc = Arc::new(user);
-            entry.insert(Some(Arc::clone(&user_arc)));
-            Some(user_arc)
+    /// Returns the users and groups in the cache, and the IDs and names
+    /// known not to exist, in a form that can be serialized.
+    #[cfg(feature = "serde")]
+    pub fn snapshot(&self) -> CacheSnapshot {
+        let mut snapshot = CacheSnapshot::default();
+
+        for (&uid, user) in self.users.forward.borrow().iter() {
+            match *user {
+                Some(ref user) => if let Ok(form) = UserForm::from_user::<value::Error>(user) {
+                    snapshot.users.push(form);
+                },
+                None => snapshot.missing_uids.push(uid),
+            }
         }
-        else {
-            entry.insert(None);
-            None
+
+        for (&gid, group) in self.groups.forward.borrow().iter() {
+            match *group {
+                Some(ref group) => if let Ok(form) = GroupForm::from_group::<value::Error>(group) {
+                    snapshot.groups.push(form);
+                },
+                None => snapshot.missing_gids.push(gid),
+            }
         }
+
+        snapshot.missing_usernames = self.users.missing_names();
+        snapshot.missing_groupnames = self.groups.missing_names();
+
+        snapshot.users.sort_by_key(|u| u.uid);
+        snapshot.groups.sort_by_key(|g| g.gid);
+        snapshot.missing_uids.sort();
+        snapshot.missing_gids.sort();
+        snapshot
     }
 
-    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
-        let mut users_backward = self.users.backward.borrow_mut();
-
-        let entry = match users_backward.entry(Arc::from(username.as_ref())) {
-            Vacant(e) => e,
-            Occupied(e) => {
-                return (*e.get()).and_then(|uid| {
-                    let users_forward = self.users.forward.borrow_mut();
-                    users_forward[&uid].as_ref().map(Arc::clone)
-                })
+    /// Adds the users and groups in the given snapshot to the cache, along
+    /// with the IDs and names it knows not to exist, replacing any already
+    /// there.
+    #[cfg(feature = "serde")]
+    pub fn load_snapshot(&self, snapshot: CacheSnapshot) {
+        for form in snapshot.users {
+            if let Ok(user) = form.into_user::<value::Error>() {
+                self.users.load(user.uid(), Some(user), self.policy);
+            }
+        }
+
+        for form in snapshot.groups {
+            let group = form.into_group();
+            self.groups.load(group.gid(), Some(group), self.policy);
+        }
+
+        if ! self.policy.keep_missing {
+            return;
+        }
+
+        for uid in snapshot.missing_uids {
+            self.users.load(uid, None, self.policy);
+        }
+
+        for gid in snapshot.missing_gids {
+            self.groups.load(gid, None, self.policy);
+        }
+
+        for name in snapshot.missing_usernames {
+            self.users.load_missing_name(OsString::from(name), self.policy);
+        }
+
+        for name in snapshot.missing_groupnames {
+            self.groups.load_missing_name(OsString::from(name), self.policy);
+        }
+    }
+
+    /// Forgets every user and group if the files they come from changed.
+    #[cfg(feature = "watch-files")]
+    fn check_files(&self) {
+        if self.watch.changed() {
+            self.users.clear();
+            self.groups.clear();
+        }
+    }
+
+    #[cfg(not(feature = "watch-files"))]
+    fn check_files(&self) {}
+}
+
+
+impl<K: Hash + Eq + Copy, V> BiMap<K, V> {
+
+    fn new(name: fn(&V) -> &OsStr) -> Self {
+        Self {
+            forward:  RefCell::new(HashMap::new()),
+            backward: RefCell::new(HashMap::new()),
+            recency:  RefCell::new(Recency { clock: 0, last_used: HashMap::new(), by_age: BTreeMap::new() }),
+            name,
+        }
+    }
+
+    /// Forgets every entry.
+    #[cfg(feature = "watch-files")]
+    fn clear(&self) {
+        self.forward.borrow_mut().clear();
+        self.backward.borrow_mut().clear();
+
+        let mut recency = self.recency.borrow_mut();
+        recency.last_used.clear();
+        recency.by_age.clear();
+    }
+
+    /// Stores the entry for the given ID, and its name along with it.
+    fn insert(&self, id: K, entry: Option<Arc<V>>) {
+        if let Some(ref value) = entry {
+            self.backward.borrow_mut().insert(Arc::from((self.name)(value)), Some(id));
+        }
+
+        self.forward.borrow_mut().insert(id, entry);
+    }
+
+    /// Stores the given entry, from somewhere other than a lookup.
+    #[cfg(feature = "serde")]
+    fn load(&self, id: K, value: Option<V>, policy: Policy) {
+        self.insert(id, value.map(Arc::new));
+        self.used(Slot::Id(id), policy);
+    }
+
+    /// Stores a name as not existing, from somewhere other than a lookup.
+    #[cfg(feature = "serde")]
+    fn load_missing_name(&self, name: OsString, policy: Policy) {
+        let name = Arc::from(name.as_os_str());
+        self.backward.borrow_mut().insert(Arc::clone(&name), None);
+        self.used(Slot::MissingName(name), policy);
+    }
+
+    /// Returns the names known not to exist, if they are valid UTF-8,
+    /// sorted.
+    #[cfg(feature = "serde")]
+    fn missing_names(&self) -> Vec<String> {
+        let mut names = self.backward.borrow().iter()
+                            .filter(|&(_, id)| id.is_none())
+                            .filter_map(|(name, _)| name.to_str().map(str::to_owned))
+                            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Returns the entry for the given ID, looking it up if it isn’t cached.
+    fn by_id<F>(&self, id: K, policy: Policy, lookup: F) -> Option<Arc<V>>
+    where F: FnOnce(K) -> Option<V>
+    {
+        let cached = self.forward.borrow().get(&id).cloned();
+        let entry = match cached {
+            Some(entry) => entry,
+            None => {
+                let entry = lookup(id).map(Arc::new);
+                if entry.is_none() && ! policy.keep_missing {
+                    return None;
+                }
+
+                self.insert(id, entry.clone());
+                entry
             }
         };
 
-        if let Some(user) = super::get_user_by_name(username) {
-            let uid = user.uid();
-            let user_arc = Arc::new(user);
+        self.used(Slot::Id(id), policy);
+        entry
+    }
+
+    /// Returns the entry for the given name, looking it up if it isn’t
+    /// cached.
+    fn by_name<F, I>(&self, name: &OsStr, policy: Policy, lookup: F, id: I) -> Option<Arc<V>>
+    where F: FnOnce(&OsStr) -> Option<V>,
+          I: FnOnce(&V) -> K,
+    {
+        let cached = self.backward.borrow().get_key_value(name).map(|(name, key)| (Arc::clone(name), *key));
+        match cached {
+            Some((_, Some(key))) => {
+                let entry = self.forward.borrow().get(&key).cloned().unwrap_or(None);
+                self.used(Slot::Id(key), policy);
+                entry
+            }
+            Some((name, None)) => {
+                self.used(Slot::MissingName(name), policy);
+                None
+            }
+            None => {
+                if let Some(value) = lookup(name) {
+                    let key = id(&value);
+                    let value = Arc::new(value);
+                    self.insert(key, Some(Arc::clone(&value)));
+                    self.used(Slot::Id(key), policy);
+                    Some(value)
+                }
+                else if policy.keep_missing {
+                    let name = Arc::from(name);
+                    self.backward.borrow_mut().insert(Arc::clone(&name), None);
+                    self.used(Slot::MissingName(name), policy);
+                    None
+                }
+                else {
+                    None
+                }
+            }
+        }
+    }
 
-            let mut users_forward = self.users.forward.borrow_mut();
-            users_forward.insert(uid, Some(Arc::clone(&user_arc)));
-            entry.insert(Some(uid));
+    /// Marks the given entry as just used, and evicts the least recently
+    /// used entries if that makes too many.
+    fn used(&self, slot: Slot<K>, policy: Policy) {
+        let capacity = match policy.capacity {
+            Some(capacity) => capacity,
+            None           => return,
+        };
 
-            Some(user_arc)
+        let mut recency = self.recency.borrow_mut();
+        recency.touch(slot);
+
+        while recency.by_age.len() > capacity {
+            match recency.pop_oldest() {
+                Some(Slot::Id(id)) => {
+                    if let Some(Some(value)) = self.forward.borrow_mut().remove(&id) {
+                        self.backward.borrow_mut().remove((self.name)(&value));
+                    }
+                }
+                Some(Slot::MissingName(name)) => {
+                    self.backward.borrow_mut().remove(&name);
+                }
+                None => break,
+            }
         }
-        else {
-            entry.insert(None);
-            None
+    }
+}
+
+impl<K: Hash + Eq + Clone> Recency<K> {
+    fn touch(&mut self, slot: Slot<K>) {
+        self.clock += 1;
+        if let Some(age) = self.last_used.insert(slot.clone(), self.clock) {
+            self.by_age.remove(&age);
         }
+
+        self.by_age.insert(self.clock, slot);
+    }
+
+    fn pop_oldest(&mut self) -> Option<Slot<K>> {
+        let age = *self.by_age.keys().next()?;
+        let slot = self.by_age.remove(&age)?;
+        self.last_used.remove(&slot);
+        Some(slot)
+    }
+}
+
+
+impl Users for UsersCache {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        self.check_files();
+        self.users.by_id(uid, self.policy, ::get_user_by_uid)
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        self.check_files();
+        self.users.by_name(username.as_ref(), self.policy, ::get_user_by_name, User::uid)
     }
 
     fn get_current_uid(&self) -> uid_t {
         self.uid.get().unwrap_or_else(|| {
-            let uid = super::get_current_uid();
+            let uid = ::get_current_uid();
             self.uid.set(Some(uid));
             uid
         })
@@ -261,12 +689,12 @@ impl Users for UsersCache {
 
     fn get_current_username(&self) -> Option<Arc<OsStr>> {
         let uid = self.get_current_uid();
-        self.get_user_by_uid(uid).map(|u| Arc::clone(&u.name_arc))
+        self.get_user_by_uid(uid).map(|u| Arc::from(u.name()))
     }
 
     fn get_effective_uid(&self) -> uid_t {
         self.euid.get().unwrap_or_else(|| {
-            let uid = super::get_effective_uid();
+            let uid = ::get_effective_uid();
             self.euid.set(Some(uid));
             uid
         })
@@ -274,67 +702,25 @@ impl Users for UsersCache {
 
     fn get_effective_username(&self) -> Option<Arc<OsStr>> {
         let uid = self.get_effective_uid();
-        self.get_user_by_uid(uid).map(|u| Arc::clone(&u.name_arc))
+        self.get_user_by_uid(uid).map(|u| Arc::from(u.name()))
     }
 }
 
 
 impl Groups for UsersCache {
     fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
-        let mut groups_forward = self.groups.forward.borrow_mut();
-
-        let entry = match groups_forward.entry(gid) {
-            Vacant(e) => e,
-            Occupied(e) => return e.get().as_ref().map(Arc::clone),
-        };
-
-        if let Some(group) = super::get_group_by_gid(gid) {
-            let new_group_name = Arc::clone(&group.name_arc);
-            let mut groups_backward = self.groups.backward.borrow_mut();
-            groups_backward.insert(new_group_name, Some(gid));
-
-            let group_arc = Arc::new(group);
-            entry.insert(Some(Arc::clone(&group_arc)));
-            Some(group_arc)
-        }
-        else {
-            entry.insert(None);
-            None
-        }
+        self.check_files();
+        self.groups.by_id(gid, self.policy, ::get_group_by_gid)
     }
 
     fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
-        let mut groups_backward = self.groups.backward.borrow_mut();
-
-        let entry = match groups_backward.entry(Arc::from(group_name.as_ref())) {
-            Vacant(e) => e,
-            Occupied(e) => {
-                return (*e.get()).and_then(|gid| {
-                    let groups_forward = self.groups.forward.borrow_mut();
-                    groups_forward[&gid].as_ref().cloned()
-                });
-            }
-        };
-
-        if let Some(group) = super::get_group_by_name(group_name) {
-            let group_arc = Arc::new(group.clone());
-            let gid = group.gid();
-
-            let mut groups_forward = self.groups.forward.borrow_mut();
-            groups_forward.insert(gid, Some(Arc::clone(&group_arc)));
-            entry.insert(Some(gid));
-
-            Some(group_arc)
-        }
-        else {
-            entry.insert(None);
-            None
-        }
+        self.check_files();
+        self.groups.by_name(group_name.as_ref(), self.policy, ::get_group_by_name, Group::gid)
     }
 
     fn get_current_gid(&self) -> gid_t {
         self.gid.get().unwrap_or_else(|| {
-            let gid = super::get_current_gid();
+            let gid = ::get_current_gid();
             self.gid.set(Some(gid));
             gid
         })
@@ -342,12 +728,12 @@ impl Groups for UsersCache {
 
     fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
         let gid = self.get_current_gid();
-        self.get_group_by_gid(gid).map(|g| Arc::clone(&g.name_arc))
+        self.get_group_by_gid(gid).map(|g| Arc::from(g.name()))
     }
 
     fn get_effective_gid(&self) -> gid_t {
         self.egid.get().unwrap_or_else(|| {
-            let gid = super::get_effective_gid();
+            let gid = ::get_effective_gid();
             self.egid.set(Some(gid));
             gid
         })
@@ -355,6 +741,147 @@ impl Groups for UsersCache {
 
     fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
         let gid = self.get_effective_gid();
-        self.get_group_by_gid(gid).map(|g| Arc::clone(&g.name_arc))
+        self.get_group_by_gid(gid).map(|g| Arc::from(g.name()))
+    }
+}
+
+
+#[cfg(test)]
+#[cfg(feature = "mock")]
+mod test {
+    use super::*;
+    use test::{override_backend, mock_alice};
+
+    #[test]
+    fn same_arc() {
+        let _guard = override_backend(mock_alice());
+        let cache = UsersCache::new();
+
+        let user = cache.get_user_by_uid(1000).unwrap();
+        let by_name = cache.get_user_by_name("alice").unwrap();
+        assert!(Arc::ptr_eq(&user, &by_name));
+        assert_eq!(cache.get_current_username(), Some(Arc::from(OsStr::new("alice"))));
+        assert_eq!(cache.get_group_by_name("users").map(|g| g.gid()), Some(100));
+    }
+
+    #[test]
+    fn remembers_missing() {
+        let cache = UsersCache::new();
+
+        {
+            let _guard = override_backend(mock_alice());
+            assert!(cache.get_user_by_uid(1001).is_none());
+            assert!(cache.get_group_by_gid(1001).is_none());
+        }
+
+        let mut changed = mock_alice();
+        changed.add_user(User::new(1001, "bob", 100));
+        let _guard = override_backend(changed);
+        assert!(cache.get_user_by_uid(1001).is_none());
+        assert!(cache.get_user_by_name("bob").is_some());
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut users = mock_alice();
+        users.add_user(User::new(1001, "bob", 100));
+        users.add_user(User::new(1002, "carol", 100));
+        let _guard = override_backend(users);
+
+        let cache = UsersCache::with_capacity(2);
+        let alice = cache.get_user_by_uid(1000).unwrap();
+        cache.get_user_by_uid(1001).unwrap();
+        assert!(Arc::ptr_eq(&alice, &cache.get_user_by_uid(1000).unwrap()));
+
+        // Bob was used least recently, so makes room for Carol.
+        cache.get_user_by_name("carol").unwrap();
+        assert!(Arc::ptr_eq(&alice, &cache.get_user_by_name("alice").unwrap()));
+        assert_eq!(cache.users.forward.borrow().len(), 2);
+        assert!(! cache.users.forward.borrow().contains_key(&1001));
+        assert!(! cache.users.backward.borrow().contains_key(OsStr::new("bob")));
+    }
+
+    #[test]
+    fn without_negative_caching() {
+        let cache = UsersCache::new().without_negative_caching();
+
+        {
+            let _guard = override_backend(mock_alice());
+            assert!(cache.get_user_by_uid(1001).is_none());
+            assert!(cache.get_user_by_name("bob").is_none());
+        }
+
+        let mut changed = mock_alice();
+        changed.add_user(User::new(1001, "bob", 100));
+        let _guard = override_backend(changed);
+        assert!(cache.get_user_by_uid(1001).is_some());
+        assert!(cache.get_user_by_name("bob").is_some());
+    }
+
+    #[test]
+    fn groups_by_gids() {
+        let cache = UsersCache::new();
+        let _guard = override_backend(mock_alice());
+
+        let groups = cache.get_groups_by_gids(&[ 100, 100, 10 ]);
+        assert_eq!(groups.len(), 1);
+        assert!(Arc::ptr_eq(&groups[&100], &cache.get_group_by_gid(100).unwrap()));
+        assert!(cache.groups.forward.borrow().contains_key(&10));
+    }
+
+    #[test]
+    fn preload_all() {
+        let users = unsafe { all_users() }.collect::<Vec<_>>();
+        let cache = UsersCache::new();
+        unsafe { cache.preload_all() };
+
+        assert_eq!(cache.users.forward.borrow().len(), users.iter().map(|u| u.uid()).collect::<BTreeSet<_>>().len());
+        assert!(users.iter().all(|u| cache.groups.forward.borrow().contains_key(&u.primary_group_id())));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn snapshot_round_trip() {
+        use serde_json;
+        use std::path::Path;
+        use os::unix::{UserExt, GroupExt};
+        use UserBuilderExt;
+
+        let mut detailed = mock_alice();
+        detailed.add_user(User::builder(1000, "alice", 100).home_dir("/home/alice").shell("/bin/zsh").gecos("Alice,,,").build());
+        detailed.add_group(Group::new(100, "users").add_member("alice"));
+
+        let cache = UsersCache::new();
+        {
+            let _guard = override_backend(detailed);
+            cache.get_user_by_uid(1000).unwrap();
+            cache.get_group_by_gid(100).unwrap();
+            assert!(cache.get_user_by_uid(1001).is_none());
+            assert!(cache.get_group_by_name("wheel").is_none());
+        }
+
+        let json = serde_json::to_string(&cache.snapshot()).unwrap();
+        let snapshot: CacheSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot, cache.snapshot());
+
+        let mut changed = mock_alice();
+        changed.add_user(User::new(1001, "bob", 100));
+        let _guard = override_backend(changed);
+
+        let restored = UsersCache::new();
+        restored.load_snapshot(snapshot);
+        let alice = restored.get_user_by_name("alice").unwrap();
+        assert_eq!(alice.uid(), 1000);
+        assert_eq!(alice.home_dir(), Path::new("/home/alice"));
+        assert_eq!(alice.shell(), Path::new("/bin/zsh"));
+        #[cfg(not(users_passthrough))]
+        {
+            use gecos::GecosExt;
+            assert_eq!(alice.full_name(), Some("Alice".into()));
+        }
+        assert_eq!(restored.get_group_by_gid(100).unwrap().members(), &[ OsString::from("alice") ]);
+        assert!(restored.get_user_by_uid(1001).is_none());
+        assert!(restored.get_group_by_name("wheel").is_none());
+        assert_eq!(restored.snapshot(), cache.snapshot());
     }
 }
@@ -0,0 +1,175 @@
+//! Trying several backends in turn.
+//!
+//! A `ChainedBackend` asks each of its backends in the order they were
+//! added, and answers with the first user or group one of them has. That
+//! makes a resolution policy, such as “the system’s users, then those in a
+//! mounted image, then the stub”, something to build once and pass around,
+//! rather than `or_else` calls repeated at every lookup:
+//!
+//! ```
+//! use users::{Users, User, ChainedBackend};
+//! use users::mock::MockUsers;
+//! use users::stub::Stub;
+//!
+//! let mut local = MockUsers::with_current_uid(1000);
+//! local.add_user(User::new(1000, "alice", 100));
+//!
+//! let mut directory = MockUsers::with_current_uid(1000);
+//! directory.add_user(User::new(2000, "bob", 100));
+//!
+//! let chain = ChainedBackend::new().then(local).then(directory).then(Stub);
+//! assert_eq!(chain.get_user_by_uid(2000).map(|u| u.name().to_owned()), Some("bob".into()));
+//! ```
+//!
+//! The current and effective IDs come from the first backend, since every
+//! backend has an answer for them; their names are then looked up through
+//! the whole chain. A chain is itself a backend, so it can be given to
+//! `set_backend`, or be part of another chain.
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::sync::Arc;
+
+use {User, Group, Users, Groups, DynUsers, DynGroups, uid_t, gid_t};
+
+
+/// Both `DynUsers` and `DynGroups`, so one box can hold a backend.
+trait Link: DynUsers + DynGroups {}
+
+impl<B: DynUsers + DynGroups> Link for B {}
+
+
+/// Backends asked one after another, until one of them has an answer.
+#[derive(Default)]
+pub struct ChainedBackend {
+    links: Vec<Box<dyn Link + Send + Sync>>,
+}
+
+impl ChainedBackend {
+
+    /// Creates a chain with no backends in it, which has no users or
+    /// groups, and gives IDs of 0.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a backend to the end of the chain, to be asked after all the
+    /// others.
+    pub fn then<B: Users + Groups + Send + Sync + 'static>(mut self, backend: B) -> Self {
+        self.links.push(Box::new(backend));
+        self
+    }
+
+    /// Returns the number of backends in the chain.
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    /// Returns whether the chain has no backends in it.
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Returns the first answer from a backend in the chain.
+    fn first<T, F: Fn(&dyn Link) -> Option<T>>(&self, f: F) -> Option<T> {
+        self.links.iter().find_map(|link| f(&**link))
+    }
+}
+
+impl fmt::Debug for ChainedBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChainedBackend").field("len", &self.links.len()).finish()
+    }
+}
+
+
+impl Users for ChainedBackend {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        self.first(|link| link.user_by_uid(uid))
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        let username = username.as_ref();
+        self.first(|link| link.user_by_name(username))
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        self.links.first().map_or(0, |link| link.current_uid())
+    }
+
+    fn get_current_username(&self) -> Option<Arc<OsStr>> {
+        self.get_user_by_uid(self.get_current_uid()).map(|u| Arc::from(u.name()))
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        self.links.first().map_or(0, |link| link.effective_uid())
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<OsStr>> {
+        self.get_user_by_uid(self.get_effective_uid()).map(|u| Arc::from(u.name()))
+    }
+}
+
+impl Groups for ChainedBackend {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        self.first(|link| link.group_by_gid(gid))
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+        let group_name = group_name.as_ref();
+        self.first(|link| link.group_by_name(group_name))
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        self.links.first().map_or(0, |link| link.current_gid())
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
+        self.get_group_by_gid(self.get_current_gid()).map(|g| Arc::from(g.name()))
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        self.links.first().map_or(0, |link| link.effective_gid())
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
+        self.get_group_by_gid(self.get_effective_gid()).map(|g| Arc::from(g.name()))
+    }
+}
+
+
+#[cfg(test)]
+#[cfg(feature = "mock")]
+mod test {
+    use super::*;
+    use mock::MockUsers;
+
+    #[test]
+    fn in_order() {
+        let mut first = MockUsers::with_current_uid(2000);
+        first.add_user(User::new(1000, "alice", 100));
+
+        let mut second = MockUsers::with_current_uid(1000);
+        second.add_user(User::new(1000, "shadowed", 100));
+        second.add_user(User::new(2000, "bob", 100));
+        second.add_group(Group::new(100, "users"));
+
+        let chain = ChainedBackend::new().then(first).then(second);
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain.get_user_by_uid(1000).unwrap().name(), "alice");
+        assert_eq!(chain.get_user_by_name("bob").unwrap().uid(), 2000);
+        assert!(chain.get_user_by_name("shadowed").is_some());
+        assert_eq!(chain.get_current_uid(), 2000);
+        assert_eq!(&*chain.get_current_username().unwrap(), OsStr::new("bob"));
+        assert_eq!(chain.get_group_by_gid(100).unwrap().name(), "users");
+    }
+
+    #[test]
+    fn empty() {
+        let chain = ChainedBackend::new();
+        assert!(chain.is_empty());
+        assert_eq!(chain.get_current_uid(), 0);
+        assert!(chain.get_user_by_uid(0).is_none());
+        assert!(chain.get_current_groupname().is_none());
+    }
+}
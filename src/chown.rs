@@ -0,0 +1,109 @@
+//! Changing the owner of files by user and group name.
+//!
+//! `std::os::unix::fs::chown` takes IDs, so changing a file’s owner to a
+//! user and group given by name means looking both up first:
+//!
+//! ```no_run
+//! use users::chown_by_name;
+//!
+//! chown_by_name("/var/lib/app/state", Some("alice"), Some("staff")).expect("Failed to chown");
+//! chown_by_name("/var/lib/app/logs", None, Some("adm")).expect("Failed to chgrp");
+//! ```
+//!
+//! The names are looked up through the backend set with `set_backend`, if
+//! there is one, and through the C library otherwise. Leaving a name out
+//! leaves that ID as it is. Symbolic links are followed.
+
+use std::ffi::CString;
+use std::io;
+use std::os::raw::c_int;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use libc;
+
+use {Error, try_get_user_by_name, try_get_group_by_name, uid_t, gid_t};
+
+
+/// Changes the owner of the file at the given path to the user and group
+/// with the given names.
+///
+/// Fails with `ErrorKind::NotFound`, naming the user or group, if either
+/// doesn’t exist, in which case the file is left alone.
+pub fn chown_by_name<P: AsRef<Path>>(path: P, username: Option<&str>, groupname: Option<&str>) -> io::Result<()> {
+    let (uid, gid) = resolve(username, groupname)?;
+    let path = CString::new(path.as_ref().as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    check(unsafe { libc::chown(path.as_ptr(), uid, gid) })
+}
+
+/// Changes the owner of the given open file to the user and group with the
+/// given names, like `chown_by_name`.
+pub fn fchown_by_name<F: AsRawFd>(file: &F, username: Option<&str>, groupname: Option<&str>) -> io::Result<()> {
+    let (uid, gid) = resolve(username, groupname)?;
+    check(unsafe { libc::fchown(file.as_raw_fd(), uid, gid) })
+}
+
+/// Looks up both names before anything gets changed. A name left out
+/// becomes the ID -1, which leaves that ID as it is.
+fn resolve(username: Option<&str>, groupname: Option<&str>) -> io::Result<(uid_t, gid_t)> {
+    let uid = match username.map(try_get_user_by_name).transpose() {
+        Ok(user)             => user.map(|u| u.uid()),
+        Err(Error::NotFound) => return Err(not_found("user", username)),
+        Err(e)               => return Err(e.into()),
+    };
+
+    let gid = match groupname.map(try_get_group_by_name).transpose() {
+        Ok(group)            => group.map(|g| g.gid()),
+        Err(Error::NotFound) => return Err(not_found("group", groupname)),
+        Err(e)               => return Err(e.into()),
+    };
+
+    Ok((uid.unwrap_or(uid_t::MAX), gid.unwrap_or(gid_t::MAX)))
+}
+
+fn check(status: c_int) -> io::Result<()> {
+    if status == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+fn not_found(what: &str, name: Option<&str>) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("no such {}: {}", what, name.unwrap_or_default()))
+}
+
+
+// With the stub, there is no one to look up unless it has been configured.
+#[cfg(all(test, users_passthrough))]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs::{self, File};
+    use std::os::unix::fs::MetadataExt;
+    use std::process;
+    use {get_current_uid, get_current_username, get_current_groupname};
+
+    #[test]
+    fn unknown_names() {
+        let error = chown_by_name("/nonexistent", Some("no such user, surely"), None).unwrap_err();
+        assert_eq!(error.to_string(), "no such user: no such user, surely");
+
+        let error = chown_by_name("/nonexistent", None, Some("no such group, surely")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert_eq!(error.to_string(), "no such group: no such group, surely");
+    }
+
+    #[test]
+    fn to_ourselves() {
+        let path = env::temp_dir().join(format!("users-chown-{}", process::id()));
+        let file = File::create(&path).unwrap();
+
+        let username = get_current_username().unwrap();
+        let groupname = get_current_groupname().unwrap();
+        chown_by_name(&path, username.to_str(), groupname.to_str()).unwrap();
+        fchown_by_name(&file, username.to_str(), None).unwrap();
+        assert_eq!(file.metadata().unwrap().uid(), get_current_uid());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+//! Remembering who the current user is, as the real user ID of a process
+//! only changes when the process itself changes it.
+
+use std::cell::Cell;
+use std::ffi::OsString;
+use std::marker::PhantomData;
+use std::sync::RwLock;
+
+use uid_t;
+
+
+static CURRENT_UID: RwLock<Option<uid_t>> = RwLock::new(None);
+
+static CURRENT_USERNAME: RwLock<Option<Option<OsString>>> = RwLock::new(None);
+
+thread_local! {
+    /// How many impersonations are in effect on this thread.
+    static IMPERSONATING: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Forgets the current user’s ID and name, so that they get looked up again
+/// the next time they are asked for.
+///
+/// Prompt renderers and per-request permission checks can ask for the
+/// current user many times a second, so `get_current_uid` and
+/// `get_current_username` look them up the first time only, and answer
+/// from memory from then on. A backend set with `set_backend`, and the
+/// stub, are still asked every time, as is the system on a thread that is
+/// impersonating another user on Windows, which only that thread sees.
+///
+/// The functions in the [`switch`](switch/index.html) module that change
+/// the real user ID — `set_current_uid`, `set_both_uid`, `set_all_uid`, and
+/// `drop_privileges` — call this themselves. The guards of
+/// `switch_user_group` only change the effective user ID, so they don’t
+/// need to. After changing the real user ID any other way, such as by
+/// calling `setuid` directly, call this.
+pub fn refresh_current_identity() {
+    *CURRENT_UID.write().unwrap_or_else(|e| e.into_inner()) = None;
+    *CURRENT_USERNAME.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Returns the remembered current user ID, looking it up if there is none.
+pub(crate) fn current_uid(lookup: fn() -> uid_t) -> uid_t {
+    memoized(&CURRENT_UID, lookup)
+}
+
+/// Returns the remembered current username, looking it up if there is none.
+pub(crate) fn current_username(lookup: fn() -> Option<OsString>) -> Option<OsString> {
+    memoized(&CURRENT_USERNAME, lookup)
+}
+
+fn memoized<T: Clone>(cell: &RwLock<Option<T>>, lookup: fn() -> T) -> T {
+    // The impersonated user is neither remembered nor answered with the
+    // process’s own.
+    if IMPERSONATING.with(Cell::get) > 0 {
+        return lookup();
+    }
+
+    if let Some(ref value) = *cell.read().unwrap_or_else(|e| e.into_inner()) {
+        return value.clone();
+    }
+
+    // The lookup happens with the lock held, so that a refresh waits for it
+    // to finish, instead of a stale value being put in after it.
+    let mut value = cell.write().unwrap_or_else(|e| e.into_inner());
+    value.get_or_insert_with(lookup).clone()
+}
+
+
+/// Makes the current user get looked up every time on the current thread,
+/// until this is dropped.
+pub(crate) struct Impersonating {
+
+    // It belongs to the thread it was made on, so it must be dropped there.
+    thread: PhantomData<*const ()>,
+}
+
+/// Stops remembering the current user on this thread while it impersonates
+/// another user.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn impersonating() -> Impersonating {
+    IMPERSONATING.with(|i| i.set(i.get() + 1));
+    Impersonating { thread: PhantomData }
+}
+
+impl Drop for Impersonating {
+    fn drop(&mut self) {
+        IMPERSONATING.with(|i| i.set(i.get() - 1));
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CELL: RwLock<Option<uid_t>> = RwLock::new(None);
+    static LOOKUPS: AtomicUsize = AtomicUsize::new(0);
+
+    fn lookup() -> uid_t {
+        LOOKUPS.fetch_add(1, Ordering::SeqCst);
+        1000
+    }
+
+    #[test]
+    fn looked_up_once() {
+        assert_eq!(memoized(&CELL, lookup), 1000);
+        assert_eq!(memoized(&CELL, lookup), 1000);
+        assert_eq!(LOOKUPS.load(Ordering::SeqCst), 1);
+
+        *CELL.write().unwrap() = None;
+        assert_eq!(memoized(&CELL, lookup), 1000);
+        assert_eq!(LOOKUPS.load(Ordering::SeqCst), 2);
+
+        {
+            let _impersonating = impersonating();
+            memoized(&CELL, lookup);
+            assert_eq!(LOOKUPS.load(Ordering::SeqCst), 3);
+        }
+
+        memoized(&CELL, lookup);
+        assert_eq!(LOOKUPS.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,210 @@
+//! Reading a stub configuration from a TOML or JSON file.
+//!
+//! The file declares the current user and group, along with tables of
+//! users and groups, and group members:
+//!
+//! ```toml
+//! current_uid = 1000
+//! current_gid = 100
+//! home_dir = "/home/alice"
+//!
+//! [[users]]
+//! uid = 1000
+//! name = "alice"
+//! primary_group = 100
+//! shell = "/bin/zsh"
+//!
+//! [[groups]]
+//! gid = 100
+//! name = "users"
+//!
+//! [[groups]]
+//! gid = 10
+//! name = "wheel"
+//! members = [ "alice" ]
+//! ```
+//!
+//! Users can also have a `home_dir` and a `shell`.
+//!
+//! A file whose name ends in `.json` is read as JSON with the same
+//! structure, and any other as TOML.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json;
+use toml;
+
+use {User, Group, uid_t, gid_t};
+use os::unix::UserExt;
+use stub::StubConfig;
+
+
+/// The environment variable naming a file to read when the stub was never
+/// configured.
+pub const PATH_VAR: &str = "USERS_STUB_DB";
+
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Database {
+    #[serde(default)]
+    current_uid: uid_t,
+
+    #[serde(default)]
+    current_gid: gid_t,
+
+    current_username: Option<String>,
+
+    home_dir: Option<PathBuf>,
+
+    #[serde(default)]
+    users: Vec<UserEntry>,
+
+    #[serde(default)]
+    groups: Vec<GroupEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UserEntry {
+    uid: uid_t,
+    name: String,
+    primary_group: gid_t,
+
+    #[serde(default)]
+    home_dir: Option<String>,
+
+    #[serde(default)]
+    shell: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GroupEntry {
+    gid: gid_t,
+    name: String,
+
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+impl Database {
+    fn into_config(self) -> StubConfig {
+        let mut config = StubConfig::new().with_current_uid(self.current_uid)
+                                          .with_current_gid(self.current_gid);
+
+        if let Some(username) = self.current_username {
+            config = config.with_current_username(&username);
+        }
+
+        if let Some(home_dir) = self.home_dir {
+            config = config.with_current_home_dir(home_dir);
+        }
+
+        for user in self.users {
+            let mut entry = User::new(user.uid, &user.name, user.primary_group);
+            if let Some(home_dir) = user.home_dir {
+                entry = entry.with_home_dir(&home_dir);
+            }
+            if let Some(shell) = user.shell {
+                entry = entry.with_shell(&shell);
+            }
+
+            config.add_user(entry);
+        }
+
+        for group in self.groups {
+            for member in &group.members {
+                config.add_member(group.gid, member);
+            }
+
+            config.add_group(Group::new(group.gid, &group.name));
+        }
+
+        config
+    }
+}
+
+
+/// Parses the contents of a database file, as JSON if `json` is set and
+/// as TOML otherwise.
+fn parse(contents: &str, json: bool) -> io::Result<StubConfig> {
+    let database: Database = if json {
+        serde_json::from_str(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    }
+    else {
+        toml::from_str(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    };
+
+    Ok(database.into_config())
+}
+
+/// Reads the database file at the given path.
+pub fn load(path: &Path) -> io::Result<StubConfig> {
+    let contents = fs::read_to_string(path)?;
+    let json = path.extension() == Some(OsStr::new("json"));
+    parse(&contents, json)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static TOML: &str = r#"
+        current_uid = 1000
+        current_gid = 100
+
+        [[users]]
+        uid = 1000
+        name = "alice"
+        primary_group = 100
+        shell = "/bin/zsh"
+
+        [[groups]]
+        gid = 100
+        name = "users"
+
+        [[groups]]
+        gid = 10
+        name = "wheel"
+        members = [ "alice" ]
+    "#;
+
+    static JSON: &str = r#"{
+        "current_uid": 1000,
+        "current_gid": 100,
+        "users": [ { "uid": 1000, "name": "alice", "primary_group": 100, "shell": "/bin/zsh" } ],
+        "groups": [ { "gid": 100, "name": "users" }, { "gid": 10, "name": "wheel", "members": [ "alice" ] } ]
+    }"#;
+
+    #[test]
+    fn toml_and_json_agree() {
+        let toml = format!("{:?}", parse(TOML, false).unwrap());
+        let json = format!("{:?}", parse(JSON, true).unwrap());
+        assert!(toml.contains("alice"));
+        assert!(toml.contains("wheel"));
+        assert_eq!(toml.len(), json.len());
+        assert!(format!("{:#?}", parse(TOML, false).unwrap()).contains("/bin/zsh"));
+    }
+
+    #[test]
+    fn empty() {
+        assert!(parse("", false).is_ok());
+    }
+
+    #[test]
+    fn unknown_field() {
+        let error = parse("current_user = 1000", false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn missing_file() {
+        let error = load(Path::new("/nonexistent/users.toml")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
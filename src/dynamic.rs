@@ -0,0 +1,243 @@
+//! Object-safe versions of the `Users` and `Groups` traits.
+//!
+//! The `get_user_by_name` and `get_group_by_name` methods are generic over
+//! the type of name, so there can be no `dyn Users`. On most Unix platforms
+//! the traits come from the real `users` crate, so this crate can’t change
+//! them either. Instead, every type that implements `Users` also implements
+//! `DynUsers`, which takes names as `&OsStr`, and boxes and references to a
+//! `dyn DynUsers` implement `Users` again, and the same goes for groups:
+//!
+//! ```
+//! use users::{Users, DynUsers, User};
+//! use users::mock::MockUsers;
+//!
+//! struct App {
+//!     users: Box<dyn DynUsers + Send + Sync>,
+//! }
+//!
+//! let mut mock = MockUsers::with_current_uid(1000);
+//! mock.add_user(User::new(1000, "alice", 100));
+//!
+//! let app = App { users: Box::new(mock) };
+//! assert_eq!(app.users.get_user_by_name("alice").map(|u| u.uid()), Some(1000));
+//! ```
+//!
+//! The methods of these traits are named differently to those of `Users`
+//! and `Groups`, so having both in scope doesn’t make calls ambiguous.
+
+use std::ffi::OsStr;
+use std::sync::Arc;
+
+use {User, Group, Users, Groups, uid_t, gid_t};
+
+
+/// The methods of `Users`, in a form that can be made into a trait object.
+pub trait DynUsers {
+
+    /// Returns a `User` if one exists for the given user ID, like
+    /// `Users::get_user_by_uid`.
+    fn user_by_uid(&self, uid: uid_t) -> Option<Arc<User>>;
+
+    /// Returns a `User` if one exists for the given username, like
+    /// `Users::get_user_by_name`.
+    fn user_by_name(&self, username: &OsStr) -> Option<Arc<User>>;
+
+    /// Returns the user ID for the user running the process, like
+    /// `Users::get_current_uid`.
+    fn current_uid(&self) -> uid_t;
+
+    /// Returns the username of the user running the process, like
+    /// `Users::get_current_username`.
+    fn current_username(&self) -> Option<Arc<OsStr>>;
+
+    /// Returns the effective user ID, like `Users::get_effective_uid`.
+    fn effective_uid(&self) -> uid_t;
+
+    /// Returns the effective username, like
+    /// `Users::get_effective_username`.
+    fn effective_username(&self) -> Option<Arc<OsStr>>;
+}
+
+/// The methods of `Groups`, in a form that can be made into a trait object.
+pub trait DynGroups {
+
+    /// Returns a `Group` if one exists for the given group ID, like
+    /// `Groups::get_group_by_gid`.
+    fn group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>>;
+
+    /// Returns a `Group` if one exists for the given group name, like
+    /// `Groups::get_group_by_name`.
+    fn group_by_name(&self, group_name: &OsStr) -> Option<Arc<Group>>;
+
+    /// Returns the group ID for the user running the process, like
+    /// `Groups::get_current_gid`.
+    fn current_gid(&self) -> gid_t;
+
+    /// Returns the group name of the user running the process, like
+    /// `Groups::get_current_groupname`.
+    fn current_groupname(&self) -> Option<Arc<OsStr>>;
+
+    /// Returns the effective group ID, like `Groups::get_effective_gid`.
+    fn effective_gid(&self) -> gid_t;
+
+    /// Returns the effective group name, like
+    /// `Groups::get_effective_groupname`.
+    fn effective_groupname(&self) -> Option<Arc<OsStr>>;
+}
+
+
+impl<T: Users> DynUsers for T {
+    fn user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        self.get_user_by_uid(uid)
+    }
+
+    fn user_by_name(&self, username: &OsStr) -> Option<Arc<User>> {
+        self.get_user_by_name(username)
+    }
+
+    fn current_uid(&self) -> uid_t {
+        self.get_current_uid()
+    }
+
+    fn current_username(&self) -> Option<Arc<OsStr>> {
+        self.get_current_username()
+    }
+
+    fn effective_uid(&self) -> uid_t {
+        self.get_effective_uid()
+    }
+
+    fn effective_username(&self) -> Option<Arc<OsStr>> {
+        self.get_effective_username()
+    }
+}
+
+impl<T: Groups> DynGroups for T {
+    fn group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        self.get_group_by_gid(gid)
+    }
+
+    fn group_by_name(&self, group_name: &OsStr) -> Option<Arc<Group>> {
+        self.get_group_by_name(group_name)
+    }
+
+    fn current_gid(&self) -> gid_t {
+        self.get_current_gid()
+    }
+
+    fn current_groupname(&self) -> Option<Arc<OsStr>> {
+        self.get_current_groupname()
+    }
+
+    fn effective_gid(&self) -> gid_t {
+        self.get_effective_gid()
+    }
+
+    fn effective_groupname(&self) -> Option<Arc<OsStr>> {
+        self.get_effective_groupname()
+    }
+}
+
+
+// Only boxes of, and references to, trait objects can implement `Users` and
+// `Groups`: the traits might belong to the real crate, and these are the
+// only types that count as this crate’s for implementing them.
+macro_rules! impl_users {
+    ($($t:ty),*) => {$(
+        impl<'a> Users for $t {
+            fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+                (**self).user_by_uid(uid)
+            }
+
+            fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+                (**self).user_by_name(username.as_ref())
+            }
+
+            fn get_current_uid(&self) -> uid_t {
+                (**self).current_uid()
+            }
+
+            fn get_current_username(&self) -> Option<Arc<OsStr>> {
+                (**self).current_username()
+            }
+
+            fn get_effective_uid(&self) -> uid_t {
+                (**self).effective_uid()
+            }
+
+            fn get_effective_username(&self) -> Option<Arc<OsStr>> {
+                (**self).effective_username()
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_groups {
+    ($($t:ty),*) => {$(
+        impl<'a> Groups for $t {
+            fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+                (**self).group_by_gid(gid)
+            }
+
+            fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+                (**self).group_by_name(group_name.as_ref())
+            }
+
+            fn get_current_gid(&self) -> gid_t {
+                (**self).current_gid()
+            }
+
+            fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
+                (**self).current_groupname()
+            }
+
+            fn get_effective_gid(&self) -> gid_t {
+                (**self).effective_gid()
+            }
+
+            fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
+                (**self).effective_groupname()
+            }
+        }
+    )*};
+}
+
+impl_users!(
+    Box<dyn DynUsers + 'a>, Box<dyn DynUsers + Send + 'a>, Box<dyn DynUsers + Send + Sync + 'a>,
+    &'a dyn DynUsers, &'a (dyn DynUsers + Send), &'a (dyn DynUsers + Send + Sync)
+);
+
+impl_groups!(
+    Box<dyn DynGroups + 'a>, Box<dyn DynGroups + Send + 'a>, Box<dyn DynGroups + Send + Sync + 'a>,
+    &'a dyn DynGroups, &'a (dyn DynGroups + Send), &'a (dyn DynGroups + Send + Sync)
+);
+
+
+#[cfg(test)]
+#[cfg(feature = "mock")]
+mod test {
+    use super::*;
+    use test::mock_alice;
+
+    fn current_name<U: Users>(users: &U) -> Option<Arc<OsStr>> {
+        users.get_current_username()
+    }
+
+    #[test]
+    fn boxed() {
+        let boxed: Box<dyn DynUsers + Send + Sync> = Box::new(mock_alice());
+        assert_eq!(current_name(&boxed), Some(Arc::from(OsStr::new("alice"))));
+        assert_eq!(boxed.get_user_by_name("alice").map(|u| u.uid()), Some(1000));
+
+        let groups: Box<dyn DynGroups> = Box::new(mock_alice());
+        assert_eq!(groups.get_group_by_name("users").map(|g| g.gid()), Some(100));
+    }
+
+    #[test]
+    fn referenced() {
+        let users = mock_alice();
+        let dynamic: &dyn DynUsers = &users;
+        assert_eq!(current_name(&dynamic), Some(Arc::from(OsStr::new("alice"))));
+        assert_eq!(dynamic.user_by_uid(1000).map(|u| u.uid()), Some(1000));
+    }
+}
@@ -0,0 +1,141 @@
+//! The error type of the fallible lookups.
+
+use std::error;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+
+
+/// Why a user or group couldn’t be looked up.
+///
+/// The lookup functions returning an `Option` answer `None` whether there
+/// is no such user or the user database couldn’t be read. Their `try_`
+/// counterparts, such as
+/// [`try_get_user_by_uid`](fn.try_get_user_by_uid.html), tell those apart.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+
+    /// There is no such user or group.
+    NotFound,
+
+    /// The user database couldn’t be read, with the error the C library or
+    /// the operating system gave.
+    Io(io::Error),
+
+    /// The name can’t be looked up, as it contains a null byte.
+    InvalidName,
+
+    /// There is no user database on this platform, and the stub hasn’t been
+    /// configured to stand in for one.
+    Unsupported,
+
+    /// The service the user database is read through, such as a directory
+    /// server behind NSS, couldn’t be reached or didn’t answer in time.
+    BackendUnavailable,
+}
+
+impl Error {
+
+    /// Turns the result of a lookup that can fail into one that distinguishes
+    /// the user or group not being there.
+    pub(crate) fn from_lookup<T>(result: io::Result<Option<T>>) -> Result<T, Self> {
+        match result {
+            Ok(Some(entry)) => Ok(entry),
+            Ok(None)        => Err(Error::NotFound),
+            Err(e)          => Err(Error::from(e)),
+        }
+    }
+}
+
+/// Fails for a name with a null byte in it, which no user database can
+/// have, as the C library would cut it off there.
+pub(crate) fn check_name(name: &OsStr) -> Result<(), Error> {
+    // Lossy conversion only replaces what isn’t Unicode, so any null byte
+    // is still there afterwards.
+    if name.to_string_lossy().contains('\0') { Err(Error::InvalidName) } else { Ok(()) }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::ConnectionRefused |
+            io::ErrorKind::ConnectionReset   |
+            io::ErrorKind::NotConnected      |
+            io::ErrorKind::TimedOut          |
+            io::ErrorKind::WouldBlock        => Error::BackendUnavailable,
+            io::ErrorKind::Unsupported       => Error::Unsupported,
+            _                                => Error::Io(error),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::NotFound           => io::Error::new(io::ErrorKind::NotFound, error.to_string()),
+            Error::Io(e)              => e,
+            Error::InvalidName        => io::Error::new(io::ErrorKind::InvalidInput, error.to_string()),
+            Error::Unsupported        => io::Error::new(io::ErrorKind::Unsupported, error.to_string()),
+            Error::BackendUnavailable => io::Error::new(io::ErrorKind::NotConnected, error.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::NotFound           => write!(f, "no such user or group"),
+            Error::Io(ref e)          => write!(f, "failed to read the user database: {}", e),
+            Error::InvalidName        => write!(f, "name contains a null byte"),
+            Error::Unsupported        => write!(f, "there is no user database on this platform"),
+            Error::BackendUnavailable => write!(f, "the user database is unavailable"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _                => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookups() {
+        assert_eq!(Error::from_lookup(Ok(Some(1))).unwrap(), 1);
+        assert!(matches!(Error::from_lookup::<u32>(Ok(None)), Err(Error::NotFound)));
+        assert!(matches!(Error::from_lookup::<u32>(Err(io::Error::from(io::ErrorKind::TimedOut))), Err(Error::BackendUnavailable)));
+        assert!(matches!(Error::from_lookup::<u32>(Err(io::Error::from_raw_os_error(5))), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn names() {
+        assert!(check_name(OsStr::new("alice")).is_ok());
+        assert!(matches!(check_name(OsStr::new("al\0ice")), Err(Error::InvalidName)));
+    }
+
+    #[test]
+    fn into_io() {
+        assert_eq!(io::Error::from(Error::NotFound).kind(), io::ErrorKind::NotFound);
+        assert_eq!(io::Error::from(Error::Io(io::Error::from_raw_os_error(5))).raw_os_error(), Some(5));
+    }
+
+    #[test]
+    #[cfg(users_passthrough)]
+    fn native() {
+        use {get_user_by_uid, try_get_user_by_uid, try_get_user_by_name, try_get_group_by_gid};
+
+        assert_eq!(try_get_user_by_uid(0).unwrap().name(), get_user_by_uid(0).unwrap().name());
+        assert_eq!(try_get_group_by_gid(0).unwrap().gid(), 0);
+        assert!(matches!(try_get_user_by_name("no such user, surely"), Err(Error::NotFound)));
+        assert!(matches!(try_get_user_by_name("ro\0ot"), Err(Error::InvalidName)));
+    }
+}
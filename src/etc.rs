@@ -0,0 +1,404 @@
+//! Parsers for the `/etc/passwd` and `/etc/group` file formats.
+//!
+//! Both files hold one colon-separated entry per line. Blank lines and `#`
+//! comments are skipped, as are the `+` and `-` lines that NIS uses to pull
+//! in entries from elsewhere, since there is nowhere to pull them from. Any
+//! line that doesn’t parse is skipped too, rather than failing the whole file.
+//! Single lines can be parsed with `User::from_passwd_line` and
+//! `Group::from_group_line`, which say what is wrong with them instead.
+//!
+//! BSD’s `master.passwd`, which has three more fields than `/etc/passwd`,
+//! can be read the same way: its lines carry password change and expiry
+//! times as well.
+
+#[cfg(users_passthrough)]
+use libc::time_t;
+#[cfg(not(users_passthrough))]
+use base::time_t;
+
+use std::error::Error;
+use std::fmt;
+
+use {User, Group, UserBuilder, GroupBuilder, uid_t, gid_t};
+use gecos;
+use os::unix::{UserExt, GroupExt};
+
+
+/// One entry from `/etc/passwd`.
+#[derive(PartialEq, Debug)]
+pub struct PasswdEntry<'a> {
+    pub name: &'a str,
+    pub password: &'a str,
+    pub uid: uid_t,
+    pub gid: gid_t,
+    pub change: time_t,
+    pub expire: time_t,
+    pub gecos: &'a str,
+    pub home_dir: &'a str,
+    pub shell: &'a str,
+}
+
+impl<'a> PasswdEntry<'a> {
+
+    /// Parses a single `name:password:uid:gid:gecos:home:shell` line, or a
+    /// `name:password:uid:gid:class:change:expire:gecos:home:shell` line
+    /// from `master.passwd`. Any fields but the name and IDs can be empty,
+    /// and extra colons at the end are ignored.
+    pub fn parse(line: &'a str) -> Result<Self, ParseError> {
+        let mut fields = entry_fields(line)?;
+        while fields.len() > 7 && fields.len() != 10 && fields.last() == Some(&"") {
+            fields.pop();
+        }
+
+        let (change, expire) = match fields.len() {
+            7  => (0, 0),
+            10 => (parse_number(fields[5])?, parse_number(fields[6])?),
+            n  => return Err(ParseError::FieldCount { expected: 7, found: n }),
+        };
+
+        let last = fields.len() - 1;
+        Ok(PasswdEntry {
+            name: parse_name(fields[0])?,
+            password: fields[1],
+            uid: parse_number(fields[2])?,
+            gid: parse_number(fields[3])?,
+            change,
+            expire,
+            gecos: fields[last - 2],
+            home_dir: fields[last - 1],
+            shell: fields[last],
+        })
+    }
+
+    pub fn to_user(&self) -> User {
+        let user = UserBuilder::new(self.uid, self.name, self.gid)
+            .password(self.password)
+            .gecos(self.gecos)
+            .home_dir(self.home_dir)
+            .shell(self.shell)
+            .build();
+
+        // The real crate’s users have nowhere to keep these.
+        #[cfg(not(users_passthrough))]
+        let user = user.with_password_times(self.change, self.expire);
+
+        user
+    }
+}
+
+
+/// One entry from `/etc/group`.
+#[derive(PartialEq, Debug)]
+pub struct GroupEntry<'a> {
+    pub name: &'a str,
+    pub gid: gid_t,
+    pub members: Vec<&'a str>,
+}
+
+impl<'a> GroupEntry<'a> {
+
+    /// Parses a single `name:password:gid:member,member` line. The password
+    /// is only ever a placeholder, so it isn’t kept.
+    pub fn parse(line: &'a str) -> Result<Self, ParseError> {
+        let mut fields = entry_fields(line)?;
+        while fields.len() > 4 && fields.last() == Some(&"") {
+            fields.pop();
+        }
+
+        if fields.len() != 4 {
+            return Err(ParseError::FieldCount { expected: 4, found: fields.len() });
+        }
+
+        Ok(GroupEntry {
+            name: parse_name(fields[0])?,
+            gid: parse_number(fields[2])?,
+            members: fields[3].split(',').map(str::trim).filter(|m| !m.is_empty()).collect(),
+        })
+    }
+
+    pub fn to_group(&self) -> Group {
+        GroupBuilder::new(self.gid, self.name).members(&self.members).build()
+    }
+}
+
+
+/// Every entry in the contents of a passwd file.
+pub fn passwd_entries<'a>(contents: &'a str) -> impl Iterator<Item=PasswdEntry<'a>> {
+    contents.lines().filter_map(|line| PasswdEntry::parse(line).ok())
+}
+
+/// Every entry in the contents of a group file.
+pub fn group_entries<'a>(contents: &'a str) -> impl Iterator<Item=GroupEntry<'a>> {
+    contents.lines().filter_map(|line| GroupEntry::parse(line).ok())
+}
+
+/// Splits a line into its fields, or fails for lines that are not entries
+/// at all.
+pub fn entry_fields(line: &str) -> Result<Vec<&str>, ParseError> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.trim().is_empty() || line.starts_with('#') || line.starts_with('+') || line.starts_with('-') {
+        return Err(ParseError::NotAnEntry);
+    }
+
+    Ok(line.split(':').collect())
+}
+
+/// Checks that a name field isn’t empty.
+pub fn parse_name(field: &str) -> Result<&str, ParseError> {
+    if field.is_empty() {
+        return Err(ParseError::EmptyName);
+    }
+
+    Ok(field)
+}
+
+/// Parses an ID or time field.
+pub fn parse_number<N: ::std::str::FromStr>(field: &str) -> Result<N, ParseError> {
+    field.parse().map_err(|_| ParseError::InvalidNumber(field.to_owned()))
+}
+
+
+/// The error returned when a line is not a valid `passwd`, `group`, or
+/// `shadow` entry.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum ParseError {
+
+    /// The line is blank, a comment, or an NIS `+` or `-` line.
+    NotAnEntry,
+
+    /// The line has the wrong number of colon-separated fields.
+    FieldCount {
+
+        /// The number of fields an entry has.
+        expected: usize,
+
+        /// The number of fields the line has.
+        found: usize,
+    },
+
+    /// The name field is empty.
+    EmptyName,
+
+    /// An ID or time field isn’t a number.
+    InvalidNumber(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::NotAnEntry                     => write!(f, "line is not an entry"),
+            ParseError::FieldCount { expected, found } => write!(f, "expected {} fields, found {}", expected, found),
+            ParseError::EmptyName                      => write!(f, "name is empty"),
+            ParseError::InvalidNumber(field)           => write!(f, "invalid number {:?}", field),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+
+/// Adds `User::from_passwd_line` and `User::to_passwd_line`.
+pub trait UserLineExt: Sized {
+
+    /// Parses a line of a `passwd(5)` file into a user. A line from BSD’s
+    /// `master.passwd` works too.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use users::{User, UserLineExt};
+    /// use users::os::unix::UserExt;
+    ///
+    /// let user = User::from_passwd_line("alice:x:1000:100:Alice:/home/alice:/bin/sh").unwrap();
+    /// assert_eq!(user.uid(), 1000);
+    /// assert_eq!(user.home_dir(), std::path::Path::new("/home/alice"));
+    ///
+    /// assert!(User::from_passwd_line("alice:x:one:100:::").is_err());
+    /// ```
+    fn from_passwd_line(line: &str) -> Result<Self, ParseError>;
+
+    /// Writes this user as a line of a `passwd(5)` file, without a line
+    /// break, which `from_passwd_line` reads back as the same user.
+    ///
+    /// Fields that aren’t valid UTF-8 are written lossily, and nothing
+    /// stops a field from containing a colon. The real crate’s users don’t
+    /// keep a GECOS field, so with it, the host’s field for the user is
+    /// written, or an empty one if the host doesn’t have them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use users::{User, UserBuilderExt, UserLineExt};
+    ///
+    /// let user = User::builder(1000, "alice", 100).password("x").home_dir("/home/alice").shell("/bin/sh").build();
+    /// assert!(user.to_passwd_line().starts_with("alice:x:1000:100:"));
+    /// assert!(user.to_passwd_line().ends_with(":/home/alice:/bin/sh"));
+    /// ```
+    fn to_passwd_line(&self) -> String;
+}
+
+impl UserLineExt for User {
+    fn from_passwd_line(line: &str) -> Result<Self, ParseError> {
+        PasswdEntry::parse(line).map(|entry| entry.to_user())
+    }
+
+    fn to_passwd_line(&self) -> String {
+        format!("{}:{}:{}:{}:{}:{}:{}",
+                self.name().to_string_lossy(),
+                self.password().to_string_lossy(),
+                self.uid(),
+                self.primary_group_id(),
+                gecos::field(self).unwrap_or_default().to_string_lossy(),
+                self.home_dir().to_string_lossy(),
+                self.shell().to_string_lossy())
+    }
+}
+
+
+/// Adds `Group::from_group_line` and `Group::to_group_line`.
+pub trait GroupLineExt: Sized {
+
+    /// Parses a line of a `group(5)` file into a group, along with its
+    /// members.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use users::{Group, GroupLineExt, ParseError};
+    /// use users::os::unix::GroupExt;
+    ///
+    /// let group = Group::from_group_line("wheel:x:10:root,alice").unwrap();
+    /// assert_eq!(group.gid(), 10);
+    /// assert_eq!(group.members().len(), 2);
+    ///
+    /// assert_eq!(Group::from_group_line("wheel:x:10").unwrap_err(),
+    ///            ParseError::FieldCount { expected: 4, found: 3 });
+    /// ```
+    fn from_group_line(line: &str) -> Result<Self, ParseError>;
+
+    /// Writes this group as a line of a `group(5)` file, without a line
+    /// break, which `from_group_line` reads back as the same group. The
+    /// password is always the `x` placeholder.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use users::{Group, GroupLineExt};
+    /// use users::os::unix::GroupExt;
+    ///
+    /// let group = Group::new(10, "wheel").add_member("root").add_member("alice");
+    /// assert_eq!(group.to_group_line(), "wheel:x:10:root,alice");
+    /// ```
+    fn to_group_line(&self) -> String;
+}
+
+impl GroupLineExt for Group {
+    fn from_group_line(line: &str) -> Result<Self, ParseError> {
+        GroupEntry::parse(line).map(|entry| entry.to_group())
+    }
+
+    fn to_group_line(&self) -> String {
+        let members = self.members().iter().map(|m| m.to_string_lossy()).collect::<Vec<_>>();
+        format!("{}:x:{}:{}", self.name().to_string_lossy(), self.gid(), members.join(","))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn passwd_line() {
+        let entry = PasswdEntry::parse("daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin").unwrap();
+        assert_eq!(entry, PasswdEntry { name: "daemon", password: "x", uid: 1, gid: 1, change: 0, expire: 0, gecos: "daemon",
+                                        home_dir: "/usr/sbin", shell: "/usr/sbin/nologin" });
+    }
+
+    #[test]
+    fn master_passwd_line() {
+        let entry = PasswdEntry::parse("alice:$2b$hash:1001:1001:staff:1700000000:1800000000:Alice:/home/alice:/bin/sh").unwrap();
+        assert_eq!(entry, PasswdEntry { name: "alice", password: "$2b$hash", uid: 1001, gid: 1001,
+                                        change: 1700000000, expire: 1800000000, gecos: "Alice",
+                                        home_dir: "/home/alice", shell: "/bin/sh" });
+    }
+
+    #[test]
+    #[cfg(not(users_passthrough))]
+    fn master_passwd_user() {
+        use os::bsd::UserExt;
+        use os::unix::UserExt as UnixUserExt;
+
+        let user = PasswdEntry::parse("alice:*:1001:1001::1700000000:0:Alice:/home/alice:/bin/sh").unwrap().to_user();
+        assert_eq!(user.password_change_time(), 1700000000);
+        assert_eq!(user.password_expire_time(), 0);
+        assert_eq!(user.home_dir(), ::std::path::Path::new("/home/alice"));
+        assert_eq!(user.gecos_field(), "Alice");
+    }
+
+    #[test]
+    fn passwd_skips() {
+        let contents = "# comment\n\n+@netgroup::::::\nroot:x:0:0:root:/root:/bin/sh\nbroken:x:zero:0::/:\nshort:x:1\n";
+        let names = passwd_entries(contents).map(|e| e.name).collect::<Vec<_>>();
+        assert_eq!(names, vec![ "root" ]);
+    }
+
+    #[test]
+    fn passwd_empty_fields() {
+        let entry = PasswdEntry::parse("nobody:x:65534:65534:::").unwrap();
+        assert_eq!((entry.gecos, entry.home_dir, entry.shell), ("", "", ""));
+
+        let entry = PasswdEntry::parse("nobody:x:65534:65534::/:/bin/false::").unwrap();
+        assert_eq!(entry.shell, "/bin/false");
+    }
+
+    #[test]
+    fn passwd_errors() {
+        assert_eq!(PasswdEntry::parse("# comment"), Err(ParseError::NotAnEntry));
+        assert_eq!(PasswdEntry::parse("short:x:1"), Err(ParseError::FieldCount { expected: 7, found: 3 }));
+        assert_eq!(PasswdEntry::parse(":x:1:1:::"), Err(ParseError::EmptyName));
+        assert_eq!(PasswdEntry::parse("broken:x:zero:0::/:"), Err(ParseError::InvalidNumber("zero".into())));
+    }
+
+    #[test]
+    fn group_line() {
+        let entry = GroupEntry::parse("wheel:x:10:root,alice\r").unwrap();
+        assert_eq!(entry, GroupEntry { name: "wheel", gid: 10, members: vec![ "root", "alice" ] });
+    }
+
+    #[test]
+    fn group_without_members() {
+        let entry = GroupEntry::parse("nogroup:x:65534:").unwrap();
+        assert!(entry.members.is_empty());
+
+        let entry = GroupEntry::parse("nogroup::65534:::").unwrap();
+        assert!(entry.members.is_empty());
+    }
+
+    #[test]
+    fn lines_round_trip() {
+        // The real crate’s users don’t keep a GECOS field, and the host
+        // doesn’t have this user to look it up from.
+        let line = "nobody-here:$6$hash:31337:100:Alice,,,:/home/alice:/bin/zsh";
+        let user = User::from_passwd_line(line).unwrap();
+        if cfg!(users_passthrough) {
+            assert_eq!(user.to_passwd_line(), "nobody-here:$6$hash:31337:100::/home/alice:/bin/zsh");
+        }
+        else {
+            assert_eq!(user.to_passwd_line(), line);
+        }
+
+        let line = "wheel:x:10:root,alice";
+        assert_eq!(Group::from_group_line(line).unwrap().to_group_line(), line);
+        assert_eq!(Group::new(65534, "nogroup").to_group_line(), "nogroup:x:65534:");
+    }
+
+    #[test]
+    fn group_errors() {
+        assert_eq!(GroupEntry::parse(""), Err(ParseError::NotAnEntry));
+        assert_eq!(GroupEntry::parse("wheel:x:10:root:extra"), Err(ParseError::FieldCount { expected: 4, found: 5 }));
+        assert_eq!(GroupEntry::parse(":x:10:"), Err(ParseError::EmptyName));
+        assert_eq!(GroupEntry::parse("wheel:x::root"), Err(ParseError::InvalidNumber("".into())));
+    }
+}
@@ -0,0 +1,75 @@
+//! Fallible variants of the functions returning the process’s IDs.
+//!
+//! `get_current_uid` and friends always return an ID, even where the stub
+//! can only make one up. These variants fail with `ErrorKind::Unsupported`
+//! instead when the ID would be a dummy, which only ever happens with the
+//! `strict-stub` feature; everywhere else, they always succeed.
+
+use std::io;
+
+use super::{uid_t, gid_t};
+use super::{get_current_uid, get_effective_uid, get_current_gid, get_effective_gid};
+
+#[cfg(all(not(users_passthrough), feature = "strict-stub"))]
+use backend::is_registered;
+
+#[cfg(all(not(users_passthrough), feature = "strict-stub"))]
+use platform::{Current, Platform};
+
+
+/// Fails if the stub would only return a dummy value for the given function.
+#[track_caller]
+#[cfg_attr(not(all(not(users_passthrough), feature = "strict-stub")), allow(unused_variables))]
+fn check(function: &str) -> io::Result<()> {
+    #[cfg(all(not(users_passthrough), feature = "strict-stub"))]
+    {
+        if Current::is_dummy() && ! is_registered() {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, format!("{} is not supported by the users stub on this platform", function)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the user ID for the user running the process, unless it is
+/// unknown.
+///
+/// # Examples
+///
+/// ```
+/// use users::try_get_current_uid;
+///
+/// match try_get_current_uid() {
+///     Ok(uid) => println!("The ID of the current user is {}", uid),
+///     Err(e)  => println!("The current user is unknown: {}", e),
+/// }
+/// ```
+#[track_caller]
+pub fn try_get_current_uid() -> io::Result<uid_t> {
+    check("get_current_uid")?;
+    Ok(get_current_uid())
+}
+
+/// Returns the user ID for the effective user running the process, unless
+/// it is unknown.
+#[track_caller]
+pub fn try_get_effective_uid() -> io::Result<uid_t> {
+    check("get_effective_uid")?;
+    Ok(get_effective_uid())
+}
+
+/// Returns the group ID for the user running the process, unless it is
+/// unknown.
+#[track_caller]
+pub fn try_get_current_gid() -> io::Result<gid_t> {
+    check("get_current_gid")?;
+    Ok(get_current_gid())
+}
+
+/// Returns the group ID for the effective user running the process, unless
+/// it is unknown.
+#[track_caller]
+pub fn try_get_effective_gid() -> io::Result<gid_t> {
+    check("get_effective_gid")?;
+    Ok(get_effective_gid())
+}
@@ -0,0 +1,237 @@
+//! Looking users and groups up in `passwd` and `group` files anywhere.
+//!
+//! A `FileBackend` reads the files it was given directly, without going
+//! through the C library or NSS, so it answers the same way everywhere:
+//! for the `/etc` of a container image mounted somewhere else, or in a
+//! static binary that can’t load NSS modules.
+//!
+//! ```no_run
+//! use users::{Users, FileBackend};
+//!
+//! let image = FileBackend::in_dir("/mnt/image/etc");
+//! if let Some(user) = image.get_user_by_uid(1000) {
+//!     println!("In the image, UID 1000 is {:?}", user.name());
+//! }
+//! ```
+//!
+//! The files are read again for every lookup, so the answers follow any
+//! changes made to them. A file that is missing or can’t be read counts as
+//! empty, and lines that don’t parse are skipped. Bytes that aren’t valid
+//! UTF-8, such as a GECOS field in Latin-1, are replaced with U+FFFD, so
+//! they only affect the line they are in.
+//!
+//! The current and effective IDs are the process’s own, as there is no way
+//! to tell them from the files; the names for them are looked up in the
+//! files, though.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use {User, Group, Users, Groups, uid_t, gid_t};
+use etc::{passwd_entries, group_entries};
+
+
+/// Users and groups from a `passwd` and a `group` file.
+#[derive(Clone, Debug)]
+pub struct FileBackend {
+    passwd: PathBuf,
+    group: PathBuf,
+    uid: uid_t,
+    gid: gid_t,
+    euid: uid_t,
+    egid: gid_t,
+}
+
+impl FileBackend {
+
+    /// Creates a backend reading the given files. Neither is read until
+    /// the first lookup.
+    pub fn new<P: AsRef<Path>, G: AsRef<Path>>(passwd_path: P, group_path: G) -> Self {
+        let (uid, gid, euid, egid) = process_ids();
+
+        Self {
+            passwd: passwd_path.as_ref().to_owned(),
+            group: group_path.as_ref().to_owned(),
+            uid, gid, euid, egid,
+        }
+    }
+
+    /// Creates a backend reading the `passwd` and `group` files in the
+    /// given directory, such as the `/etc` of a mounted image.
+    pub fn in_dir<P: AsRef<Path>>(dir: P) -> Self {
+        let dir = dir.as_ref();
+        Self::new(dir.join("passwd"), dir.join("group"))
+    }
+
+    /// Sets the current and effective user ID the backend reports.
+    pub fn with_current_uid(mut self, uid: uid_t) -> Self {
+        self.uid = uid;
+        self.euid = uid;
+        self
+    }
+
+    /// Sets the current and effective group ID the backend reports.
+    pub fn with_current_gid(mut self, gid: gid_t) -> Self {
+        self.gid = gid;
+        self.egid = gid;
+        self
+    }
+
+    /// Returns every user in the `passwd` file, in the order they are
+    /// listed, or the error from reading it.
+    pub fn all_users(&self) -> io::Result<Vec<User>> {
+        let contents = read_lossy(&self.passwd)?;
+        Ok(passwd_entries(&contents).map(|e| e.to_user()).collect())
+    }
+
+    /// Returns every group in the `group` file, in the order they are
+    /// listed, or the error from reading it.
+    pub fn all_groups(&self) -> io::Result<Vec<Group>> {
+        let contents = read_lossy(&self.group)?;
+        Ok(group_entries(&contents).map(|e| e.to_group()).collect())
+    }
+
+    fn find_user<F: Fn(uid_t, &str) -> bool>(&self, matches: F) -> Option<Arc<User>> {
+        let contents = read_lossy(&self.passwd).ok()?;
+        let user = passwd_entries(&contents).find(|e| matches(e.uid, e.name))?;
+        Some(Arc::new(user.to_user()))
+    }
+
+    fn find_group<F: Fn(gid_t, &str) -> bool>(&self, matches: F) -> Option<Arc<Group>> {
+        let contents = read_lossy(&self.group).ok()?;
+        let group = group_entries(&contents).find(|e| matches(e.gid, e.name))?;
+        Some(Arc::new(group.to_group()))
+    }
+}
+
+/// Reads a file, replacing anything that isn’t valid UTF-8 rather than
+/// failing on it.
+fn read_lossy(path: &Path) -> io::Result<String> {
+    Ok(String::from_utf8_lossy(&fs::read(path)?).into_owned())
+}
+
+/// Returns the process’s current and effective IDs from the platform
+/// itself. The functions at the crate root would ask the backend set with
+/// `set_backend` instead, which may be this very one.
+#[cfg(users_passthrough)]
+pub(crate) fn process_ids() -> (uid_t, gid_t, uid_t, gid_t) {
+    use users_orig as native;
+    (native::get_current_uid(), native::get_current_gid(), native::get_effective_uid(), native::get_effective_gid())
+}
+
+#[cfg(not(users_passthrough))]
+pub(crate) fn process_ids() -> (uid_t, gid_t, uid_t, gid_t) {
+    use platform::{Current, Platform};
+    (<Current as Platform>::get_current_uid(), <Current as Platform>::get_current_gid(),
+     <Current as Platform>::get_effective_uid(), <Current as Platform>::get_effective_gid())
+}
+
+
+impl Users for FileBackend {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        self.find_user(|id, _| id == uid)
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        let username = username.as_ref();
+        self.find_user(|_, name| OsStr::new(name) == username)
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        self.uid
+    }
+
+    fn get_current_username(&self) -> Option<Arc<OsStr>> {
+        self.get_user_by_uid(self.uid).map(|u| Arc::from(u.name()))
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        self.euid
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<OsStr>> {
+        self.get_user_by_uid(self.euid).map(|u| Arc::from(u.name()))
+    }
+}
+
+impl Groups for FileBackend {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        self.find_group(|id, _| id == gid)
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+        let group_name = group_name.as_ref();
+        self.find_group(|_, name| OsStr::new(name) == group_name)
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        self.gid
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
+        self.get_group_by_gid(self.gid).map(|g| Arc::from(g.name()))
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        self.egid
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
+        self.get_group_by_gid(self.egid).map(|g| Arc::from(g.name()))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::process;
+    use os::unix::{UserExt, GroupExt};
+
+    #[test]
+    fn lookups() {
+        let dir = env::temp_dir().join(format!("users-files-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("passwd"), "root:x:0:0:root:/root:/bin/sh\nalice:x:1000:100:Alice:/home/alice:/bin/zsh\n").unwrap();
+        fs::write(dir.join("group"), "root:x:0:\nusers:x:100:alice\n").unwrap();
+
+        let files = FileBackend::in_dir(&dir).with_current_uid(1000).with_current_gid(100);
+        assert_eq!(files.get_user_by_name("alice").unwrap().shell(), Path::new("/bin/zsh"));
+        assert_eq!(files.get_user_by_uid(0).unwrap().name(), "root");
+        assert!(files.get_user_by_uid(1001).is_none());
+        assert_eq!(files.get_group_by_gid(100).unwrap().members(), &[ "alice".into() ][..] as &[::std::ffi::OsString]);
+        assert_eq!(&*files.get_current_username().unwrap(), OsStr::new("alice"));
+        assert_eq!(&*files.get_current_groupname().unwrap(), OsStr::new("users"));
+        assert_eq!(files.all_users().unwrap().len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let dir = env::temp_dir().join(format!("users-files-latin1-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("passwd"), b"bjorn:x:1001:100:Bj\xf6rn:/home/bjorn:/bin/sh\nalice:x:1000:100:Alice:/home/alice:/bin/zsh\n").unwrap();
+        fs::write(dir.join("group"), b"users:x:100:alice,bj\xf6rn\n").unwrap();
+
+        let files = FileBackend::in_dir(&dir);
+        assert_eq!(files.get_user_by_name("alice").unwrap().uid(), 1000);
+        assert_eq!(files.get_user_by_uid(1001).unwrap().name(), "bjorn");
+        assert_eq!(files.all_users().unwrap().len(), 2);
+        assert_eq!(files.get_group_by_gid(100).unwrap().members().len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_files() {
+        let files = FileBackend::new("/nonexistent/passwd", "/nonexistent/group");
+        assert!(files.get_user_by_uid(0).is_none());
+        assert!(files.get_group_by_name("root").is_none());
+        assert!(files.all_groups().is_err());
+    }
+}
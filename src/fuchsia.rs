@@ -0,0 +1,189 @@
+//! The Fuchsia backend.
+//!
+//! Fuchsia has no passwd database: a component’s identity comes from the
+//! capabilities it is given, not from a user ID. Ports of Unix tools still
+//! ask who they are running as, though, so this backend answers with a
+//! single synthetic user and its primary group. Every lookup finds that
+//! user or group and nothing else, and switching to any other ID fails
+//! rather than pretending to succeed.
+//!
+//! The synthetic user defaults to `fuchsia`, with user and group ID 0, and
+//! can be replaced before anything looks it up.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use users::{User, Group, get_current_username};
+//! use users::fuchsia::set_synthetic_user;
+//!
+//! set_synthetic_user(User::new(1000, "shell", 1000), Group::new(1000, "shell"));
+//! assert_eq!(get_current_username(), Some("shell".into()));
+//! ```
+
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::sync::RwLock;
+
+use backend::Backend;
+use base::{User, Group, uid_t, gid_t};
+use platform::{Platform, same_groups};
+
+
+static SYNTHETIC: RwLock<Option<(User, Group)>> = RwLock::new(None);
+
+/// Sets the user, and its primary group, that the process is reported to
+/// run as.
+pub fn set_synthetic_user(user: User, group: Group) {
+    *SYNTHETIC.write().unwrap_or_else(|e| e.into_inner()) = Some((user, group));
+}
+
+/// Returns the synthetic user and its primary group.
+fn synthetic() -> (User, Group) {
+    let synthetic = SYNTHETIC.read().unwrap_or_else(|e| e.into_inner());
+    match *synthetic {
+        Some((ref user, ref group)) => (user.clone(), group.clone()),
+        None                        => (User::new(0, "fuchsia", 0), Group::new(0, "fuchsia")),
+    }
+}
+
+/// Succeeds if the requested ID is the synthetic one, and fails otherwise.
+fn unchanged(requested: u32, current: u32) -> io::Result<()> {
+    if requested == current {
+        Ok(())
+    }
+    else {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Fuchsia processes have no user or group IDs to switch"))
+    }
+}
+
+fn unchanged_groups(requested: &[gid_t], current: &[gid_t]) -> io::Result<()> {
+    if same_groups(requested, current) {
+        Ok(())
+    }
+    else {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Fuchsia processes have no user or group IDs to switch"))
+    }
+}
+
+
+/// The backend reporting the synthetic user.
+pub(crate) struct Fuchsia;
+
+impl Platform for Fuchsia {
+    const BACKEND: Backend = Backend::Synthetic;
+
+    fn get_user_by_uid(uid: uid_t) -> Option<User> {
+        Some(synthetic().0).filter(|user| user.uid() == uid)
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(username: &S) -> Option<User> {
+        Some(synthetic().0).filter(|user| user.name() == username.as_ref())
+    }
+
+    fn get_group_by_gid(gid: gid_t) -> Option<Group> {
+        Some(synthetic().1).filter(|group| group.gid() == gid)
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(groupname: &S) -> Option<Group> {
+        Some(synthetic().1).filter(|group| group.name() == groupname.as_ref())
+    }
+
+    fn get_current_uid() -> uid_t {
+        synthetic().0.uid()
+    }
+
+    fn get_current_username() -> Option<OsString> {
+        Some(synthetic().0.name().to_owned())
+    }
+
+    fn get_effective_uid() -> uid_t {
+        Self::get_current_uid()
+    }
+
+    fn get_effective_username() -> Option<OsString> {
+        Self::get_current_username()
+    }
+
+    fn get_current_gid() -> gid_t {
+        synthetic().1.gid()
+    }
+
+    fn get_current_groupname() -> Option<OsString> {
+        Some(synthetic().1.name().to_owned())
+    }
+
+    fn get_effective_gid() -> gid_t {
+        Self::get_current_gid()
+    }
+
+    fn get_effective_groupname() -> Option<OsString> {
+        Self::get_current_groupname()
+    }
+
+    fn group_access_list() -> io::Result<Vec<Group>> {
+        Ok(vec![ synthetic().1 ])
+    }
+
+    fn get_user_groups<S: AsRef<OsStr> + ?Sized>(username: &S, gid: gid_t) -> Option<Vec<Group>> {
+        let (user, group) = synthetic();
+        if user.name() == username.as_ref() && group.gid() == gid {
+            Some(vec![ group ])
+        }
+        else {
+            None
+        }
+    }
+
+    fn all_users() -> Vec<User> {
+        vec![ synthetic().0 ]
+    }
+
+    fn all_groups() -> Vec<Group> {
+        vec![ synthetic().1 ]
+    }
+
+    fn set_current_uid(uid: uid_t) -> io::Result<()> {
+        unchanged(uid, Self::get_current_uid())
+    }
+
+    fn set_current_gid(gid: gid_t) -> io::Result<()> {
+        unchanged(gid, Self::get_current_gid())
+    }
+
+    fn set_effective_uid(uid: uid_t) -> io::Result<()> {
+        unchanged(uid, Self::get_current_uid())
+    }
+
+    fn set_effective_gid(gid: gid_t) -> io::Result<()> {
+        unchanged(gid, Self::get_current_gid())
+    }
+
+    fn set_both_uid(ruid: uid_t, euid: uid_t) -> io::Result<()> {
+        unchanged(ruid, Self::get_current_uid()).and(unchanged(euid, Self::get_current_uid()))
+    }
+
+    fn set_both_gid(rgid: gid_t, egid: gid_t) -> io::Result<()> {
+        unchanged(rgid, Self::get_current_gid()).and(unchanged(egid, Self::get_current_gid()))
+    }
+
+    fn set_all_uid(ruid: uid_t, euid: uid_t, suid: uid_t) -> io::Result<()> {
+        Self::set_both_uid(ruid, euid).and(unchanged(suid, Self::get_current_uid()))
+    }
+
+    fn set_all_gid(rgid: gid_t, egid: gid_t, sgid: gid_t) -> io::Result<()> {
+        Self::set_both_gid(rgid, egid).and(unchanged(sgid, Self::get_current_gid()))
+    }
+
+    fn set_supplementary_groups(gids: &[gid_t]) -> io::Result<()> {
+        unchanged_groups(gids, &Self::supplementary_groups()?)
+    }
+
+    fn init_groups(username: &OsStr, gid: gid_t) -> io::Result<()> {
+        let gids = Self::get_user_group_ids(username, gid).unwrap_or_else(|| vec![ gid ]);
+        unchanged_groups(&gids, &Self::supplementary_groups()?)
+    }
+
+    fn switch_user_group(uid: uid_t, gid: gid_t) -> io::Result<()> {
+        unchanged(uid, Self::get_current_uid()).and(unchanged(gid, Self::get_current_gid()))
+    }
+}
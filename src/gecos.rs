@@ -0,0 +1,165 @@
+//! Parsing the GECOS field of a user’s `passwd` entry.
+//!
+//! The field is free-form, but by long convention it holds up to five
+//! comma-separated parts: the user’s full name, their room number, their
+//! office and home phone numbers, and anything else. Following `finger`, an
+//! `&` in the full name stands for the login name, capitalised.
+//!
+//! The stub keeps the field of users read from a `passwd` file or the C
+//! library. The real crate’s users have nowhere to keep it, so with it, the
+//! field is looked up again by user ID each time, and is only known if the
+//! host has a user with that ID and the same name.
+//!
+//! ```
+//! use users::gecos::Gecos;
+//!
+//! let gecos = Gecos::parse("Alice Appleseed,42,555-0100,,");
+//! assert_eq!(gecos.full_name.as_ref().map(String::as_str), Some("Alice Appleseed"));
+//! assert_eq!(gecos.room.as_ref().map(String::as_str), Some("42"));
+//! assert_eq!(gecos.home_phone, None);
+//! ```
+
+use std::ffi::OsString;
+
+use User;
+
+
+/// The parts of a GECOS field. Empty parts are `None`.
+#[derive(PartialEq, Eq, Clone, Default, Debug)]
+pub struct Gecos {
+
+    /// The user’s full name, with any `&` left in.
+    pub full_name: Option<String>,
+
+    /// The user’s room or building number.
+    pub room: Option<String>,
+
+    /// The user’s office phone number.
+    pub office_phone: Option<String>,
+
+    /// The user’s home phone number.
+    pub home_phone: Option<String>,
+
+    /// Anything after the fourth comma, such as an email address.
+    pub other: Option<String>,
+}
+
+impl Gecos {
+
+    /// Splits a GECOS field into its parts. Missing parts are `None`, and
+    /// any commas past the fourth are left in `other`.
+    pub fn parse(field: &str) -> Self {
+        let mut parts = field.splitn(5, ',').map(|part| {
+            let part = part.trim();
+            if part.is_empty() { None } else { Some(part.to_owned()) }
+        });
+
+        Gecos {
+            full_name:    parts.next().and_then(|p| p),
+            room:         parts.next().and_then(|p| p),
+            office_phone: parts.next().and_then(|p| p),
+            home_phone:   parts.next().and_then(|p| p),
+            other:        parts.next().and_then(|p| p),
+        }
+    }
+
+    /// Returns the full name with any `&` replaced by the given login name,
+    /// capitalised.
+    pub fn full_name_for(&self, username: &str) -> Option<String> {
+        let full_name = self.full_name.as_ref()?;
+
+        let mut chars = username.chars();
+        let capitalised = match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None        => String::new(),
+        };
+
+        Some(full_name.replace('&', &capitalised))
+    }
+}
+
+
+/// Access to the GECOS field of `User`s.
+pub trait GecosExt {
+
+    /// Returns this user’s GECOS field, split into its parts, or `None` if
+    /// the field isn’t known.
+    fn gecos(&self) -> Option<Gecos>;
+
+    /// Returns this user’s full name, if the GECOS field is known and has
+    /// one, with any `&` replaced by the login name.
+    fn full_name(&self) -> Option<String>;
+}
+
+impl GecosExt for User {
+    fn gecos(&self) -> Option<Gecos> {
+        field(self).map(|f| Gecos::parse(&f.to_string_lossy()))
+    }
+
+    fn full_name(&self) -> Option<String> {
+        self.gecos()?.full_name_for(&self.name().to_string_lossy())
+    }
+}
+
+/// Returns the user’s GECOS field from the host’s users database, as long
+/// as the user there with the same ID has the same name.
+#[cfg(users_passthrough)]
+pub(crate) fn field(user: &User) -> Option<OsString> {
+    match ::passthrough::get_gecos(user.uid()) {
+        Some((name, gecos)) if name.as_os_str() == user.name() => Some(gecos),
+        _                                                      => None,
+    }
+}
+
+/// Returns the user’s GECOS field, which the stub always keeps.
+#[cfg(not(users_passthrough))]
+pub(crate) fn field(user: &User) -> Option<OsString> {
+    Some(user.gecos_field().to_owned())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn all_parts() {
+        let gecos = Gecos::parse("Alice Appleseed,Room 1,555-0100,555-0199,alice@example.com,x");
+        assert_eq!(gecos, Gecos {
+            full_name:    Some("Alice Appleseed".into()),
+            room:         Some("Room 1".into()),
+            office_phone: Some("555-0100".into()),
+            home_phone:   Some("555-0199".into()),
+            other:        Some("alice@example.com,x".into()),
+        });
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(Gecos::parse(""), Gecos::default());
+        assert_eq!(Gecos::parse(",,,"), Gecos::default());
+    }
+
+    #[test]
+    fn ampersand() {
+        let gecos = Gecos::parse("& the Builder,,,");
+        assert_eq!(gecos.full_name_for("bob"), Some("Bob the Builder".into()));
+        assert_eq!(Gecos::default().full_name_for("bob"), None);
+    }
+
+    #[test]
+    fn dummy_user() {
+        assert_eq!(User::new(31337, "nobody-here", 31337).full_name(), None);
+    }
+
+    #[test]
+    #[cfg(users_passthrough)]
+    fn looked_up() {
+        let (name, gecos) = ::passthrough::get_gecos(0).unwrap();
+        assert_eq!(field(&User::new(0, &name, 0)), Some(gecos));
+
+        // A user the host doesn’t have isn’t given another user’s field.
+        assert_eq!(field(&User::new(0, "nobody-here", 0)), None);
+        assert_eq!(User::new(31337, "nobody-here", 31337).gecos(), None);
+    }
+}
@@ -0,0 +1,108 @@
+//! Supplying the current user’s identity on platforms without one.
+//!
+//! Some targets, such as `wasm32-unknown-unknown` running in a browser, have
+//! no user database at all, so the stub has to make up the current user’s ID
+//! and name. Often the application itself does know who is using it, though —
+//! a wasm module might get told by the JavaScript side, for example. An
+//! `IdentityProvider` lets it pass that on, so that code further down the
+//! dependency tree that asks for the current user gets a useful answer.
+//!
+//! A provider is only consulted on platforms where this crate has no backend
+//! of its own, and by the [`Stub`](../stub/struct.Stub.html) type; on Unix,
+//! and on Windows with the `windows-native` feature, the operating system
+//! always knows best.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::ffi::OsString;
+//! use users::identity::{IdentityProvider, set_identity_provider};
+//!
+//! struct LoggedIn { uid: u32, name: String }
+//!
+//! impl IdentityProvider for LoggedIn {
+//!     fn current_uid(&self) -> u32 {
+//!         self.uid
+//!     }
+//!
+//!     fn current_username(&self) -> Option<OsString> {
+//!         Some(self.name.clone().into())
+//!     }
+//! }
+//!
+//! set_identity_provider(Box::new(LoggedIn { uid: 1000, name: "alice".into() }));
+//! ```
+
+use std::ffi::OsString;
+use std::sync::RwLock;
+
+use uid_t;
+
+
+/// A source of the current user’s identity.
+pub trait IdentityProvider: Send + Sync {
+
+    /// Returns the ID of the user running the program.
+    fn current_uid(&self) -> uid_t;
+
+    /// Returns the name of the user running the program, if known.
+    fn current_username(&self) -> Option<OsString>;
+}
+
+
+static PROVIDER: RwLock<Option<Box<dyn IdentityProvider>>> = RwLock::new(None);
+
+/// Makes the stub answer `get_current_uid`, `get_current_username` and
+/// their effective counterparts from the given provider, replacing any that
+/// was set before.
+pub fn set_identity_provider(provider: Box<dyn IdentityProvider>) {
+    *PROVIDER.write().unwrap_or_else(|e| e.into_inner()) = Some(provider);
+}
+
+/// Removes the provider, if any, going back to the stub’s dummy values.
+pub fn clear_identity_provider() {
+    *PROVIDER.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Runs the given function on the current provider, if there is one.
+#[allow(dead_code)]  // only consulted by the stub backend
+pub(crate) fn with_provider<T, F>(f: F) -> Option<T>
+where F: FnOnce(&dyn IdentityProvider) -> T
+{
+    let provider = PROVIDER.read().unwrap_or_else(|e| e.into_inner());
+    provider.as_ref().map(|p| f(&**p))
+}
+
+
+#[cfg(all(test, not(feature = "deny-stub-at-runtime"), not(any(users_passthrough, all(windows, feature = "windows-native"), target_os = "wasi", target_os = "fuchsia", target_os = "illumos", target_os = "aix"))))]
+mod test {
+    use super::*;
+    use {get_current_uid, get_current_username, get_effective_uid, get_resuid};
+
+    struct Fixed;
+
+    impl IdentityProvider for Fixed {
+        fn current_uid(&self) -> uid_t {
+            1000
+        }
+
+        fn current_username(&self) -> Option<OsString> {
+            Some("alice".into())
+        }
+    }
+
+    #[test]
+    fn provided() {
+        let (uid, username) = (get_current_uid(), get_current_username());
+
+        set_identity_provider(Box::new(Fixed));
+        assert_eq!(get_current_uid(), 1000);
+        assert_eq!(get_effective_uid(), 1000);
+        assert_eq!(get_resuid(), (1000, 1000, 1000));
+        assert_eq!(get_current_username(), Some("alice".into()));
+
+        clear_identity_provider();
+        assert_eq!(get_current_uid(), uid);
+        assert_eq!(get_current_username(), username);
+    }
+}
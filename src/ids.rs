@@ -0,0 +1,232 @@
+//! User and group IDs that can’t be mixed up.
+//!
+//! `uid_t` and `gid_t` are both plain `u32`s, so nothing stops a group ID
+//! from being passed where a user ID goes, or a function taking both from
+//! being called with them the wrong way round. `Uid` and `Gid` wrap them in
+//! types of their own, which can be converted from and to the raw IDs, but
+//! not into each other, and which have no arithmetic:
+//!
+//! ```
+//! use users::ids::{self, Uid, Gid, UserIdsExt};
+//!
+//! fn owned_by(uid: Uid, gid: Gid) -> String {
+//!     format!("{}:{}", uid, gid)
+//! }
+//!
+//! let uid = ids::get_current_uid();
+//! let gid = ids::get_current_gid();
+//! assert_eq!(owned_by(uid, gid), format!("{}:{}", users::get_current_uid(), users::get_current_gid()));
+//!
+//! if let Some(user) = ids::get_user_by_uid(uid) {
+//!     assert_eq!(user.typed_uid(), uid);
+//! }
+//! ```
+//!
+//! The functions in this module are typed variants of those at the crate
+//! root, and answer the same way.
+
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use {User, Group, uid_t, gid_t};
+
+
+/// A user ID.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Uid(pub uid_t);
+
+/// A group ID.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Gid(pub gid_t);
+
+impl Uid {
+
+    /// The user ID of root.
+    pub const ROOT: Uid = Uid(0);
+
+    /// Returns the raw user ID.
+    pub fn as_raw(self) -> uid_t {
+        self.0
+    }
+
+    /// Returns whether this is the user ID of root.
+    pub fn is_root(self) -> bool {
+        self == Self::ROOT
+    }
+}
+
+impl Gid {
+
+    /// The group ID of root’s group.
+    pub const ROOT: Gid = Gid(0);
+
+    /// Returns the raw group ID.
+    pub fn as_raw(self) -> gid_t {
+        self.0
+    }
+
+    /// Returns whether this is the group ID of root’s group.
+    pub fn is_root(self) -> bool {
+        self == Self::ROOT
+    }
+}
+
+impl From<uid_t> for Uid {
+    fn from(uid: uid_t) -> Self {
+        Uid(uid)
+    }
+}
+
+impl From<Uid> for uid_t {
+    fn from(uid: Uid) -> Self {
+        uid.0
+    }
+}
+
+impl From<gid_t> for Gid {
+    fn from(gid: gid_t) -> Self {
+        Gid(gid)
+    }
+}
+
+impl From<Gid> for gid_t {
+    fn from(gid: Gid) -> Self {
+        gid.0
+    }
+}
+
+impl fmt::Display for Uid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for Gid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for Uid {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Uid)
+    }
+}
+
+impl FromStr for Gid {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Gid)
+    }
+}
+
+
+/// Typed access to the IDs of `User`s.
+pub trait UserIdsExt {
+
+    /// Returns this user’s ID.
+    fn typed_uid(&self) -> Uid;
+
+    /// Returns the ID of this user’s primary group.
+    fn typed_primary_group_id(&self) -> Gid;
+}
+
+impl UserIdsExt for User {
+    fn typed_uid(&self) -> Uid {
+        Uid(self.uid())
+    }
+
+    fn typed_primary_group_id(&self) -> Gid {
+        Gid(self.primary_group_id())
+    }
+}
+
+/// Typed access to the ID of `Group`s.
+pub trait GroupIdsExt {
+
+    /// Returns this group’s ID.
+    fn typed_gid(&self) -> Gid;
+}
+
+impl GroupIdsExt for Group {
+    fn typed_gid(&self) -> Gid {
+        Gid(self.gid())
+    }
+}
+
+
+/// Searches for a user with the given ID, like
+/// [`get_user_by_uid`](../fn.get_user_by_uid.html).
+pub fn get_user_by_uid(uid: Uid) -> Option<User> {
+    ::get_user_by_uid(uid.0)
+}
+
+/// Searches for a group with the given ID, like
+/// [`get_group_by_gid`](../fn.get_group_by_gid.html).
+pub fn get_group_by_gid(gid: Gid) -> Option<Group> {
+    ::get_group_by_gid(gid.0)
+}
+
+/// Returns the user ID of the user running the process, like
+/// [`get_current_uid`](../fn.get_current_uid.html).
+pub fn get_current_uid() -> Uid {
+    Uid(::get_current_uid())
+}
+
+/// Returns the user ID of the effective user running the process, like
+/// [`get_effective_uid`](../fn.get_effective_uid.html).
+pub fn get_effective_uid() -> Uid {
+    Uid(::get_effective_uid())
+}
+
+/// Returns the group ID of the user running the process, like
+/// [`get_current_gid`](../fn.get_current_gid.html).
+pub fn get_current_gid() -> Gid {
+    Gid(::get_current_gid())
+}
+
+/// Returns the group ID of the effective user running the process, like
+/// [`get_effective_gid`](../fn.get_effective_gid.html).
+pub fn get_effective_gid() -> Gid {
+    Gid(::get_effective_gid())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Uid::from(1000), Uid(1000));
+        assert_eq!(uid_t::from(Uid(1000)), 1000);
+        assert_eq!(Gid::from(100).as_raw(), 100);
+        assert_eq!("1000".parse::<Uid>().unwrap(), Uid(1000));
+        assert!("alice".parse::<Gid>().is_err());
+        assert_eq!(Uid(1000).to_string(), "1000");
+        assert!(Uid::ROOT.is_root() && ! Gid(100).is_root());
+    }
+
+    #[test]
+    fn users() {
+        let user = User::new(1000, "alice", 100);
+        assert_eq!(user.typed_uid(), Uid(1000));
+        assert_eq!(user.typed_primary_group_id(), Gid(100));
+        assert_eq!(Group::new(100, "users").typed_gid(), Gid(100));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde() {
+        assert_eq!(::serde_json::to_string(&Uid(1000)).unwrap(), "1000");
+        assert_eq!(::serde_json::from_str::<Gid>("100").unwrap(), Gid(100));
+    }
+}
@@ -2,35 +2,260 @@
 extern crate cfg_if;
 
 cfg_if! {
-    if #[cfg(all(unix, not(feature = "force-stub")))] {
+    if #[cfg(users_passthrough)] {
         extern crate users_orig;
         pub use users_orig::*;
+
+        extern crate libc;
+
+        mod passthrough;
+        pub use passthrough::{get_user_by_uid, get_user_by_name};
+        pub use passthrough::{get_group_by_gid, get_group_by_name};
+        pub use passthrough::{try_get_user_by_uid, try_get_user_by_name};
+        pub use passthrough::{try_get_group_by_gid, try_get_group_by_name};
+        pub use passthrough::{get_current_uid, get_current_username};
+        pub use passthrough::{get_effective_uid, get_effective_username};
+        pub use passthrough::{get_current_gid, get_current_groupname};
+        pub use passthrough::{get_effective_gid, get_effective_groupname};
+        pub use passthrough::{get_resuid, get_resgid};
+        pub use passthrough::{all_groups, try_all_users};
     } else {
         
+        #[cfg(feature = "logging")]
+        #[macro_use]
+        extern crate log;
+
         mod base;
-        pub use base::{User, Group, os};
+        pub use base::{User, Group};
         pub use base::{get_user_by_uid, get_user_by_name};
         pub use base::{get_group_by_gid, get_group_by_name};
+        pub use base::{try_get_user_by_uid, try_get_user_by_name};
+        pub use base::{try_get_group_by_gid, try_get_group_by_name};
         pub use base::{get_current_uid, get_current_username};
         pub use base::{get_effective_uid, get_effective_username};
         pub use base::{get_current_gid, get_current_groupname};
         pub use base::{get_effective_gid, get_effective_groupname};
+        pub use base::{get_resuid, get_resgid};
         pub use base::{get_user_groups, group_access_list};
-        pub use base::{all_users};
+        pub use base::{all_users, all_groups, try_all_users};
         pub use base::{uid_t, gid_t};
-        
-        #[cfg(feature = "cache")]
-        pub mod cache;
-        
-        #[cfg(feature = "cache")]
-        pub use cache::UsersCache;
-        
-        #[cfg(feature = "mock")]
-        pub mod mock;
-        
-        pub mod switch;
-        
+
+        #[cfg(all(windows, feature = "windows-native"))]
+        extern crate winapi;
+
+        #[cfg(all(windows, feature = "windows-native"))]
+        mod windows;
+
+        pub mod sid;
+
+        #[cfg(target_os = "wasi")]
+        mod wasi;
+
+        #[cfg(target_os = "fuchsia")]
+        pub mod fuchsia;
+
+        #[cfg(any(target_os = "illumos", target_os = "aix"))]
+        extern crate libc;
+
+        #[cfg(any(target_os = "illumos", target_os = "aix"))]
+        mod posix;
+
         mod traits;
         pub use traits::{Users, Groups};
     }
 }
+
+/// OS-specific extensions to users and groups.
+pub mod os {
+    #[cfg(users_passthrough)]
+    pub use users_orig::os::*;
+
+    #[cfg(not(users_passthrough))]
+    pub use base::os::*;
+
+    pub mod macos;
+}
+
+mod current;
+pub use current::refresh_current_identity;
+
+mod error;
+pub use error::Error;
+
+pub mod ids;
+pub use ids::{Uid, Gid, UserIdsExt, GroupIdsExt};
+
+pub mod privileges;
+
+pub mod switch;
+
+pub mod switcher;
+
+// With the real crate in use, only the stub’s `Users` and `Groups` impls
+// are ever asked anything.
+#[cfg_attr(users_passthrough, allow(dead_code))]
+mod platform;
+
+pub mod identity;
+
+#[cfg_attr(users_passthrough, allow(dead_code))]
+pub mod stub;
+
+#[cfg(feature = "serde")]
+extern crate serde;
+
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde_derive;
+
+#[cfg(feature = "serde")]
+pub mod serialize;
+
+#[cfg(any(feature = "file-db", feature = "userdb", all(test, feature = "serde")))]
+extern crate serde_json;
+
+#[cfg(feature = "file-db")]
+extern crate toml;
+
+#[cfg(feature = "file-db")]
+pub mod db;
+
+mod backend;
+pub use backend::{backend_info, Backend, BackendInfo};
+pub use backend::{set_backend, clear_backend};
+
+mod dynamic;
+pub use dynamic::{DynUsers, DynGroups};
+
+pub mod snapshot;
+pub use snapshot::UsersSnapshot;
+
+mod listing;
+pub use listing::{all_users_snapshot, all_groups_snapshot};
+
+mod membership;
+pub use membership::{get_group_members, user_in_group, UserRef, GroupRef};
+
+mod batch;
+pub use batch::{get_users_by_uids, get_groups_by_gids};
+
+mod login;
+pub use login::{get_login_name, get_login_uid};
+
+pub mod sessions;
+
+#[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+pub mod reentrant;
+
+#[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+pub mod raw;
+
+#[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+pub use raw::{RawUserExt, RawGroupExt};
+
+#[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+mod chown;
+
+#[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+pub use chown::{chown_by_name, fchown_by_name};
+
+pub mod accounts;
+pub use accounts::AccountExt;
+
+pub mod shells;
+pub use shells::{has_valid_shell, read_shells};
+
+pub mod subids;
+
+pub mod userns;
+
+pub mod gecos;
+pub use gecos::GecosExt;
+
+mod builder;
+pub use builder::{UserBuilder, UserBuilderExt};
+pub use builder::{GroupBuilder, GroupBuilderExt};
+
+#[cfg(feature = "tokio")]
+extern crate tokio;
+
+#[cfg(feature = "async")]
+mod asynchronous;
+
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncUsers, AsyncGroups, LookupFuture, Ready};
+
+#[cfg(feature = "tokio")]
+pub use asynchronous::SpawnBlocking;
+
+pub mod test;
+
+#[cfg(test)]
+mod parity;
+
+#[cfg(feature = "cache")]
+pub mod cache;
+
+#[cfg(feature = "cache")]
+pub use cache::UsersCache;
+
+#[cfg(feature = "cache")]
+pub mod shared_cache;
+
+#[cfg(feature = "watch-files")]
+mod watch;
+
+#[cfg(feature = "watch-files")]
+pub use watch::{watch, Watcher, AccountEvent};
+
+#[cfg(feature = "cache")]
+pub use shared_cache::SharedUsersCache;
+
+#[cfg(feature = "mock")]
+pub mod mock;
+
+#[cfg(feature = "proptest")]
+extern crate proptest;
+
+#[cfg(feature = "proptest")]
+pub mod strategies;
+
+mod etc;
+pub use etc::{ParseError, UserLineExt, GroupLineExt};
+
+pub mod files;
+pub use files::FileBackend;
+
+#[cfg(feature = "shadow")]
+pub mod shadow;
+
+#[cfg(feature = "shadow")]
+pub use shadow::{get_shadow_by_name, Shadow, ShadowExt};
+
+pub mod chain;
+pub use chain::ChainedBackend;
+
+#[cfg(all(feature = "nss-modules", users_passthrough))]
+pub mod nss;
+
+#[cfg(all(feature = "userdb", unix))]
+pub mod userdb;
+
+#[cfg(all(feature = "sssd", unix))]
+pub mod sssd;
+
+#[cfg(all(feature = "nix", any(users_passthrough, target_os = "illumos", target_os = "aix")))]
+extern crate nix;
+
+#[cfg(all(feature = "nix", any(users_passthrough, target_os = "illumos", target_os = "aix")))]
+pub mod nix_interop;
+
+#[cfg(all(feature = "process", any(users_passthrough, target_os = "illumos", target_os = "aix")))]
+pub mod process;
+
+mod fallible;
+pub use fallible::{try_get_current_uid, try_get_effective_uid};
+pub use fallible::{try_get_current_gid, try_get_effective_gid};
+
+#[cfg(any(target_os = "android", test))]
+mod android;
@@ -0,0 +1,88 @@
+//! Listing every user and group without `unsafe`.
+//!
+//! `all_users` and `all_groups` are `unsafe` because the system keeps one
+//! position in the users and groups databases for the whole process, so two
+//! threads listing at once would each see a mess. Everything in this crate
+//! that lists users or groups takes the same lock first, and code calling
+//! the `unsafe` functions directly has promised not to list at the same
+//! time, so the functions here can be safe:
+//!
+//! ```
+//! for user in users::all_users_snapshot() {
+//!     println!("{:?} has ID {}", user.name(), user.uid());
+//! }
+//! ```
+//!
+//! `try_all_users` is safe for the same reason, and also says when the
+//! users can’t be listed, rather than listing none:
+//!
+//! ```
+//! match users::try_all_users() {
+//!     Ok(users) => for user in users {
+//!         match user {
+//!             Ok(user) => println!("{:?}", user.name()),
+//!             Err(e)   => println!("Stopped early: {}", e),
+//!         }
+//!     },
+//!     Err(e) => println!("Can’t list users: {}", e),
+//! }
+//! ```
+
+use std::sync::{Mutex, MutexGuard};
+
+use {User, Group, all_users, all_groups};
+
+
+/// Held by whatever in this crate is listing users or groups.
+static LISTING: Mutex<()> = Mutex::new(());
+
+/// Waits until nothing else in this crate is listing users or groups, and
+/// keeps it that way until the guard is dropped.
+pub(crate) fn lock() -> MutexGuard<'static, ()> {
+    LISTING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Every user on the system, listed in one go.
+///
+/// This is the safe version of `all_users`: it can be called from any
+/// thread, at the cost of collecting the users before returning them.
+pub fn all_users_snapshot() -> Vec<User> {
+    let _listing = lock();
+    unsafe { all_users() }.collect()
+}
+
+/// Every group on the system, listed in one go.
+///
+/// This is the safe version of `all_groups`.
+pub fn all_groups_snapshot() -> Vec<Group> {
+    let _listing = lock();
+    unsafe { all_groups() }.collect()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io;
+    use std::thread;
+    use try_all_users;
+
+    #[test]
+    fn try_all_users_agrees() {
+        match try_all_users() {
+            Ok(users) => assert_eq!(users.map(Result::unwrap).count(), all_users_snapshot().len()),
+            Err(e)    => assert_eq!(e.kind(), io::ErrorKind::Unsupported),
+        }
+    }
+
+    #[test]
+    fn from_many_threads() {
+        let expected = all_users_snapshot().len();
+        let threads = (0 .. 4).map(|_| thread::spawn(|| all_users_snapshot().len()))
+                              .collect::<Vec<_>>();
+
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), expected);
+        }
+    }
+}
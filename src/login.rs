@@ -0,0 +1,120 @@
+//! Finding out who logged in, rather than who the process runs as.
+//!
+//! After `su` or `sudo`, `get_current_username` names the user switched
+//! to. The login name and the audit login user ID stay those of the user
+//! who logged in, which is who tools that log or report actions usually
+//! want:
+//!
+//! ```
+//! use users::{get_current_username, get_login_name};
+//!
+//! if let (Some(login), Some(current)) = (get_login_name(), get_current_username()) {
+//!     if login != current {
+//!         println!("{:?} acting as {:?}", login, current);
+//!     }
+//! }
+//! ```
+//!
+//! Processes started outside of a login session, such as services and
+//! cron jobs, have neither.
+
+use std::ffi::OsString;
+
+use uid_t;
+
+#[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+use std::ffi::CStr;
+
+#[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+use std::os::raw::{c_char, c_int};
+
+#[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+use std::os::unix::ffi::OsStringExt;
+
+#[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+use libc;
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+use get_user_by_name;
+
+
+// NOTE: libc only has the non-reentrant getlogin
+#[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+extern "C" {
+    fn getlogin_r(buf: *mut c_char, bufsize: libc::size_t) -> c_int;
+}
+
+
+/// Returns the name of the user logged in on the controlling terminal of
+/// the process, with `getlogin_r`, which may differ from the user the
+/// process runs as.
+///
+/// Returns `None` without a login session, and on platforms without
+/// `getlogin_r`.
+pub fn get_login_name() -> Option<OsString> {
+    #[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+    {
+        let mut buf = vec![0 as c_char; 256];
+        loop {
+            match unsafe { getlogin_r(buf.as_mut_ptr(), buf.len()) } {
+                0 => {
+                    let name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+                    return Some(OsString::from_vec(name.to_bytes().to_vec())).filter(|name| ! name.is_empty());
+                }
+                libc::ERANGE if buf.len() < 4096 => {
+                    let len = buf.len() * 2;
+                    buf.resize(len, 0);
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    #[cfg(not(any(users_passthrough, target_os = "illumos", target_os = "aix")))]
+    None
+}
+
+/// Returns the audit login user ID of the process: the user who logged in
+/// to the session it belongs to, which stays the same across `su`, `sudo`,
+/// and `setuid` programs.
+///
+/// On Linux, it comes from `/proc/self/loginuid`. Elsewhere, it is the user
+/// ID of the login name. Returns `None` without a login session.
+pub fn get_login_uid() -> Option<uid_t> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let contents = ::std::fs::read_to_string("/proc/self/loginuid").ok()?;
+        parse_login_uid(&contents)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    get_user_by_name(&get_login_name()?).map(|user| user.uid())
+}
+
+/// Parses the contents of `/proc/self/loginuid`, where `-1` as an unsigned
+/// number means there is no login session.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn parse_login_uid(contents: &str) -> Option<uid_t> {
+    contents.trim().parse().ok().filter(|&uid: &uid_t| uid != uid_t::MAX)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn login_uid() {
+        assert_eq!(parse_login_uid("1000"), Some(1000));
+        assert_eq!(parse_login_uid("4294967295"), None);
+        assert_eq!(parse_login_uid(""), None);
+    }
+
+    #[test]
+    fn login_name() {
+        if let Some(name) = get_login_name() {
+            assert!(! name.is_empty());
+        }
+    }
+}
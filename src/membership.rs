@@ -0,0 +1,196 @@
+//! Working out who is in which group.
+//!
+//! A user is in a group either because it is their primary group, which
+//! only their own entry says, or because they are listed among the group’s
+//! members. Answering “who is in this group?” means putting the two
+//! together:
+//!
+//! ```no_run
+//! let wheel = users::get_group_by_name("wheel").expect("no wheel group");
+//! for user in users::get_group_members(wheel.gid()).unwrap() {
+//!     println!("{:?} is in wheel", user.name());
+//! }
+//! ```
+//!
+//! Asking whether one user is in one group is cheaper, needing only the
+//! IDs of the user’s groups:
+//!
+//! ```no_run
+//! if users::user_in_group("alice", "wheel").unwrap() {
+//!     println!("alice may administer this machine");
+//! }
+//! ```
+
+use std::ffi::OsStr;
+use std::io;
+
+use {User, uid_t, gid_t};
+use {get_user_by_uid, get_user_by_name, get_group_by_gid, get_group_by_name, try_all_users};
+use os::unix::GroupExt;
+
+#[cfg(users_passthrough)]
+use passthrough::get_user_group_ids;
+
+#[cfg(not(users_passthrough))]
+use base::get_user_group_ids;
+
+
+/// A user, given either by ID or by name.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum UserRef<'a> {
+
+    /// The user with this ID.
+    Id(uid_t),
+
+    /// The user with this name.
+    Name(&'a OsStr),
+}
+
+/// A group, given either by ID or by name.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum GroupRef<'a> {
+
+    /// The group with this ID.
+    Id(gid_t),
+
+    /// The group with this name.
+    Name(&'a OsStr),
+}
+
+impl<'a> From<uid_t> for UserRef<'a> {
+    fn from(uid: uid_t) -> Self {
+        UserRef::Id(uid)
+    }
+}
+
+impl<'a> From<&'a OsStr> for UserRef<'a> {
+    fn from(name: &'a OsStr) -> Self {
+        UserRef::Name(name)
+    }
+}
+
+impl<'a> From<&'a str> for UserRef<'a> {
+    fn from(name: &'a str) -> Self {
+        UserRef::Name(OsStr::new(name))
+    }
+}
+
+impl<'a> From<gid_t> for GroupRef<'a> {
+    fn from(gid: gid_t) -> Self {
+        GroupRef::Id(gid)
+    }
+}
+
+impl<'a> From<&'a OsStr> for GroupRef<'a> {
+    fn from(name: &'a OsStr) -> Self {
+        GroupRef::Name(name)
+    }
+}
+
+impl<'a> From<&'a str> for GroupRef<'a> {
+    fn from(name: &'a str) -> Self {
+        GroupRef::Name(OsStr::new(name))
+    }
+}
+
+
+/// Returns whether a user is in a group, either as their primary group or
+/// as one of its members.
+///
+/// Both can be given by ID or by name. Only the IDs of the user’s groups
+/// are looked up, with one call to `getgrouplist` where there is one, so
+/// this is cheap enough to check on every request.
+///
+/// # Errors
+///
+/// Fails with `ErrorKind::NotFound` if there is no such user or group, or
+/// if the user’s groups can’t be listed.
+pub fn user_in_group<'u, 'g, U, G>(user: U, group: G) -> io::Result<bool>
+where U: Into<UserRef<'u>>,
+      G: Into<GroupRef<'g>>,
+{
+    let user = match user.into() {
+        UserRef::Id(uid)    => get_user_by_uid(uid),
+        UserRef::Name(name) => get_user_by_name(name),
+    }.ok_or_else(|| not_found("user"))?;
+
+    let gid = match group.into() {
+        GroupRef::Id(gid)    => gid,
+        GroupRef::Name(name) => get_group_by_name(name).ok_or_else(|| not_found("group"))?.gid(),
+    };
+
+    if user.primary_group_id() == gid {
+        return Ok(true);
+    }
+
+    let gids = get_user_group_ids(user.name(), user.primary_group_id())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("the groups of {:?} can’t be listed", user.name())))?;
+    Ok(gids.contains(&gid))
+}
+
+fn not_found(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("there is no such {}", what))
+}
+
+
+/// Returns every user in the group with the given ID: those whose primary
+/// group it is, and those listed as its members, sorted by user ID.
+///
+/// Members who aren’t in the list of every user, as happens with some
+/// network users databases, are looked up by name; members that can’t be
+/// found at all are left out.
+///
+/// # Errors
+///
+/// Fails with `ErrorKind::NotFound` if there is no such group, and with
+/// whatever error `try_all_users` gives if the users can’t be listed.
+pub fn get_group_members(gid: gid_t) -> io::Result<Vec<User>> {
+    let group = get_group_by_gid(gid)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("there is no group with ID {}", gid)))?;
+
+    let mut members = Vec::new();
+    for user in try_all_users()? {
+        let user = user?;
+        if user.primary_group_id() == gid || group.members().iter().any(|m| m == user.name()) {
+            members.push(user);
+        }
+    }
+
+    for name in group.members() {
+        if ! members.iter().any(|u| u.name() == name) {
+            members.extend(get_user_by_name(name));
+        }
+    }
+
+    members.sort_by_key(User::uid);
+    members.dedup_by_key(|u| u.uid());
+    Ok(members)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use get_current_uid;
+
+    #[test]
+    fn in_primary_group() {
+        let uid = get_current_uid();
+        match get_user_by_uid(uid) {
+            Some(user) => {
+                assert!(user_in_group(uid, user.primary_group_id()).unwrap());
+                assert!(user_in_group(user.name(), user.primary_group_id()).unwrap());
+            },
+            None => assert_eq!(user_in_group(uid, 0).unwrap_err().kind(), io::ErrorKind::NotFound),
+        }
+    }
+
+    #[test]
+    fn includes_current_user() {
+        let gid = get_user_by_uid(get_current_uid()).map_or(0, |u| u.primary_group_id());
+        match get_group_members(gid) {
+            Ok(members) => assert!(members.iter().any(|u| u.uid() == get_current_uid())),
+            Err(e)      => assert!(e.kind() == io::ErrorKind::NotFound || e.kind() == io::ErrorKind::Unsupported),
+        }
+    }
+}
@@ -27,7 +27,95 @@
 //! users.add_group(Group::new(100, "funkyppl"));
 //! ```
 //!
+//! A table can also be read from text in the `/etc/passwd` and `/etc/group`
+//! formats, so fixtures can be copied from a real system:
+//!
+//! ```
+//! use users::Users;
+//! use users::mock::MockUsers;
+//!
+//! let mut users = MockUsers::from_passwd_str(1000, "alice:x:1000:100:Alice:/home/alice:/bin/sh");
+//! users.add_group_str("users:x:100:\nwheel:x:10:alice");
+//! assert_eq!(users.get_current_username().as_ref().map(|n| &**n), Some("alice".as_ref()));
+//! ```
+//!
+//! With the `serde` feature, a whole table can be serialized and
+//! deserialized, in JSON or any other format serde supports, so fixtures can
+//! live in data files. Failing lookups are not part of it.
+//!
+//! The current group ID starts out the same as the user ID, and the
+//! effective IDs the same as the current ones. They can all be changed with
+//! `set_current_gid` and its siblings.
+//!
 //! The exports get re-exported into the mock module, for simpler `use` lines.
+//! This module is the same on every platform, rather than the real `users`
+//! crate’s on Unix, so tests that use it work the same everywhere.
+//!
+//!
+//! ## Failing Lookups
+//!
+//! Looking up a user can fail for reasons other than there being no such
+//! user, such as a directory service that can’t be reached. The real
+//! functions return `None` then, and leave the reason in `errno`. A mock
+//! table can be told to fail lookups the same way with `fail_lookup`, after
+//! which they return `None`, and `take_error` returns the error:
+//!
+//! ```
+//! use std::io;
+//! use users::Users;
+//! use users::mock::{MockUsers, User, Lookup};
+//!
+//! let mut users = MockUsers::with_current_uid(1000);
+//! users.add_user(User::new(1000, "alice", 100));
+//! users.fail_lookup(Lookup::UserByUid, io::Error::from_raw_os_error(5));
+//!
+//! assert!(users.get_user_by_uid(1000).is_none());
+//! assert_eq!(users.take_error().and_then(|e| e.raw_os_error()), Some(5));
+//! ```
+//!
+//!
+//! ## Recording Lookups
+//!
+//! To check which lookups code performs, and how many, wrap a table in a
+//! `Recording`. It passes every lookup on, and keeps a list of them with
+//! their arguments and results:
+//!
+//! ```
+//! use users::Users;
+//! use users::mock::{MockUsers, Recording, User};
+//!
+//! let mut users = MockUsers::with_current_uid(1000);
+//! users.add_user(User::new(1000, "alice", 100));
+//!
+//! let users = Recording::new(users);
+//! users.get_user_by_uid(1000);
+//! users.get_user_by_name("bob");
+//!
+//! assert_eq!(users.calls().len(), 2);
+//! users.assert_called_with("get_user_by_uid", 1000);
+//! users.assert_called_with("get_user_by_name", "bob");
+//! ```
+//!
+//!
+//! ## Sharing Mock Users
+//!
+//! A `MockUsers` table can be used from several threads at once, but not
+//! changed while it is. To keep adding users to a table that a service
+//! under test is already using, wrap it in a `SharedMockUsers`, which can be
+//! cloned and handed out, and changed through any of its clones:
+//!
+//! ```
+//! use std::thread;
+//! use users::Users;
+//! use users::mock::{MockUsers, SharedMockUsers, User};
+//!
+//! let users = SharedMockUsers::new(MockUsers::with_current_uid(1000));
+//! let service = users.clone();
+//!
+//! users.write().add_user(User::new(1000, "alice", 100));
+//! let name = thread::spawn(move || service.get_current_username()).join().unwrap();
+//! assert!(name.is_some());
+//! ```
 //!
 //!
 //! ## Using Mock Users
@@ -57,30 +145,81 @@
 //! ```
 
 use std::collections::HashMap;
-use std::ffi::OsStr;
-use std::sync::Arc;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::sync::{Arc, Mutex, RwLock, RwLockWriteGuard};
 
 pub use super::{uid_t, gid_t};
-pub use base::{User, Group};
-pub use traits::{Users, Groups};
+pub use super::{User, Group};
+pub use super::{Users, Groups};
+
+use etc::{passwd_entries, group_entries};
+use os::unix::GroupExt;
+
+#[cfg(feature = "serde")]
+use os::unix::UserExt;
+
+#[cfg(feature = "serde")]
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+
+/// The lookups of a mock table that can be made to fail.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum Lookup {
+
+    /// `get_user_by_uid`.
+    UserByUid,
+
+    /// `get_user_by_name`.
+    UserByName,
+
+    /// `get_current_username` and `get_effective_username`.
+    Username,
+
+    /// `get_group_by_gid`.
+    GroupByGid,
+
+    /// `get_group_by_name`.
+    GroupByName,
+
+    /// `get_current_groupname` and `get_effective_groupname`.
+    Groupname,
+}
 
 
 /// A mocking users table that you can add your own users and groups to.
+///
+/// It serves as a groups table too, so it implements both `Users` and
+/// `Groups`.
 pub struct MockUsers {
     users: HashMap<uid_t, Arc<User>>,
     groups: HashMap<gid_t, Arc<Group>>,
     uid: uid_t,
+    gid: gid_t,
+    euid: uid_t,
+    egid: gid_t,
+    failures: HashMap<Lookup, io::Error>,
+    error: Mutex<Option<io::Error>>,
 }
 
 
 impl MockUsers {
 
-    /// Create a new, empty mock users table.
+    /// Create a new, empty mock users table. The current and effective user
+    /// and group IDs all start out as the given user ID.
     pub fn with_current_uid(current_uid: uid_t) -> Self {
         Self {
             users: HashMap::new(),
             groups: HashMap::new(),
             uid: current_uid,
+            gid: current_uid,
+            euid: current_uid,
+            egid: current_uid,
+            failures: HashMap::new(),
+            error: Mutex::new(None),
         }
     }
 
@@ -93,15 +232,145 @@ impl MockUsers {
     pub fn add_group(&mut self, group: Group) -> Option<Arc<Group>> {
         self.groups.insert(group.gid(), Arc::new(group))
     }
+
+    /// Lists the named user as a member of the group with the given ID, if
+    /// it is in the table.
+    pub fn add_member<S: AsRef<OsStr> + ?Sized>(&mut self, gid: gid_t, username: &S) {
+        if let Some(group) = self.groups.get_mut(&gid) {
+            *group = Arc::new(Group::clone(group).add_member(username));
+        }
+    }
+
+    /// Create a mock users table holding the users in the given text, in
+    /// the `/etc/passwd` format. The current group ID is the current
+    /// user’s primary group, if they are among them.
+    pub fn from_passwd_str(current_uid: uid_t, passwd: &str) -> Self {
+        let mut users = Self::with_current_uid(current_uid);
+        users.add_passwd_str(passwd);
+
+        if let Some(gid) = users.users.get(&current_uid).map(|u| u.primary_group_id()) {
+            users.set_current_gid(gid);
+        }
+
+        users
+    }
+
+    /// Create a mock users table holding the groups in the given text, in
+    /// the `/etc/group` format.
+    pub fn from_group_str(current_uid: uid_t, group: &str) -> Self {
+        let mut users = Self::with_current_uid(current_uid);
+        users.add_group_str(group);
+        users
+    }
+
+    /// Add the users in the given text, in the `/etc/passwd` format, to the
+    /// users table. Lines that aren’t entries are skipped.
+    pub fn add_passwd_str(&mut self, passwd: &str) {
+        for entry in passwd_entries(passwd) {
+            self.add_user(entry.to_user());
+        }
+    }
+
+    /// Add the groups in the given text, in the `/etc/group` format, to the
+    /// groups table. Lines that aren’t entries are skipped.
+    pub fn add_group_str(&mut self, group: &str) {
+        for entry in group_entries(group) {
+            self.add_group(entry.to_group());
+        }
+    }
+
+    /// Sets the ID of the user running the process, along with the
+    /// effective user ID.
+    pub fn set_current_uid(&mut self, uid: uid_t) {
+        self.uid = uid;
+        self.euid = uid;
+    }
+
+    /// Sets the ID of the group running the process, along with the
+    /// effective group ID.
+    pub fn set_current_gid(&mut self, gid: gid_t) {
+        self.gid = gid;
+        self.egid = gid;
+    }
+
+    /// Sets the effective user ID, leaving the current one alone.
+    pub fn set_effective_uid(&mut self, uid: uid_t) {
+        self.euid = uid;
+    }
+
+    /// Sets the effective group ID, leaving the current one alone.
+    pub fn set_effective_gid(&mut self, gid: gid_t) {
+        self.egid = gid;
+    }
+
+    /// Makes the given lookup fail with the given error from now on,
+    /// returning `None` and leaving a copy of the error for `take_error`.
+    pub fn fail_lookup(&mut self, lookup: Lookup, error: io::Error) {
+        self.failures.insert(lookup, error);
+    }
+
+    /// Makes all lookups succeed again.
+    pub fn clear_failures(&mut self) {
+        self.failures.clear();
+    }
+
+    /// Returns the error the last failed lookup failed with, if any lookup
+    /// failed since the last call.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.error.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+
+    /// Returns whether the given lookup should fail, and if it should,
+    /// records a copy of the error it fails with.
+    fn fails(&self, lookup: Lookup) -> bool {
+        let error = match self.failures.get(&lookup) {
+            Some(error) => error,
+            None        => return false,
+        };
+
+        let copy = match error.raw_os_error() {
+            Some(code) => io::Error::from_raw_os_error(code),
+            None       => io::Error::new(error.kind(), error.to_string()),
+        };
+
+        *self.error.lock().unwrap_or_else(|e| e.into_inner()) = Some(copy);
+        true
+    }
+
+    /// Returns the name of the user with the given ID, if it is in the table.
+    fn username(&self, uid: uid_t) -> Option<Arc<OsStr>> {
+        if self.fails(Lookup::Username) {
+            return None;
+        }
+
+        self.users.get(&uid).map(|u| Arc::from(u.name()))
+    }
+
+    /// Returns the name of the group with the given ID, if it is in the table.
+    fn groupname(&self, gid: gid_t) -> Option<Arc<OsStr>> {
+        if self.fails(Lookup::Groupname) {
+            return None;
+        }
+
+        self.groups.get(&gid).map(|g| Arc::from(g.name()))
+    }
 }
 
 
 impl Users for MockUsers {
     fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        if self.fails(Lookup::UserByUid) {
+            return None;
+        }
+
         self.users.get(&uid).cloned()
     }
 
     fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        if self.fails(Lookup::UserByName) {
+            return None;
+        }
+
         self.users.values().find(|u| u.name() == username.as_ref()).cloned()
     }
 
@@ -110,53 +379,447 @@ impl Users for MockUsers {
     }
 
     fn get_current_username(&self) -> Option<Arc<OsStr>> {
-        self.users.get(&self.uid).map(|u| Arc::clone(&u.name_arc))
+        self.username(self.uid)
     }
 
     fn get_effective_uid(&self) -> uid_t {
-        self.uid
+        self.euid
     }
 
     fn get_effective_username(&self) -> Option<Arc<OsStr>> {
-        self.users.get(&self.uid).map(|u| Arc::clone(&u.name_arc))
+        self.username(self.euid)
     }
 }
 
 
 impl Groups for MockUsers {
     fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        if self.fails(Lookup::GroupByGid) {
+            return None;
+        }
+
         self.groups.get(&gid).cloned()
     }
 
     fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+        if self.fails(Lookup::GroupByName) {
+            return None;
+        }
+
         self.groups.values().find(|g| g.name() == group_name.as_ref()).cloned()
     }
 
-    fn get_current_gid(&self) -> uid_t {
-        self.uid
+    fn get_current_gid(&self) -> gid_t {
+        self.gid
     }
 
     fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
-        self.groups.get(&self.uid).map(|u| Arc::clone(&u.name_arc))
+        self.groupname(self.gid)
     }
 
-    fn get_effective_gid(&self) -> uid_t {
-        self.uid
+    fn get_effective_gid(&self) -> gid_t {
+        self.egid
     }
 
     fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
-        self.groups.get(&self.uid).map(|u| Arc::clone(&u.name_arc))
+        self.groupname(self.egid)
+    }
+}
+
+
+/// A mock users table that can be shared between threads, and changed
+/// while it is.
+///
+/// Clones refer to the same table.
+#[derive(Clone)]
+pub struct SharedMockUsers {
+    inner: Arc<RwLock<MockUsers>>,
+}
+
+impl SharedMockUsers {
+
+    /// Shares the given mock users table.
+    pub fn new(users: MockUsers) -> Self {
+        Self { inner: Arc::new(RwLock::new(users)) }
+    }
+
+    /// Create a new, empty shared mock users table.
+    pub fn with_current_uid(current_uid: uid_t) -> Self {
+        Self::new(MockUsers::with_current_uid(current_uid))
+    }
+
+    /// Locks the table for changes, such as adding users, which every clone
+    /// sees once the guard is dropped.
+    pub fn write<'a>(&'a self) -> RwLockWriteGuard<'a, MockUsers> {
+        self.inner.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Runs the given function on the table, locked for reading.
+    fn read<T, F: FnOnce(&MockUsers) -> T>(&self, f: F) -> T {
+        f(&self.inner.read().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl Users for SharedMockUsers {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        self.read(|u| u.get_user_by_uid(uid))
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        self.read(|u| u.get_user_by_name(username))
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        self.read(|u| u.get_current_uid())
+    }
+
+    fn get_current_username(&self) -> Option<Arc<OsStr>> {
+        self.read(|u| u.get_current_username())
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        self.read(|u| u.get_effective_uid())
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<OsStr>> {
+        self.read(|u| u.get_effective_username())
+    }
+}
+
+impl Groups for SharedMockUsers {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        self.read(|u| u.get_group_by_gid(gid))
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+        self.read(|u| u.get_group_by_name(group_name))
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        self.read(|u| u.get_current_gid())
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
+        self.read(|u| u.get_current_groupname())
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        self.read(|u| u.get_effective_gid())
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
+        self.read(|u| u.get_effective_groupname())
+    }
+}
+
+
+/// The form `MockUsers` takes when serialized.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Table {
+    current_uid: uid_t,
+    current_gid: gid_t,
+    effective_uid: uid_t,
+    effective_gid: gid_t,
+
+    #[serde(default)]
+    users: Vec<TableUser>,
+
+    #[serde(default)]
+    groups: Vec<TableGroup>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TableUser {
+    uid: uid_t,
+    name: String,
+    primary_group: gid_t,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    home_dir: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shell: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TableGroup {
+    gid: gid_t,
+    name: String,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    members: Vec<String>,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for MockUsers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let dummy = User::new(0, "", 0);
+        let unless_dummy = |path: &Path, dummy: &Path| {
+            if path == dummy { None } else { Some(path.to_string_lossy().into_owned()) }
+        };
+
+        let mut users = self.users.values().map(|u| TableUser {
+            uid: u.uid(),
+            name: u.name().to_string_lossy().into_owned(),
+            primary_group: u.primary_group_id(),
+            home_dir: unless_dummy(u.home_dir(), dummy.home_dir()),
+            shell: unless_dummy(u.shell(), dummy.shell()),
+        }).collect::<Vec<_>>();
+        users.sort_by_key(|u| u.uid);
+
+        let mut groups = self.groups.values().map(|g| TableGroup {
+            gid: g.gid(),
+            name: g.name().to_string_lossy().into_owned(),
+            members: g.members().iter().map(|m| m.to_string_lossy().into_owned()).collect(),
+        }).collect::<Vec<_>>();
+        groups.sort_by_key(|g| g.gid);
+
+        let table = Table {
+            current_uid: self.uid,
+            current_gid: self.gid,
+            effective_uid: self.euid,
+            effective_gid: self.egid,
+            users,
+            groups,
+        };
+
+        table.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for MockUsers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let table = Table::deserialize(deserializer)?;
+
+        let mut users = MockUsers::with_current_uid(table.current_uid);
+        users.gid = table.current_gid;
+        users.euid = table.effective_uid;
+        users.egid = table.effective_gid;
+
+        for user in table.users {
+            let mut entry = User::new(user.uid, &user.name, user.primary_group);
+            if let Some(home_dir) = user.home_dir {
+                entry = entry.with_home_dir(&home_dir);
+            }
+            if let Some(shell) = user.shell {
+                entry = entry.with_shell(&shell);
+            }
+
+            users.add_user(entry);
+        }
+
+        for group in table.groups {
+            let members = group.members.iter();
+            users.add_group(members.fold(Group::new(group.gid, &group.name), |g, m| g.add_member(m)));
+        }
+
+        Ok(users)
+    }
+}
+
+
+/// The argument a recorded lookup was given.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Argument {
+
+    /// The lookup takes no argument, such as `get_current_uid`.
+    None,
+
+    /// A user or group ID.
+    Id(u32),
+
+    /// A user or group name.
+    Name(OsString),
+}
+
+impl From<u32> for Argument {
+    fn from(id: u32) -> Self {
+        Argument::Id(id)
+    }
+}
+
+impl<'a> From<&'a str> for Argument {
+    fn from(name: &'a str) -> Self {
+        Argument::Name(name.into())
+    }
+}
+
+impl<'a> From<&'a OsStr> for Argument {
+    fn from(name: &'a OsStr) -> Self {
+        Argument::Name(name.to_owned())
+    }
+}
+
+/// The result of a recorded lookup.
+#[derive(Debug, Clone)]
+pub enum Answer {
+
+    /// The user found, if any.
+    User(Option<Arc<User>>),
+
+    /// The group found, if any.
+    Group(Option<Arc<Group>>),
+
+    /// A user or group ID.
+    Id(u32),
+
+    /// A user or group name, if any.
+    Name(Option<Arc<OsStr>>),
+}
+
+/// A lookup made through a `Recording`.
+#[derive(Debug, Clone)]
+pub struct Call {
+    function: &'static str,
+    argument: Argument,
+    answer: Answer,
+}
+
+impl Call {
+
+    /// Returns the name of the `Users` or `Groups` method called.
+    pub fn function(&self) -> &'static str {
+        self.function
+    }
+
+    /// Returns the argument the method was given.
+    pub fn argument(&self) -> &Argument {
+        &self.argument
+    }
+
+    /// Returns what the method returned.
+    pub fn answer(&self) -> &Answer {
+        &self.answer
+    }
+}
+
+
+/// A users and groups table that records the lookups made through it,
+/// before passing them on to another.
+pub struct Recording<U> {
+    inner: U,
+    calls: Mutex<Vec<Call>>,
+}
+
+impl<U> Recording<U> {
+
+    /// Wraps the given table, with no calls recorded yet.
+    pub fn new(inner: U) -> Self {
+        Self { inner, calls: Mutex::new(Vec::new()) }
+    }
+
+    /// Returns the wrapped table.
+    pub fn inner(&self) -> &U {
+        &self.inner
+    }
+
+    /// Returns the wrapped table, dropping the calls recorded.
+    pub fn into_inner(self) -> U {
+        self.inner
+    }
+
+    /// Returns the calls recorded, oldest first.
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Forgets the calls recorded so far.
+    pub fn clear_calls(&self) {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// Panics, listing the calls recorded, unless the method with the given
+    /// name was called with the given argument.
+    pub fn assert_called_with<A: Into<Argument>>(&self, function: &str, argument: A) {
+        let argument = argument.into();
+        let calls = self.calls();
+
+        if ! calls.iter().any(|c| c.function == function && c.argument == argument) {
+            panic!("{} was not called with {:?}; calls were: {:?}", function, argument, calls);
+        }
+    }
+
+    /// Records a call and passes its answer on.
+    fn record<T, F>(&self, function: &'static str, argument: Argument, result: T, answer: F) -> T
+    where F: FnOnce(&T) -> Answer
+    {
+        let call = Call { function, argument, answer: answer(&result) };
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).push(call);
+        result
+    }
+}
+
+impl<U: Users> Users for Recording<U> {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        self.record("get_user_by_uid", Argument::Id(uid), self.inner.get_user_by_uid(uid), |u| Answer::User(u.clone()))
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        let argument = Argument::from(username.as_ref());
+        self.record("get_user_by_name", argument, self.inner.get_user_by_name(username), |u| Answer::User(u.clone()))
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        self.record("get_current_uid", Argument::None, self.inner.get_current_uid(), |&id| Answer::Id(id))
+    }
+
+    fn get_current_username(&self) -> Option<Arc<OsStr>> {
+        self.record("get_current_username", Argument::None, self.inner.get_current_username(), |n| Answer::Name(n.clone()))
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        self.record("get_effective_uid", Argument::None, self.inner.get_effective_uid(), |&id| Answer::Id(id))
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<OsStr>> {
+        self.record("get_effective_username", Argument::None, self.inner.get_effective_username(), |n| Answer::Name(n.clone()))
+    }
+}
+
+impl<U: Groups> Groups for Recording<U> {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        self.record("get_group_by_gid", Argument::Id(gid), self.inner.get_group_by_gid(gid), |g| Answer::Group(g.clone()))
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+        let argument = Argument::from(group_name.as_ref());
+        self.record("get_group_by_name", argument, self.inner.get_group_by_name(group_name), |g| Answer::Group(g.clone()))
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        self.record("get_current_gid", Argument::None, self.inner.get_current_gid(), |&id| Answer::Id(id))
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
+        self.record("get_current_groupname", Argument::None, self.inner.get_current_groupname(), |n| Answer::Name(n.clone()))
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        self.record("get_effective_gid", Argument::None, self.inner.get_effective_gid(), |&id| Answer::Id(id))
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
+        self.record("get_effective_groupname", Argument::None, self.inner.get_effective_groupname(), |n| Answer::Name(n.clone()))
     }
 }
 
 
 #[cfg(test)]
 mod test {
-    use super::MockUsers;
-    use base::{User, Group};
-    use traits::{Users, Groups};
+    use super::{MockUsers, SharedMockUsers, Lookup, Recording, Argument, Answer};
+    use {User, Group};
+    use {Users, Groups};
+    use os::unix::GroupExt;
 
-    use std::ffi::OsStr;
+    use std::ffi::{OsStr, OsString};
+    use std::io;
     use std::sync::Arc;
 
     #[test]
@@ -178,7 +841,7 @@ mod test {
         let mut users = MockUsers::with_current_uid(0);
         users.add_user(User::new(1337, "fred", 101));
         assert_eq!(Some(Arc::from(OsStr::new("fred"))),
-                   users.get_user_by_uid(1337).map(|u| Arc::clone(&u.name_arc)))
+                   users.get_user_by_uid(1337).map(|u| Arc::<OsStr>::from(u.name())))
     }
 
     #[test]
@@ -201,7 +864,7 @@ mod test {
     fn no_uid() {
         let users = MockUsers::with_current_uid(0);
         assert_eq!(None,
-                   users.get_user_by_uid(1337).map(|u| Arc::clone(&u.name_arc)))
+                   users.get_user_by_uid(1337).map(|u| Arc::<OsStr>::from(u.name())))
     }
 
     #[test]
@@ -209,7 +872,7 @@ mod test {
         let mut users = MockUsers::with_current_uid(0);
         users.add_group(Group::new(1337, "fred"));
         assert_eq!(Some(Arc::from(OsStr::new("fred"))),
-                   users.get_group_by_gid(1337).map(|g| Arc::clone(&g.name_arc)))
+                   users.get_group_by_gid(1337).map(|g| Arc::<OsStr>::from(g.name())))
     }
 
     #[test]
@@ -232,6 +895,135 @@ mod test {
     fn no_gid() {
         let users = MockUsers::with_current_uid(0);
         assert_eq!(None,
-                   users.get_group_by_gid(1337).map(|g| Arc::clone(&g.name_arc)))
+                   users.get_group_by_gid(1337).map(|g| Arc::<OsStr>::from(g.name())))
+    }
+
+    #[test]
+    fn failing_lookup() {
+        let mut users = MockUsers::with_current_uid(1337);
+        users.add_user(User::new(1337, "fred", 101));
+        users.fail_lookup(Lookup::UserByName, io::Error::new(io::ErrorKind::TimedOut, "ldap"));
+
+        assert_eq!(None, users.get_user_by_name("fred").map(|u| u.uid()));
+        assert_eq!(Some(io::ErrorKind::TimedOut), users.take_error().map(|e| e.kind()));
+        assert!(users.take_error().is_none());
+
+        assert_eq!(Some(1337), users.get_user_by_uid(1337).map(|u| u.uid()));
+        assert!(users.take_error().is_none());
+
+        users.clear_failures();
+        assert_eq!(Some(1337), users.get_user_by_name("fred").map(|u| u.uid()));
+    }
+
+    #[test]
+    fn gid_separate_from_uid() {
+        let mut users = MockUsers::with_current_uid(1337);
+        users.add_group(Group::new(100, "users"));
+        users.set_current_gid(100);
+        assert_eq!(1337, users.get_current_uid());
+        assert_eq!(100, users.get_current_gid());
+        assert_eq!(Some(Arc::from(OsStr::new("users"))),
+                   users.get_current_groupname())
+    }
+
+    #[test]
+    fn effective_uid() {
+        let mut users = MockUsers::with_current_uid(1337);
+        users.add_user(User::new(0, "root", 0));
+        users.set_effective_uid(0);
+        assert_eq!(1337, users.get_current_uid());
+        assert_eq!(Some(Arc::from(OsStr::new("root"))),
+                   users.get_effective_username())
+    }
+
+    #[test]
+    fn recording() {
+        let mut users = MockUsers::with_current_uid(1337);
+        users.add_user(User::new(1337, "fred", 101));
+
+        let users = Recording::new(users);
+        users.get_current_uid();
+        users.get_user_by_uid(1337);
+        users.get_user_by_name("santa");
+
+        let calls = users.calls();
+        assert_eq!(3, calls.len());
+        assert_eq!("get_current_uid", calls[0].function());
+        assert_eq!(&Argument::None, calls[0].argument());
+        match *calls[1].answer() {
+            Answer::User(Some(ref user)) => assert_eq!(1337, user.uid()),
+            ref answer                   => panic!("unexpected answer {:?}", answer),
+        }
+
+        users.assert_called_with("get_user_by_name", "santa");
+
+        users.clear_calls();
+        assert!(users.calls().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "get_group_by_gid was not called")]
+    fn recording_not_called() {
+        let users = Recording::new(MockUsers::with_current_uid(1337));
+        users.get_group_by_gid(100);
+        users.assert_called_with("get_group_by_gid", 101);
+    }
+
+    #[test]
+    fn passwd_and_group() {
+        let mut users = MockUsers::from_passwd_str(1000, "root:x:0:0:root:/root:/bin/sh\n# comment\nalice:x:1000:100::/home/alice:/bin/sh\n");
+        users.add_group_str("root:x:0:\nusers:x:100:alice\n");
+
+        assert_eq!(Some(0), users.get_user_by_name("root").map(|u| u.uid()));
+        assert_eq!(Some(Arc::from(OsStr::new("alice"))), users.get_current_username());
+        assert_eq!(Some(Arc::from(OsStr::new("users"))), users.get_current_groupname());
+        assert_eq!(users.get_group_by_gid(100).unwrap().members(), &[ OsString::from("alice") ]);
+    }
+
+    #[test]
+    fn members() {
+        let mut users = MockUsers::with_current_uid(1000);
+        users.add_group(Group::new(10, "wheel").add_member("root"));
+        users.add_member(10, "alice");
+        users.add_member(11, "alice");
+
+        assert_eq!(users.get_group_by_gid(10).unwrap().members(), &[ OsString::from("root"), OsString::from("alice") ]);
+        assert!(users.get_group_by_gid(11).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        use serde_json;
+        use os::unix::UserExt;
+        use std::path::Path;
+
+        let mut users = MockUsers::with_current_uid(1337);
+        users.add_user(User::new(1337, "fred", 101).with_shell("/bin/fish"));
+        users.add_group(Group::new(101, "staff").add_member("wilma"));
+        users.set_current_gid(101);
+        users.set_effective_uid(0);
+
+        let json = serde_json::to_string(&users).unwrap();
+        let users: MockUsers = serde_json::from_str(&json).unwrap();
+        assert_eq!(1337, users.get_current_uid());
+        assert_eq!(0, users.get_effective_uid());
+        assert_eq!(Some(Arc::from(OsStr::new("staff"))), users.get_current_groupname());
+        assert_eq!(Some(101), users.get_user_by_name("fred").map(|u| u.primary_group_id()));
+        assert_eq!(users.get_group_by_gid(101).unwrap().members(), &[ OsString::from("wilma") ]);
+        assert_eq!(users.get_user_by_uid(1337).unwrap().shell(), Path::new("/bin/fish"));
+        assert_eq!(users.get_user_by_uid(1337).unwrap().home_dir(), User::new(0, "", 0).home_dir());
+    }
+
+    #[test]
+    fn shared() {
+        fn send_sync<T: Send + Sync>(_: &T) {}
+
+        let users = SharedMockUsers::with_current_uid(1337);
+        let clone = users.clone();
+        send_sync(&clone);
+
+        users.write().add_user(User::new(1337, "fred", 101));
+        assert_eq!(Some(Arc::from(OsStr::new("fred"))), clone.get_current_username());
     }
 }
@@ -0,0 +1,205 @@
+//! Converting between this crate’s types and those of the `nix` crate.
+//!
+//! Programs that make their system calls through `nix` and look users up
+//! through this crate end up with two of everything. `Uid` and `Gid`
+//! convert to and from `nix::unistd::Uid` and `nix::unistd::Gid` with
+//! `From`. The real crate’s `User` and `Group`, like `nix`’s, can’t be given
+//! impls from here, so users and groups are converted through `NixUserExt`
+//! and `NixGroupExt` instead:
+//!
+//! ```
+//! extern crate nix;
+//! # extern crate users;
+//! use users::{User, Uid};
+//! use users::nix_interop::NixUserExt;
+//!
+//! # fn main() {
+//! let user = User::new(1000, "alice", 100);
+//! let theirs = user.to_nix().unwrap();
+//! assert_eq!(theirs.uid, nix::unistd::Uid::from_raw(1000));
+//! assert_eq!(Uid::from(theirs.uid), Uid(1000));
+//!
+//! let ours = User::from_nix(&theirs);
+//! assert_eq!(ours.name(), "alice");
+//! # }
+//! ```
+//!
+//! `nix` wants names as UTF-8, and the other strings without null bytes,
+//! so converting to its types can fail. The fields this crate doesn’t keep,
+//! such as the login class and the password times on the BSDs, are left
+//! empty or zero, and a group’s password is always empty.
+
+use std::error;
+use std::ffi::{CString, OsStr};
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+
+use nix::unistd;
+
+use {User, Group, UserBuilder, Uid, Gid};
+use os::unix::{UserExt, GroupExt};
+
+
+impl From<Uid> for unistd::Uid {
+    fn from(uid: Uid) -> Self {
+        unistd::Uid::from_raw(uid.0)
+    }
+}
+
+impl From<unistd::Uid> for Uid {
+    fn from(uid: unistd::Uid) -> Self {
+        Uid(uid.as_raw())
+    }
+}
+
+impl From<Gid> for unistd::Gid {
+    fn from(gid: Gid) -> Self {
+        unistd::Gid::from_raw(gid.0)
+    }
+}
+
+impl From<unistd::Gid> for Gid {
+    fn from(gid: unistd::Gid) -> Self {
+        Gid(gid.as_raw())
+    }
+}
+
+
+/// A user or group that can’t be converted to `nix`’s type, because one of
+/// its strings can’t be represented there.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ConversionError {
+    what: &'static str,
+    problem: &'static str,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.what, self.problem)
+    }
+}
+
+impl error::Error for ConversionError {}
+
+fn utf8(what: &'static str, s: &OsStr) -> Result<String, ConversionError> {
+    s.to_str().map(str::to_owned).ok_or(ConversionError { what, problem: "is not valid UTF-8" })
+}
+
+fn c_string(what: &'static str, s: &OsStr) -> Result<CString, ConversionError> {
+    CString::new(s.as_bytes()).map_err(|_| ConversionError { what, problem: "contains a null byte" })
+}
+
+
+/// Converting `User`s to and from `nix::unistd::User`.
+pub trait NixUserExt: Sized {
+
+    /// Converts a user as `nix` looked it up.
+    fn from_nix(user: &unistd::User) -> Self;
+
+    /// Converts this user for `nix`, failing if its name isn’t valid UTF-8,
+    /// or its password or GECOS field has a null byte in it.
+    fn to_nix(&self) -> Result<unistd::User, ConversionError>;
+}
+
+impl NixUserExt for User {
+    fn from_nix(user: &unistd::User) -> Self {
+        let builder = UserBuilder::new(user.uid.as_raw(), &user.name, user.gid.as_raw())
+            .home_dir(&user.dir)
+            .shell(&user.shell)
+            .password(OsStr::from_bytes(user.passwd.as_bytes()));
+
+        #[cfg(not(all(target_os = "android", target_pointer_width = "32")))]
+        let builder = builder.gecos(OsStr::from_bytes(user.gecos.as_bytes()));
+
+        builder.build()
+    }
+
+    fn to_nix(&self) -> Result<unistd::User, ConversionError> {
+        Ok(unistd::User {
+            name: utf8("user name", self.name())?,
+            passwd: c_string("password", self.password())?,
+            uid: unistd::Uid::from_raw(self.uid()),
+            gid: unistd::Gid::from_raw(self.primary_group_id()),
+
+            #[cfg(not(all(target_os = "android", target_pointer_width = "32")))]
+            gecos: c_string("GECOS field", &::gecos::field(self).unwrap_or_default())?,
+
+            dir: self.home_dir().to_owned(),
+            shell: self.shell().to_owned(),
+
+            #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "illumos", target_os = "solaris", target_os = "aix", target_os = "fuchsia", target_os = "haiku", target_os = "hurd")))]
+            class: CString::default(),
+
+            #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "illumos", target_os = "solaris", target_os = "aix", target_os = "fuchsia", target_os = "haiku", target_os = "hurd")))]
+            change: 0,
+
+            #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "illumos", target_os = "solaris", target_os = "aix", target_os = "fuchsia", target_os = "haiku", target_os = "hurd")))]
+            expire: 0,
+        })
+    }
+}
+
+/// Converting `Group`s to and from `nix::unistd::Group`.
+pub trait NixGroupExt: Sized {
+
+    /// Converts a group as `nix` looked it up.
+    fn from_nix(group: &unistd::Group) -> Self;
+
+    /// Converts this group for `nix`, failing if its name, or the name of
+    /// one of its members, isn’t valid UTF-8.
+    fn to_nix(&self) -> Result<unistd::Group, ConversionError>;
+}
+
+impl NixGroupExt for Group {
+    fn from_nix(group: &unistd::Group) -> Self {
+        group.mem.iter().fold(Group::new(group.gid.as_raw(), &group.name), |group, member| group.add_member(member))
+    }
+
+    fn to_nix(&self) -> Result<unistd::Group, ConversionError> {
+        Ok(unistd::Group {
+            name: utf8("group name", self.name())?,
+            passwd: CString::default(),
+            gid: unistd::Gid::from_raw(self.gid()),
+            mem: self.members().iter().map(|m| utf8("member name", m)).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use UserBuilderExt;
+
+    #[test]
+    fn ids() {
+        assert_eq!(unistd::Uid::from(Uid(1000)), unistd::Uid::from_raw(1000));
+        assert_eq!(Gid::from(unistd::Gid::from_raw(100)), Gid(100));
+    }
+
+    #[test]
+    fn users() {
+        let user = User::builder(1000, "alice", 100).home_dir("/home/alice").shell("/bin/zsh").build();
+        let theirs = user.to_nix().unwrap();
+        assert_eq!(theirs.name, "alice");
+        assert_eq!(theirs.dir, user.home_dir());
+
+        let ours = User::from_nix(&theirs);
+        assert_eq!((ours.uid(), ours.primary_group_id()), (1000, 100));
+        assert_eq!(ours.shell(), user.shell());
+    }
+
+    #[test]
+    fn groups() {
+        let group = Group::new(10, "wheel").add_member("alice");
+        let theirs = group.to_nix().unwrap();
+        assert_eq!(theirs.mem, vec![ "alice".to_owned() ]);
+        assert_eq!(Group::from_nix(&theirs).members(), group.members());
+    }
+
+    #[test]
+    fn unrepresentable() {
+        let user = User::new(1000, OsStr::from_bytes(b"\xffalice"), 100);
+        assert_eq!(user.to_nix().unwrap_err().to_string(), "user name is not valid UTF-8");
+    }
+}
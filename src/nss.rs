@@ -0,0 +1,354 @@
+//! Looking users and groups up by loading NSS modules directly.
+//!
+//! The C library finds users through the modules `/etc/nsswitch.conf`
+//! lists, such as `libnss_sss` or `libnss_ldap`. A program that bypasses
+//! it, or is linked against a C library without NSS, such as musl, only
+//! sees the users in `/etc/passwd`. An `NssBackend` loads the same modules
+//! itself, with `dlopen`, and calls their `_nss_*_getpwnam_r` and similar
+//! entry points, so the users they provide can be found anyway:
+//!
+//! ```no_run
+//! use users::Users;
+//! use users::nss::NssBackend;
+//!
+//! let nss = NssBackend::system().unwrap();
+//! println!("Using the {:?} modules", nss.passwd_services());
+//! if let Some(user) = nss.get_user_by_name("alice") {
+//!     println!("alice is UID {}", user.uid());
+//! }
+//! ```
+//!
+//! Modules that can’t be loaded, or lack an entry point, are skipped, and
+//! the rest are asked in the order they are listed until one finds the user
+//! or group. The actions in square brackets in `nsswitch.conf` are ignored.
+//!
+//! This is only available with the `nss-modules` feature, and on the Unix
+//! platforms the real crate is used on. Loading modules from a statically
+//! linked binary only works if its C library supports `dlopen`.
+
+use std::ffi::{CString, OsStr};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use libc::{self, size_t};
+
+use passthrough::{passwd_to_user, group_to_group};
+use {User, Group, Users, Groups, uid_t, gid_t};
+
+
+/// The configuration file listing the modules to use.
+pub const NSSWITCH_CONF: &str = "/etc/nsswitch.conf";
+
+/// The largest buffer a module is given for the strings of an entry.
+const MAX_BUFFER: usize = 1024 * 1024;
+
+// The values of `enum nss_status`.
+const NSS_STATUS_TRYAGAIN: c_int = -2;
+const NSS_STATUS_UNAVAIL: c_int = -1;
+const NSS_STATUS_SUCCESS: c_int = 1;
+
+type GetpwnamR = unsafe extern "C" fn(*const c_char, *mut libc::passwd, *mut c_char, size_t, *mut c_int) -> c_int;
+type GetpwuidR = unsafe extern "C" fn(uid_t, *mut libc::passwd, *mut c_char, size_t, *mut c_int) -> c_int;
+type GetgrnamR = unsafe extern "C" fn(*const c_char, *mut libc::group, *mut c_char, size_t, *mut c_int) -> c_int;
+type GetgrgidR = unsafe extern "C" fn(gid_t, *mut libc::group, *mut c_char, size_t, *mut c_int) -> c_int;
+
+
+/// A loaded `libnss_*` module, and the entry points it has.
+struct Module {
+    service: String,
+    handle: *mut c_void,
+    getpwnam_r: Option<GetpwnamR>,
+    getpwuid_r: Option<GetpwuidR>,
+    getgrnam_r: Option<GetgrnamR>,
+    getgrgid_r: Option<GetgrgidR>,
+}
+
+// The entry points are the reentrant ones, and the handle is only used to
+// unload the module once nothing refers to it.
+unsafe impl Send for Module {}
+unsafe impl Sync for Module {}
+
+impl Module {
+
+    /// Loads the module for the given service, such as `sss` for
+    /// `libnss_sss.so.2`.
+    fn load(service: &str) -> Option<Self> {
+        let library = CString::new(format!("libnss_{}.so.2", service)).ok()?;
+        let handle = unsafe { libc::dlopen(library.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return None;
+        }
+
+        unsafe {
+            Some(Self {
+                service: service.to_owned(),
+                handle,
+                getpwnam_r: symbol(handle, service, "getpwnam_r").map(|f| mem::transmute::<*mut c_void, GetpwnamR>(f)),
+                getpwuid_r: symbol(handle, service, "getpwuid_r").map(|f| mem::transmute::<*mut c_void, GetpwuidR>(f)),
+                getgrnam_r: symbol(handle, service, "getgrnam_r").map(|f| mem::transmute::<*mut c_void, GetgrnamR>(f)),
+                getgrgid_r: symbol(handle, service, "getgrgid_r").map(|f| mem::transmute::<*mut c_void, GetgrgidR>(f)),
+            })
+        }
+    }
+}
+
+impl Drop for Module {
+    fn drop(&mut self) {
+        unsafe { libc::dlclose(self.handle) };
+    }
+}
+
+/// Looks up the `_nss_<service>_<function>` entry point in a module.
+unsafe fn symbol(handle: *mut c_void, service: &str, function: &str) -> Option<*mut c_void> {
+    let name = CString::new(format!("_nss_{}_{}", service, function)).ok()?;
+    let f = libc::dlsym(handle, name.as_ptr());
+    if f.is_null() { None } else { Some(f) }
+}
+
+/// Calls an entry point with larger and larger buffers until the entry
+/// fits, converting it if the module found one. The entry’s strings point
+/// into the buffer, so it can’t be returned as it is.
+fn call<E, T, F>(mut f: F, convert: unsafe fn(&E) -> T) -> Option<T>
+where F: FnMut(*mut E, *mut c_char, size_t, *mut c_int) -> c_int
+{
+    let mut buf = vec![0 as c_char; 1024];
+
+    loop {
+        let mut entry: E = unsafe { mem::zeroed() };
+        let mut errno = 0;
+
+        let status = f(&mut entry, buf.as_mut_ptr(), buf.len(), &mut errno);
+        if status == NSS_STATUS_TRYAGAIN && errno == libc::ERANGE && buf.len() < MAX_BUFFER {
+            let len = buf.len() * 2;
+            buf.resize(len, 0);
+            continue;
+        }
+
+        return if status == NSS_STATUS_SUCCESS { Some(unsafe { convert(&entry) }) } else { None };
+    }
+}
+
+
+/// Users and groups from NSS modules, loaded without the C library’s help.
+#[derive(Clone)]
+pub struct NssBackend {
+    passwd: Vec<Arc<Module>>,
+    group: Vec<Arc<Module>>,
+}
+
+impl NssBackend {
+
+    /// Loads the modules the system’s `/etc/nsswitch.conf` lists for the
+    /// `passwd` and `group` databases.
+    pub fn system() -> io::Result<Self> {
+        Self::from_config(NSSWITCH_CONF)
+    }
+
+    /// Loads the modules the given `nsswitch.conf` file lists for the
+    /// `passwd` and `group` databases.
+    pub fn from_config<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut loaded = Vec::new();
+
+        let mut modules = |database| {
+            services(&contents, database).into_iter().filter_map(|service| {
+                find_or_load(&mut loaded, service)
+            }).collect()
+        };
+
+        let passwd = modules("passwd");
+        let group = modules("group");
+        Ok(Self { passwd, group })
+    }
+
+    /// Loads the modules for the given services, such as `sss` or `ldap`,
+    /// to be used for both users and groups.
+    pub fn with_services<I>(services: I) -> Self
+    where I: IntoIterator,
+          I::Item: AsRef<str>,
+    {
+        let modules: Vec<_> = services.into_iter()
+            .filter_map(|service| Module::load(service.as_ref()).map(Arc::new))
+            .collect();
+
+        Self { passwd: modules.clone(), group: modules }
+    }
+
+    /// Returns the services whose modules are asked about users, in order.
+    pub fn passwd_services(&self) -> Vec<&str> {
+        self.passwd.iter().map(|m| &*m.service).collect()
+    }
+
+    /// Returns the services whose modules are asked about groups, in order.
+    pub fn group_services(&self) -> Vec<&str> {
+        self.group.iter().map(|m| &*m.service).collect()
+    }
+
+    /// Asks each module for a user, with the entry point `f` picks, until
+    /// one finds it.
+    fn find_user<F>(&self, f: F) -> Option<Arc<User>>
+    where F: Fn(&Module, *mut libc::passwd, *mut c_char, size_t, *mut c_int) -> Option<c_int>
+    {
+        self.passwd.iter().find_map(|module| {
+            call(|entry, buf, len, errno| f(module, entry, buf, len, errno).unwrap_or(NSS_STATUS_UNAVAIL), passwd_to_user)
+        }).map(Arc::new)
+    }
+
+    /// Asks each module for a group, with the entry point `f` picks, until
+    /// one finds it.
+    fn find_group<F>(&self, f: F) -> Option<Arc<Group>>
+    where F: Fn(&Module, *mut libc::group, *mut c_char, size_t, *mut c_int) -> Option<c_int>
+    {
+        self.group.iter().find_map(|module| {
+            call(|entry, buf, len, errno| f(module, entry, buf, len, errno).unwrap_or(NSS_STATUS_UNAVAIL), group_to_group)
+        }).map(Arc::new)
+    }
+}
+
+/// Returns the already loaded module for the given service, or loads it.
+fn find_or_load(loaded: &mut Vec<Arc<Module>>, service: &str) -> Option<Arc<Module>> {
+    if let Some(module) = loaded.iter().find(|m| m.service == service) {
+        return Some(Arc::clone(module));
+    }
+
+    let module = Arc::new(Module::load(service)?);
+    loaded.push(Arc::clone(&module));
+    Some(module)
+}
+
+/// Returns the services an `nsswitch.conf` file lists for a database, in
+/// order, leaving out any actions in square brackets.
+fn services<'a>(contents: &'a str, database: &str) -> Vec<&'a str> {
+    let line = contents.lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .filter_map(|line| line.split_once(':'))
+        .find(|&(name, _)| name.trim() == database);
+
+    let mut services = Vec::new();
+    let mut in_action = false;
+
+    if let Some((_, list)) = line {
+        for word in list.split_whitespace() {
+            if word.starts_with('[') {
+                in_action = true;
+            }
+            if ! in_action {
+                services.push(word);
+            }
+            if word.ends_with(']') {
+                in_action = false;
+            }
+        }
+    }
+
+    services
+}
+
+impl fmt::Debug for NssBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NssBackend")
+            .field("passwd", &self.passwd_services())
+            .field("group", &self.group_services())
+            .finish()
+    }
+}
+
+
+impl Users for NssBackend {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        self.find_user(|module, entry, buf, len, errno| {
+            let f = module.getpwuid_r?;
+            Some(unsafe { f(uid, entry, buf, len, errno) })
+        })
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        let username = CString::new(username.as_ref().as_bytes()).ok()?;
+        self.find_user(|module, entry, buf, len, errno| {
+            let f = module.getpwnam_r?;
+            Some(unsafe { f(username.as_ptr(), entry, buf, len, errno) })
+        })
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        unsafe { libc::getuid() }
+    }
+
+    fn get_current_username(&self) -> Option<Arc<OsStr>> {
+        self.get_user_by_uid(self.get_current_uid()).map(|u| Arc::from(u.name()))
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        unsafe { libc::geteuid() }
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<OsStr>> {
+        self.get_user_by_uid(self.get_effective_uid()).map(|u| Arc::from(u.name()))
+    }
+}
+
+impl Groups for NssBackend {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        self.find_group(|module, entry, buf, len, errno| {
+            let f = module.getgrgid_r?;
+            Some(unsafe { f(gid, entry, buf, len, errno) })
+        })
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+        let group_name = CString::new(group_name.as_ref().as_bytes()).ok()?;
+        self.find_group(|module, entry, buf, len, errno| {
+            let f = module.getgrnam_r?;
+            Some(unsafe { f(group_name.as_ptr(), entry, buf, len, errno) })
+        })
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        unsafe { libc::getgid() }
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
+        self.get_group_by_gid(self.get_current_gid()).map(|g| Arc::from(g.name()))
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        unsafe { libc::getegid() }
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
+        self.get_group_by_gid(self.get_effective_gid()).map(|g| Arc::from(g.name()))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nsswitch() {
+        let conf = "# comment\npasswd:  files systemd [NOTFOUND=return] sss # trailing\ngroup: files [SUCCESS=merge] ldap\n";
+        assert_eq!(services(conf, "passwd"), vec![ "files", "systemd", "sss" ]);
+        assert_eq!(services(conf, "group"), vec![ "files", "ldap" ]);
+        assert!(services(conf, "shadow").is_empty());
+    }
+
+    #[test]
+    fn files_module() {
+        let nss = NssBackend::with_services(&[ "files", "no-such-service" ]);
+        if nss.passwd_services().is_empty() {
+            return;  // no glibc to load it from
+        }
+
+        assert_eq!(nss.passwd_services(), vec![ "files" ]);
+        assert_eq!(nss.get_user_by_uid(0).map(|u| u.name().to_owned()), Some("root".into()));
+        assert_eq!(nss.get_group_by_name("root").map(|g| g.gid()), Some(0));
+        assert!(nss.get_user_by_name("no such user").is_none());
+    }
+}
@@ -0,0 +1,113 @@
+//! Extensions to users for macOS.
+//!
+//! On macOS, accounts are records in Open Directory, which identifies them
+//! by a UUID, the GeneratedUID, rather than by their user ID. These are
+//! looked up through the membership API each time they are asked for, so
+//! the same `User` type can carry them with either the real crate or the
+//! stub.
+//!
+//! Everywhere else, there is no directory to ask, and every lookup comes
+//! back empty.
+
+use std::ffi::OsString;
+
+use {User, uid_t};
+
+
+/// macOS-specific fields for `User`s.
+#[derive(PartialEq, Eq, Clone, Default, Debug)]
+pub struct UserExtras {
+
+    /// The UUID of the user’s directory record, in its usual
+    /// `XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX` form.
+    pub generated_uid: Option<String>,
+
+    /// The user’s real name, such as “Alice Appleseed”.
+    pub real_name: Option<OsString>,
+}
+
+impl UserExtras {
+
+    /// Looks up the directory record of the user with the given ID.
+    ///
+    /// The membership API makes up a UUID for IDs that have no record, so
+    /// only the real name tells whether the user exists.
+    pub fn lookup(uid: uid_t) -> Self {
+        Self {
+            generated_uid: imp::generated_uid(uid),
+            real_name:     imp::real_name(uid),
+        }
+    }
+}
+
+/// macOS-specific extensions for `User`s.
+pub trait UserExt {
+
+    /// Looks up this user’s directory record.
+    fn macos_extras(&self) -> UserExtras;
+}
+
+impl UserExt for User {
+    fn macos_extras(&self) -> UserExtras {
+        UserExtras::lookup(self.uid())
+    }
+}
+
+
+#[cfg(all(target_os = "macos", users_passthrough))]
+mod imp {
+    use std::ffi::OsString;
+    use std::os::raw::c_int;
+
+    use gecos::Gecos;
+    use passthrough::get_gecos;
+    use uid_t;
+
+    extern "C" {
+        fn mbr_uid_to_uuid(uid: uid_t, uu: *mut u8) -> c_int;
+    }
+
+    pub fn generated_uid(uid: uid_t) -> Option<String> {
+        let mut uu = [0_u8; 16];
+        if unsafe { mbr_uid_to_uuid(uid, uu.as_mut_ptr()) } != 0 {
+            return None;
+        }
+
+        let hex = uu.iter().map(|b| format!("{:02X}", b)).collect::<String>();
+        Some(format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]))
+    }
+
+    /// Reads the real name from the user’s GECOS field.
+    pub fn real_name(uid: uid_t) -> Option<OsString> {
+        let (_, gecos) = get_gecos(uid)?;
+        Gecos::parse(&gecos.to_string_lossy()).full_name.map(OsString::from)
+    }
+}
+
+#[cfg(not(all(target_os = "macos", users_passthrough)))]
+mod imp {
+    use std::ffi::OsString;
+
+    use uid_t;
+
+    pub fn generated_uid(_uid: uid_t) -> Option<String> {
+        None
+    }
+
+    pub fn real_name(_uid: uid_t) -> Option<OsString> {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn current_user() {
+        let extras = UserExtras::lookup(::get_current_uid());
+        let macos = cfg!(all(target_os = "macos", users_passthrough));
+        assert_eq!(extras.generated_uid.is_some(), macos);
+    }
+}
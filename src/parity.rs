@@ -0,0 +1,111 @@
+//! Checks that the `Users` and `Groups` traits have the same methods, with
+//! the same signatures, whichever crate they come from.
+//!
+//! With the real crate in use, the impls here are checked against its
+//! traits; with the stub in use, against this crate’s. Generic code that
+//! compiles on one platform then compiles on all of them, as long as the
+//! tests pass both with and without the `force-stub` feature.
+
+use std::ffi::{OsStr, OsString};
+use std::sync::Arc;
+
+use {User, Group, Users, Groups, uid_t, gid_t};
+
+
+/// Implements every method, and nothing else.
+struct Everything;
+
+impl Users for Everything {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        Some(Arc::new(User::new(uid, "alice", 100)))
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        Some(Arc::new(User::new(1000, username.as_ref(), 100)))
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        1000
+    }
+
+    fn get_current_username(&self) -> Option<Arc<OsStr>> {
+        Some(Arc::from(OsStr::new("alice")))
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        0
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<OsStr>> {
+        Some(Arc::from(OsStr::new("root")))
+    }
+}
+
+impl Groups for Everything {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        Some(Arc::new(Group::new(gid, "users")))
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+        Some(Arc::new(Group::new(100, group_name.as_ref())))
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        100
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
+        Some(Arc::from(OsStr::new("users")))
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        0
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
+        Some(Arc::from(OsStr::new("root")))
+    }
+}
+
+
+/// Calls every method the way generic downstream code would, with every
+/// kind of name argument.
+fn call_all<U: Users + Groups>(users: &U) -> Vec<Option<OsString>> {
+    let name = |n: Option<Arc<OsStr>>| n.map(|n| n.to_os_string());
+
+    vec![
+        users.get_user_by_uid(users.get_current_uid()).map(|u| u.name().to_owned()),
+        users.get_user_by_name("bob").map(|u| u.name().to_owned()),
+        users.get_user_by_name(&String::from("bob")).map(|u| u.name().to_owned()),
+        users.get_user_by_name(OsStr::new("bob")).map(|u| u.name().to_owned()),
+        name(users.get_current_username()),
+        users.get_user_by_uid(users.get_effective_uid()).map(|u| u.name().to_owned()),
+        name(users.get_effective_username()),
+        users.get_group_by_gid(users.get_current_gid()).map(|g| g.name().to_owned()),
+        users.get_group_by_name("staff").map(|g| g.name().to_owned()),
+        users.get_group_by_name(&OsString::from("staff")).map(|g| g.name().to_owned()),
+        name(users.get_current_groupname()),
+        users.get_group_by_gid(users.get_effective_gid()).map(|g| g.name().to_owned()),
+        name(users.get_effective_groupname()),
+    ]
+}
+
+
+#[test]
+fn methods() {
+    let names = call_all(&Everything);
+    let expected = [ "alice", "bob", "bob", "bob", "alice", "alice", "root",
+                     "users", "staff", "staff", "users", "users", "root" ];
+
+    assert_eq!(names, expected.iter().map(|n| Some(OsString::from(n))).collect::<Vec<_>>());
+}
+
+#[test]
+#[cfg(feature = "cache")]
+fn implementors() {
+    fn users_and_groups<U: Users + Groups>() {}
+
+    users_and_groups::<::UsersCache>();
+    users_and_groups::<::SharedUsersCache>();
+    users_and_groups::<::stub::Stub>();
+}
@@ -0,0 +1,370 @@
+//! The functions the real `users` crate provides, put in front of a backend
+//! set with `set_backend`.
+//!
+//! Everything else is re-exported from the real crate as it is, except for
+//! `all_groups` and `try_all_users`, which it lacks.
+
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::io;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::ffi::OsStrExt;
+
+use libc;
+
+use backend::with_registered;
+use current;
+use error::{check_name, Error};
+use listing;
+use reentrant::with_entry;
+use os::unix::{UserExt, GroupExt};
+use super::{User, Group, uid_t, gid_t};
+
+
+/// The real crate’s functions, with the Android app user names on Android.
+mod native {
+    pub use users_orig::*;
+
+    #[cfg(target_os = "android")]
+    pub use android::{get_current_username, get_effective_username};
+}
+
+
+/// Searches for a `User` with the given ID in the system’s user database,
+/// or the backend set with `set_backend`.
+pub fn get_user_by_uid(uid: uid_t) -> Option<User> {
+    try_get_user_by_uid(uid).ok()
+}
+
+/// Searches for a `User` with the given username in the system’s user
+/// database, or the backend set with `set_backend`.
+pub fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(username: &S) -> Option<User> {
+    try_get_user_by_name(username).ok()
+}
+
+/// Searches for a `Group` with the given ID in the system’s group database,
+/// or the backend set with `set_backend`.
+pub fn get_group_by_gid(gid: gid_t) -> Option<Group> {
+    with_registered(|b| b.group_by_gid(gid))
+        .unwrap_or_else(|| native::get_group_by_gid(gid))
+}
+
+/// Searches for a `Group` with the given group name in the system’s group
+/// database, or the backend set with `set_backend`.
+pub fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(groupname: &S) -> Option<Group> {
+    with_registered(|b| b.group_by_name(groupname.as_ref()))
+        .unwrap_or_else(|| native::get_group_by_name(groupname))
+}
+
+/// Searches for a `User` with the given ID in the system’s user database,
+/// or the backend set with `set_backend`, telling a missing user apart from
+/// one that couldn’t be looked up.
+pub fn try_get_user_by_uid(uid: uid_t) -> Result<User, Error> {
+    if let Some(user) = with_registered(|b| b.user_by_uid(uid)) {
+        return user.ok_or(Error::NotFound);
+    }
+
+    let user = unsafe { with_entry(|pwd, buf, len, result| libc::getpwuid_r(uid, pwd, buf, len, result), |entry| passwd_to_user(entry)) };
+
+    #[cfg(target_os = "android")]
+    let user = user.map(|user| user.or_else(|| ::android::synthetic_user(uid)));
+
+    Error::from_lookup(user)
+}
+
+/// Searches for a `User` with the given username in the system’s user
+/// database, or the backend set with `set_backend`, telling a missing user
+/// apart from one that couldn’t be looked up.
+pub fn try_get_user_by_name<S: AsRef<OsStr> + ?Sized>(username: &S) -> Result<User, Error> {
+    let username = username.as_ref();
+    check_name(username)?;
+
+    if let Some(user) = with_registered(|b| b.user_by_name(username)) {
+        return user.ok_or(Error::NotFound);
+    }
+
+    let username = CString::new(username.as_bytes()).map_err(|_| Error::InvalidName)?;
+    Error::from_lookup(unsafe { with_entry(|pwd, buf, len, result| libc::getpwnam_r(username.as_ptr(), pwd, buf, len, result), |entry| passwd_to_user(entry)) })
+}
+
+/// Searches for a `Group` with the given ID in the system’s group database,
+/// or the backend set with `set_backend`, telling a missing group apart
+/// from one that couldn’t be looked up.
+pub fn try_get_group_by_gid(gid: gid_t) -> Result<Group, Error> {
+    if let Some(group) = with_registered(|b| b.group_by_gid(gid)) {
+        return group.ok_or(Error::NotFound);
+    }
+
+    Error::from_lookup(unsafe { with_entry(|grp, buf, len, result| libc::getgrgid_r(gid, grp, buf, len, result), |entry| group_to_group(entry)) })
+}
+
+/// Searches for a `Group` with the given group name in the system’s group
+/// database, or the backend set with `set_backend`, telling a missing group
+/// apart from one that couldn’t be looked up.
+pub fn try_get_group_by_name<S: AsRef<OsStr> + ?Sized>(groupname: &S) -> Result<Group, Error> {
+    let groupname = groupname.as_ref();
+    check_name(groupname)?;
+
+    if let Some(group) = with_registered(|b| b.group_by_name(groupname)) {
+        return group.ok_or(Error::NotFound);
+    }
+
+    let groupname = CString::new(groupname.as_bytes()).map_err(|_| Error::InvalidName)?;
+    Error::from_lookup(unsafe { with_entry(|grp, buf, len, result| libc::getgrnam_r(groupname.as_ptr(), grp, buf, len, result), |entry| group_to_group(entry)) })
+}
+
+/// Returns the user ID for the user running the process, as looked up the
+/// first time (see `refresh_current_identity`), or the one the backend set
+/// with `set_backend` reports.
+pub fn get_current_uid() -> uid_t {
+    with_registered(|b| b.current_uid())
+        .unwrap_or_else(|| current::current_uid(native::get_current_uid))
+}
+
+/// Returns the username of the user running the process, as looked up the
+/// first time (see `refresh_current_identity`), or the one the backend set
+/// with `set_backend` reports.
+pub fn get_current_username() -> Option<OsString> {
+    with_registered(|b| b.current_username())
+        .unwrap_or_else(|| current::current_username(native::get_current_username))
+}
+
+/// Returns the user ID for the effective user running the process, or the
+/// one the backend set with `set_backend` reports.
+pub fn get_effective_uid() -> uid_t {
+    with_registered(|b| b.effective_uid())
+        .unwrap_or_else(native::get_effective_uid)
+}
+
+/// Returns the username of the effective user running the process, or the
+/// one the backend set with `set_backend` reports.
+pub fn get_effective_username() -> Option<OsString> {
+    with_registered(|b| b.effective_username())
+        .unwrap_or_else(native::get_effective_username)
+}
+
+/// Returns the group ID for the user running the process, or the one the
+/// backend set with `set_backend` reports.
+pub fn get_current_gid() -> gid_t {
+    with_registered(|b| b.current_gid())
+        .unwrap_or_else(native::get_current_gid)
+}
+
+/// Returns the groupname of the user running the process, or the one the
+/// backend set with `set_backend` reports.
+pub fn get_current_groupname() -> Option<OsString> {
+    with_registered(|b| b.current_groupname())
+        .unwrap_or_else(native::get_current_groupname)
+}
+
+/// Returns the group ID for the effective user running the process, or the
+/// one the backend set with `set_backend` reports.
+pub fn get_effective_gid() -> gid_t {
+    with_registered(|b| b.effective_gid())
+        .unwrap_or_else(native::get_effective_gid)
+}
+
+/// Returns the groupname of the effective user running the process, or the
+/// one the backend set with `set_backend` reports.
+pub fn get_effective_groupname() -> Option<OsString> {
+    with_registered(|b| b.effective_groupname())
+        .unwrap_or_else(native::get_effective_groupname)
+}
+
+/// Returns the real, effective, and saved user IDs of the process, or the
+/// current and effective ones the backend set with `set_backend` reports,
+/// with the effective one as the saved one.
+///
+/// Where there is no `getresuid`, such as on macOS, the saved user ID is
+/// reported as the effective one, which is what it starts out as.
+pub fn get_resuid() -> (uid_t, uid_t, uid_t) {
+    with_registered(|b| { let euid = b.effective_uid(); (b.current_uid(), euid, euid) })
+        .unwrap_or_else(native_resuid)
+}
+
+/// Returns the real, effective, and saved group IDs of the process, or the
+/// current and effective ones the backend set with `set_backend` reports,
+/// with the effective one as the saved one.
+///
+/// Where there is no `getresgid`, such as on macOS, the saved group ID is
+/// reported as the effective one, which is what it starts out as.
+pub fn get_resgid() -> (gid_t, gid_t, gid_t) {
+    with_registered(|b| { let egid = b.effective_gid(); (b.current_gid(), egid, egid) })
+        .unwrap_or_else(native_resgid)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "dragonfly", target_os = "openbsd"))]
+pub(crate) fn native_resuid() -> (uid_t, uid_t, uid_t) {
+    let (mut ruid, mut euid, mut suid) = (0, 0, 0);
+    // can only fail for bad pointers
+    unsafe { libc::getresuid(&mut ruid, &mut euid, &mut suid) };
+    (ruid, euid, suid)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "dragonfly", target_os = "openbsd"))]
+pub(crate) fn native_resgid() -> (gid_t, gid_t, gid_t) {
+    let (mut rgid, mut egid, mut sgid) = (0, 0, 0);
+    // can only fail for bad pointers
+    unsafe { libc::getresgid(&mut rgid, &mut egid, &mut sgid) };
+    (rgid, egid, sgid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "dragonfly", target_os = "openbsd")))]
+pub(crate) fn native_resuid() -> (uid_t, uid_t, uid_t) {
+    let euid = native::get_effective_uid();
+    (native::get_current_uid(), euid, euid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "dragonfly", target_os = "openbsd")))]
+pub(crate) fn native_resgid() -> (gid_t, gid_t, gid_t) {
+    let egid = native::get_effective_gid();
+    (native::get_current_gid(), egid, egid)
+}
+
+
+/// Creates a new iterator over every group present on the system.
+///
+/// # Safety
+///
+/// This uses `getgrent`, which is not thread-safe, in the same way as
+/// `all_users` uses `getpwent`: it must not be called while any other
+/// thread is listing groups.
+pub unsafe fn all_groups() -> impl Iterator<Item=Group> {
+    let mut groups = Vec::new();
+
+    libc::setgrent();
+    loop {
+        let entry = libc::getgrent();
+        if entry.is_null() {
+            break;
+        }
+
+        groups.push(group_to_group(&*entry));
+    }
+    libc::endgrent();
+
+    groups.into_iter()
+}
+
+/// Lists every user present on the system, or fails if the users database
+/// can’t be read.
+///
+/// Unlike `all_users`, this is safe, as it waits for anything else in this
+/// crate that is listing users. Where the C library doesn’t say where it
+/// keeps `errno`, errors partway through look like the end of the list.
+pub fn try_all_users() -> io::Result<impl Iterator<Item=io::Result<User>>> {
+    let _listing = listing::lock();
+    let mut users = Vec::new();
+
+    unsafe {
+        libc::setpwent();
+        loop {
+            let checked = clear_errno();
+            let entry = libc::getpwent();
+            if ! entry.is_null() {
+                users.push(Ok(passwd_to_user(&*entry)));
+                continue;
+            }
+
+            let error = io::Error::last_os_error();
+            if checked && ! matches!(error.raw_os_error(), Some(0) | Some(libc::ENOENT)) {
+                if users.is_empty() {
+                    libc::endpwent();
+                    return Err(error);
+                }
+
+                users.push(Err(error));
+            }
+            break;
+        }
+        libc::endpwent();
+    }
+
+    Ok(users.into_iter())
+}
+
+/// Returns the IDs of the groups the given user is a member of, without
+/// looking the groups themselves up, as `get_user_groups` does.
+pub(crate) fn get_user_group_ids(username: &OsStr, gid: gid_t) -> Option<Vec<gid_t>> {
+    let name = CString::new(username.as_bytes()).ok()?;
+
+    // macOS uses i32 instead of gid_t in getgrouplist
+    #[cfg(target_os = "macos")]
+    let mut buff: Vec<i32> = vec![0; 1024];
+    #[cfg(not(target_os = "macos"))]
+    let mut buff: Vec<gid_t> = vec![0; 1024];
+
+    let mut count = buff.len() as c_int;
+
+    #[cfg(target_os = "macos")]
+    let res = unsafe { libc::getgrouplist(name.as_ptr(), gid as i32, buff.as_mut_ptr(), &mut count) };
+    #[cfg(not(target_os = "macos"))]
+    let res = unsafe { libc::getgrouplist(name.as_ptr(), gid, buff.as_mut_ptr(), &mut count) };
+
+    if res < 0 {
+        return None;
+    }
+
+    buff.truncate(count as usize);
+    Some(buff.into_iter().map(|id| id as gid_t).collect())
+}
+
+/// Returns the name and GECOS field of the user with the given ID, which
+/// the real crate doesn’t keep.
+pub(crate) fn get_gecos(uid: uid_t) -> Option<(OsString, OsString)> {
+    unsafe {
+        with_entry(|pwd, buf, len, result| libc::getpwuid_r(uid, pwd, buf, len, result),
+                   |entry| (from_c(entry.pw_name).to_owned(), from_c(entry.pw_gecos).to_owned())).ok()?
+    }
+}
+
+/// Converts a `passwd` entry, whose strings must be valid.
+pub(crate) unsafe fn passwd_to_user(entry: &libc::passwd) -> User {
+    User::new(entry.pw_uid, from_c(entry.pw_name), entry.pw_gid)
+        .with_home_dir(from_c(entry.pw_dir))
+        .with_shell(from_c(entry.pw_shell))
+        .with_password(from_c(entry.pw_passwd))
+}
+
+/// Converts a `group` entry, whose strings must be valid.
+pub(crate) unsafe fn group_to_group(entry: &libc::group) -> Group {
+    let mut group = Group::new(entry.gr_gid, from_c(entry.gr_name));
+
+    let mut member = entry.gr_mem;
+    while ! member.is_null() && ! (*member).is_null() {
+        group = group.add_member(from_c(*member));
+        member = member.offset(1);
+    }
+
+    group
+}
+
+unsafe fn from_c<'a>(p: *const c_char) -> &'a OsStr {
+    if p.is_null() {
+        return OsStr::new("");
+    }
+
+    OsStr::from_bytes(CStr::from_ptr(p).to_bytes())
+}
+
+/// Sets `errno` to zero, returning whether it could.
+#[cfg(any(target_os = "linux", target_os = "emscripten", target_os = "android", target_os = "netbsd",
+          target_os = "openbsd", target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+unsafe fn clear_errno() -> bool {
+    #[cfg(any(target_os = "linux", target_os = "emscripten"))]
+    use libc::__errno_location as errno_location;
+
+    #[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+    use libc::__errno as errno_location;
+
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    use libc::__error as errno_location;
+
+    *errno_location() = 0;
+    true
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "emscripten", target_os = "android", target_os = "netbsd",
+              target_os = "openbsd", target_os = "macos", target_os = "ios", target_os = "freebsd")))]
+unsafe fn clear_errno() -> bool {
+    false
+}
@@ -0,0 +1,288 @@
+//! The platform-specific backends behind the functions in `base`.
+//!
+//! Each backend implements `Platform` for a unit struct, and `Current` names
+//! the one for the platform being compiled for, unless the real `users`
+//! crate is used instead. Every method has a default
+//! that returns the unconfigured stub’s dummy value, so a backend only needs to override
+//! what the platform can actually provide.
+
+use std::ffi::{OsStr, OsString};
+use std::io;
+
+#[cfg(all(not(users_passthrough), feature = "logging"))]
+use std::sync::Mutex;
+
+use backend::Backend;
+use {User, Group, uid_t, gid_t};
+
+#[cfg(all(not(users_passthrough), not(any(all(windows, feature = "windows-native"), target_os = "wasi", target_os = "fuchsia", target_os = "illumos", target_os = "aix"))))]
+use stub;
+
+#[cfg(all(windows, feature = "windows-native"))]
+use windows;
+
+#[cfg(target_os = "wasi")]
+use wasi;
+
+#[cfg(target_os = "fuchsia")]
+use fuchsia;
+
+#[cfg(any(target_os = "illumos", target_os = "aix"))]
+use posix;
+
+
+/// The lookups a platform backend can provide.
+pub trait Platform {
+
+    /// Which backend this is, as reported by `backend_info`.
+    const BACKEND: Backend;
+
+    /// Whether this is the stub, which has no platform to ask.
+    const IS_STUB: bool = false;
+
+    /// Whether the switching functions actually change who the process,
+    /// or the calling thread, runs as.
+    const SWITCHES: bool = false;
+
+    /// Returns whether the answers are only the dummy values, because the
+    /// backend knows nothing and nobody told it anything.
+    fn is_dummy() -> bool {
+        false
+    }
+
+    fn get_user_by_uid(_uid: uid_t) -> Option<User> {
+        None
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(_username: &S) -> Option<User> {
+        None
+    }
+
+    fn get_group_by_gid(_gid: gid_t) -> Option<Group> {
+        None
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(_groupname: &S) -> Option<Group> {
+        None
+    }
+
+    /// Looks a user up by ID, failing if the user database can’t be read.
+    fn try_get_user_by_uid(uid: uid_t) -> io::Result<Option<User>> {
+        Ok(Self::get_user_by_uid(uid))
+    }
+
+    /// Looks a user up by name, failing if the user database can’t be read.
+    fn try_get_user_by_name(username: &OsStr) -> io::Result<Option<User>> {
+        Ok(Self::get_user_by_name(username))
+    }
+
+    /// Looks a group up by ID, failing if the group database can’t be read.
+    fn try_get_group_by_gid(gid: gid_t) -> io::Result<Option<Group>> {
+        Ok(Self::get_group_by_gid(gid))
+    }
+
+    /// Looks a group up by name, failing if the group database can’t be
+    /// read.
+    fn try_get_group_by_name(groupname: &OsStr) -> io::Result<Option<Group>> {
+        Ok(Self::get_group_by_name(groupname))
+    }
+
+    fn get_current_uid() -> uid_t {
+        0
+    }
+
+    fn get_current_username() -> Option<OsString> {
+        None
+    }
+
+    fn get_effective_uid() -> uid_t {
+        0
+    }
+
+    fn get_effective_username() -> Option<OsString> {
+        None
+    }
+
+    fn get_current_gid() -> gid_t {
+        0
+    }
+
+    fn get_current_groupname() -> Option<OsString> {
+        None
+    }
+
+    fn get_effective_gid() -> gid_t {
+        0
+    }
+
+    fn get_effective_groupname() -> Option<OsString> {
+        None
+    }
+
+    /// Returns the real, effective, and saved user IDs. Without a way to ask
+    /// for the saved one, it is taken to be the effective one, as it is
+    /// right after `exec`.
+    fn get_resuid() -> (uid_t, uid_t, uid_t) {
+        let euid = Self::get_effective_uid();
+        (Self::get_current_uid(), euid, euid)
+    }
+
+    /// Returns the real, effective, and saved group IDs, the same way.
+    fn get_resgid() -> (gid_t, gid_t, gid_t) {
+        let egid = Self::get_effective_gid();
+        (Self::get_current_gid(), egid, egid)
+    }
+
+    fn group_access_list() -> io::Result<Vec<Group>> {
+        Ok(vec![])
+    }
+
+    fn get_user_groups<S: AsRef<OsStr> + ?Sized>(_username: &S, _gid: gid_t) -> Option<Vec<Group>> {
+        None
+    }
+
+    fn get_user_group_ids<S: AsRef<OsStr> + ?Sized>(username: &S, gid: gid_t) -> Option<Vec<gid_t>> {
+        Self::get_user_groups(username, gid).map(|groups| groups.iter().map(Group::gid).collect())
+    }
+
+    fn all_users() -> Vec<User> {
+        Vec::new()
+    }
+
+    fn all_groups() -> Vec<Group> {
+        Vec::new()
+    }
+
+    fn try_all_users() -> io::Result<Vec<io::Result<User>>> {
+        Ok(Self::all_users().into_iter().map(Ok).collect())
+    }
+
+    fn set_current_uid(_uid: uid_t) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_current_gid(_gid: gid_t) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_effective_uid(_uid: uid_t) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_effective_gid(_gid: gid_t) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_both_uid(_ruid: uid_t, _euid: uid_t) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_both_gid(_rgid: gid_t, _egid: gid_t) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_all_uid(_ruid: uid_t, _euid: uid_t, _suid: uid_t) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_all_gid(_rgid: gid_t, _egid: gid_t, _sgid: gid_t) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Returns the supplementary group IDs, including those that can’t be
+    /// looked up.
+    fn supplementary_groups() -> io::Result<Vec<gid_t>> {
+        Ok(Self::group_access_list()?.iter().map(Group::gid).collect())
+    }
+
+    fn set_supplementary_groups(_gids: &[gid_t]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn init_groups(_username: &OsStr, _gid: gid_t) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Checks whether `switch_user_group` can switch to the given IDs;
+    /// anything it has to undo is handled by the guard.
+    fn switch_user_group(_uid: uid_t, _gid: gid_t) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+/// The backend for the platform being compiled for.
+#[cfg(all(windows, feature = "windows-native"))]
+pub type Current = windows::Windows;
+
+/// The backend for the platform being compiled for.
+#[cfg(target_os = "wasi")]
+pub type Current = wasi::Wasi;
+
+/// The backend for the platform being compiled for.
+#[cfg(target_os = "fuchsia")]
+pub type Current = fuchsia::Fuchsia;
+
+/// The backend for the platform being compiled for.
+#[cfg(any(target_os = "illumos", target_os = "aix"))]
+pub type Current = posix::Posix;
+
+/// The backend for the platform being compiled for.
+#[cfg(all(not(users_passthrough), not(any(all(windows, feature = "windows-native"), target_os = "wasi", target_os = "fuchsia", target_os = "illumos", target_os = "aix"))))]
+pub type Current = stub::Stub;
+
+
+/// Returns whether two lists of group IDs have the same groups, in any
+/// order.
+#[cfg(any(all(windows, feature = "windows-native"), target_os = "fuchsia"))]
+pub(crate) fn same_groups(a: &[gid_t], b: &[gid_t]) -> bool {
+    let (mut a, mut b) = (a.to_vec(), b.to_vec());
+    a.sort_unstable();
+    a.dedup();
+    b.sort_unstable();
+    b.dedup();
+    a == b
+}
+
+/// The error for asking to set a saved ID other than the effective one
+/// where `setreuid` or `setregid` has to do instead.
+#[cfg(any(target_os = "illumos", target_os = "aix", all(users_passthrough, not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "dragonfly", target_os = "openbsd")))))]
+pub(crate) fn saved_id_unsupported(function: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("{} cannot set a saved ID other than the effective one on this platform", function))
+}
+
+
+/// Records that the public function named was called, which, if the stub
+/// is in use, logs a warning the first time with the `logging` feature, and
+/// panics with the `deny-stub-at-runtime` feature.
+///
+/// The panic is reported at the location of whoever called the public
+/// function, so that test suites can find what used the stub.
+#[cfg(not(users_passthrough))]
+#[track_caller]
+#[inline]
+pub fn stub_used(function: &'static str) {
+    if ! Current::IS_STUB {
+        return;
+    }
+
+    if cfg!(feature = "deny-stub-at-runtime") {
+        panic!("users::{} used the stub, which the deny-stub-at-runtime feature forbids", function);
+    }
+
+    #[cfg(feature = "logging")]
+    warn_once(function);
+}
+
+/// The functions that have been warned about already.
+#[cfg(all(not(users_passthrough), feature = "logging"))]
+static WARNED: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+#[cfg(all(not(users_passthrough), feature = "logging"))]
+fn warn_once(function: &'static str) {
+    let mut warned = WARNED.lock().unwrap_or_else(|e| e.into_inner());
+    if ! warned.contains(&function) {
+        warned.push(function);
+        warn!("users-stub: {} called on a platform without user database support", function);
+    }
+}
@@ -0,0 +1,312 @@
+//! The backend for Unix platforms the real `users` crate doesn’t cover,
+//! illumos and AIX, which asks the C library just like it would.
+//!
+//! Lookups use the reentrant `getpw*_r` and `getgr*_r` functions, retrying
+//! with a bigger buffer when an entry doesn’t fit (see the
+//! [`reentrant`](../reentrant/index.html) module). A user’s supplementary
+//! groups come from `getgrouplist` on illumos and from `getgrset` on AIX,
+//! which has no `getgrouplist`.
+//!
+//! The `set_*` functions in `switch` call their libc namesakes, but
+//! `switch_user_group` fails, as its guard can’t yet switch back.
+
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::ptr;
+
+use libc::{c_char, c_int, passwd, group};
+
+use backend::Backend;
+use base::{User, Group, uid_t, gid_t};
+use base::os::unix::{UserExt, GroupExt};
+use platform::{Platform, saved_id_unsupported};
+use reentrant::{with_entry, MAXIMUM_BUFFER};
+
+
+unsafe fn from_c(p: *const c_char) -> OsString {
+    if p.is_null() {
+        return OsString::new();
+    }
+
+    OsStr::from_bytes(CStr::from_ptr(p).to_bytes()).to_owned()
+}
+
+pub(crate) unsafe fn passwd_to_user(entry: &passwd) -> User {
+    User::new(entry.pw_uid, &from_c(entry.pw_name), entry.pw_gid)
+        .with_home_dir(&from_c(entry.pw_dir))
+        .with_shell(&from_c(entry.pw_shell))
+        .with_password(&from_c(entry.pw_passwd))
+        .with_gecos_field(&from_c(entry.pw_gecos))
+}
+
+pub(crate) unsafe fn group_to_group(entry: &group) -> Group {
+    let mut group = Group::new(entry.gr_gid, &from_c(entry.gr_name));
+
+    let mut member = entry.gr_mem;
+    while ! member.is_null() && ! (*member).is_null() {
+        group = group.add_member(&from_c(*member));
+        member = member.offset(1);
+    }
+
+    group
+}
+
+fn to_c(name: &OsStr) -> Option<CString> {
+    CString::new(name.as_bytes()).ok()
+}
+
+fn check(status: c_int) -> io::Result<()> {
+    if status == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// The IDs of the groups the given user is a member of, primary group first.
+#[cfg(not(target_os = "aix"))]
+fn group_ids(username: &CStr, gid: gid_t) -> Option<Vec<gid_t>> {
+    let mut count: c_int = 32;
+
+    loop {
+        let mut buf = vec![0 as gid_t; count as usize];
+        let previous = count;
+        let status = unsafe { libc::getgrouplist(username.as_ptr(), gid, buf.as_mut_ptr(), &mut count) };
+
+        if status >= 0 {
+            buf.truncate(count as usize);
+            return Some(buf);
+        }
+
+        // Some implementations report the size needed and some don’t.
+        count = if count > previous { count } else { previous * 2 };
+        if count as usize > MAXIMUM_BUFFER {
+            return None;
+        }
+    }
+}
+
+/// The IDs of the groups the given user is a member of, primary group first.
+#[cfg(target_os = "aix")]
+fn group_ids(username: &CStr, gid: gid_t) -> Option<Vec<gid_t>> {
+    // `getgrset` returns the group IDs as a malloc’d, comma-separated list.
+    let list = unsafe { libc::getgrset(username.as_ptr()) };
+    if list.is_null() {
+        return None;
+    }
+
+    let mut gids = vec![ gid ];
+    let parsed = unsafe { CStr::from_ptr(list) }.to_string_lossy()
+                     .split(',')
+                     .filter_map(|id| id.trim().parse().ok())
+                     .collect::<Vec<gid_t>>();
+    unsafe { libc::free(list as *mut _) };
+
+    gids.extend(parsed.into_iter().filter(|&id| id != gid));
+    Some(gids)
+}
+
+
+/// The backend using the C library.
+pub struct Posix;
+
+impl Platform for Posix {
+    const BACKEND: Backend = Backend::Native;
+    const SWITCHES: bool = true;
+
+    fn get_user_by_uid(uid: uid_t) -> Option<User> {
+        Self::try_get_user_by_uid(uid).ok()?
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(username: &S) -> Option<User> {
+        Self::try_get_user_by_name(username.as_ref()).ok()?
+    }
+
+    fn get_group_by_gid(gid: gid_t) -> Option<Group> {
+        Self::try_get_group_by_gid(gid).ok()?
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(groupname: &S) -> Option<Group> {
+        Self::try_get_group_by_name(groupname.as_ref()).ok()?
+    }
+
+    fn try_get_user_by_uid(uid: uid_t) -> io::Result<Option<User>> {
+        unsafe { with_entry(|pwd, buf, len, result| libc::getpwuid_r(uid, pwd, buf, len, result), |entry| passwd_to_user(entry)) }
+    }
+
+    fn try_get_user_by_name(username: &OsStr) -> io::Result<Option<User>> {
+        let username = match to_c(username) {
+            Some(username) => username,
+            None           => return Ok(None),
+        };
+
+        unsafe { with_entry(|pwd, buf, len, result| libc::getpwnam_r(username.as_ptr(), pwd, buf, len, result), |entry| passwd_to_user(entry)) }
+    }
+
+    fn try_get_group_by_gid(gid: gid_t) -> io::Result<Option<Group>> {
+        unsafe { with_entry(|grp, buf, len, result| libc::getgrgid_r(gid, grp, buf, len, result), |entry| group_to_group(entry)) }
+    }
+
+    fn try_get_group_by_name(groupname: &OsStr) -> io::Result<Option<Group>> {
+        let groupname = match to_c(groupname) {
+            Some(groupname) => groupname,
+            None            => return Ok(None),
+        };
+
+        unsafe { with_entry(|grp, buf, len, result| libc::getgrnam_r(groupname.as_ptr(), grp, buf, len, result), |entry| group_to_group(entry)) }
+    }
+
+    fn get_current_uid() -> uid_t {
+        unsafe { libc::getuid() }
+    }
+
+    fn get_current_username() -> Option<OsString> {
+        Self::get_user_by_uid(Self::get_current_uid()).map(|user| user.name().to_owned())
+    }
+
+    fn get_effective_uid() -> uid_t {
+        unsafe { libc::geteuid() }
+    }
+
+    fn get_effective_username() -> Option<OsString> {
+        Self::get_user_by_uid(Self::get_effective_uid()).map(|user| user.name().to_owned())
+    }
+
+    fn get_current_gid() -> gid_t {
+        unsafe { libc::getgid() }
+    }
+
+    fn get_current_groupname() -> Option<OsString> {
+        Self::get_group_by_gid(Self::get_current_gid()).map(|group| group.name().to_owned())
+    }
+
+    fn get_effective_gid() -> gid_t {
+        unsafe { libc::getegid() }
+    }
+
+    fn get_effective_groupname() -> Option<OsString> {
+        Self::get_group_by_gid(Self::get_effective_gid()).map(|group| group.name().to_owned())
+    }
+
+    fn group_access_list() -> io::Result<Vec<Group>> {
+        let mut buf = Self::supplementary_groups()?;
+        buf.sort();
+        buf.dedup();
+        Ok(buf.into_iter().filter_map(Self::get_group_by_gid).collect())
+    }
+
+    fn get_user_groups<S: AsRef<OsStr> + ?Sized>(username: &S, gid: gid_t) -> Option<Vec<Group>> {
+        let username = to_c(username.as_ref())?;
+        let gids = group_ids(&username, gid)?;
+        Some(gids.into_iter().filter_map(Self::get_group_by_gid).collect())
+    }
+
+    fn get_user_group_ids<S: AsRef<OsStr> + ?Sized>(username: &S, gid: gid_t) -> Option<Vec<gid_t>> {
+        group_ids(&to_c(username.as_ref())?, gid)
+    }
+
+    fn all_users() -> Vec<User> {
+        let mut users = Vec::new();
+
+        unsafe {
+            libc::setpwent();
+            loop {
+                let entry = libc::getpwent();
+                if entry.is_null() {
+                    break;
+                }
+
+                users.push(passwd_to_user(&*entry));
+            }
+            libc::endpwent();
+        }
+
+        users
+    }
+
+    fn all_groups() -> Vec<Group> {
+        let mut groups = Vec::new();
+
+        unsafe {
+            libc::setgrent();
+            loop {
+                let entry = libc::getgrent();
+                if entry.is_null() {
+                    break;
+                }
+
+                groups.push(group_to_group(&*entry));
+            }
+            libc::endgrent();
+        }
+
+        groups
+    }
+
+    fn set_current_uid(uid: uid_t) -> io::Result<()> {
+        check(unsafe { libc::setuid(uid) })
+    }
+
+    fn set_current_gid(gid: gid_t) -> io::Result<()> {
+        check(unsafe { libc::setgid(gid) })
+    }
+
+    fn set_effective_uid(uid: uid_t) -> io::Result<()> {
+        check(unsafe { libc::seteuid(uid) })
+    }
+
+    fn set_effective_gid(gid: gid_t) -> io::Result<()> {
+        check(unsafe { libc::setegid(gid) })
+    }
+
+    fn set_both_uid(ruid: uid_t, euid: uid_t) -> io::Result<()> {
+        check(unsafe { libc::setreuid(ruid, euid) })
+    }
+
+    fn set_both_gid(rgid: gid_t, egid: gid_t) -> io::Result<()> {
+        check(unsafe { libc::setregid(rgid, egid) })
+    }
+
+    // neither has setresuid, but setreuid sets the saved ID to the effective one
+    fn set_all_uid(ruid: uid_t, euid: uid_t, suid: uid_t) -> io::Result<()> {
+        if suid != euid {
+            return Err(saved_id_unsupported("set_all_uid"));
+        }
+
+        Self::set_both_uid(ruid, euid)
+    }
+
+    fn set_all_gid(rgid: gid_t, egid: gid_t, sgid: gid_t) -> io::Result<()> {
+        if sgid != egid {
+            return Err(saved_id_unsupported("set_all_gid"));
+        }
+
+        Self::set_both_gid(rgid, egid)
+    }
+
+    fn supplementary_groups() -> io::Result<Vec<gid_t>> {
+        let count = unsafe { libc::getgroups(0, ptr::null_mut()) };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buf = vec![0 as gid_t; count as usize];
+        let count = unsafe { libc::getgroups(count, buf.as_mut_ptr()) };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        buf.truncate(count as usize);
+        Ok(buf)
+    }
+
+    fn set_supplementary_groups(gids: &[gid_t]) -> io::Result<()> {
+        check(unsafe { libc::setgroups(gids.len() as _, gids.as_ptr()) })
+    }
+
+    fn init_groups(username: &OsStr, gid: gid_t) -> io::Result<()> {
+        let username = to_c(username).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "username contains a NUL byte"))?;
+        check(unsafe { libc::initgroups(username.as_ptr(), gid) })
+    }
+
+    fn switch_user_group(_uid: uid_t, _gid: gid_t) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "switch_user_group is not supported on this platform, use the set_* functions"))
+    }
+}
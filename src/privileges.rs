@@ -0,0 +1,59 @@
+//! Checking whether the process runs with administrative privileges.
+//!
+//! Code that needs to gate privileged operations usually wants to know
+//! “am I root?” on Unix and “am I elevated?” on Windows. The functions in
+//! this module answer both questions on every platform, so callers don’t
+//! need their own `cfg` blocks:
+//!
+//! - On Unix, the process is privileged if its effective user ID is 0.
+//! - On Windows with the `windows-native` feature, the process is
+//!   privileged if its token is elevated, meaning it runs as an
+//!   administrator past User Account Control.
+//! - Everywhere else, the stub cannot tell, and reports the process as
+//!   unprivileged.
+//!
+//! ## Example
+//!
+//! ```
+//! use users::privileges::is_elevated;
+//!
+//! if !is_elevated() {
+//!     println!("Not running with administrative privileges; some features are disabled");
+//! }
+//! ```
+
+#[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+use super::get_effective_uid;
+
+#[cfg(all(windows, feature = "windows-native"))]
+use windows;
+
+
+/// Returns whether the process runs with administrative privileges: as
+/// root on Unix, or with an elevated token on Windows.
+///
+/// # Windows functions used
+///
+/// - [`GetTokenInformation`](https://docs.microsoft.com/en-us/windows/win32/api/securitybaseapi/nf-securitybaseapi-gettokeninformation)
+///   with `TokenElevation`
+pub fn is_elevated() -> bool {
+    #[cfg(any(users_passthrough, target_os = "illumos", target_os = "aix"))]
+    let elevated = get_effective_uid() == 0;
+
+    #[cfg(all(windows, feature = "windows-native"))]
+    let elevated = windows::is_elevated();
+
+    #[cfg(not(any(users_passthrough, target_os = "illumos", target_os = "aix", all(windows, feature = "windows-native"))))]
+    let elevated = false;
+
+    elevated
+}
+
+/// Returns whether the process runs as root.
+///
+/// Windows has no root user, so this is the same as
+/// [`is_elevated`](fn.is_elevated.html) there; it is provided so that
+/// Unix-minded code reads naturally on every platform.
+pub fn is_root() -> bool {
+    is_elevated()
+}
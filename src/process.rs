@@ -0,0 +1,109 @@
+//! Running commands as another user.
+//!
+//! A daemon that starts as root and spawns children as an unprivileged
+//! user has to look the user up, list their supplementary groups, and then
+//! have the child set its groups, group ID, and user ID, in that order,
+//! before it runs the program. `apply_user` does all of it for a
+//! `std::process::Command`:
+//!
+//! ```no_run
+//! use std::process::Command;
+//! use users::os::unix::UserExt;
+//! use users::process::apply_user;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let mut command = Command::new("/usr/sbin/php-fpm");
+//! let user = apply_user(&mut command, "www-data")?;
+//! command.env("HOME", user.home_dir()).spawn()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The IDs can’t be set with `CommandExt::uid` and `CommandExt::gid`, as
+//! the standard library gives no stable way to set the supplementary
+//! groups, and the child can’t set them itself once it has switched user.
+//! So all three are set by a `pre_exec` hook instead. Those hooks run after
+//! the standard library has switched user, so calling `uid` on the same
+//! command as well makes the hook fail.
+//!
+//! The environment is left alone; the user `apply_user` returns has what is
+//! needed to set `HOME`, `USER`, and so on.
+
+use std::ffi::OsStr;
+use std::io;
+use std::os::raw::c_int;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use libc;
+
+use {User, Error, try_get_user_by_name, get_user_groups, gid_t};
+
+
+/// Looks the user with the given name up, along with their supplementary
+/// groups, and makes the command run as them.
+///
+/// Fails with `ErrorKind::NotFound` if there is no such user, and with the
+/// lookup’s error if the user or their groups can’t be looked up. Spawning
+/// the command then fails unless the process may switch to the user, which
+/// usually takes being root.
+pub fn apply_user<S: AsRef<OsStr> + ?Sized>(command: &mut Command, username: &S) -> io::Result<User> {
+    let username = username.as_ref();
+    let user = match try_get_user_by_name(username) {
+        Ok(user)             => user,
+        Err(Error::NotFound) => return Err(io::Error::new(io::ErrorKind::NotFound, format!("no such user: {}", username.to_string_lossy()))),
+        Err(e)               => return Err(e.into()),
+    };
+
+    let groups = get_user_groups(username, user.primary_group_id())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, format!("failed to list the groups of {}", username.to_string_lossy())))?;
+
+    apply_ids(command, &user, groups.iter().map(|g| g.gid()).collect());
+    Ok(user)
+}
+
+/// Makes the command set the given groups, then the user’s primary group,
+/// then the user ID, before running the program.
+fn apply_ids(command: &mut Command, user: &User, gids: Vec<gid_t>) {
+    let (uid, gid) = (user.uid(), user.primary_group_id());
+
+    // Only async-signal-safe functions can be called between fork and exec,
+    // so everything is worked out beforehand, and nothing gets allocated.
+    let set_ids = move || unsafe {
+        check(libc::setgroups(gids.len() as _, gids.as_ptr()))?;
+        check(libc::setgid(gid))?;
+        check(libc::setuid(uid))
+    };
+
+    unsafe { command.pre_exec(set_ids); }
+}
+
+fn check(status: c_int) -> io::Result<()> {
+    if status == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use get_current_uid;
+
+    #[test]
+    fn unknown_user() {
+        let error = apply_user(&mut Command::new("true"), "no such user, surely").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert_eq!(error.to_string(), "no such user: no such user, surely");
+    }
+
+    #[test]
+    fn as_root() {
+        if get_current_uid() != 0 {
+            return;
+        }
+
+        let mut command = Command::new("id");
+        apply_user(&mut command, "root").unwrap();
+        let output = command.arg("-u").output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "0");
+    }
+}
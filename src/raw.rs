@@ -0,0 +1,118 @@
+//! Converting the C library’s `passwd` and `group` structs.
+//!
+//! Code that already has an entry as the C library gave it, such as one
+//! handed to a PAM module, or one from a `getpwent` loop, can turn it into
+//! a `User` or `Group` without looking it up again:
+//!
+//! ```
+//! extern crate libc;
+//! # extern crate users;
+//! use users::{User, RawUserExt};
+//!
+//! # fn main() {
+//! let entry = unsafe { libc::getpwuid(0) };
+//! if ! entry.is_null() {
+//!     let user = unsafe { User::from_raw_passwd(&*entry) };
+//!     assert_eq!(user.uid(), 0);
+//! }
+//! # }
+//! ```
+//!
+//! The strings are copied, so the struct can be freed or overwritten once
+//! the call returns.
+
+use libc::{passwd, group};
+
+use {User, Group};
+
+
+/// Converting `passwd` structs into `User`s.
+pub trait RawUserExt {
+
+    /// Converts a `passwd` struct, copying its strings.
+    ///
+    /// # Safety
+    ///
+    /// Each of the struct’s string fields must be either null, which is
+    /// read as an empty string, or a valid pointer to a null-terminated
+    /// string.
+    unsafe fn from_raw_passwd(entry: &passwd) -> Self;
+}
+
+impl RawUserExt for User {
+    unsafe fn from_raw_passwd(entry: &passwd) -> Self {
+        #[cfg(users_passthrough)]
+        return ::passthrough::passwd_to_user(entry);
+
+        #[cfg(not(users_passthrough))]
+        ::posix::passwd_to_user(entry)
+    }
+}
+
+/// Converting `group` structs into `Group`s.
+pub trait RawGroupExt {
+
+    /// Converts a `group` struct, copying its name and members.
+    ///
+    /// # Safety
+    ///
+    /// `gr_name` must be either null, which is read as an empty string, or
+    /// a valid pointer to a null-terminated string. `gr_mem` must be either
+    /// null, for no members, or a valid pointer to an array of such
+    /// strings, ended by a null pointer.
+    unsafe fn from_raw_group(entry: &group) -> Self;
+}
+
+impl RawGroupExt for Group {
+    unsafe fn from_raw_group(entry: &group) -> Self {
+        #[cfg(users_passthrough)]
+        return ::passthrough::group_to_group(entry);
+
+        #[cfg(not(users_passthrough))]
+        ::posix::group_to_group(entry)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::{CString, OsString};
+    use std::mem;
+    use std::path::Path;
+    use std::ptr;
+    use os::unix::{UserExt, GroupExt};
+
+    #[test]
+    fn user() {
+        let name = CString::new("alice").unwrap();
+        let dir = CString::new("/home/alice").unwrap();
+
+        let mut entry: passwd = unsafe { mem::zeroed() };
+        entry.pw_name = name.as_ptr() as *mut _;
+        entry.pw_dir = dir.as_ptr() as *mut _;
+        entry.pw_uid = 1000;
+        entry.pw_gid = 100;
+
+        let user = unsafe { User::from_raw_passwd(&entry) };
+        assert_eq!((user.name().to_str(), user.uid(), user.primary_group_id()), (Some("alice"), 1000, 100));
+        assert_eq!(user.home_dir(), Path::new("/home/alice"));
+        assert_eq!(user.shell(), Path::new(""));
+    }
+
+    #[test]
+    fn group() {
+        let name = CString::new("wheel").unwrap();
+        let alice = CString::new("alice").unwrap();
+        let mut members = [ alice.as_ptr() as *mut _, ptr::null_mut() ];
+
+        let mut entry: group = unsafe { mem::zeroed() };
+        entry.gr_name = name.as_ptr() as *mut _;
+        entry.gr_gid = 10;
+        entry.gr_mem = members.as_mut_ptr();
+
+        let group = unsafe { Group::from_raw_group(&entry) };
+        assert_eq!((group.name().to_str(), group.gid()), (Some("wheel"), 10));
+        assert_eq!(group.members(), &[ OsString::from("alice") ]);
+    }
+}
@@ -0,0 +1,273 @@
+//! Looking users and groups up into a buffer the caller provides.
+//!
+//! The reentrant `getpw*_r` and `getgr*_r` functions write the strings of
+//! an entry into a buffer they are given, and fail with `ERANGE` when it is
+//! too small, such as for a group with thousands of members. The lookup
+//! functions at the crate root retry with bigger and bigger buffers, then
+//! copy the entry into a `User` or `Group`. When scanning through many IDs,
+//! the functions here avoid those allocations: they look entries up into
+//! the same buffer every time, and hand out views of it.
+//!
+//! ```no_run
+//! use users::reentrant::{get_user_by_uid_in, is_buffer_too_small};
+//!
+//! let mut buf = vec![0; 1024];
+//! for uid in 0 .. 65536 {
+//!     loop {
+//!         match get_user_by_uid_in(uid, &mut buf) {
+//!             Ok(Some(user))                        => println!("{} is {:?}", uid, user.name()),
+//!             Ok(None)                              => {}
+//!             Err(ref e) if is_buffer_too_small(e) => { let len = buf.len() * 2; buf.resize(len, 0); continue }
+//!             Err(e)                                => panic!("{}", e),
+//!         }
+//!         break;
+//!     }
+//! }
+//! ```
+//!
+//! These always ask the C library, and never a backend set with
+//! `set_backend`.
+
+use std::ffi::{CStr, CString, OsStr};
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr;
+
+use libc::{self, passwd, group, size_t};
+
+use {User, Group, uid_t, gid_t};
+
+
+/// The buffer size to start with when growing one.
+pub(crate) const INITIAL_BUFFER: usize = 1024;
+
+/// The buffer size to give up at, in case `ERANGE` keeps coming back.
+pub(crate) const MAXIMUM_BUFFER: usize = 1024 * 1024;
+
+
+/// Calls one of the `get*_r` functions, growing the buffer until the entry
+/// fits, and converts the entry if one was found. The entry’s strings point
+/// into the buffer, so it can’t be returned as it is.
+pub(crate) unsafe fn with_entry<E, T, F, C>(mut lookup: F, convert: C) -> io::Result<Option<T>>
+where F: FnMut(*mut E, *mut c_char, size_t, *mut *mut E) -> c_int,
+      C: FnOnce(&E) -> T,
+{
+    let mut buf = vec![0 as c_char; INITIAL_BUFFER];
+
+    loop {
+        let mut entry: E = mem::zeroed();
+        let mut result = ptr::null_mut();
+
+        let status = lookup(&mut entry, buf.as_mut_ptr(), buf.len(), &mut result);
+        if status == libc::ERANGE && buf.len() < MAXIMUM_BUFFER {
+            let len = buf.len() * 2;
+            buf.resize(len, 0);
+            continue;
+        }
+
+        return match status {
+            0 if ! result.is_null() => Ok(Some(convert(&entry))),
+            0 | libc::ENOENT        => Ok(None),
+            _                       => Err(io::Error::from_raw_os_error(status)),
+        };
+    }
+}
+
+/// Calls one of the `get*_r` functions once, with the given buffer.
+unsafe fn entry_in<E, F>(buf: &mut [u8], lookup: F) -> io::Result<Option<E>>
+where F: FnOnce(*mut E, *mut c_char, size_t, *mut *mut E) -> c_int,
+{
+    let mut entry: E = mem::zeroed();
+    let mut result = ptr::null_mut();
+
+    match lookup(&mut entry, buf.as_mut_ptr() as *mut c_char, buf.len(), &mut result) {
+        0 if ! result.is_null() => Ok(Some(entry)),
+        0 | libc::ENOENT        => Ok(None),
+        status                  => Err(io::Error::from_raw_os_error(status)),
+    }
+}
+
+/// Returns whether a lookup failed because the buffer it was given is too
+/// small for the entry, in which case it is worth retrying with a bigger
+/// one.
+pub fn is_buffer_too_small(error: &io::Error) -> bool {
+    error.raw_os_error() == Some(libc::ERANGE)
+}
+
+
+/// A user entry whose strings are in a buffer provided by the caller.
+pub struct UserEntry<'buf> {
+    entry: passwd,
+    _buf: PhantomData<&'buf [u8]>,
+}
+
+impl<'buf> UserEntry<'buf> {
+
+    /// Returns this user’s ID.
+    pub fn uid(&self) -> uid_t {
+        self.entry.pw_uid
+    }
+
+    /// Returns the ID of this user’s primary group.
+    pub fn primary_group_id(&self) -> gid_t {
+        self.entry.pw_gid
+    }
+
+    /// Returns this user’s name.
+    pub fn name(&self) -> &'buf OsStr {
+        unsafe { from_c(self.entry.pw_name) }
+    }
+
+    /// Returns this user’s GECOS field, usually their full name.
+    pub fn gecos(&self) -> &'buf OsStr {
+        unsafe { from_c(self.entry.pw_gecos) }
+    }
+
+    /// Returns the path to this user’s home directory.
+    pub fn home_dir(&self) -> &'buf Path {
+        Path::new(unsafe { from_c(self.entry.pw_dir) })
+    }
+
+    /// Returns the path to this user’s shell.
+    pub fn shell(&self) -> &'buf Path {
+        Path::new(unsafe { from_c(self.entry.pw_shell) })
+    }
+
+    /// Copies this entry into a `User`, as the lookup functions at the crate
+    /// root return.
+    pub fn to_user(&self) -> User {
+        #[cfg(users_passthrough)]
+        return unsafe { ::passthrough::passwd_to_user(&self.entry) };
+
+        #[cfg(not(users_passthrough))]
+        unsafe { ::posix::passwd_to_user(&self.entry) }
+    }
+}
+
+/// A group entry whose strings are in a buffer provided by the caller.
+pub struct GroupEntry<'buf> {
+    entry: group,
+    _buf: PhantomData<&'buf [u8]>,
+}
+
+impl<'buf> GroupEntry<'buf> {
+
+    /// Returns this group’s ID.
+    pub fn gid(&self) -> gid_t {
+        self.entry.gr_gid
+    }
+
+    /// Returns this group’s name.
+    pub fn name(&self) -> &'buf OsStr {
+        unsafe { from_c(self.entry.gr_name) }
+    }
+
+    /// Returns the names of the users listed as members of this group.
+    pub fn members(&self) -> impl Iterator<Item=&'buf OsStr> + '_ {
+        let mut member = self.entry.gr_mem;
+        ::std::iter::from_fn(move || unsafe {
+            if member.is_null() || (*member).is_null() {
+                return None;
+            }
+
+            let name = from_c(*member);
+            member = member.offset(1);
+            Some(name)
+        })
+    }
+
+    /// Copies this entry into a `Group`, as the lookup functions at the
+    /// crate root return.
+    pub fn to_group(&self) -> Group {
+        #[cfg(users_passthrough)]
+        return unsafe { ::passthrough::group_to_group(&self.entry) };
+
+        #[cfg(not(users_passthrough))]
+        unsafe { ::posix::group_to_group(&self.entry) }
+    }
+}
+
+
+/// Looks up the user with the given ID with `getpwuid_r`, into the given
+/// buffer, failing so that `is_buffer_too_small` holds if it doesn’t fit.
+pub fn get_user_by_uid_in(uid: uid_t, buf: &mut [u8]) -> io::Result<Option<UserEntry<'_>>> {
+    let entry = unsafe { entry_in(buf, |pwd, buf, len, result| libc::getpwuid_r(uid, pwd, buf, len, result))? };
+    Ok(entry.map(|entry| UserEntry { entry, _buf: PhantomData }))
+}
+
+/// Looks up the user with the given name with `getpwnam_r`, into the given
+/// buffer, failing so that `is_buffer_too_small` holds if it doesn’t fit.
+pub fn get_user_by_name_in<'buf, S: AsRef<OsStr> + ?Sized>(username: &S, buf: &'buf mut [u8]) -> io::Result<Option<UserEntry<'buf>>> {
+    let username = match CString::new(username.as_ref().as_bytes()) {
+        Ok(username) => username,
+        Err(_)       => return Ok(None),
+    };
+
+    let entry = unsafe { entry_in(buf, |pwd, buf, len, result| libc::getpwnam_r(username.as_ptr(), pwd, buf, len, result))? };
+    Ok(entry.map(|entry| UserEntry { entry, _buf: PhantomData }))
+}
+
+/// Looks up the group with the given ID with `getgrgid_r`, into the given
+/// buffer, failing so that `is_buffer_too_small` holds if it doesn’t fit.
+pub fn get_group_by_gid_in(gid: gid_t, buf: &mut [u8]) -> io::Result<Option<GroupEntry<'_>>> {
+    let entry = unsafe { entry_in(buf, |grp, buf, len, result| libc::getgrgid_r(gid, grp, buf, len, result))? };
+    Ok(entry.map(|entry| GroupEntry { entry, _buf: PhantomData }))
+}
+
+/// Looks up the group with the given name with `getgrnam_r`, into the given
+/// buffer, failing so that `is_buffer_too_small` holds if it doesn’t fit.
+pub fn get_group_by_name_in<'buf, S: AsRef<OsStr> + ?Sized>(groupname: &S, buf: &'buf mut [u8]) -> io::Result<Option<GroupEntry<'buf>>> {
+    let groupname = match CString::new(groupname.as_ref().as_bytes()) {
+        Ok(groupname) => groupname,
+        Err(_)        => return Ok(None),
+    };
+
+    let entry = unsafe { entry_in(buf, |grp, buf, len, result| libc::getgrnam_r(groupname.as_ptr(), grp, buf, len, result))? };
+    Ok(entry.map(|entry| GroupEntry { entry, _buf: PhantomData }))
+}
+
+unsafe fn from_c<'a>(p: *const c_char) -> &'a OsStr {
+    if p.is_null() {
+        return OsStr::new("");
+    }
+
+    OsStr::from_bytes(CStr::from_ptr(p).to_bytes())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use os::unix::{UserExt, GroupExt};
+
+    #[test]
+    fn root() {
+        let mut buf = vec![0; INITIAL_BUFFER];
+        let user = get_user_by_uid_in(0, &mut buf).unwrap().unwrap();
+        assert_eq!(user.name(), OsStr::new("root"));
+        assert_eq!(user.to_user().home_dir(), user.home_dir());
+    }
+
+    #[test]
+    fn too_small() {
+        let mut buf = [0; 1];
+        let error = get_user_by_uid_in(0, &mut buf).err().unwrap();
+        assert!(is_buffer_too_small(&error));
+    }
+
+    #[test]
+    fn groups() {
+        let mut buf = vec![0; INITIAL_BUFFER];
+        let group = get_group_by_gid_in(0, &mut buf).unwrap().unwrap();
+        let name = group.name().to_owned();
+        assert_eq!(group.to_group().members().len(), group.members().count());
+
+        let mut other = vec![0; INITIAL_BUFFER];
+        assert_eq!(get_group_by_name_in(&name, &mut other).unwrap().map(|g| g.gid()), Some(0));
+        assert!(get_user_by_name_in("no such user, surely", &mut other).unwrap().is_none());
+    }
+}
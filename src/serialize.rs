@@ -0,0 +1,342 @@
+//! Serializing and deserializing `User`s and `Group`s with `serde`.
+//!
+//! With the stub, `User` and `Group` implement `Serialize` and `Deserialize`
+//! themselves. The real crate’s types can’t be given impls from here, so
+//! for those, and for code that has to work with both, the `user` and
+//! `group` modules can be used with `#[serde(with = "…")]`:
+//!
+//! ```
+//! #[macro_use] extern crate serde_derive;
+//! # extern crate serde_json;
+//! # extern crate users;
+//! use users::User;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Session {
+//!     #[serde(with = "users::serialize::user")]
+//!     user: User,
+//! }
+//!
+//! # fn main() {
+//! let session = Session { user: User::new(1000, "alice", 100) };
+//! let json = serde_json::to_string(&session).unwrap();
+//! let session: Session = serde_json::from_str(&json).unwrap();
+//! assert_eq!(session.user.name(), "alice");
+//! # }
+//! ```
+//!
+//! Users are written with their home directory, shell, password, and GECOS
+//! field, as well as the fields only the stub keeps, such as the password
+//! times, when they are set. Names and paths have to be valid UTF-8. The
+//! real crate’s users can’t keep a GECOS field, so with it, the host’s is
+//! written, and it is dropped when users are read back.
+
+use std::path::Path;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::{de, ser};
+use serde::ser::Error;
+
+use {User, Group, UserBuilder, GroupBuilder, uid_t, gid_t};
+use os::unix::{UserExt, GroupExt};
+
+#[cfg(not(users_passthrough))]
+use os::bsd::UserExt as BsdUserExt;
+
+#[cfg(all(windows, not(users_passthrough)))]
+use os::windows::UserExt as WindowsUserExt;
+
+
+/// The form a `User` takes when serialized.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct UserForm {
+    pub(crate) uid: uid_t,
+    name: String,
+    primary_group: gid_t,
+
+    #[serde(default)]
+    home_dir: Option<String>,
+
+    #[serde(default)]
+    shell: Option<String>,
+
+    #[serde(default)]
+    password: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gecos: Option<String>,
+
+    #[cfg(not(users_passthrough))]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    password_change_time: i64,
+
+    #[cfg(not(users_passthrough))]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    password_expire_time: i64,
+
+    #[cfg(all(windows, not(users_passthrough)))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sid: Option<String>,
+
+    #[cfg(all(windows, not(users_passthrough)))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    domain: Option<String>,
+
+    #[cfg(all(windows, not(users_passthrough)))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    profile_dir: Option<String>,
+
+    #[cfg(all(windows, not(users_passthrough)))]
+    #[serde(default, skip_serializing_if = "is_false")]
+    disabled: bool,
+}
+
+/// The form a `Group` takes when serialized.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GroupForm {
+    pub(crate) gid: gid_t,
+    name: String,
+
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+#[cfg(not(users_passthrough))]
+fn is_zero(n: &i64) -> bool {
+    *n == 0
+}
+
+#[cfg(all(windows, not(users_passthrough)))]
+fn is_false(b: &bool) -> bool {
+    ! *b
+}
+
+fn utf8<E: Error, S: AsRef<::std::ffi::OsStr> + ?Sized>(what: &str, s: &S) -> Result<String, E> {
+    s.as_ref().to_str().map(str::to_owned).ok_or_else(|| E::custom(format!("{} is not valid UTF-8", what)))
+}
+
+fn path<E: Error>(what: &str, p: &Path) -> Result<String, E> {
+    utf8(what, p.as_os_str())
+}
+
+
+impl UserForm {
+
+    /// Takes the fields of a user, failing if one that has to be a string
+    /// isn’t valid UTF-8.
+    pub(crate) fn from_user<E: ser::Error>(user: &User) -> Result<Self, E> {
+        Ok(UserForm {
+            uid: user.uid(),
+            name: utf8("user name", user.name())?,
+            primary_group: user.primary_group_id(),
+            home_dir: Some(path("home directory", user.home_dir())?),
+            shell: Some(path("shell", user.shell())?),
+            password: Some(utf8("password", user.password())?),
+
+            gecos: match ::gecos::field(user) {
+                Some(ref gecos) if ! gecos.is_empty() => Some(utf8("GECOS field", gecos)?),
+                _                                     => None,
+            },
+
+            #[cfg(not(users_passthrough))]
+            password_change_time: user.password_change_time(),
+
+            #[cfg(not(users_passthrough))]
+            password_expire_time: user.password_expire_time(),
+
+            #[cfg(all(windows, not(users_passthrough)))]
+            sid: user.sid().map(ToString::to_string),
+
+            #[cfg(all(windows, not(users_passthrough)))]
+            domain: user.domain().map(|d| utf8("domain", d)).transpose()?,
+
+            #[cfg(all(windows, not(users_passthrough)))]
+            profile_dir: user.profile_dir().map(|p| path("profile directory", p)).transpose()?,
+
+            #[cfg(all(windows, not(users_passthrough)))]
+            disabled: user.is_disabled(),
+        })
+    }
+
+    /// Builds the user back up. Fields that are left out get the same
+    /// dummy values as `User::new` gives them.
+    pub(crate) fn into_user<E: de::Error>(self) -> Result<User, E> {
+        let mut builder = UserBuilder::new(self.uid, &self.name, self.primary_group);
+        if let Some(home_dir) = self.home_dir {
+            builder = builder.home_dir(&home_dir);
+        }
+        if let Some(shell) = self.shell {
+            builder = builder.shell(&shell);
+        }
+        if let Some(password) = self.password {
+            builder = builder.password(&password);
+        }
+        if let Some(gecos) = self.gecos {
+            builder = builder.gecos(&gecos);
+        }
+
+        let user = builder.build();
+
+        #[cfg(not(users_passthrough))]
+        let user = user.with_password_times(self.password_change_time, self.password_expire_time);
+
+        #[cfg(all(windows, not(users_passthrough)))]
+        let user = {
+            let mut user = user;
+            if let Some(sid) = self.sid {
+                user = user.with_sid(sid.parse().map_err(E::custom)?);
+            }
+            if let Some(domain) = self.domain {
+                user = user.with_domain(&domain);
+            }
+            if let Some(profile_dir) = self.profile_dir {
+                user = user.with_profile_dir(&profile_dir);
+            }
+            user.with_disabled(self.disabled)
+        };
+
+        Ok(user)
+    }
+}
+
+impl GroupForm {
+
+    /// Takes the fields of a group, failing if its name, or the name of one
+    /// of its members, isn’t valid UTF-8.
+    pub(crate) fn from_group<E: ser::Error>(group: &Group) -> Result<Self, E> {
+        Ok(GroupForm {
+            gid: group.gid(),
+            name: utf8("group name", group.name())?,
+            members: group.members().iter().map(|m| utf8("member name", m)).collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Builds the group back up.
+    pub(crate) fn into_group(self) -> Group {
+        GroupBuilder::new(self.gid, &self.name).members(&self.members).build()
+    }
+}
+
+
+/// Serializing and deserializing `User`s, for `#[serde(with = "…")]`.
+pub mod user {
+    use super::*;
+
+    /// Serializes a user.
+    pub fn serialize<S: Serializer>(user: &User, serializer: S) -> Result<S::Ok, S::Error> {
+        UserForm::from_user(user)?.serialize(serializer)
+    }
+
+    /// Deserializes a user. Fields that are left out get the same dummy
+    /// values as `User::new` gives them.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<User, D::Error> {
+        UserForm::deserialize(deserializer)?.into_user()
+    }
+}
+
+
+/// Serializing and deserializing `Group`s, for `#[serde(with = "…")]`.
+pub mod group {
+    use super::*;
+
+    /// Serializes a group.
+    pub fn serialize<S: Serializer>(group: &Group, serializer: S) -> Result<S::Ok, S::Error> {
+        GroupForm::from_group(group)?.serialize(serializer)
+    }
+
+    /// Deserializes a group.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Group, D::Error> {
+        Ok(GroupForm::deserialize(deserializer)?.into_group())
+    }
+}
+
+
+#[cfg(not(users_passthrough))]
+impl Serialize for User {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        user::serialize(self, serializer)
+    }
+}
+
+#[cfg(not(users_passthrough))]
+impl<'de> Deserialize<'de> for User {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        user::deserialize(deserializer)
+    }
+}
+
+#[cfg(not(users_passthrough))]
+impl Serialize for Group {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        group::serialize(self, serializer)
+    }
+}
+
+#[cfg(not(users_passthrough))]
+impl<'de> Deserialize<'de> for Group {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        group::deserialize(deserializer)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+    use UserBuilderExt;
+
+    #[derive(Serialize, Deserialize)]
+    struct Both {
+        #[serde(with = "user")]
+        user: User,
+
+        #[serde(with = "group")]
+        group: Group,
+    }
+
+    #[test]
+    fn round_trip() {
+        let both = Both {
+            user: User::builder(1000, "alice", 100).home_dir("/home/alice").shell("/bin/zsh").build(),
+            group: Group::new(10, "wheel").add_member("alice"),
+        };
+
+        let json = serde_json::to_string(&both).unwrap();
+        let both: Both = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(both.user.name(), "alice");
+        assert_eq!(both.user.primary_group_id(), 100);
+        assert_eq!(both.user.home_dir(), Path::new("/home/alice"));
+        assert_eq!(both.user.shell(), Path::new("/bin/zsh"));
+        assert_eq!(both.group.gid(), 10);
+        assert_eq!(both.group.members(), &[ "alice".into() ][..] as &[::std::ffi::OsString]);
+    }
+
+    #[test]
+    fn dummy_fields() {
+        let json = r#"{ "uid": 1000, "name": "alice", "primary_group": 100 }"#;
+        let both = format!(r#"{{ "user": {}, "group": {{ "gid": 100, "name": "users" }} }}"#, json);
+        let both: Both = serde_json::from_str(&both).unwrap();
+
+        let dummy = User::new(1000, "alice", 100);
+        assert_eq!(both.user.home_dir(), dummy.home_dir());
+        assert_eq!(both.user.shell(), dummy.shell());
+        assert!(both.group.members().is_empty());
+    }
+
+    #[test]
+    #[cfg(not(users_passthrough))]
+    fn stub_extras() {
+        use gecos::GecosExt;
+
+        let user = User::builder(1000, "alice", 100).gecos("Alice,,,").build().with_password_times(10, 20);
+        let user: User = serde_json::from_str(&serde_json::to_string(&user).unwrap()).unwrap();
+
+        assert_eq!(user.full_name(), Some("Alice".into()));
+        assert_eq!(user.password_change_time(), 10);
+        assert_eq!(user.password_expire_time(), 20);
+    }
+}
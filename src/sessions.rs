@@ -0,0 +1,352 @@
+//! Listing the users logged in right now.
+//!
+//! On Unix, the login sessions are those `who` lists, from the utmpx
+//! database. On Windows with the `windows-native` feature, they are the
+//! Remote Desktop Services sessions with a user logged on, including the
+//! console one:
+//!
+//! ```no_run
+//! use users::sessions::sessions;
+//!
+//! for session in sessions().unwrap() {
+//!     println!("{:?} on {:?}", session.username, session.tty);
+//! }
+//! ```
+//!
+//! Elsewhere, including with the stub, listing them fails with
+//! `ErrorKind::Unsupported`.
+//!
+//! The time a user last logged in, which account audits use to find
+//! dormant accounts, comes from `/var/log/lastlog` or else
+//! `/var/log/wtmp` on Linux, and from the account itself for local
+//! accounts on Windows:
+//!
+//! ```no_run
+//! use users::{all_users_snapshot, sessions::last_login_for};
+//!
+//! for user in all_users_snapshot() {
+//!     if last_login_for(user.uid()).unwrap().is_none() {
+//!         println!("{:?} has never logged in", user.name());
+//!     }
+//! }
+//! ```
+
+use std::ffi::OsString;
+use std::io;
+use std::time::SystemTime;
+
+#[cfg(target_os = "linux")]
+use std::ffi::OsStr;
+
+#[cfg(target_os = "linux")]
+use std::fs::File;
+
+#[cfg(target_os = "linux")]
+use std::io::{Read, Seek, SeekFrom};
+
+#[cfg(users_utmpx)]
+use std::os::raw::{c_char, c_short};
+
+#[cfg(users_utmpx)]
+use std::os::unix::ffi::OsStringExt;
+
+#[cfg(target_os = "linux")]
+use std::os::unix::ffi::OsStrExt;
+
+#[cfg(users_utmpx)]
+use std::sync::Mutex;
+
+#[cfg(any(users_utmpx, target_os = "linux"))]
+use std::time::{Duration, UNIX_EPOCH};
+
+#[cfg(users_utmpx)]
+use libc;
+
+use uid_t;
+
+#[cfg(target_os = "linux")]
+use get_user_by_uid;
+
+#[cfg(all(windows, feature = "windows-native"))]
+use windows;
+
+
+/// The file holding the last login of every user, indexed by user ID.
+pub const LASTLOG_PATH: &str = "/var/log/lastlog";
+
+/// The file logging every login and logout.
+pub const WTMP_PATH: &str = "/var/log/wtmp";
+
+
+/// A user’s login session.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Session {
+
+    /// The name of the user logged in.
+    pub username: OsString,
+
+    /// The terminal the session is on, such as `pts/0` or `tty1`, or on
+    /// Windows, its window station, such as `Console` or `RDP-Tcp#0`.
+    pub tty: OsString,
+
+    /// The host the user logged in from, for a remote session.
+    pub host: Option<OsString>,
+
+    /// When the user logged in.
+    pub login_time: SystemTime,
+}
+
+
+/// When and where a user last logged in.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct LastLogin {
+
+    /// When the user logged in.
+    pub time: SystemTime,
+
+    /// The terminal the user logged in on, if recorded.
+    pub tty: Option<OsString>,
+
+    /// The host the user logged in from, for a remote login, if recorded.
+    pub host: Option<OsString>,
+}
+
+
+/// Returns the current login sessions.
+///
+/// # Windows functions used
+///
+/// - [`WTSEnumerateSessionsW`](https://docs.microsoft.com/en-us/windows/win32/api/wtsapi32/nf-wtsapi32-wtsenumeratesessionsw)
+/// - [`WTSQuerySessionInformationW`](https://docs.microsoft.com/en-us/windows/win32/api/wtsapi32/nf-wtsapi32-wtsquerysessioninformationw)
+pub fn sessions() -> io::Result<Vec<Session>> {
+    #[cfg(users_utmpx)]
+    return Ok(utmpx_sessions());
+
+    #[cfg(all(windows, feature = "windows-native"))]
+    return windows::sessions();
+
+    #[cfg(not(any(users_utmpx, all(windows, feature = "windows-native"))))]
+    Err(io::Error::new(io::ErrorKind::Unsupported, "listing login sessions is not supported on this platform"))
+}
+
+/// Returns when the user with the given ID last logged in, or `None` if
+/// they never have, as far as the records go.
+///
+/// On Linux, this is their entry in `/var/log/lastlog`, or if it has none,
+/// their latest login in `/var/log/wtmp`, which can’t be read if missing.
+/// On Windows, it is the last logon of a local account, and domain accounts
+/// fail with `ErrorKind::Unsupported`, as each domain controller keeps its
+/// own. Elsewhere, it always fails with `ErrorKind::Unsupported`.
+///
+/// # Windows functions used
+///
+/// - [`NetUserGetInfo`](https://docs.microsoft.com/en-us/windows/win32/api/lmaccess/nf-lmaccess-netusergetinfo)
+pub fn last_login_for(uid: uid_t) -> io::Result<Option<LastLogin>> {
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(login) = read_lastlog(LASTLOG_PATH, uid)? {
+            return Ok(Some(login));
+        }
+
+        match get_user_by_uid(uid) {
+            Some(user) => Ok(parse_wtmp(&::std::fs::read(WTMP_PATH)?, user.name().as_bytes())),
+            None       => Ok(None),
+        }
+    }
+
+    #[cfg(all(windows, feature = "windows-native"))]
+    return windows::last_login_for(uid);
+
+    #[cfg(not(any(target_os = "linux", all(windows, feature = "windows-native"))))]
+    {
+        let _ = uid;
+        Err(io::Error::new(io::ErrorKind::Unsupported, "last logins are not recorded on this platform"))
+    }
+}
+
+
+/// Guards the utmpx database’s iteration, which isn’t thread-safe.
+#[cfg(users_utmpx)]
+static UTMPX: Mutex<()> = Mutex::new(());
+
+/// A user process entry, as opposed to a boot time or dead process one.
+#[cfg(all(users_utmpx, target_os = "freebsd"))]
+const USER_PROCESS: c_short = 4;
+
+/// A user process entry, as opposed to a boot time or dead process one.
+#[cfg(all(users_utmpx, not(target_os = "freebsd")))]
+const USER_PROCESS: c_short = 7;
+
+#[cfg(users_utmpx)]
+fn utmpx_sessions() -> Vec<Session> {
+    let _lock = UTMPX.lock().unwrap_or_else(|e| e.into_inner());
+    let mut sessions = Vec::new();
+
+    unsafe {
+        libc::setutxent();
+        loop {
+            let entry = libc::getutxent();
+            if entry.is_null() {
+                break;
+            }
+
+            let entry = &*entry;
+            if entry.ut_type != USER_PROCESS {
+                continue;
+            }
+
+            let host = field(&entry.ut_host);
+            sessions.push(Session {
+                username:   field(&entry.ut_user),
+                tty:        field(&entry.ut_line),
+                host:       if host.is_empty() { None } else { Some(host) },
+                login_time: timestamp(entry.ut_tv.tv_sec, entry.ut_tv.tv_usec),
+            });
+        }
+        libc::endutxent();
+    }
+
+    sessions
+}
+
+/// Reads a fixed-size field, which is only terminated if it isn’t full.
+#[cfg(users_utmpx)]
+fn field(chars: &[c_char]) -> OsString {
+    let bytes = chars.iter().map(|&c| c as u8).take_while(|&b| b != 0).collect::<Vec<_>>();
+    OsString::from_vec(bytes)
+}
+
+/// The time in a `timeval`, whose fields are 32 bits wide on some platforms.
+#[cfg(users_utmpx)]
+#[allow(clippy::useless_conversion)]
+fn timestamp<S: Into<i64>, U: Into<i64>>(seconds: S, micros: U) -> SystemTime {
+    let (seconds, micros) = (seconds.into(), micros.into());
+    let since = Duration::from_secs(seconds.unsigned_abs()) + Duration::from_micros(micros.unsigned_abs());
+    if seconds >= 0 { UNIX_EPOCH + since } else { UNIX_EPOCH - since }
+}
+
+
+/// The size of a `lastlog` record: a 32-bit time, then the line and host.
+#[cfg(target_os = "linux")]
+const LASTLOG_SIZE: usize = 4 + 32 + 256;
+
+/// The size of a `utmp` record, as in `wtmp`, with 32-bit times.
+#[cfg(target_os = "linux")]
+const UTMP_SIZE: usize = 384;
+
+/// Reads the record of a user ID from a `lastlog` file, where records past
+/// the end or with no time mean the user never logged in, as does a
+/// missing file.
+#[cfg(target_os = "linux")]
+fn read_lastlog(path: &str, uid: uid_t) -> io::Result<Option<LastLogin>> {
+    let mut file = match File::open(path) {
+        Ok(file)                                          => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e)                                            => return Err(e),
+    };
+
+    let mut record = [0; LASTLOG_SIZE];
+    file.seek(SeekFrom::Start(u64::from(uid) * LASTLOG_SIZE as u64))?;
+    match file.read_exact(&mut record) {
+        Ok(())                                                 => Ok(parse_lastlog(&record)),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e)                                                 => Err(e),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn parse_lastlog(record: &[u8; LASTLOG_SIZE]) -> Option<LastLogin> {
+    let seconds = u32::from_ne_bytes([ record[0], record[1], record[2], record[3] ]);
+    if seconds == 0 {
+        return None;
+    }
+
+    Some(LastLogin {
+        time: UNIX_EPOCH + Duration::from_secs(u64::from(seconds)),
+        tty:  bytes_field(&record[4 .. 36]),
+        host: bytes_field(&record[36 ..]),
+    })
+}
+
+/// Finds the latest login of the user with the given name in the contents
+/// of a `wtmp` file.
+#[cfg(target_os = "linux")]
+fn parse_wtmp(contents: &[u8], username: &[u8]) -> Option<LastLogin> {
+    contents.chunks_exact(UTMP_SIZE).rev()
+        .find(|record| i16::from_ne_bytes([ record[0], record[1] ]) == 7 && trim_nul(&record[44 .. 76]) == username)
+        .map(|record| {
+            let seconds = u32::from_ne_bytes([ record[340], record[341], record[342], record[343] ]);
+            LastLogin {
+                time: UNIX_EPOCH + Duration::from_secs(u64::from(seconds)),
+                tty:  bytes_field(&record[8 .. 40]),
+                host: bytes_field(&record[76 .. 332]),
+            }
+        })
+}
+
+#[cfg(target_os = "linux")]
+fn trim_nul(bytes: &[u8]) -> &[u8] {
+    &bytes[.. bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len())]
+}
+
+#[cfg(target_os = "linux")]
+fn bytes_field(bytes: &[u8]) -> Option<OsString> {
+    Some(OsStr::from_bytes(trim_nul(bytes)).to_os_string()).filter(|s| ! s.is_empty())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(users_utmpx)]
+    fn fields() {
+        let chars = [ b'p' as c_char, b't' as c_char, b's' as c_char, b'/' as c_char, b'0' as c_char, 0, b'x' as c_char ];
+        assert_eq!(field(&chars), OsString::from("pts/0"));
+        assert_eq!(field(&[ b'a' as c_char; 4 ]), OsString::from("aaaa"));
+        assert_eq!(timestamp(1, 500_000i32), UNIX_EPOCH + Duration::from_millis(1500));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn lastlog() {
+        let mut record = [0; LASTLOG_SIZE];
+        assert_eq!(parse_lastlog(&record), None);
+
+        record[.. 4].copy_from_slice(&1_600_000_000_u32.to_ne_bytes());
+        record[4 .. 9].copy_from_slice(b"pts/1");
+        assert_eq!(parse_lastlog(&record), Some(LastLogin {
+            time: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+            tty:  Some("pts/1".into()),
+            host: None,
+        }));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wtmp() {
+        let record = |kind: i16, user: &[u8], time: u32, host: &[u8]| {
+            let mut record = vec![0; UTMP_SIZE];
+            record[.. 2].copy_from_slice(&kind.to_ne_bytes());
+            record[44 .. 44 + user.len()].copy_from_slice(user);
+            record[76 .. 76 + host.len()].copy_from_slice(host);
+            record[340 .. 344].copy_from_slice(&time.to_ne_bytes());
+            record
+        };
+
+        let contents = [ record(7, b"alice", 100, b"example.com"), record(7, b"bob", 200, b""), record(8, b"alice", 300, b"") ].concat();
+        let login = parse_wtmp(&contents, b"alice").unwrap();
+        assert_eq!(login.time, UNIX_EPOCH + Duration::from_secs(100));
+        assert_eq!(login.host, Some("example.com".into()));
+        assert_eq!(parse_wtmp(&contents, b"carol"), None);
+    }
+
+    #[test]
+    fn listing() {
+        match sessions() {
+            Ok(sessions) => assert!(sessions.iter().all(|s| ! s.username.is_empty())),
+            Err(e)       => assert_eq!(e.kind(), io::ErrorKind::Unsupported),
+        }
+    }
+}
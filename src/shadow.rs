@@ -0,0 +1,398 @@
+//! Reading users’ entries in the shadow password database.
+//!
+//! The password hashes, and the rules for when passwords have to be
+//! changed and accounts expire, are kept apart from the rest of a user’s
+//! entry, in `/etc/shadow`, which only root can read:
+//!
+//! ```no_run
+//! use users::shadow::get_shadow_by_name;
+//!
+//! match get_shadow_by_name("alice") {
+//!     Ok(Some(shadow)) => println!("Password last changed on day {:?}", shadow.last_change),
+//!     Ok(None)         => println!("No such user"),
+//!     Err(e)           => println!("Can’t read the shadow database: {}", e),
+//! }
+//! ```
+//!
+//! On Linux, entries are looked up through the C library, so they can come
+//! from any NSS module that has them. Everywhere else, `/etc/shadow` is
+//! read directly, if there is one.
+//!
+//! The days are counted from the 1st of January 1970. Fields left empty in
+//! the entry are `None`.
+//!
+//! ## Password aging
+//!
+//! Rather than working the days out by hand, `Shadow` can say when the
+//! password and the account expire, and `ShadowExt` does the same for a
+//! `User`, looking their entry up first:
+//!
+//! ```no_run
+//! use users::{User, ShadowExt};
+//!
+//! let user = User::new(1000, "alice", 100);
+//! if let Ok(Some(days)) = user.days_until_expiry() {
+//!     println!("alice’s password expires in {} days", days);
+//! }
+//! ```
+//!
+//! A maximum age of 99999 days, the usual way of saying “never”, counts as
+//! no maximum at all, as does an expiry day of 0, which the login tools
+//! ignore.
+//!
+//! This is only available with the `shadow` feature.
+
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use User;
+use accounts::is_locked_password;
+use etc::{entry_fields, parse_name, parse_number, ParseError};
+
+
+/// The file the shadow entries are read from, where the C library isn’t
+/// asked for them.
+pub const SHADOW_PATH: &str = "/etc/shadow";
+
+
+/// The maximum password age that means it never has to be changed.
+const NEVER: i64 = 99999;
+
+/// The number of seconds in a day.
+const DAY: u64 = 24 * 60 * 60;
+
+
+/// A user’s entry in the shadow password database.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Shadow {
+
+    /// The user’s login name.
+    pub name: OsString,
+
+    /// The user’s password hash, or a marker such as `!` or `*` for
+    /// accounts that can’t log in with a password.
+    pub password: OsString,
+
+    /// The day the password was last changed. Day 0 means it has to be
+    /// changed at the next login.
+    pub last_change: Option<i64>,
+
+    /// The number of days after a change before the password can be
+    /// changed again.
+    pub min_age: Option<i64>,
+
+    /// The number of days after a change before the password has to be
+    /// changed again.
+    pub max_age: Option<i64>,
+
+    /// The number of days before the password has to be changed that the
+    /// user is warned about it.
+    pub warn_period: Option<i64>,
+
+    /// The number of days after the password has to be changed that it is
+    /// still accepted, so that it can be.
+    pub inactive_period: Option<i64>,
+
+    /// The day the account expires.
+    pub expire: Option<i64>,
+}
+
+impl Shadow {
+
+    /// Parses a single
+    /// `name:password:change:min:max:warn:inactive:expire:reserved` line.
+    pub fn from_shadow_line(line: &str) -> Result<Self, ParseError> {
+        let mut fields = entry_fields(line)?;
+        while fields.len() > 9 && fields.last() == Some(&"") {
+            fields.pop();
+        }
+
+        if fields.len() != 9 {
+            return Err(ParseError::FieldCount { expected: 9, found: fields.len() });
+        }
+
+        let days = |field: &str| if field.is_empty() { Ok(None) } else { parse_number(field).map(Some) };
+
+        Ok(Shadow {
+            name: parse_name(fields[0])?.into(),
+            password: fields[1].into(),
+            last_change: days(fields[2])?,
+            min_age: days(fields[3])?,
+            max_age: days(fields[4])?,
+            warn_period: days(fields[5])?,
+            inactive_period: days(fields[6])?,
+            expire: days(fields[7])?,
+        })
+    }
+
+    /// Returns when the password has to be changed, or `None` if it never
+    /// has to be. A password that has to be changed at the next login
+    /// expired on the 1st of January 1970. Days too far off to be a
+    /// `SystemTime` count as never.
+    pub fn password_expires_at(&self) -> Option<SystemTime> {
+        self.password_expiry_day().and_then(to_time)
+    }
+
+    /// Returns when the account expires, or `None` if it never does, or
+    /// is too far off to be a `SystemTime`.
+    pub fn account_expires_at(&self) -> Option<SystemTime> {
+        self.account_expiry_day().and_then(to_time)
+    }
+
+    /// Returns whether the user can no longer log in at all: either the
+    /// account has expired, or the password has, and the days it was
+    /// still accepted for have passed too.
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_on(today())
+    }
+
+    /// Returns the number of days until the password has to be changed,
+    /// which is negative if it already had to be, or `None` if it never
+    /// has to be.
+    pub fn days_until_expiry(&self) -> Option<i64> {
+        self.days_until_expiry_on(today())
+    }
+
+    /// Returns whether the password locks the account, as a password
+    /// starting with `!` or `*` does.
+    pub fn is_locked(&self) -> bool {
+        is_locked_password(&self.password)
+    }
+
+    fn password_expiry_day(&self) -> Option<i64> {
+        match (self.last_change?, self.max_age?) {
+            (_, max) if ! (0 .. NEVER).contains(&max) => None,
+            (0, _)                                    => Some(0),
+            (change, max)                             => change.checked_add(max),
+        }
+    }
+
+    fn account_expiry_day(&self) -> Option<i64> {
+        self.expire.filter(|&day| day > 0)
+    }
+
+    fn is_expired_on(&self, today: i64) -> bool {
+        if self.account_expiry_day().map_or(false, |day| today >= day) {
+            return true;
+        }
+
+        match (self.password_expiry_day(), self.inactive_period) {
+            (Some(day), Some(inactive)) if day > 0 && inactive >= 0 => day.checked_add(inactive).map_or(false, |end| today >= end),
+            _                                                        => false,
+        }
+    }
+
+    fn days_until_expiry_on(&self, today: i64) -> Option<i64> {
+        self.password_expiry_day().and_then(|day| day.checked_sub(today))
+    }
+}
+
+/// Returns the start of the given day, if a `SystemTime` can hold it.
+fn to_time(day: i64) -> Option<SystemTime> {
+    let secs = (day.max(0) as u64).checked_mul(DAY)?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+/// Returns the number of the current day.
+fn today() -> i64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    (now.as_secs() / DAY) as i64
+}
+
+
+/// Password aging for `User`s, from their shadow entries.
+///
+/// Every method looks the user’s entry up by name, failing if the database
+/// can’t be read. Users without an entry never expire.
+pub trait ShadowExt {
+
+    /// Returns this user’s shadow entry, if they have one.
+    fn shadow(&self) -> io::Result<Option<Shadow>>;
+
+    /// Returns when this user’s password has to be changed, like
+    /// `Shadow::password_expires_at`.
+    fn password_expires_at(&self) -> io::Result<Option<SystemTime>>;
+
+    /// Returns whether this user can no longer log in at all, like
+    /// `Shadow::is_expired`.
+    fn is_expired(&self) -> io::Result<bool>;
+
+    /// Returns the number of days until this user’s password has to be
+    /// changed, like `Shadow::days_until_expiry`.
+    fn days_until_expiry(&self) -> io::Result<Option<i64>>;
+}
+
+impl ShadowExt for User {
+    fn shadow(&self) -> io::Result<Option<Shadow>> {
+        get_shadow_by_name(self.name())
+    }
+
+    fn password_expires_at(&self) -> io::Result<Option<SystemTime>> {
+        Ok(self.shadow()?.and_then(|s| s.password_expires_at()))
+    }
+
+    fn is_expired(&self) -> io::Result<bool> {
+        Ok(self.shadow()?.map_or(false, |s| s.is_expired()))
+    }
+
+    fn days_until_expiry(&self) -> io::Result<Option<i64>> {
+        Ok(self.shadow()?.and_then(|s| s.days_until_expiry()))
+    }
+}
+
+
+/// Searches for the shadow entry of the user with the given name. Reading
+/// the database usually fails unless the process runs as root.
+pub fn get_shadow_by_name<S: AsRef<OsStr> + ?Sized>(username: &S) -> io::Result<Option<Shadow>> {
+    imp::get_shadow_by_name(username.as_ref())
+}
+
+
+#[cfg(all(target_os = "linux", users_passthrough))]
+mod imp {
+    use std::ffi::{CStr, CString, OsStr, OsString};
+    use std::io;
+    use std::os::raw::{c_char, c_long};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    use libc;
+
+    use reentrant::with_entry;
+    use super::Shadow;
+
+    pub fn get_shadow_by_name(username: &OsStr) -> io::Result<Option<Shadow>> {
+        let username = match CString::new(username.as_bytes()) {
+            Ok(username) => username,
+            Err(_)       => return Ok(None),
+        };
+
+        unsafe {
+            with_entry(|spwd, buf, len, result| libc::getspnam_r(username.as_ptr(), spwd, buf, len, result), |entry| spwd_to_shadow(entry))
+        }
+    }
+
+    // `c_long` is only `i64` on 64-bit targets.
+    #[allow(clippy::useless_conversion)]
+    unsafe fn spwd_to_shadow(entry: &libc::spwd) -> Shadow {
+        let string = |p: *const c_char| {
+            if p.is_null() { OsString::new() } else { OsString::from_vec(CStr::from_ptr(p).to_bytes().to_vec()) }
+        };
+
+        // The C library uses -1 for empty fields.
+        let days = |n: c_long| if n == -1 { None } else { Some(i64::from(n)) };
+
+        Shadow {
+            name: string(entry.sp_namp),
+            password: string(entry.sp_pwdp),
+            last_change: days(entry.sp_lstchg),
+            min_age: days(entry.sp_min),
+            max_age: days(entry.sp_max),
+            warn_period: days(entry.sp_warn),
+            inactive_period: days(entry.sp_inact),
+            expire: days(entry.sp_expire),
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", users_passthrough)))]
+mod imp {
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::io;
+
+    use super::{Shadow, SHADOW_PATH};
+
+    pub fn get_shadow_by_name(username: &OsStr) -> io::Result<Option<Shadow>> {
+        let contents = match fs::read_to_string(SHADOW_PATH) {
+            Ok(contents)                                     => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e)                                           => return Err(e),
+        };
+
+        Ok(contents.lines()
+            .filter_map(|line| Shadow::from_shadow_line(line).ok())
+            .find(|shadow| shadow.name == username))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shadow_line() {
+        let shadow = Shadow::from_shadow_line("alice:$6$hash:19000:0:99999:7:::").unwrap();
+        assert_eq!(shadow, Shadow {
+            name: "alice".into(),
+            password: "$6$hash".into(),
+            last_change: Some(19000),
+            min_age: Some(0),
+            max_age: Some(99999),
+            warn_period: Some(7),
+            inactive_period: None,
+            expire: None,
+        });
+    }
+
+    #[test]
+    fn shadow_errors() {
+        assert_eq!(Shadow::from_shadow_line("alice:!:19000"), Err(ParseError::FieldCount { expected: 9, found: 3 }));
+        assert_eq!(Shadow::from_shadow_line("alice:!:soon::::::"), Err(ParseError::InvalidNumber("soon".into())));
+    }
+
+    #[test]
+    fn aging() {
+        let shadow = Shadow::from_shadow_line("alice:$6$hash:19000:0:90:7:14::").unwrap();
+        assert_eq!(shadow.password_expires_at(), Some(UNIX_EPOCH + Duration::from_secs(19090 * DAY)));
+        assert_eq!(shadow.days_until_expiry_on(19080), Some(10));
+        assert_eq!(shadow.days_until_expiry_on(19100), Some(-10));
+        assert!(! shadow.is_expired_on(19100));
+        assert!(shadow.is_expired_on(19104));
+        assert_eq!(shadow.account_expires_at(), None);
+    }
+
+    #[test]
+    fn never() {
+        let shadow = Shadow::from_shadow_line("root:*:19000:0:99999:7:::0").unwrap();
+        assert_eq!(shadow.password_expires_at(), None);
+        assert_eq!(shadow.account_expires_at(), None);
+        assert!(! shadow.is_expired_on(i64::from(u32::MAX)));
+
+        let shadow = Shadow::from_shadow_line("bob:$6$hash:0:0:90:7::19000:").unwrap();
+        assert_eq!(shadow.password_expires_at(), Some(UNIX_EPOCH));
+        assert!(! shadow.is_expired_on(18999));
+        assert!(shadow.is_expired_on(19000));
+    }
+
+    #[test]
+    fn far_off() {
+        let shadow = Shadow::from_shadow_line("alice:x:19000:0:99998:7::999999999999999:").unwrap();
+        assert_eq!(shadow.account_expires_at(), None);
+        assert!(! shadow.is_expired_on(19100));
+
+        let shadow = Shadow::from_shadow_line(&format!("alice:x:{}:0:99998:7:::", i64::MAX)).unwrap();
+        assert_eq!(shadow.password_expires_at(), None);
+        assert_eq!(shadow.days_until_expiry_on(19100), None);
+
+        let shadow = Shadow::from_shadow_line(&format!("alice:x:{}:0:99998:7:5::", i64::MAX - 99998)).unwrap();
+        assert_eq!(shadow.password_expires_at(), None);
+        assert!(! shadow.is_expired_on(19100));
+    }
+
+    #[test]
+    fn locked() {
+        assert!(Shadow::from_shadow_line("daemon:*:19000:0:99999:7:::").unwrap().is_locked());
+        assert!(Shadow::from_shadow_line("bob:!$6$hash:19000:0:99999:7:::").unwrap().is_locked());
+        assert!(! Shadow::from_shadow_line("alice:$6$hash:19000:0:99999:7:::").unwrap().is_locked());
+    }
+
+    #[test]
+    fn unknown_user() {
+        // Without root, reading the database may well fail instead.
+        if let Ok(shadow) = get_shadow_by_name("no such user") {
+            assert!(shadow.is_none());
+        }
+    }
+}
@@ -0,0 +1,488 @@
+//! A cache for users and groups that can be shared between threads.
+//!
+//! The [`UsersCache`](../cache/struct.UsersCache.html) keeps its maps in
+//! `RefCell`s, so it can’t be sent to or shared with other threads, and a
+//! program with several threads ends up with one cache each. The
+//! [`SharedUsersCache`](struct.SharedUsersCache.html) stores the same
+//! results behind locks instead, so one cache, in an `Arc` or a `static`,
+//! can serve all of them.
+//!
+//! Its maps are split into shards, each behind its own `RwLock`, so threads
+//! looking up different users rarely wait for each other. Looking up a user
+//! or group that’s already cached only takes a read lock on one shard, and
+//! the current and effective IDs are stored in atomics, taking no lock at
+//! all once known.
+//!
+//! ## Expiring entries
+//!
+//! A long-running daemon would never see new users, or groups that were
+//! renamed, if it kept their first answers for ever. A cache created with
+//! [`with_ttl`](struct.SharedUsersCache.html#method.with_ttl) lets entries
+//! expire after a while, and looks them up again the next time they are
+//! asked for. The current and effective IDs never expire, as only the
+//! process itself changes them.
+//!
+//! Users and groups that weren’t found, such as the owners of files whose
+//! accounts were deleted, are cached too, so that scanning a directory full
+//! of them doesn’t ask a network users database over and over. They can be
+//! given a time of their own with
+//! [`with_negative_ttl`](struct.SharedUsersCache.html#method.with_negative_ttl),
+//! or not be cached at all with
+//! [`without_negative_caching`](struct.SharedUsersCache.html#method.without_negative_caching).
+//!
+//! With the `watch-files` feature, every entry is also forgotten as soon as
+//! `/etc/passwd` or `/etc/group` changes, checked at most once a second.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use std::thread;
+//! use users::{Users, SharedUsersCache};
+//!
+//! let cache = Arc::new(SharedUsersCache::new());
+//!
+//! let handles = (0..4).map(|_| {
+//!     let cache = Arc::clone(&cache);
+//!     thread::spawn(move || cache.get_user_by_uid(502).map(|u| u.uid()))
+//! }).collect::<Vec<_>>();
+//!
+//! for handle in handles {
+//!     println!("{:?}", handle.join().unwrap());
+//! }
+//! ```
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use {User, Group, Users, Groups, uid_t, gid_t};
+
+#[cfg(feature = "watch-files")]
+use watch::Watch;
+
+
+/// How many shards each map is split into.
+const SHARDS: usize = 16;
+
+
+/// A producer of user and group instances that caches every result, and
+/// can be used from several threads at once.
+///
+/// For more information, see the [`users::shared_cache` module documentation](index.html).
+#[derive(Default)]
+pub struct SharedUsersCache {
+    users:  BiMap<uid_t, User>,
+    groups: BiMap<gid_t, Group>,
+
+    uid:  IdCell,
+    gid:  IdCell,
+    euid: IdCell,
+    egid: IdCell,
+
+    expiry: Expiry,
+
+    #[cfg(feature = "watch-files")]
+    watch: Watch,
+}
+
+/// A map split into shards by the hash of its keys.
+struct Sharded<K, V> {
+    shards: Vec<RwLock<HashMap<K, Entry<V>>>>,
+}
+
+/// A value in a map, with when it expires, if it does.
+struct Entry<V> {
+    value: V,
+    expires: Option<Instant>,
+}
+
+/// How long entries are kept for, if not for ever: one time for users and
+/// groups that were found, and another for those that weren’t.
+#[derive(Default, Clone, Copy)]
+struct Expiry {
+    found: Option<Duration>,
+    missing: Option<Duration>,
+}
+
+/// The same as the `UsersCache`’s: IDs to entries, and names back to IDs.
+struct BiMap<K, V> {
+    forward:  Sharded<K, Option<Arc<V>>>,
+    backward: Sharded<Arc<OsStr>, Option<K>>,
+}
+
+/// An ID that is looked up once, then read without locking.
+#[derive(Default)]
+struct IdCell {
+    known: AtomicBool,
+    id: AtomicU32,
+}
+
+
+impl<K: Hash + Eq, V> Default for Sharded<K, V> {
+    fn default() -> Self {
+        Self { shards: (0 .. SHARDS).map(|_| RwLock::new(HashMap::new())).collect() }
+    }
+}
+
+impl<K: Hash + Eq, V: Clone> Sharded<K, V> {
+
+    fn shard<Q: Hash + ?Sized>(&self, key: &Q) -> &RwLock<HashMap<K, Entry<V>>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARDS]
+    }
+
+    /// Returns the entry for the given key, if there is one that hasn’t
+    /// expired, taking only a read lock.
+    fn get<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<V>
+    where K: ::std::borrow::Borrow<Q>
+    {
+        let shard = self.shard(key).read().unwrap_or_else(|e| e.into_inner());
+        shard.get(key).filter(|e| e.is_fresh()).map(|e| e.value.clone())
+    }
+
+    /// Forgets every entry.
+    #[cfg(feature = "watch-files")]
+    fn clear(&self) {
+        for shard in &self.shards {
+            shard.write().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+    }
+
+    /// Inserts the given entry, to expire after the given time, unless
+    /// another thread got there first, and returns whichever is now in the
+    /// map.
+    fn insert(&self, key: K, value: V, ttl: Option<Duration>) -> V {
+        let mut shard = self.shard(&key).write().unwrap_or_else(|e| e.into_inner());
+        let expires = ttl.and_then(|ttl| Instant::now().checked_add(ttl));
+
+        let entry = shard.entry(key).or_insert_with(|| Entry { value: value.clone(), expires });
+        if ! entry.is_fresh() {
+            *entry = Entry { value, expires };
+        }
+
+        entry.value.clone()
+    }
+}
+
+impl<V> Entry<V> {
+    fn is_fresh(&self) -> bool {
+        match self.expires {
+            Some(expires)  => Instant::now() < expires,
+            None           => true,
+        }
+    }
+}
+
+impl Expiry {
+
+    /// Returns whether users and groups that weren’t found are stored at
+    /// all, which they aren’t if they would expire straight away.
+    fn keeps_missing(&self) -> bool {
+        self.missing != Some(Duration::from_secs(0))
+    }
+}
+
+impl<K: Hash + Eq, V> Default for BiMap<K, V> {
+    fn default() -> Self {
+        Self { forward: Sharded::default(), backward: Sharded::default() }
+    }
+}
+
+impl<K: Hash + Eq + Copy, V> BiMap<K, V> {
+
+    /// Forgets every entry.
+    #[cfg(feature = "watch-files")]
+    fn clear(&self) {
+        self.forward.clear();
+        self.backward.clear();
+    }
+
+    /// Returns the entry for the given ID, looking it up if it isn’t cached,
+    /// or has expired.
+    fn by_id<F, N>(&self, id: K, expiry: Expiry, lookup: F, name: N) -> Option<Arc<V>>
+    where F: FnOnce(K) -> Option<V>,
+          N: FnOnce(&V) -> &OsStr,
+    {
+        if let Some(entry) = self.forward.get(&id) {
+            return entry;
+        }
+
+        match lookup(id) {
+            Some(value) => {
+                let value = self.forward.insert(id, Some(Arc::new(value)), expiry.found);
+                if let Some(ref value) = value {
+                    self.backward.insert(Arc::from(name(value)), Some(id), expiry.found);
+                }
+                value
+            }
+            None if expiry.keeps_missing() => self.forward.insert(id, None, expiry.missing),
+            None => None,
+        }
+    }
+
+    /// Returns the entry for the given name, looking it up if it isn’t
+    /// cached, or has expired.
+    fn by_name<F, I, N>(&self, name: &OsStr, expiry: Expiry, lookup: F, id: I, name_of: N) -> Option<Arc<V>>
+    where F: FnOnce(&OsStr) -> Option<V>,
+          I: FnOnce(&V) -> K,
+          N: Fn(&V) -> &OsStr,
+    {
+        match self.backward.get(name) {
+            Some(None)      => return None,
+            Some(Some(key)) => match self.forward.get(&key) {
+                // The entry for the ID may have been looked up again since,
+                // and found renamed.
+                Some(Some(ref entry)) if name_of(entry) != name => {},
+                Some(entry) => return entry,
+                None        => {},
+            },
+            None            => {},
+        }
+
+        match lookup(name) {
+            Some(value) => {
+                let key = id(&value);
+                let value = self.forward.insert(key, Some(Arc::new(value)), expiry.found);
+                self.backward.insert(Arc::from(name), Some(key), expiry.found);
+                value
+            }
+            None => {
+                if expiry.keeps_missing() {
+                    self.backward.insert(Arc::from(name), None, expiry.missing);
+                }
+                None
+            }
+        }
+    }
+}
+
+impl IdCell {
+    fn get_or<F: FnOnce() -> u32>(&self, lookup: F) -> u32 {
+        if self.known.load(Ordering::Acquire) {
+            return self.id.load(Ordering::Relaxed);
+        }
+
+        let id = lookup();
+        self.id.store(id, Ordering::Relaxed);
+        self.known.store(true, Ordering::Release);
+        id
+    }
+}
+
+
+impl SharedUsersCache {
+
+    /// Creates a new empty cache.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use users::SharedUsersCache;
+    ///
+    /// let cache = SharedUsersCache::new();
+    /// ```
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new empty cache whose user and group entries expire after
+    /// the given time, and are looked up again the next time they are asked
+    /// for.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use users::SharedUsersCache;
+    ///
+    /// let cache = SharedUsersCache::with_ttl(Duration::from_secs(300));
+    /// ```
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self { expiry: Expiry { found: Some(ttl), missing: Some(ttl) }, ..Self::default() }
+    }
+
+    /// Makes users and groups that weren’t found expire after the given
+    /// time, instead of after the same time as those that were, or never.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use users::SharedUsersCache;
+    ///
+    /// let cache = SharedUsersCache::with_ttl(Duration::from_secs(300))
+    ///                              .with_negative_ttl(Duration::from_secs(10));
+    /// ```
+    pub fn with_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.expiry.missing = Some(ttl);
+        self
+    }
+
+    /// Makes the cache not store users and groups that weren’t found at
+    /// all, so they are looked up every time they are asked for.
+    pub fn without_negative_caching(self) -> Self {
+        self.with_negative_ttl(Duration::from_secs(0))
+    }
+
+    /// Forgets every user and group if the files they come from changed.
+    #[cfg(feature = "watch-files")]
+    fn check_files(&self) {
+        if self.watch.changed() {
+            self.users.clear();
+            self.groups.clear();
+        }
+    }
+
+    #[cfg(not(feature = "watch-files"))]
+    fn check_files(&self) {}
+}
+
+
+impl Users for SharedUsersCache {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        self.check_files();
+        self.users.by_id(uid, self.expiry, ::get_user_by_uid, User::name)
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        self.check_files();
+        self.users.by_name(username.as_ref(), self.expiry, ::get_user_by_name, User::uid, User::name)
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        self.uid.get_or(::get_current_uid)
+    }
+
+    fn get_current_username(&self) -> Option<Arc<OsStr>> {
+        let uid = self.get_current_uid();
+        self.get_user_by_uid(uid).map(|u| Arc::from(u.name()))
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        self.euid.get_or(::get_effective_uid)
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<OsStr>> {
+        let uid = self.get_effective_uid();
+        self.get_user_by_uid(uid).map(|u| Arc::from(u.name()))
+    }
+}
+
+
+impl Groups for SharedUsersCache {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        self.check_files();
+        self.groups.by_id(gid, self.expiry, ::get_group_by_gid, Group::name)
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+        self.check_files();
+        self.groups.by_name(group_name.as_ref(), self.expiry, ::get_group_by_name, Group::gid, Group::name)
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        self.gid.get_or(::get_current_gid)
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
+        let gid = self.get_current_gid();
+        self.get_group_by_gid(gid).map(|g| Arc::from(g.name()))
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        self.egid.get_or(::get_effective_gid)
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
+        let gid = self.get_effective_gid();
+        self.get_group_by_gid(gid).map(|g| Arc::from(g.name()))
+    }
+}
+
+
+#[cfg(test)]
+#[cfg(feature = "mock")]
+mod test {
+    use super::*;
+    use test::{override_backend, mock_alice};
+
+    #[test]
+    fn send_sync() {
+        fn send_sync<T: Send + Sync>(_: &T) {}
+        send_sync(&SharedUsersCache::new());
+    }
+
+    #[test]
+    fn same_arc() {
+        let _guard = override_backend(mock_alice());
+        let cache = SharedUsersCache::new();
+
+        let user = cache.get_user_by_uid(1000).unwrap();
+        let by_name = cache.get_user_by_name("alice").unwrap();
+        assert!(Arc::ptr_eq(&user, &by_name));
+        assert_eq!(cache.get_current_username(), Some(Arc::from(OsStr::new("alice"))));
+        assert_eq!(cache.get_group_by_name("users").map(|g| g.gid()), Some(100));
+    }
+
+    #[test]
+    fn remembers_missing() {
+        let cache = SharedUsersCache::new();
+
+        {
+            let _guard = override_backend(mock_alice());
+            assert!(cache.get_user_by_name("bob").is_none());
+            assert_eq!(cache.get_current_uid(), 1000);
+        }
+
+        let mut changed = mock_alice();
+        changed.add_user(User::new(1001, "bob", 100));
+        changed.set_current_uid(1001);
+        let _guard = override_backend(changed);
+        assert!(cache.get_user_by_name("bob").is_none());
+        assert_eq!(cache.get_current_uid(), 1000);
+    }
+
+    #[test]
+    fn expired() {
+        let cache = SharedUsersCache::with_ttl(Duration::from_secs(0));
+
+        {
+            let _guard = override_backend(mock_alice());
+            assert_eq!(cache.get_group_by_gid(100).map(|g| g.name().to_owned()), Some("users".into()));
+            assert!(cache.get_user_by_name("bob").is_none());
+        }
+
+        let mut changed = mock_alice();
+        changed.add_user(User::new(1001, "bob", 100));
+        changed.add_group(Group::new(100, "staff"));
+        let _guard = override_backend(changed);
+        assert_eq!(cache.get_group_by_gid(100).map(|g| g.name().to_owned()), Some("staff".into()));
+        assert!(cache.get_group_by_name("users").is_none());
+        assert!(cache.get_user_by_name("bob").is_some());
+    }
+
+    #[test]
+    fn missing_expire_separately() {
+        let cache = SharedUsersCache::new().with_negative_ttl(Duration::from_secs(0));
+
+        {
+            let _guard = override_backend(mock_alice());
+            assert!(cache.get_user_by_uid(1001).is_none());
+            assert!(cache.get_user_by_name("bob").is_none());
+            assert!(cache.get_user_by_uid(1000).is_some());
+        }
+
+        let mut changed = mock_alice();
+        changed.add_user(User::new(1001, "bob", 100));
+        changed.add_user(User::new(1000, "carol", 100));
+        let _guard = override_backend(changed);
+        assert!(cache.get_user_by_uid(1001).is_some());
+        assert_eq!(cache.get_user_by_uid(1000).map(|u| u.name().to_owned()), Some("alice".into()));
+    }
+}
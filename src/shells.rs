@@ -0,0 +1,110 @@
+//! Telling login shells apart from those that refuse logins.
+//!
+//! `/etc/shells` lists the shells users may choose to log in with, and
+//! accounts that shouldn’t be logged into are given a shell such as
+//! `nologin` or `false` instead. Together, they say which users are
+//! interactive ones:
+//!
+//! ```no_run
+//! use users::{all_users_snapshot, has_valid_shell};
+//!
+//! let interactive = all_users_snapshot().iter().filter(|u| has_valid_shell(u)).count();
+//! println!("{} users can log in", interactive);
+//! ```
+//!
+//! The file is read once, the first time it is needed, and the same list
+//! is used from then on. Without one, the shells are `/bin/sh` and
+//! `/bin/csh`, as the C library has it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use User;
+use os::unix::UserExt;
+
+
+/// The file listing the valid login shells.
+pub const SHELLS_PATH: &str = "/etc/shells";
+
+/// The shells used if there is no `/etc/shells`.
+const DEFAULT_SHELLS: &[&str] = &[ "/bin/sh", "/bin/csh" ];
+
+/// The names of programs that only refuse logins.
+const NOLOGIN_SHELLS: &[&str] = &[ "nologin", "false" ];
+
+
+static SHELLS: RwLock<Option<Arc<Vec<PathBuf>>>> = RwLock::new(None);
+
+/// Returns the valid login shells from `/etc/shells`, reading it the
+/// first time only.
+pub fn read_shells() -> Arc<Vec<PathBuf>> {
+    if let Some(ref shells) = *SHELLS.read().unwrap_or_else(|e| e.into_inner()) {
+        return Arc::clone(shells);
+    }
+
+    let shells = read_shells_from(SHELLS_PATH).unwrap_or_else(|_| DEFAULT_SHELLS.iter().map(PathBuf::from).collect());
+    Arc::clone(SHELLS.write().unwrap_or_else(|e| e.into_inner()).get_or_insert_with(|| Arc::new(shells)))
+}
+
+/// Reads the valid login shells from a file in the `/etc/shells` format,
+/// without caching them.
+pub fn read_shells_from<P: AsRef<Path>>(path: P) -> io::Result<Vec<PathBuf>> {
+    Ok(parse_shells(&fs::read_to_string(path)?))
+}
+
+/// Parses text in the `/etc/shells` format: one path per line, with blank
+/// lines and `#` comments skipped.
+pub fn parse_shells(contents: &str) -> Vec<PathBuf> {
+    contents.lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('/'))
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Returns whether a shell only refuses logins, such as
+/// `/usr/sbin/nologin` or `/bin/false`.
+pub fn is_nologin_shell<P: AsRef<Path>>(shell: P) -> bool {
+    let shell = shell.as_ref();
+    shell.as_os_str().is_empty() || shell.file_name().and_then(|n| n.to_str()).map_or(false, |n| NOLOGIN_SHELLS.contains(&n))
+}
+
+/// Returns whether a user’s shell is one they can log in with: one listed
+/// in `/etc/shells`, and not one that refuses logins.
+pub fn has_valid_shell(user: &User) -> bool {
+    let shell = user.shell();
+    ! is_nologin_shell(shell) && read_shells().iter().any(|s| s == shell)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use UserBuilderExt;
+
+    #[test]
+    fn parse() {
+        let shells = parse_shells("# /etc/shells: valid login shells\n/bin/sh\n\n  /usr/bin/zsh  \n/usr/sbin/nologin\n");
+        assert_eq!(shells, vec![ PathBuf::from("/bin/sh"), "/usr/bin/zsh".into(), "/usr/sbin/nologin".into() ]);
+    }
+
+    #[test]
+    fn nologin() {
+        assert!(is_nologin_shell("/usr/sbin/nologin"));
+        assert!(is_nologin_shell("/bin/false"));
+        assert!(is_nologin_shell(""));
+        assert!(! is_nologin_shell("/bin/sh"));
+    }
+
+    #[test]
+    fn users() {
+        assert!(! has_valid_shell(&User::builder(1, "daemon", 1).shell("/usr/sbin/nologin").build()));
+        assert!(! has_valid_shell(&User::builder(1000, "alice", 100).shell("/nonexistent/shell").build()));
+
+        if read_shells().iter().any(|s| s == Path::new("/bin/sh")) {
+            assert!(has_valid_shell(&User::builder(1000, "alice", 100).shell("/bin/sh").build()));
+        }
+    }
+}
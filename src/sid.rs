@@ -0,0 +1,369 @@
+//! Mapping between Windows security identifiers and numeric IDs.
+//!
+//! Windows identifies accounts by SIDs (`S-1-5-21-…-1001`) rather than by
+//! numbers, but this crate’s API deals in `uid_t` and `gid_t`. This module
+//! provides the same deterministic mapping Cygwin and MSYS2 use, so the IDs
+//! reported here agree with the ones shown by `id` in a Cygwin shell:
+//!
+//! | SID                            | ID                        |
+//! |--------------------------------|---------------------------|
+//! | `S-1-5-RID` (NT Authority)     | `RID`                     |
+//! | `S-1-5-32-RID` (Builtin)       | `RID`                     |
+//! | `S-1-5-X-RID`                  | `0x1000 * X + RID`        |
+//! | `S-1-X-Y` (other well-known)   | `0x10000 + 0x100 * X + Y` |
+//! | local machine account          | `0x30000 + RID`           |
+//! | `S-1-16-RID` (mandatory label) | `0x60000 + RID`           |
+//! | primary domain account         | `0x100000 + RID`          |
+//!
+//! SIDs outside of these ranges, such as accounts from trusted domains or
+//! service SIDs with many sub-authorities, have no ID.
+//!
+//! ## Example
+//!
+//! ```
+//! use users::sid::{Sid, SidMapping};
+//!
+//! let machine: Sid = "S-1-5-21-1004336348-1177238915-682003330".parse().unwrap();
+//! let mapping = SidMapping::new().with_machine_domain(machine.clone());
+//!
+//! assert_eq!(mapping.sid_to_id(&machine.with_rid(1001)), Some(197609));
+//! assert_eq!(mapping.id_to_sid(197609), Some(machine.with_rid(1001)));
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+
+/// The identifier authority of most SIDs, “NT Authority”.
+const NT_AUTHORITY: u64 = 5;
+
+/// The identifier authority of mandatory integrity labels.
+const MANDATORY_LABEL_AUTHORITY: u64 = 16;
+
+/// The sub-authority of the Builtin domain under NT Authority.
+const BUILTIN_DOMAIN_RID: u32 = 32;
+
+/// RIDs of well-known aliases in the Builtin domain start at
+/// `DOMAIN_ALIAS_RID_ADMINS` (544); anything below this is taken to be a
+/// single-RID NT Authority SID such as SYSTEM (18).
+const FIRST_BUILTIN_ALIAS_RID: u32 = 500;
+
+const NT_AUTHORITY_OFFSET:    u32 = 0x1000;
+const WELL_KNOWN_OFFSET:      u32 = 0x10000;
+const MACHINE_OFFSET:         u32 = 0x30000;
+const MANDATORY_LABEL_OFFSET: u32 = 0x60000;
+const PRIMARY_DOMAIN_OFFSET:  u32 = 0x100000;
+
+/// The first ID past the primary domain range, where Cygwin starts
+/// handing out IDs for trusted domains.
+const TRUSTED_DOMAIN_OFFSET:  u32 = 0x80000000;
+
+
+/// A Windows security identifier.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Sid {
+    authority: u64,
+    sub_authorities: Vec<u32>,
+}
+
+impl Sid {
+
+    /// Creates a new SID from its identifier authority and sub-authorities.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use users::sid::Sid;
+    ///
+    /// let system = Sid::new(5, &[18]);
+    /// assert_eq!(system.to_string(), "S-1-5-18");
+    /// ```
+    pub fn new(authority: u64, sub_authorities: &[u32]) -> Self {
+        Self { authority, sub_authorities: sub_authorities.to_vec() }
+    }
+
+    /// Returns this SID’s identifier authority.
+    pub fn authority(&self) -> u64 {
+        self.authority
+    }
+
+    /// Returns this SID’s sub-authorities.
+    pub fn sub_authorities(&self) -> &[u32] {
+        &self.sub_authorities
+    }
+
+    /// Returns the relative ID, the last sub-authority, of this SID.
+    pub fn rid(&self) -> Option<u32> {
+        self.sub_authorities.last().cloned()
+    }
+
+    /// Returns the SID of the domain this SID belongs to, which is this SID
+    /// with its relative ID removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use users::sid::Sid;
+    ///
+    /// let admins: Sid = "S-1-5-32-544".parse().unwrap();
+    /// assert_eq!(admins.domain(), Some(Sid::new(5, &[32])));
+    /// ```
+    pub fn domain(&self) -> Option<Sid> {
+        let (_, domain) = self.sub_authorities.split_last()?;
+        Some(Self::new(self.authority, domain))
+    }
+
+    /// Returns a new SID in this SID’s domain with the given relative ID.
+    pub fn with_rid(&self, rid: u32) -> Sid {
+        let mut sid = self.clone();
+        sid.sub_authorities.push(rid);
+        sid
+    }
+
+    /// Whether this SID is the given domain SID followed by exactly one RID.
+    fn is_in_domain(&self, domain: &Sid) -> bool {
+        self.authority == domain.authority
+            && self.sub_authorities.len() == domain.sub_authorities.len() + 1
+            && self.sub_authorities.starts_with(&domain.sub_authorities)
+    }
+}
+
+impl fmt::Display for Sid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Authorities that don’t fit in 32 bits are written in hex.
+        if self.authority >> 32 == 0 {
+            write!(f, "S-1-{}", self.authority)?;
+        }
+        else {
+            write!(f, "S-1-0x{:012X}", self.authority)?;
+        }
+
+        for sub_authority in &self.sub_authorities {
+            write!(f, "-{}", sub_authority)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Sid {
+    type Err = ParseSidError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('-');
+
+        match (parts.next(), parts.next()) {
+            (Some(prefix), Some("1")) if prefix.eq_ignore_ascii_case("S") => {},
+            _ => return Err(ParseSidError),
+        }
+
+        let authority = match parts.next() {
+            Some(hex) if hex.starts_with("0x") || hex.starts_with("0X") => u64::from_str_radix(&hex[2..], 16),
+            Some(decimal) => decimal.parse(),
+            None => return Err(ParseSidError),
+        };
+
+        let sub_authorities = parts.map(str::parse).collect::<Result<Vec<u32>, _>>();
+
+        match (authority, sub_authorities) {
+            (Ok(authority), Ok(sub_authorities)) => Ok(Self { authority, sub_authorities }),
+            _ => Err(ParseSidError),
+        }
+    }
+}
+
+
+/// The error returned when a string is not a valid SID.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ParseSidError;
+
+impl fmt::Display for ParseSidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid SID string")
+    }
+}
+
+impl Error for ParseSidError {}
+
+
+/// Translates SIDs into user and group IDs and back again.
+///
+/// The SIDs of local and domain accounts are only meaningful relative to
+/// the domain they were issued in, so the mapping needs to know the SIDs of
+/// the local machine’s account domain and, on a domain-joined machine, of
+/// the primary domain.
+#[derive(Clone, Default, Debug)]
+pub struct SidMapping {
+    machine_domain: Option<Sid>,
+    primary_domain: Option<Sid>,
+}
+
+impl SidMapping {
+
+    /// Creates a mapping that only knows about well-known SIDs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the SID of the local machine’s account domain.
+    pub fn with_machine_domain(mut self, sid: Sid) -> Self {
+        self.machine_domain = Some(sid);
+        self
+    }
+
+    /// Sets the SID of the domain the machine is joined to.
+    pub fn with_primary_domain(mut self, sid: Sid) -> Self {
+        self.primary_domain = Some(sid);
+        self
+    }
+
+    /// Returns the user or group ID for the given SID, if it has one.
+    pub fn sid_to_id(&self, sid: &Sid) -> Option<u32> {
+        let subs = sid.sub_authorities();
+
+        if let Some(ref machine) = self.machine_domain {
+            if sid.is_in_domain(machine) {
+                return offset(MACHINE_OFFSET, sid.rid()?, MANDATORY_LABEL_OFFSET);
+            }
+        }
+
+        if let Some(ref domain) = self.primary_domain {
+            if sid.is_in_domain(domain) {
+                return offset(PRIMARY_DOMAIN_OFFSET, sid.rid()?, TRUSTED_DOMAIN_OFFSET);
+            }
+        }
+
+        match (sid.authority(), subs) {
+            (NT_AUTHORITY, &[rid]) => {
+                Some(rid).filter(|&rid| rid < FIRST_BUILTIN_ALIAS_RID)
+            },
+            (NT_AUTHORITY, &[BUILTIN_DOMAIN_RID, rid]) => {
+                Some(rid).filter(|rid| (FIRST_BUILTIN_ALIAS_RID..NT_AUTHORITY_OFFSET).contains(rid))
+            },
+            (NT_AUTHORITY, &[x, rid]) if x > 0 && x < WELL_KNOWN_OFFSET / NT_AUTHORITY_OFFSET && rid < NT_AUTHORITY_OFFSET => {
+                Some(NT_AUTHORITY_OFFSET * x + rid)
+            },
+            (MANDATORY_LABEL_AUTHORITY, &[rid]) => {
+                offset(MANDATORY_LABEL_OFFSET, rid, PRIMARY_DOMAIN_OFFSET)
+            },
+            (authority, &[y]) if authority != NT_AUTHORITY && authority < 0x100 && y < 0x100 => {
+                Some(WELL_KNOWN_OFFSET + 0x100 * authority as u32 + y)
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the SID for the given user or group ID, if it maps to one.
+    pub fn id_to_sid(&self, id: u32) -> Option<Sid> {
+        let sid = if id < FIRST_BUILTIN_ALIAS_RID {
+            Sid::new(NT_AUTHORITY, &[id])
+        }
+        else if id < NT_AUTHORITY_OFFSET {
+            Sid::new(NT_AUTHORITY, &[BUILTIN_DOMAIN_RID, id])
+        }
+        else if id < WELL_KNOWN_OFFSET {
+            Sid::new(NT_AUTHORITY, &[id / NT_AUTHORITY_OFFSET, id % NT_AUTHORITY_OFFSET])
+        }
+        else if id < MACHINE_OFFSET {
+            Sid::new(u64::from((id - WELL_KNOWN_OFFSET) / 0x100), &[id % 0x100])
+        }
+        else if id < MANDATORY_LABEL_OFFSET {
+            self.machine_domain.as_ref()?.with_rid(id - MACHINE_OFFSET)
+        }
+        else if id < PRIMARY_DOMAIN_OFFSET {
+            Sid::new(MANDATORY_LABEL_AUTHORITY, &[id - MANDATORY_LABEL_OFFSET])
+        }
+        else if id < TRUSTED_DOMAIN_OFFSET {
+            self.primary_domain.as_ref()?.with_rid(id - PRIMARY_DOMAIN_OFFSET)
+        }
+        else {
+            return None;
+        };
+
+        // Some IDs in the gaps between ranges don’t survive the round trip,
+        // such as a well-known SID under an authority that has its own range.
+        Some(sid).filter(|sid| self.sid_to_id(sid) == Some(id))
+    }
+}
+
+/// Adds `base` to `rid`, as long as the result stays below `limit`.
+fn offset(base: u32, rid: u32, limit: u32) -> Option<u32> {
+    if rid < limit - base {
+        Some(base + rid)
+    }
+    else {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{Sid, SidMapping};
+
+    fn machine() -> Sid {
+        "S-1-5-21-1004336348-1177238915-682003330".parse().unwrap()
+    }
+
+    fn domain() -> Sid {
+        "S-1-5-21-3623811015-3361044348-30300820".parse().unwrap()
+    }
+
+    fn mapping() -> SidMapping {
+        SidMapping::new().with_machine_domain(machine()).with_primary_domain(domain())
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let sid: Sid = "S-1-5-21-1004336348-1177238915-682003330-1001".parse().unwrap();
+        assert_eq!(sid.authority(), 5);
+        assert_eq!(sid.rid(), Some(1001));
+        assert_eq!(sid.to_string(), "S-1-5-21-1004336348-1177238915-682003330-1001");
+    }
+
+    #[test]
+    fn parse_hex_authority() {
+        let sid: Sid = "S-1-0x0000DEADBEEF00-1".parse().unwrap();
+        assert_eq!(sid.authority(), 0xDEADBEEF00);
+        assert_eq!(sid.to_string(), "S-1-0x00DEADBEEF00-1");
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!("".parse::<Sid>().is_err());
+        assert!("S-2-5-18".parse::<Sid>().is_err());
+        assert!("S-1-5-eighteen".parse::<Sid>().is_err());
+        assert!("S-1-5-18-".parse::<Sid>().is_err());
+    }
+
+    #[test]
+    fn well_known() {
+        let mapping = SidMapping::new();
+        assert_eq!(mapping.sid_to_id(&"S-1-5-18".parse().unwrap()), Some(18));
+        assert_eq!(mapping.sid_to_id(&"S-1-5-32-544".parse().unwrap()), Some(544));
+        assert_eq!(mapping.sid_to_id(&"S-1-1-0".parse().unwrap()), Some(0x10100));
+        assert_eq!(mapping.sid_to_id(&"S-1-16-8192".parse().unwrap()), Some(401408));
+    }
+
+    #[test]
+    fn accounts() {
+        assert_eq!(mapping().sid_to_id(&machine().with_rid(1001)), Some(197609));
+        assert_eq!(mapping().sid_to_id(&machine().with_rid(513)), Some(197121));
+        assert_eq!(mapping().sid_to_id(&domain().with_rid(513)), Some(1049089));
+    }
+
+    #[test]
+    fn unknown_domain() {
+        let stranger: Sid = "S-1-5-21-1-2-3-1001".parse().unwrap();
+        assert_eq!(mapping().sid_to_id(&stranger), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        for &id in &[18, 544, 0x10100, 197609, 401408, 1049089] {
+            let sid = mapping().id_to_sid(id).unwrap();
+            assert_eq!(mapping().sid_to_id(&sid), Some(id), "{}", sid);
+        }
+    }
+}
@@ -0,0 +1,503 @@
+//! A view of every user and group, all taken at the same moment.
+//!
+//! Asking the system about users one at a time gives answers from whenever
+//! each question was asked: if a user gets renamed in between, looking them
+//! up by ID and then by name can disagree. A `UsersSnapshot` lists every
+//! user and group in one pass, and from then on answers everything from
+//! memory, so its answers always agree with each other:
+//!
+//! ```
+//! use users::{Users, UsersSnapshot};
+//!
+//! let snapshot = unsafe { UsersSnapshot::capture() };
+//! for user in snapshot.users() {
+//!     assert_eq!(snapshot.get_user_by_name(user.name()).map(|u| u.uid()),
+//!                Some(user.uid()));
+//! }
+//! ```
+//!
+//! Having everything at hand, a snapshot can also answer questions that
+//! take more than one lookup, such as which users have IDs in a range, or
+//! which groups a user is in.
+//!
+//! Snapshots can also be made from lists of users and groups, which makes
+//! them a simple way to give the stub something to answer with:
+//!
+//! ```
+//! use users::{User, Group, UsersSnapshot};
+//!
+//! let snapshot = UsersSnapshot::new(
+//!     vec![ User::new(1000, "alice", 100) ],
+//!     vec![ Group::new(100, "users") ],
+//! ).with_current_uid(1000).with_current_gid(100);
+//!
+//! users::set_backend(snapshot);
+//! assert_eq!(users::get_current_username().unwrap(), "alice");
+//! # users::clear_backend();
+//! ```
+//!
+//! Comparing two snapshots with `diff` shows what changed between them,
+//! which users and groups were added or removed and which of their details
+//! are different now:
+//!
+//! ```
+//! use users::{User, UsersSnapshot};
+//! use users::snapshot::UserField;
+//!
+//! let before = UsersSnapshot::new(vec![ User::new(1000, "alice", 100) ], vec![]);
+//! let after = UsersSnapshot::new(vec![ User::new(1000, "alice", 200),
+//!                                      User::new(1001, "bob", 100) ], vec![]);
+//!
+//! let diff = before.diff(&after);
+//! assert_eq!(diff.added_users[0].name(), "bob");
+//! assert_eq!(diff.modified_users[0].fields, vec![ UserField::PrimaryGroup ]);
+//! ```
+//!
+//! Users and groups are matched up by name, so one whose ID changed counts
+//! as modified rather than as removed and added again.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::hash::Hash;
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::sync::Arc;
+
+use {User, Group, Users, Groups, uid_t, gid_t};
+use {all_users, all_groups};
+use os::unix::{UserExt, GroupExt};
+use accounts::AccountExt;
+use listing;
+use {get_current_uid, get_current_gid, get_effective_uid, get_effective_gid};
+
+
+/// Every user and group, as they were at one moment.
+///
+/// Where two users share an ID or a name, the first one listed wins, the
+/// same as it does for the system’s own lookups.
+#[derive(Clone)]
+pub struct UsersSnapshot {
+    users: HashMap<uid_t, Arc<User>>,
+    user_names: HashMap<Arc<OsStr>, Arc<User>>,
+    groups: HashMap<gid_t, Arc<Group>>,
+    group_names: HashMap<Arc<OsStr>, Arc<Group>>,
+    uid: uid_t,
+    gid: gid_t,
+    euid: uid_t,
+    egid: gid_t,
+}
+
+impl UsersSnapshot {
+
+    /// Lists every user and group on the system, along with the current
+    /// and effective IDs of the process.
+    ///
+    /// # Safety
+    ///
+    /// This uses `all_users` and `all_groups`, which aren’t thread-safe. It
+    /// waits for anything else in this crate that is listing users or
+    /// groups, but no other thread may call `all_users` or `all_groups`
+    /// directly, or the C library’s `getpwent` or `getgrent`, at the same
+    /// time.
+    pub unsafe fn capture() -> Self {
+        let (users, groups) = {
+            let _listing = listing::lock();
+            (all_users().collect(), all_groups().collect())
+        };
+
+        let mut snapshot = Self::new(users, groups);
+        snapshot.euid = get_effective_uid();
+        snapshot.egid = get_effective_gid();
+        snapshot
+    }
+
+    /// Creates a snapshot of the given users and groups, with the current
+    /// and effective IDs of the process.
+    pub fn new(users: Vec<User>, groups: Vec<Group>) -> Self {
+        let uid = get_current_uid();
+        let gid = get_current_gid();
+
+        let mut snapshot = Self {
+            users: HashMap::new(),
+            user_names: HashMap::new(),
+            groups: HashMap::new(),
+            group_names: HashMap::new(),
+            uid, gid, euid: uid, egid: gid,
+        };
+
+        for user in users {
+            let user = Arc::new(user);
+            snapshot.users.entry(user.uid()).or_insert_with(|| Arc::clone(&user));
+            snapshot.user_names.entry(Arc::from(user.name())).or_insert(user);
+        }
+
+        for group in groups {
+            let group = Arc::new(group);
+            snapshot.groups.entry(group.gid()).or_insert_with(|| Arc::clone(&group));
+            snapshot.group_names.entry(Arc::from(group.name())).or_insert(group);
+        }
+
+        snapshot
+    }
+
+    /// Sets the current and effective user ID the snapshot reports.
+    pub fn with_current_uid(mut self, uid: uid_t) -> Self {
+        self.uid = uid;
+        self.euid = uid;
+        self
+    }
+
+    /// Sets the current and effective group ID the snapshot reports.
+    pub fn with_current_gid(mut self, gid: gid_t) -> Self {
+        self.gid = gid;
+        self.egid = gid;
+        self
+    }
+
+    /// Sets the effective user ID the snapshot reports.
+    pub fn with_effective_uid(mut self, uid: uid_t) -> Self {
+        self.euid = uid;
+        self
+    }
+
+    /// Sets the effective group ID the snapshot reports.
+    pub fn with_effective_gid(mut self, gid: gid_t) -> Self {
+        self.egid = gid;
+        self
+    }
+
+    /// Every user in the snapshot, in no particular order.
+    pub fn users(&self) -> impl Iterator<Item=&Arc<User>> {
+        self.users.values()
+    }
+
+    /// Every group in the snapshot, in no particular order.
+    pub fn groups(&self) -> impl Iterator<Item=&Arc<Group>> {
+        self.groups.values()
+    }
+
+    /// The users in the snapshot that belong to people, in no particular
+    /// order.
+    pub fn regular_users(&self) -> impl Iterator<Item=&Arc<User>> {
+        self.users().filter(|u| ! u.is_system_account())
+    }
+
+    /// The system accounts in the snapshot, in no particular order.
+    pub fn system_users(&self) -> impl Iterator<Item=&Arc<User>> {
+        self.users().filter(|u| u.is_system_account())
+    }
+
+    /// The users whose login shell is the given one, in no particular
+    /// order.
+    pub fn users_with_shell<'a, P: AsRef<Path> + 'a>(&'a self, shell: P) -> impl Iterator<Item=&'a Arc<User>> + 'a {
+        self.users().filter(move |u| u.shell() == shell.as_ref())
+    }
+
+    /// The users with IDs in the given range, in no particular order.
+    pub fn users_in_uid_range<'a, R: RangeBounds<uid_t> + 'a>(&'a self, range: R) -> impl Iterator<Item=&'a Arc<User>> + 'a {
+        self.users().filter(move |u| range.contains(&u.uid()))
+    }
+
+    /// The groups the named user is in, either as their primary group or as
+    /// a member, in no particular order.
+    pub fn groups_containing<'a, S: AsRef<OsStr> + ?Sized>(&'a self, username: &'a S) -> impl Iterator<Item=&'a Arc<Group>> + 'a {
+        let username = username.as_ref();
+        let primary = self.user_names.get(username).map(|u| u.primary_group_id());
+
+        self.groups().filter(move |g| Some(g.gid()) == primary || g.members().iter().any(|m| m == username))
+    }
+
+    /// Works out what changed between this snapshot and a later one.
+    pub fn diff(&self, later: &UsersSnapshot) -> SnapshotDiff {
+        let (added_users, removed_users, modified_users) = diff(&self.user_names, &later.user_names, user_fields);
+        let (added_groups, removed_groups, modified_groups) = diff(&self.group_names, &later.group_names, group_fields);
+
+        SnapshotDiff { added_users, removed_users, modified_users, added_groups, removed_groups, modified_groups }
+    }
+}
+
+
+/// The differences between two snapshots, returned by `UsersSnapshot::diff`.
+///
+/// Each list is sorted by name.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotDiff {
+
+    /// Users only in the later snapshot.
+    pub added_users: Vec<Arc<User>>,
+
+    /// Users only in the earlier snapshot.
+    pub removed_users: Vec<Arc<User>>,
+
+    /// Users in both snapshots, with different details.
+    pub modified_users: Vec<Modified<User, UserField>>,
+
+    /// Groups only in the later snapshot.
+    pub added_groups: Vec<Arc<Group>>,
+
+    /// Groups only in the earlier snapshot.
+    pub removed_groups: Vec<Arc<Group>>,
+
+    /// Groups in both snapshots, with different details.
+    pub modified_groups: Vec<Modified<Group, GroupField>>,
+}
+
+impl SnapshotDiff {
+
+    /// Whether nothing changed at all.
+    pub fn is_empty(&self) -> bool {
+        self.added_users.is_empty() && self.removed_users.is_empty() && self.modified_users.is_empty()
+            && self.added_groups.is_empty() && self.removed_groups.is_empty() && self.modified_groups.is_empty()
+    }
+}
+
+/// A user or group with the same name in both snapshots, but with
+/// different details.
+#[derive(Debug, Clone)]
+pub struct Modified<T, F> {
+
+    /// How it was in the earlier snapshot.
+    pub before: Arc<T>,
+
+    /// How it is in the later snapshot.
+    pub after: Arc<T>,
+
+    /// Which of its details are different.
+    pub fields: Vec<F>,
+}
+
+/// A detail of a user that can change between snapshots.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum UserField {
+
+    /// The user ID.
+    Uid,
+
+    /// The ID of the user’s primary group.
+    PrimaryGroup,
+
+    /// The home directory.
+    HomeDir,
+
+    /// The login shell.
+    Shell,
+}
+
+/// A detail of a group that can change between snapshots.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum GroupField {
+
+    /// The group ID.
+    Gid,
+
+    /// The names of the group’s members.
+    Members,
+}
+
+
+/// Matches up the entries of two snapshots by name, sorting out which were
+/// added, removed, and changed.
+#[allow(clippy::type_complexity)]
+fn diff<T, F>(before: &HashMap<Arc<OsStr>, Arc<T>>, after: &HashMap<Arc<OsStr>, Arc<T>>, fields: fn(&T, &T) -> Vec<F>)
+    -> (Vec<Arc<T>>, Vec<Arc<T>>, Vec<Modified<T, F>>)
+{
+    let mut added = only_in(after, before);
+    let mut removed = only_in(before, after);
+    let mut modified = Vec::new();
+
+    for (name, old) in before {
+        if let Some(new) = after.get(name) {
+            let fields = fields(old, new);
+            if ! fields.is_empty() {
+                modified.push((name, Modified { before: Arc::clone(old), after: Arc::clone(new), fields }));
+            }
+        }
+    }
+
+    added.sort_by(|a, b| a.0.cmp(b.0));
+    removed.sort_by(|a, b| a.0.cmp(b.0));
+    modified.sort_by(|a, b| a.0.cmp(b.0));
+
+    (added.into_iter().map(|e| e.1).collect(),
+     removed.into_iter().map(|e| e.1).collect(),
+     modified.into_iter().map(|e| e.1).collect())
+}
+
+fn only_in<'a, K: Eq + Hash, V>(these: &'a HashMap<K, Arc<V>>, those: &HashMap<K, Arc<V>>) -> Vec<(&'a K, Arc<V>)> {
+    these.iter()
+         .filter(|&(k, _)| ! those.contains_key(k))
+         .map(|(k, v)| (k, Arc::clone(v)))
+         .collect()
+}
+
+fn user_fields(before: &User, after: &User) -> Vec<UserField> {
+    let mut fields = Vec::new();
+
+    if before.uid() != after.uid() {
+        fields.push(UserField::Uid);
+    }
+
+    if before.primary_group_id() != after.primary_group_id() {
+        fields.push(UserField::PrimaryGroup);
+    }
+
+    if before.home_dir() != after.home_dir() {
+        fields.push(UserField::HomeDir);
+    }
+
+    if before.shell() != after.shell() {
+        fields.push(UserField::Shell);
+    }
+
+    fields
+}
+
+fn group_fields(before: &Group, after: &Group) -> Vec<GroupField> {
+    let mut fields = Vec::new();
+
+    if before.gid() != after.gid() {
+        fields.push(GroupField::Gid);
+    }
+
+    if before.members() != after.members() {
+        fields.push(GroupField::Members);
+    }
+
+    fields
+}
+
+impl Users for UsersSnapshot {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        self.users.get(&uid).cloned()
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        self.user_names.get(username.as_ref()).cloned()
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        self.uid
+    }
+
+    fn get_current_username(&self) -> Option<Arc<OsStr>> {
+        self.users.get(&self.uid).map(|u| Arc::from(u.name()))
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        self.euid
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<OsStr>> {
+        self.users.get(&self.euid).map(|u| Arc::from(u.name()))
+    }
+}
+
+impl Groups for UsersSnapshot {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        self.groups.get(&gid).cloned()
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+        self.group_names.get(group_name.as_ref()).cloned()
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        self.gid
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
+        self.groups.get(&self.gid).map(|g| Arc::from(g.name()))
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        self.egid
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
+        self.groups.get(&self.egid).map(|g| Arc::from(g.name()))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn snapshot() -> UsersSnapshot {
+        UsersSnapshot::new(
+            vec![ User::new(1000, "alice", 100), User::new(1001, "bob", 100),
+                  User::new(1000, "alias", 100), User::new(1002, "alice", 100) ],
+            vec![ Group::new(100, "users"), Group::new(0, "root") ],
+        ).with_current_uid(1000).with_current_gid(100).with_effective_gid(0)
+    }
+
+    #[test]
+    fn lookups() {
+        let snapshot = snapshot();
+        assert_eq!(snapshot.get_user_by_name("bob").map(|u| u.uid()), Some(1001));
+        assert_eq!(snapshot.get_current_username(), Some(Arc::from(OsStr::new("alice"))));
+        assert_eq!(snapshot.get_current_groupname(), Some(Arc::from(OsStr::new("users"))));
+        assert_eq!(snapshot.get_effective_groupname(), Some(Arc::from(OsStr::new("root"))));
+        assert!(snapshot.get_user_by_uid(4).is_none());
+    }
+
+    #[test]
+    fn first_listed_wins() {
+        let snapshot = snapshot();
+        assert_eq!(snapshot.get_user_by_uid(1000).map(|u| u.name().to_owned()),
+                   Some("alice".into()));
+        assert_eq!(snapshot.get_user_by_name("alice").map(|u| u.uid()), Some(1000));
+        assert_eq!(snapshot.get_user_by_name("alias").map(|u| u.uid()), Some(1000));
+        assert_eq!(snapshot.users().count(), 3);
+        assert_eq!(snapshot.regular_users().count() + snapshot.system_users().count(), 3);
+    }
+
+    #[test]
+    fn queries() {
+        let snapshot = UsersSnapshot::new(
+            vec![ User::new(0, "root", 0), User::new(1000, "alice", 100), User::new(1001, "bob", 100) ],
+            vec![ Group::new(0, "root"), Group::new(100, "users"), Group::new(10, "wheel").add_member("alice") ],
+        );
+
+        let mut uids = snapshot.users_in_uid_range(1000 ..).map(|u| u.uid()).collect::<Vec<_>>();
+        uids.sort();
+        assert_eq!(uids, vec![ 1000, 1001 ]);
+
+        let mut gids = snapshot.groups_containing("alice").map(|g| g.gid()).collect::<Vec<_>>();
+        gids.sort();
+        assert_eq!(gids, vec![ 10, 100 ]);
+        assert_eq!(snapshot.groups_containing("bob").count(), 1);
+    }
+
+    #[test]
+    fn diff() {
+        let later = UsersSnapshot::new(
+            vec![ User::new(1000, "alice", 100), User::new(1003, "bob", 100),
+                  User::new(1004, "carol", 100) ],
+            vec![ Group::new(100, "users"), Group::new(10, "wheel") ],
+        );
+
+        let diff = snapshot().diff(&later);
+        assert_eq!(diff.added_users.iter().map(|u| u.uid()).collect::<Vec<_>>(), vec![ 1004 ]);
+        assert_eq!(diff.removed_users.iter().map(|u| u.uid()).collect::<Vec<_>>(), vec![ 1000 ]);
+        assert_eq!(diff.modified_users.len(), 1);
+        assert_eq!(diff.modified_users[0].after.uid(), 1003);
+        assert_eq!(diff.modified_users[0].fields, vec![ UserField::Uid ]);
+        assert_eq!(diff.added_groups[0].name(), "wheel");
+        assert_eq!(diff.removed_groups[0].name(), "root");
+        assert!(diff.modified_groups.is_empty());
+
+        assert!(later.diff(&later).is_empty());
+    }
+
+    #[test]
+    fn captured() {
+        let snapshot = unsafe { UsersSnapshot::capture() };
+        assert_eq!(snapshot.get_current_uid(), get_current_uid());
+        for group in snapshot.groups() {
+            assert!(snapshot.get_group_by_gid(group.gid()).is_some());
+        }
+    }
+}
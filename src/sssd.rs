@@ -0,0 +1,377 @@
+//! Looking users and groups up by asking SSSD directly.
+//!
+//! SSSD caches users from LDAP, Active Directory and other domains, and the
+//! C library gets them from it through the `sss` NSS module, which talks to
+//! SSSD over a socket. An `SssdBackend` speaks the same protocol on the same
+//! socket, so static binaries, which can’t load NSS modules, still see those
+//! users, and still benefit from SSSD’s cache:
+//!
+//! ```no_run
+//! use users::Users;
+//! use users::sssd::SssdBackend;
+//!
+//! let sssd = SssdBackend::new();
+//! if let Some(user) = sssd.get_user_by_name("alice@example.com") {
+//!     println!("alice is UID {}", user.uid());
+//! }
+//! ```
+//!
+//! Every lookup opens a new connection, and if SSSD isn’t running, or
+//! answers with an error, there is no user or group.
+//!
+//! This is only available with the `sssd` feature, on Unix.
+
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use files::process_ids;
+use {User, Group, Users, Groups, UserBuilder, GroupBuilder, uid_t, gid_t};
+
+
+/// The socket the `sss` NSS module talks to SSSD over.
+pub const SSSD_SOCKET: &str = "/var/lib/sss/pipes/nss";
+
+/// How long to wait for SSSD to answer before giving up.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The version of the protocol spoken.
+const PROTOCOL_VERSION: u32 = 1;
+
+/// The largest reply accepted, so a confused server can’t make this
+/// allocate any amount of memory.
+const MAX_REPLY: usize = 16 * 1024 * 1024;
+
+// The commands used, from `enum sss_cli_command`.
+const SSS_GET_VERSION: u32 = 0x0001;
+const SSS_NSS_GETPWNAM: u32 = 0x0011;
+const SSS_NSS_GETPWUID: u32 = 0x0012;
+const SSS_NSS_GETGRNAM: u32 = 0x0021;
+const SSS_NSS_GETGRGID: u32 = 0x0022;
+
+
+/// Users and groups from SSSD.
+#[derive(Clone, Debug)]
+pub struct SssdBackend {
+    socket: PathBuf,
+    uid: uid_t,
+    gid: gid_t,
+    euid: uid_t,
+    egid: gid_t,
+}
+
+impl SssdBackend {
+
+    /// Creates a backend asking SSSD on its usual socket.
+    pub fn new() -> Self {
+        Self::with_socket(SSSD_SOCKET)
+    }
+
+    /// Creates a backend asking SSSD on the socket at the given path.
+    pub fn with_socket<P: AsRef<Path>>(socket: P) -> Self {
+        let (uid, gid, euid, egid) = process_ids();
+        Self { socket: socket.as_ref().to_owned(), uid, gid, euid, egid }
+    }
+
+    /// Sends a request, after agreeing on the protocol version, and returns
+    /// the body of the reply.
+    fn request(&self, command: u32, body: &[u8]) -> io::Result<Vec<u8>> {
+        let mut stream = UnixStream::connect(&self.socket)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let version = exchange(&mut stream, SSS_GET_VERSION, &PROTOCOL_VERSION.to_ne_bytes())?;
+        if Reader::new(&version).u32() != Some(PROTOCOL_VERSION) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported protocol version"));
+        }
+
+        exchange(&mut stream, command, body)
+    }
+
+    fn find_user(&self, command: u32, body: &[u8]) -> Option<Arc<User>> {
+        let reply = self.request(command, body).ok()?;
+        parse_user(&reply).map(Arc::new)
+    }
+
+    fn find_group(&self, command: u32, body: &[u8]) -> Option<Arc<Group>> {
+        let reply = self.request(command, body).ok()?;
+        parse_group(&reply).map(Arc::new)
+    }
+}
+
+impl Default for SssdBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Sends one packet and reads the one that answers it, returning its body.
+///
+/// Each packet starts with its length, including this header, the command,
+/// a status that is zero unless something went wrong, and a reserved
+/// field, all in the machine’s byte order.
+fn exchange(stream: &mut UnixStream, command: u32, body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut packet = Vec::with_capacity(16 + body.len());
+    packet.extend_from_slice(&(16 + body.len() as u32).to_ne_bytes());
+    packet.extend_from_slice(&command.to_ne_bytes());
+    packet.extend_from_slice(&0_u32.to_ne_bytes());
+    packet.extend_from_slice(&0_u32.to_ne_bytes());
+    packet.extend_from_slice(body);
+    stream.write_all(&packet)?;
+
+    let mut header = [0_u8; 16];
+    stream.read_exact(&mut header)?;
+
+    let field = |i: usize| u32::from_ne_bytes([ header[i], header[i + 1], header[i + 2], header[i + 3] ]);
+    let (len, reply_command, status) = (field(0) as usize, field(4), field(8));
+
+    if reply_command != command || ! (16 ..= MAX_REPLY).contains(&len) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed reply"));
+    }
+
+    let mut body = vec![0; len - 16];
+    stream.read_exact(&mut body)?;
+
+    if status != 0 {
+        return Err(io::Error::from_raw_os_error(status as i32));
+    }
+
+    Ok(body)
+}
+
+/// Returns the body of a request for the entry with the given name.
+fn name_body(name: &OsStr) -> Option<Vec<u8>> {
+    let name = name.as_bytes();
+    if name.contains(&0) {
+        return None;
+    }
+
+    let mut body = name.to_vec();
+    body.push(0);
+    Some(body)
+}
+
+/// Reads the fields of a reply body in turn.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        if self.bytes.len() < 4 {
+            return None;
+        }
+
+        let (field, rest) = self.bytes.split_at(4);
+        self.bytes = rest;
+        Some(u32::from_ne_bytes([ field[0], field[1], field[2], field[3] ]))
+    }
+
+    fn string(&mut self) -> Option<OsString> {
+        let end = self.bytes.iter().position(|&b| b == 0)?;
+        let field = OsString::from_vec(self.bytes[.. end].to_vec());
+        self.bytes = &self.bytes[end + 1 ..];
+        Some(field)
+    }
+}
+
+/// Parses the reply to a user lookup: the number of results, which is 0 or
+/// 1, a reserved field, and then the IDs, name, password, GECOS field, home
+/// directory and shell.
+fn parse_user(body: &[u8]) -> Option<User> {
+    let mut reader = Reader::new(body);
+    if reader.u32()? == 0 {
+        return None;
+    }
+    reader.u32()?;
+
+    let uid = reader.u32()?;
+    let gid = reader.u32()?;
+    let name = reader.string()?;
+    let password = reader.string()?;
+    let gecos = reader.string()?;
+    let home_dir = reader.string()?;
+    let shell = reader.string()?;
+
+    Some(UserBuilder::new(uid as uid_t, &name, gid as gid_t)
+        .password(&password)
+        .gecos(&gecos)
+        .home_dir(&home_dir)
+        .shell(&shell)
+        .build())
+}
+
+/// Parses the reply to a group lookup: the number of results and a reserved
+/// field, as for users, and then the ID, the number of members, the name,
+/// password, and the members’ names.
+fn parse_group(body: &[u8]) -> Option<Group> {
+    let mut reader = Reader::new(body);
+    if reader.u32()? == 0 {
+        return None;
+    }
+    reader.u32()?;
+
+    let gid = reader.u32()?;
+    let count = reader.u32()?;
+    let name = reader.string()?;
+    reader.string()?;
+
+    let mut builder = GroupBuilder::new(gid as gid_t, &name);
+    for _ in 0 .. count {
+        builder = builder.member(&reader.string()?);
+    }
+
+    Some(builder.build())
+}
+
+
+impl Users for SssdBackend {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        self.find_user(SSS_NSS_GETPWUID, &uid.to_ne_bytes())
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        self.find_user(SSS_NSS_GETPWNAM, &name_body(username.as_ref())?)
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        self.uid
+    }
+
+    fn get_current_username(&self) -> Option<Arc<OsStr>> {
+        self.get_user_by_uid(self.uid).map(|u| Arc::from(u.name()))
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        self.euid
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<OsStr>> {
+        self.get_user_by_uid(self.euid).map(|u| Arc::from(u.name()))
+    }
+}
+
+impl Groups for SssdBackend {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        self.find_group(SSS_NSS_GETGRGID, &gid.to_ne_bytes())
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+        self.find_group(SSS_NSS_GETGRNAM, &name_body(group_name.as_ref())?)
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        self.gid
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
+        self.get_group_by_gid(self.gid).map(|g| Arc::from(g.name()))
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        self.egid
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
+        self.get_group_by_gid(self.egid).map(|g| Arc::from(g.name()))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::os::unix::net::UnixListener;
+    use std::process;
+    use std::thread;
+    use os::unix::{UserExt, GroupExt};
+
+    /// Reads a packet, returning its command and body.
+    fn receive(stream: &mut UnixStream) -> (u32, Vec<u8>) {
+        let mut header = [0_u8; 16];
+        stream.read_exact(&mut header).unwrap();
+        let mut fields = Reader::new(&header);
+        let (len, command) = (fields.u32().unwrap(), fields.u32().unwrap());
+        let mut body = vec![0; len as usize - 16];
+        stream.read_exact(&mut body).unwrap();
+        (command, body)
+    }
+
+    fn send(stream: &mut UnixStream, command: u32, body: &[u8]) {
+        let mut packet = Vec::new();
+        for field in &[ 16 + body.len() as u32, command, 0, 0 ] {
+            packet.extend_from_slice(&field.to_ne_bytes());
+        }
+        packet.extend_from_slice(body);
+        stream.write_all(&packet).unwrap();
+    }
+
+    /// Answers one connection as SSSD would, knowing only of alice and
+    /// her group.
+    fn answer(listener: &UnixListener) {
+        let (mut stream, _) = listener.accept().unwrap();
+        let (command, body) = receive(&mut stream);
+        assert_eq!(command, SSS_GET_VERSION);
+        send(&mut stream, command, &body);
+
+        let (command, body) = receive(&mut stream);
+        let mut reply = Vec::new();
+        match command {
+            SSS_NSS_GETPWNAM if body == b"alice\0" => {
+                for field in &[ 1, 0, 1000, 100 ] {
+                    reply.extend_from_slice(&(*field as u32).to_ne_bytes());
+                }
+                reply.extend_from_slice(b"alice\0*\0Alice\0/home/alice\0/bin/zsh\0");
+            }
+            SSS_NSS_GETGRGID => {
+                for field in &[ 1, 0, 100, 2 ] {
+                    reply.extend_from_slice(&(*field as u32).to_ne_bytes());
+                }
+                reply.extend_from_slice(b"users\0*\0alice\0bob\0");
+            }
+            _ => reply.extend_from_slice(&[ 0; 8 ]),
+        }
+        send(&mut stream, command, &reply);
+    }
+
+    #[test]
+    fn socket() {
+        let dir = env::temp_dir().join(format!("users-sssd-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("nss");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = thread::spawn(move || {
+            for _ in 0 .. 3 {
+                answer(&listener);
+            }
+        });
+
+        let sssd = SssdBackend::with_socket(&path);
+        let alice = sssd.get_user_by_name("alice").unwrap();
+        assert_eq!(alice.uid(), 1000);
+        assert_eq!(alice.shell(), Path::new("/bin/zsh"));
+        assert!(sssd.get_user_by_uid(1001).is_none());
+        assert_eq!(sssd.get_group_by_gid(100).unwrap().members().len(), 2);
+
+        server.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn truncated() {
+        assert!(parse_user(&[ 1, 0, 0, 0 ]).is_none());
+        assert!(parse_group(&[]).is_none());
+    }
+}
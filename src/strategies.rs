@@ -0,0 +1,115 @@
+//! Proptest strategies for users, groups and their names.
+//!
+//! With the `proptest` feature, these strategies generate values for
+//! property-based tests of code that handles users and groups. Names are
+//! mostly the sort a users database holds, but also include the edge cases
+//! code tends to forget: empty names, and names that aren’t valid UTF-8.
+//!
+//! On Unix, `User` and `Group` come from the real `users` crate, so this
+//! crate can’t implement `Arbitrary` for them; use the strategies directly
+//! instead:
+//!
+//! ```
+//! # extern crate proptest;
+//! # extern crate users;
+//! use proptest::test_runner::TestRunner;
+//! use users::strategies::user;
+//!
+//! # fn main() {
+//! let mut runner = TestRunner::default();
+//! runner.run(&user(), |user| {
+//!     assert!(! format!("{:?}", user).is_empty());
+//!     Ok(())
+//! }).unwrap();
+//! # }
+//! ```
+
+use std::ffi::OsString;
+
+use proptest::prelude::*;
+
+use {User, Group, uid_t, gid_t};
+
+
+/// Generates user and group names: usually ones like a users database
+/// holds, but sometimes empty or not valid UTF-8.
+pub fn name() -> impl Strategy<Value = OsString> {
+    prop_oneof![
+        6 => "[a-z_][a-z0-9_-]{0,31}".prop_map(OsString::from),
+        1 => Just(OsString::new()),
+        1 => "\\PC{1,16}".prop_map(OsString::from),
+        2 => unusual_name(),
+    ]
+}
+
+/// Generates names that are not valid UTF-8, where the platform has them.
+#[cfg(unix)]
+fn unusual_name() -> impl Strategy<Value = OsString> {
+    use std::os::unix::ffi::OsStringExt;
+
+    proptest::collection::vec(1..=255u8, 1..32).prop_map(OsString::from_vec)
+}
+
+/// Generates names that are not valid UTF-16, where the platform has them.
+#[cfg(windows)]
+fn unusual_name() -> impl Strategy<Value = OsString> {
+    use std::os::windows::ffi::OsStringExt;
+
+    proptest::collection::vec(1..=0xFFFFu16, 1..32).prop_map(|wide| OsString::from_wide(&wide))
+}
+
+/// Generates names with unusual characters, as names can’t be invalid
+/// Unicode on this platform.
+#[cfg(not(any(unix, windows)))]
+fn unusual_name() -> impl Strategy<Value = OsString> {
+    "[\\x01-\\x1F:,\\u{80}-\\u{10FFFF}]{1,16}".prop_map(OsString::from)
+}
+
+/// Generates user IDs, favouring those found in practice: root, system
+/// accounts, regular users, and `nobody`.
+pub fn uid() -> impl Strategy<Value = uid_t> {
+    prop_oneof![
+        Just(0),
+        1..1000u32,
+        1000..60000u32,
+        Just(65534),
+        any::<u32>(),
+    ]
+}
+
+/// Generates group IDs, favouring those found in practice, like `uid`.
+pub fn gid() -> impl Strategy<Value = gid_t> {
+    uid()
+}
+
+/// Generates users with any name, ID and primary group.
+pub fn user() -> impl Strategy<Value = User> {
+    (uid(), name(), gid()).prop_map(|(uid, name, gid)| User::new(uid, &name, gid))
+}
+
+/// Generates groups with any name and ID.
+pub fn group() -> impl Strategy<Value = Group> {
+    (gid(), name()).prop_map(|(gid, name)| Group::new(gid, &name))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn user_keeps_name(name in name(), uid in uid()) {
+            let user = User::new(uid, &name, uid);
+            prop_assert_eq!(user.name(), &*name);
+            prop_assert_eq!(user.uid(), uid);
+        }
+
+        #[test]
+        fn group_keeps_name(name in name(), gid in gid()) {
+            let group = Group::new(gid, &name);
+            prop_assert_eq!(group.name(), &*name);
+            prop_assert_eq!(group.gid(), gid);
+        }
+    }
+}
@@ -0,0 +1,636 @@
+//! Configuring what the stub reports.
+//!
+//! Where this crate has no backend for the platform, the functions at its
+//! root report the process as running as an unnamed user 0 in an unnamed
+//! group 0, and find no other users or groups at all. Downstream code then
+//! behaves as though it runs as root, which is rarely what anyone wants.
+//!
+//! A `StubConfig` lets the application describe the users and groups it
+//! wants reported instead, and `configure` puts it in place for the whole
+//! process. This is the stub’s equivalent of a users database: lookups are
+//! served from its tables, and the current user and group are the ones it
+//! names.
+//!
+//! # Examples
+//!
+//! ```
+//! use users::{User, Group, get_current_username, get_user_by_name};
+//! use users::stub::{StubConfig, configure};
+//!
+//! let mut config = StubConfig::new().with_current_uid(1000).with_current_gid(100);
+//! config.add_user(User::new(1000, "alice", 100));
+//! config.add_group(Group::new(100, "users"));
+//! configure(config);
+//!
+//! # if users::backend_info().backend() == users::Backend::Stub {
+//! assert_eq!(get_current_username(), Some("alice".into()));
+//! assert_eq!(get_user_by_name("alice").map(|u| u.uid()), Some(1000));
+//! # }
+//! ```
+//!
+//! An [`IdentityProvider`](../identity/trait.IdentityProvider.html), if one
+//! is set, takes precedence over the configured current user.
+//!
+//! ## Alongside a real backend
+//!
+//! On platforms with a backend of their own, the configuration is only
+//! consulted by the [`Stub`](struct.Stub.html) type, which answers the
+//! `Users` and `Groups` traits just like the stub answers the functions at
+//! the crate root elsewhere. That lets tests on Unix exercise what the stub
+//! would report next to what the system does:
+//!
+//! ```
+//! use users::Users;
+//! use users::stub::{Stub, StubConfig, configure};
+//!
+//! configure(StubConfig::new().with_current_uid(1000).with_current_username("alice"));
+//! assert_eq!(Stub.get_current_uid(), 1000);
+//! assert_eq!(&*Stub.get_current_username().unwrap(), "alice");
+//! ```
+//!
+//! ## The environment
+//!
+//! With the `env-stub` feature, a stub that was never configured reads the
+//! current user from environment variables such as `USER` and `HOME`
+//! instead, as [`StubConfig::from_env`](struct.StubConfig.html#method.from_env)
+//! describes. That gets Windows and wasm builds reporting something close to
+//! reality without any code changes.
+//!
+//! ## Strictness
+//!
+//! With the `strict-stub` feature, the stub stops pretending: the functions
+//! in `switch` fail with `ErrorKind::Unsupported` instead of succeeding
+//! without doing anything, as does `group_access_list` while the stub is
+//! neither configured nor given an identity provider. The user and group ID
+//! functions can’t fail, so there are `try_` variants of them, such as
+//! [`try_get_current_uid`](../fn.try_get_current_uid.html), which fail in
+//! the same circumstances; privilege-sensitive code should use those.
+//!
+//! With the `deny-stub-at-runtime` feature, going further, every function
+//! answered by the stub panics instead, naming the function and pointing at
+//! the code that called it. That way, a test suite that must never exercise
+//! stubbed behaviour finds out as soon as it does.
+//!
+//! ## Files
+//!
+//! With the `file-db` feature, a configuration can be read from a TOML or
+//! JSON file with [`load_from_path`](fn.load_from_path.html). A stub that
+//! was never configured also reads the file named by the `USERS_STUB_DB`
+//! environment variable, if it is set, which suits containers and tests
+//! that lack a real users database.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::PathBuf;
+
+#[cfg(feature = "file-db")]
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use backend::Backend;
+use {User, Group, UserBuilder, uid_t, gid_t};
+use identity;
+use os::unix::GroupExt;
+
+#[cfg(feature = "file-db")]
+use db;
+use platform::Platform;
+use {Users, Groups};
+
+
+/// The users, groups, and identity for the stub to report.
+#[derive(Clone, Debug, Default)]
+pub struct StubConfig {
+    users: HashMap<uid_t, User>,
+    groups: HashMap<gid_t, Group>,
+    members: HashMap<gid_t, Vec<OsString>>,
+    uid: uid_t,
+    gid: gid_t,
+    username: Option<OsString>,
+    home_dir: Option<PathBuf>,
+}
+
+impl StubConfig {
+
+    /// Create a new, empty configuration, which reports the same as the
+    /// unconfigured stub: user and group 0, with no names.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the ID of the user the process runs as.
+    pub fn with_current_uid(mut self, uid: uid_t) -> Self {
+        self.uid = uid;
+        self
+    }
+
+    /// Sets the ID of the group the process runs as.
+    pub fn with_current_gid(mut self, gid: gid_t) -> Self {
+        self.gid = gid;
+        self
+    }
+
+    /// Sets the name of the user the process runs as. Without it, the name
+    /// is that of the user in the table with the current user ID, if any.
+    pub fn with_current_username<S: AsRef<OsStr> + ?Sized>(mut self, username: &S) -> Self {
+        self.username = Some(username.as_ref().to_owned());
+        self
+    }
+
+    /// Sets the home directory of the user the process runs as.
+    pub fn with_current_home_dir<P: Into<PathBuf>>(mut self, home_dir: P) -> Self {
+        self.home_dir = Some(home_dir.into());
+        self
+    }
+
+    /// Create a configuration describing the user named by the environment:
+    ///
+    /// - the username comes from `USERNAME`, `USER`, or `LOGNAME`, whichever
+    ///   is set first;
+    /// - the user ID comes from `UID`, or else is made up from a hash of the
+    ///   username, so it stays the same from one run to the next;
+    /// - the group ID is the same as the user ID, and the group shares the
+    ///   user’s name, as with a user private group;
+    /// - the home directory comes from `HOME` or `USERPROFILE`.
+    ///
+    /// The user and group are added to the tables, so that they can be
+    /// looked up, too. Without a username, the configuration is empty.
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| env::var_os(name))
+    }
+
+    /// Reads a configuration from a TOML or JSON file, as the [`db`
+    /// module](../db/index.html) describes.
+    #[cfg(feature = "file-db")]
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        db::load(path.as_ref())
+    }
+
+    fn from_vars<F: Fn(&str) -> Option<OsString>>(var: F) -> Self {
+        let first = |names: &[&str]| names.iter().filter_map(|n| var(n)).find(|v| !v.is_empty());
+
+        let username = match first(&[ "USERNAME", "USER", "LOGNAME" ]) {
+            Some(username) => username,
+            None           => return Self::new(),
+        };
+
+        let uid = first(&[ "UID" ]).and_then(|uid| uid.to_str().and_then(|u| u.parse().ok()))
+                                   .unwrap_or_else(|| hashed_uid(&username));
+
+        let mut config = Self::new().with_current_uid(uid).with_current_gid(uid);
+        config.home_dir = first(&[ "HOME", "USERPROFILE" ]).map(PathBuf::from);
+
+        let mut user = UserBuilder::new(uid, &username, uid);
+        if let Some(ref home_dir) = config.home_dir {
+            user = user.home_dir(home_dir);
+        }
+        config.add_user(user.build());
+        config.add_group(Group::new(uid, &username));
+        config
+    }
+
+    /// Add a user to the users table.
+    pub fn add_user(&mut self, user: User) -> Option<User> {
+        self.users.insert(user.uid(), user)
+    }
+
+    /// Add a group to the groups table, along with its members.
+    pub fn add_group(&mut self, group: Group) -> Option<Group> {
+        let members = self.members.entry(group.gid()).or_default();
+        let added = members.iter().filter(|m| ! group.members().contains(m)).cloned().collect::<Vec<_>>();
+        for member in group.members() {
+            if ! members.contains(member) {
+                members.push(member.clone());
+            }
+        }
+
+        let group = added.iter().fold(group, |g, m| g.add_member(m));
+        self.groups.insert(group.gid(), group)
+    }
+
+    /// Lists the named user as a member of the group with the given ID, in
+    /// addition to the group being their primary group, if it is. A user
+    /// who is already listed is left as they are.
+    pub fn add_member<S: AsRef<OsStr> + ?Sized>(&mut self, gid: gid_t, username: &S) {
+        let members = self.members.entry(gid).or_default();
+        if members.iter().any(|m| m == username.as_ref()) {
+            return;
+        }
+
+        members.push(username.as_ref().to_owned());
+
+        if let Some(group) = self.groups.remove(&gid) {
+            self.groups.insert(gid, group.add_member(username));
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.users.is_empty() && self.groups.is_empty() && self.uid == 0 && self.gid == 0
+            && self.username.is_none() && self.home_dir.is_none()
+    }
+
+    fn current_username(&self) -> Option<OsString> {
+        self.username.clone()
+            .or_else(|| self.users.get(&self.uid).map(|u| u.name().to_owned()))
+    }
+
+    fn user_groups(&self, username: &OsStr, gid: gid_t) -> Vec<Group> {
+        let mut groups = self.groups.get(&gid).cloned().into_iter().collect::<Vec<_>>();
+
+        let mut others = self.members.iter()
+            .filter(|&(&g, names)| g != gid && names.iter().any(|n| n == username))
+            .filter_map(|(g, _)| self.groups.get(g).cloned())
+            .collect::<Vec<_>>();
+        others.sort_by_key(|g| g.gid());
+
+        groups.extend(others);
+        groups
+    }
+}
+
+
+/// Makes up a user ID for a username, in the range regular users’ IDs
+/// usually come from, using the 32-bit FNV-1a hash of its bytes.
+fn hashed_uid(username: &OsStr) -> uid_t {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in username.to_string_lossy().bytes() {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+
+    1000 + hash % 59_000
+}
+
+
+static CONFIG: RwLock<Option<StubConfig>> = RwLock::new(None);
+
+/// Makes the stub report what the given configuration describes, replacing
+/// any configuration that was put in place before.
+pub fn configure(config: StubConfig) {
+    *CONFIG.write().unwrap_or_else(|e| e.into_inner()) = Some(config);
+}
+
+/// Reads a configuration from a TOML or JSON file and puts it in place.
+#[cfg(feature = "file-db")]
+pub fn load_from_path<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    configure(StubConfig::from_path(path)?);
+    Ok(())
+}
+
+/// Returns the home directory of the user the stub reports, if it was
+/// configured, or taken from the environment with the `env-stub` feature.
+pub fn current_home_dir() -> Option<PathBuf> {
+    with_config(|c| c.home_dir.clone())
+}
+
+/// Runs the given function on the current configuration. If the stub was
+/// never configured, that is the one in the file named by `USERS_STUB_DB`
+/// with the `file-db` feature, which gets put in place, or else an empty
+/// one, or one read from the environment with the `env-stub` feature.
+fn with_config<T, F: FnOnce(&StubConfig) -> T>(f: F) -> T {
+    {
+        let config = CONFIG.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(ref config) = *config {
+            return f(config);
+        }
+    }
+
+    #[cfg(feature = "file-db")]
+    {
+        if let Some(path) = env::var_os(db::PATH_VAR) {
+            if load_from_path(path).is_ok() {
+                return with_config(f);
+            }
+        }
+    }
+
+    f(&unconfigured())
+}
+
+#[cfg(feature = "env-stub")]
+fn unconfigured() -> StubConfig {
+    StubConfig::from_env()
+}
+
+#[cfg(not(feature = "env-stub"))]
+fn unconfigured() -> StubConfig {
+    StubConfig::default()
+}
+
+
+/// The error the stub returns from functions it can only pretend to
+/// perform, with the `strict-stub` feature, and from `try_all_users` when
+/// no users are configured.
+pub(crate) fn unsupported(function: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("{} is not supported by the users stub on this platform", function))
+}
+
+
+/// The backend for platforms that provide nothing at all, serving what the
+/// application configured, or told it through an `IdentityProvider`.
+///
+/// It is available on every platform, and answers the `Users` and `Groups`
+/// traits the way the stub answers the functions at the crate root.
+pub struct Stub;
+
+impl Platform for Stub {
+    const BACKEND: Backend = Backend::Stub;
+    const IS_STUB: bool = true;
+
+    fn is_dummy() -> bool {
+        identity::with_provider(|_| ()).is_none() && with_config(StubConfig::is_empty)
+    }
+
+    fn get_user_by_uid(uid: uid_t) -> Option<User> {
+        with_config(|c| c.users.get(&uid).cloned())
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(username: &S) -> Option<User> {
+        with_config(|c| c.users.values().find(|u| u.name() == username.as_ref()).cloned())
+    }
+
+    fn get_group_by_gid(gid: gid_t) -> Option<Group> {
+        with_config(|c| c.groups.get(&gid).cloned())
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(groupname: &S) -> Option<Group> {
+        with_config(|c| c.groups.values().find(|g| g.name() == groupname.as_ref()).cloned())
+    }
+
+    fn get_current_uid() -> uid_t {
+        identity::with_provider(|p| p.current_uid())
+            .unwrap_or_else(|| with_config(|c| c.uid))
+    }
+
+    fn get_current_username() -> Option<OsString> {
+        identity::with_provider(|p| p.current_username())
+            .unwrap_or_else(|| with_config(StubConfig::current_username))
+    }
+
+    fn get_effective_uid() -> uid_t {
+        <Self as Platform>::get_current_uid()
+    }
+
+    fn get_effective_username() -> Option<OsString> {
+        <Self as Platform>::get_current_username()
+    }
+
+    fn get_current_gid() -> gid_t {
+        with_config(|c| c.gid)
+    }
+
+    fn get_current_groupname() -> Option<OsString> {
+        with_config(|c| c.groups.get(&c.gid).map(|g| g.name().to_owned()))
+    }
+
+    fn get_effective_gid() -> gid_t {
+        <Self as Platform>::get_current_gid()
+    }
+
+    fn get_effective_groupname() -> Option<OsString> {
+        <Self as Platform>::get_current_groupname()
+    }
+
+    fn group_access_list() -> io::Result<Vec<Group>> {
+        #[cfg(feature = "strict-stub")]
+        {
+            if Self::is_dummy() {
+                return Err(unsupported("group_access_list"));
+            }
+        }
+
+        let username = <Self as Platform>::get_current_username();
+        Ok(with_config(|c| match username {
+            Some(ref username) => c.user_groups(username, c.gid),
+            None               => c.groups.get(&c.gid).cloned().into_iter().collect(),
+        }))
+    }
+
+    fn get_user_groups<S: AsRef<OsStr> + ?Sized>(username: &S, gid: gid_t) -> Option<Vec<Group>> {
+        let username = username.as_ref();
+        with_config(|c| {
+            if c.users.values().any(|u| u.name() == username) {
+                Some(c.user_groups(username, gid))
+            }
+            else {
+                None
+            }
+        })
+    }
+
+    fn all_users() -> Vec<User> {
+        let mut users = with_config(|c| c.users.values().cloned().collect::<Vec<_>>());
+        users.sort_by_key(|u| u.uid());
+        users
+    }
+
+    fn try_all_users() -> io::Result<Vec<io::Result<User>>> {
+        if with_config(|c| c.users.is_empty()) {
+            return Err(unsupported("all_users"));
+        }
+
+        Ok(Self::all_users().into_iter().map(Ok).collect())
+    }
+
+    fn all_groups() -> Vec<Group> {
+        let mut groups = with_config(|c| c.groups.values().cloned().collect::<Vec<_>>());
+        groups.sort_by_key(|g| g.gid());
+        groups
+    }
+
+    #[cfg(feature = "strict-stub")]
+    fn set_current_uid(_uid: uid_t) -> io::Result<()> {
+        Err(unsupported("set_current_uid"))
+    }
+
+    #[cfg(feature = "strict-stub")]
+    fn set_current_gid(_gid: gid_t) -> io::Result<()> {
+        Err(unsupported("set_current_gid"))
+    }
+
+    #[cfg(feature = "strict-stub")]
+    fn set_effective_uid(_uid: uid_t) -> io::Result<()> {
+        Err(unsupported("set_effective_uid"))
+    }
+
+    #[cfg(feature = "strict-stub")]
+    fn set_effective_gid(_gid: gid_t) -> io::Result<()> {
+        Err(unsupported("set_effective_gid"))
+    }
+
+    #[cfg(feature = "strict-stub")]
+    fn set_both_uid(_ruid: uid_t, _euid: uid_t) -> io::Result<()> {
+        Err(unsupported("set_both_uid"))
+    }
+
+    #[cfg(feature = "strict-stub")]
+    fn set_both_gid(_rgid: gid_t, _egid: gid_t) -> io::Result<()> {
+        Err(unsupported("set_both_gid"))
+    }
+
+    #[cfg(feature = "strict-stub")]
+    fn set_all_uid(_ruid: uid_t, _euid: uid_t, _suid: uid_t) -> io::Result<()> {
+        Err(unsupported("set_all_uid"))
+    }
+
+    #[cfg(feature = "strict-stub")]
+    fn set_all_gid(_rgid: gid_t, _egid: gid_t, _sgid: gid_t) -> io::Result<()> {
+        Err(unsupported("set_all_gid"))
+    }
+
+    #[cfg(feature = "strict-stub")]
+    fn set_supplementary_groups(_gids: &[gid_t]) -> io::Result<()> {
+        Err(unsupported("set_supplementary_groups"))
+    }
+
+    #[cfg(feature = "strict-stub")]
+    fn init_groups(_username: &OsStr, _gid: gid_t) -> io::Result<()> {
+        Err(unsupported("init_groups"))
+    }
+
+    #[cfg(feature = "strict-stub")]
+    fn switch_user_group(_uid: uid_t, _gid: gid_t) -> io::Result<()> {
+        Err(unsupported("switch_user_group"))
+    }
+}
+
+
+impl Users for Stub {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        <Self as Platform>::get_user_by_uid(uid).map(Arc::new)
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        <Self as Platform>::get_user_by_name(username).map(Arc::new)
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        <Self as Platform>::get_current_uid()
+    }
+
+    fn get_current_username(&self) -> Option<Arc<OsStr>> {
+        <Self as Platform>::get_current_username().map(Arc::from)
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        <Self as Platform>::get_effective_uid()
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<OsStr>> {
+        <Self as Platform>::get_effective_username().map(Arc::from)
+    }
+}
+
+impl Groups for Stub {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        <Self as Platform>::get_group_by_gid(gid).map(Arc::new)
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+        <Self as Platform>::get_group_by_name(group_name).map(Arc::new)
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        <Self as Platform>::get_current_gid()
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
+        <Self as Platform>::get_current_groupname().map(Arc::from)
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        <Self as Platform>::get_effective_gid()
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
+        <Self as Platform>::get_effective_groupname().map(Arc::from)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> StubConfig {
+        let mut config = StubConfig::new().with_current_uid(1000).with_current_gid(100);
+        config.add_user(User::new(1000, "alice", 100));
+        config.add_user(User::new(1001, "bob", 100));
+        config.add_group(Group::new(100, "users"));
+        config.add_group(Group::new(10, "wheel"));
+        config.add_member(10, "alice");
+        config
+    }
+
+    #[test]
+    fn group_members() {
+        let mut config = config();
+        config.add_member(200, "bob");
+        config.add_group(Group::new(200, "staff").add_member("carol"));
+        config.add_member(200, "bob");
+        config.add_member(10, "alice");
+
+        assert_eq!(config.groups[&10].members(), &[ OsString::from("alice") ]);
+        assert_eq!(config.groups[&200].members(), &[ OsString::from("carol"), OsString::from("bob") ]);
+        assert_eq!(config.user_groups(OsStr::new("carol"), 100).len(), 2);
+    }
+
+    #[test]
+    fn current_username_from_table() {
+        assert_eq!(config().current_username(), Some("alice".into()));
+    }
+
+    #[test]
+    fn current_username_explicit() {
+        let config = config().with_current_username("carol");
+        assert_eq!(config.current_username(), Some("carol".into()));
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> StubConfig {
+        StubConfig::from_vars(|name| vars.iter().find(|v| v.0 == name).map(|v| v.1.into()))
+    }
+
+    #[test]
+    fn env_empty() {
+        let config = vars(&[ ("HOME", "/home/nobody") ]);
+        assert_eq!(config.current_username(), None);
+        assert_eq!(config.uid, 0);
+    }
+
+    #[test]
+    fn env_uid() {
+        let config = vars(&[ ("USER", "alice"), ("UID", "1234"), ("USERPROFILE", r"C:\Users\alice") ]);
+        assert_eq!(config.current_username(), Some("alice".into()));
+        assert_eq!(config.uid, 1234);
+        assert_eq!(config.home_dir, Some(r"C:\Users\alice".into()));
+        assert_eq!(config.users.get(&1234).map(|u| u.primary_group_id()), Some(1234));
+    }
+
+    #[test]
+    fn env_home_dir() {
+        use os::unix::UserExt;
+
+        let config = vars(&[ ("USER", "alice"), ("HOME", "/home/alice") ]);
+        assert_eq!(config.users.get(&config.uid).map(|u| u.home_dir().to_owned()), config.home_dir);
+    }
+
+    #[test]
+    fn env_hashed_uid() {
+        let config = vars(&[ ("USERNAME", "alice"), ("USER", "bob") ]);
+        assert_eq!(config.current_username(), Some("alice".into()));
+        assert_eq!(config.uid, hashed_uid(OsStr::new("alice")));
+        assert!(config.uid >= 1000 && config.uid < 60_000);
+        assert_ne!(hashed_uid(OsStr::new("alice")), hashed_uid(OsStr::new("bob")));
+    }
+
+    #[test]
+    fn user_groups() {
+        let groups = config().user_groups(OsStr::new("alice"), 100);
+        assert_eq!(groups.iter().map(|g| g.gid()).collect::<Vec<_>>(), vec![ 100, 10 ]);
+
+        let groups = config().user_groups(OsStr::new("bob"), 100);
+        assert_eq!(groups.iter().map(|g| g.gid()).collect::<Vec<_>>(), vec![ 100 ]);
+    }
+}
@@ -0,0 +1,181 @@
+//! Reading the subordinate ID ranges of `/etc/subuid` and `/etc/subgid`.
+//!
+//! Rootless containers map a range of IDs inside a user namespace to IDs
+//! on the host that only their user may use. Those ranges are handed out in
+//! `/etc/subuid` for user IDs and `/etc/subgid` for group IDs, one per
+//! line, as the owner’s name or user ID, the first ID, and the number of
+//! IDs:
+//!
+//! ```no_run
+//! use users::subids::subuid_ranges_for;
+//!
+//! for range in subuid_ranges_for("alice").unwrap() {
+//!     println!("alice may use UIDs {} to {}", range.start, range.end() - 1);
+//! }
+//! ```
+//!
+//! Lines that don’t parse are skipped, and a missing file has no ranges.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use {get_user_by_name, uid_t};
+
+
+/// The file listing the subordinate user IDs.
+pub const SUBUID_PATH: &str = "/etc/subuid";
+
+/// The file listing the subordinate group IDs.
+pub const SUBGID_PATH: &str = "/etc/subgid";
+
+
+/// A range of subordinate IDs.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct SubIdRange {
+
+    /// The first ID in the range.
+    pub start: u32,
+
+    /// The number of IDs in the range.
+    pub count: u32,
+}
+
+impl SubIdRange {
+
+    /// Returns the ID just past the end of the range.
+    pub fn end(&self) -> u64 {
+        u64::from(self.start) + u64::from(self.count)
+    }
+
+    /// Returns whether the given ID is in the range.
+    pub fn contains(&self, id: u32) -> bool {
+        id >= self.start && u64::from(id) < self.end()
+    }
+}
+
+
+/// The entries of a `/etc/subuid` or `/etc/subgid` file.
+#[derive(PartialEq, Eq, Clone, Default, Debug)]
+pub struct SubIds {
+    entries: Vec<(String, SubIdRange)>,
+}
+
+impl SubIds {
+
+    /// Parses the contents of a `/etc/subuid` or `/etc/subgid` file.
+    pub fn parse(contents: &str) -> Self {
+        let entries = contents.lines().filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            let mut fields = line.split(':');
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(owner), Some(start), Some(count), None) if ! owner.is_empty() => {
+                    Some((owner.to_owned(), SubIdRange { start: start.parse().ok()?, count: count.parse().ok()? }))
+                }
+                _ => None,
+            }
+        }).collect();
+
+        Self { entries }
+    }
+
+    /// Reads a `/etc/subuid` or `/etc/subgid` file. A file that doesn’t
+    /// exist has no entries.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents)                                      => Ok(Self::parse(&contents)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e)                                            => Err(e),
+        }
+    }
+
+    /// Returns the ranges of the user with the given name, and, if given,
+    /// user ID, since either can name the owner of a range.
+    pub fn ranges_for(&self, username: &str, uid: Option<uid_t>) -> Vec<SubIdRange> {
+        let uid = uid.map(|uid| uid.to_string());
+        self.entries.iter()
+            .filter(|&(owner, _)| owner == username || Some(owner) == uid.as_ref())
+            .map(|&(_, range)| range)
+            .collect()
+    }
+
+    /// Returns the owner of the range the given ID is in, as the name or
+    /// user ID the file gives, if it is in one.
+    pub fn owner_of(&self, id: u32) -> Option<&str> {
+        self.entries.iter().find(|(_, range)| range.contains(id)).map(|(owner, _)| &**owner)
+    }
+
+    /// Returns every entry, as its owner and range, in the order listed.
+    pub fn iter(&self) -> impl Iterator<Item=(&str, SubIdRange)> {
+        self.entries.iter().map(|(owner, range)| (&**owner, *range))
+    }
+}
+
+
+/// Returns the subordinate user ID ranges of the user with the given name,
+/// from `/etc/subuid`.
+pub fn subuid_ranges_for<S: AsRef<OsStr> + ?Sized>(username: &S) -> io::Result<Vec<SubIdRange>> {
+    ranges_for(SUBUID_PATH, username.as_ref())
+}
+
+/// Returns the subordinate group ID ranges of the user with the given name,
+/// from `/etc/subgid`.
+pub fn subgid_ranges_for<S: AsRef<OsStr> + ?Sized>(username: &S) -> io::Result<Vec<SubIdRange>> {
+    ranges_for(SUBGID_PATH, username.as_ref())
+}
+
+fn ranges_for(path: &str, username: &OsStr) -> io::Result<Vec<SubIdRange>> {
+    let username_str = match username.to_str() {
+        Some(name) => name,
+        None       => return Ok(Vec::new()),
+    };
+
+    let uid = get_user_by_name(username).map(|u| u.uid());
+    Ok(SubIds::from_file(path)?.ranges_for(username_str, uid))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SUBUID: &str = "alice:100000:65536\n# comment\n1001:165536:65536\nbroken:1\nalice:300000:1000\n";
+
+    #[test]
+    fn parse() {
+        let subids = SubIds::parse(SUBUID);
+        assert_eq!(subids.iter().count(), 3);
+        assert_eq!(subids.ranges_for("alice", None), vec![
+            SubIdRange { start: 100000, count: 65536 },
+            SubIdRange { start: 300000, count: 1000 },
+        ]);
+        assert_eq!(subids.ranges_for("bob", Some(1001)), vec![ SubIdRange { start: 165536, count: 65536 } ]);
+        assert!(subids.ranges_for("carol", Some(1002)).is_empty());
+    }
+
+    #[test]
+    fn owners() {
+        let subids = SubIds::parse(SUBUID);
+        assert_eq!(subids.owner_of(100000), Some("alice"));
+        assert_eq!(subids.owner_of(165535), Some("alice"));
+        assert_eq!(subids.owner_of(165536), Some("1001"));
+        assert_eq!(subids.owner_of(99999), None);
+    }
+
+    #[test]
+    fn range_end() {
+        let range = SubIdRange { start: u32::MAX, count: 1 };
+        assert_eq!(range.end(), 1 << 32);
+        assert!(range.contains(u32::MAX));
+    }
+
+    #[test]
+    fn missing_file() {
+        assert_eq!(SubIds::from_file("/nonexistent/subuid").unwrap(), SubIds::default());
+    }
+}
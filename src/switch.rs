@@ -1,54 +1,1055 @@
 //! Functions for switching the running process’s user or group.
+//!
+//! Where the real `users` crate is used, its functions are re-exported here,
+//! alongside the ones it lacks. Those that change the real user ID are
+//! wrapped, so that the current user gets looked up again afterwards (see
+//! [`refresh_current_identity`](../fn.refresh_current_identity.html)).
+//!
+//! ## Windows
+//!
+//! With the `windows-native` feature, Windows cannot simply assume another
+//! user’s identity by ID the way `setuid` does: a thread can only
+//! impersonate a user after logging them on, which takes their password.
+//! The ID-based functions in this module therefore return an error there
+//! unless the requested ID is the one the process already has, and
+//! [`switch_user_with_password`](fn.switch_user_with_password.html) is
+//! available instead.
+//!
+//! ## Fuchsia
+//!
+//! Fuchsia processes have no user or group IDs to switch between, so the
+//! functions in this module fail with `ErrorKind::Unsupported` there, unless
+//! the requested ID is that of the synthetic user the process runs as (see
+//! the [`fuchsia`](../fuchsia/index.html) module).
 
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt;
 use std::io;
-use super::base::{uid_t, gid_t, c_int};
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::RwLock;
 
-use base::{get_effective_uid, get_effective_gid};
+use {User, uid_t, gid_t};
+use current::refresh_current_identity;
+
+#[cfg(users_passthrough)]
+pub use users_orig::switch::*;
+
+#[cfg(all(feature = "fsuid", users_passthrough, any(target_os = "linux", target_os = "android")))]
+use libc;
+
+#[cfg(not(users_passthrough))]
+use super::base::c_int;
+
+#[cfg(not(users_passthrough))]
+use platform::{Current, Platform, stub_used};
+
+#[cfg(all(windows, feature = "windows-native"))]
+use windows;
 
 
 // NOTE: for whatever reason, it seems these are not available in libc on BSD platforms, so they
 //       need to be included manually
+#[cfg(not(users_passthrough))]
 extern {
     fn setreuid(ruid: uid_t, euid: uid_t) -> c_int;
     fn setregid(rgid: gid_t, egid: gid_t) -> c_int;
 }
 
 
-/// const Ok
+/// Sets the current user ID, with `setuid`.
+#[cfg(users_passthrough)]
 pub fn set_current_uid(uid: uid_t) -> io::Result<()> {
+    users_orig::switch::set_current_uid(uid)?;
+    refresh_current_identity();
     Ok(())
 }
 
-/// const Ok
-pub fn set_current_gid(gid: gid_t) -> io::Result<()> {
+/// Sets the real and effective user IDs, with `setreuid`.
+#[cfg(users_passthrough)]
+pub fn set_both_uid(ruid: uid_t, euid: uid_t) -> io::Result<()> {
+    users_orig::switch::set_both_uid(ruid, euid)?;
+    refresh_current_identity();
     Ok(())
 }
 
-/// const Ok
-pub fn set_effective_uid(uid: uid_t) -> io::Result<()> {
+/// Errors on Windows and Fuchsia unless unchanged (see module docs), const Ok otherwise
+#[cfg(not(users_passthrough))]
+#[track_caller]
+pub fn set_current_uid(uid: uid_t) -> io::Result<()> {
+    stub_used("switch::set_current_uid");
+
+    Current::set_current_uid(uid)?;
+    refresh_current_identity();
     Ok(())
 }
 
-/// const Ok
+/// Errors on Windows and Fuchsia unless unchanged (see module docs), const Ok otherwise
+#[cfg(not(users_passthrough))]
+#[track_caller]
+pub fn set_current_gid(gid: gid_t) -> io::Result<()> {
+    stub_used("switch::set_current_gid");
+
+    Current::set_current_gid(gid)
+}
+
+/// Errors on Windows and Fuchsia unless unchanged (see module docs), const Ok otherwise
+#[cfg(not(users_passthrough))]
+#[track_caller]
+pub fn set_effective_uid(uid: uid_t) -> io::Result<()> {
+    stub_used("switch::set_effective_uid");
+
+    Current::set_effective_uid(uid)
+}
+
+/// Errors on Windows and Fuchsia unless unchanged (see module docs), const Ok otherwise
+#[cfg(not(users_passthrough))]
+#[track_caller]
 pub fn set_effective_gid(gid: gid_t) -> io::Result<()> {
-    Ok(())
+    stub_used("switch::set_effective_gid");
+
+    Current::set_effective_gid(gid)
 }
 
-/// const Ok
+/// Errors on Windows and Fuchsia unless unchanged (see module docs), const Ok otherwise
+#[cfg(not(users_passthrough))]
+#[track_caller]
 pub fn set_both_uid(ruid: uid_t, euid: uid_t) -> io::Result<()> {
+    stub_used("switch::set_both_uid");
+
+    Current::set_both_uid(ruid, euid)?;
+    refresh_current_identity();
     Ok(())
 }
 
-/// const Ok
+/// Errors on Windows and Fuchsia unless unchanged (see module docs), const Ok otherwise
+#[cfg(not(users_passthrough))]
+#[track_caller]
 pub fn set_both_gid(rgid: gid_t, egid: gid_t) -> io::Result<()> {
+    stub_used("switch::set_both_gid");
+
+    Current::set_both_gid(rgid, egid)
+}
+
+/// Sets the real, effective, and saved user IDs at once, with `setresuid`.
+///
+/// Unlike `set_both_uid`, this leaves no doubt about the saved user ID,
+/// which a process could otherwise use to regain the privileges it dropped.
+/// Where there is no `setresuid`, such as on macOS, illumos, and AIX, it
+/// uses `setreuid`, which sets the saved user ID to the effective one, and
+/// fails with `ErrorKind::Unsupported` when asked for any other.
+///
+/// Errors on Windows and Fuchsia unless unchanged (see module docs), const
+/// Ok with the stub.
+#[track_caller]
+pub fn set_all_uid(ruid: uid_t, euid: uid_t, suid: uid_t) -> io::Result<()> {
+    #[cfg(users_passthrough)]
+    native::set_all_uid(ruid, euid, suid)?;
+
+    #[cfg(not(users_passthrough))]
+    {
+        stub_used("switch::set_all_uid");
+        Current::set_all_uid(ruid, euid, suid)?;
+    }
+
+    refresh_current_identity();
     Ok(())
 }
 
+/// Sets the real, effective, and saved group IDs at once, with `setresgid`.
+///
+/// Where there is no `setresgid`, it uses `setregid`, which sets the saved
+/// group ID to the effective one, and fails with `ErrorKind::Unsupported`
+/// when asked for any other.
+///
+/// Errors on Windows and Fuchsia unless unchanged (see module docs), const
+/// Ok with the stub.
+#[track_caller]
+pub fn set_all_gid(rgid: gid_t, egid: gid_t, sgid: gid_t) -> io::Result<()> {
+    #[cfg(users_passthrough)]
+    return native::set_all_gid(rgid, egid, sgid);
+
+    #[cfg(not(users_passthrough))]
+    {
+        stub_used("switch::set_all_gid");
+        Current::set_all_gid(rgid, egid, sgid)
+    }
+}
+
+/// Sets the filesystem user ID, which Linux checks file accesses against
+/// instead of the effective user ID, and returns the previous one.
+///
+/// This lets a file server act as a client when opening files, without
+/// also letting that client send it signals, as changing the effective
+/// user ID would. `setfsuid` itself cannot fail, and returns the previous
+/// ID whether it changed it or not, so the result is read back, and an
+/// error of kind `PermissionDenied` returned if it didn’t take.
+///
+/// Unlike `setuid` and the like, it only affects the calling thread.
+///
+/// Only on Linux and Android, with the `fsuid` feature.
+#[cfg(all(feature = "fsuid", users_passthrough, any(target_os = "linux", target_os = "android")))]
+pub fn set_fs_uid(uid: uid_t) -> io::Result<uid_t> {
+    let previous = unsafe { libc::setfsuid(uid) } as uid_t;
+    native::check_fs_id(uid, unsafe { libc::setfsuid(uid_t::MAX) } as uid_t)?;
+    Ok(previous)
+}
+
+/// Sets the filesystem group ID, which Linux checks file accesses against
+/// instead of the effective group ID, and returns the previous one.
+///
+/// As with `set_fs_uid`, the result is read back, and an error of kind
+/// `PermissionDenied` returned if it didn’t take.
+///
+/// Only on Linux and Android, with the `fsuid` feature.
+#[cfg(all(feature = "fsuid", users_passthrough, any(target_os = "linux", target_os = "android")))]
+pub fn set_fs_gid(gid: gid_t) -> io::Result<gid_t> {
+    let previous = unsafe { libc::setfsgid(gid) } as gid_t;
+    native::check_fs_id(gid, unsafe { libc::setfsgid(gid_t::MAX) } as gid_t)?;
+    Ok(previous)
+}
+
 /// Guard returned from a `switch_user_group` call.
+///
+/// When dropped, it switches back to the effective user and group IDs, and
+/// the supplementary groups if `switch_user_group_with_groups` changed
+/// them, that the process had before. Drop can’t report a failure to do
+/// so, other than through `take_restore_error`, so call `restore` to find
+/// out.
+///
+/// Guards nest: one dropped while a guard from a later switch is still
+/// around leaves the identity alone, and that later guard switches back to
+/// what the process had before either, when it is dropped in turn. They
+/// keep track of this per thread, so a guard can’t be sent to another one.
+///
+/// On Windows, a guard returned from `switch_user_with_password` reverts
+/// the impersonation when it is dropped.
 pub struct SwitchUserGuard {
+    token: Option<u64>,
+
+    #[cfg(all(windows, feature = "windows-native"))]
+    impersonation: Option<windows::Impersonation>,
+
+    // the saved identity is on the stack of the thread that switched
+    _thread: PhantomData<*const ()>,
+}
+
+/// What a guard switches back to.
+///
+/// These are kept on a stack per thread, so that guards dropped out of
+/// order still leave the right identity: a guard dropped while a later one
+/// is still around hands what it would have switched back to on to that
+/// one, and switches nothing.
+struct Saved {
+    token: u64,
+
+    #[cfg(users_passthrough)]
+    ids: (uid_t, gid_t),
+
+    groups: Option<Vec<gid_t>>,
 }
 
-/// nop, returns a `SwitchUserGuard`, it's nop on drop, too
+thread_local! {
+    static SAVED: RefCell<Vec<Saved>> = const { RefCell::new(Vec::new()) };
+    static NEXT_TOKEN: Cell<u64> = const { Cell::new(0) };
+}
+
+impl SwitchUserGuard {
+
+    /// Puts the identity to switch back to on this thread’s stack, and
+    /// returns the guard for it.
+    fn push(#[cfg(users_passthrough)] ids: (uid_t, gid_t)) -> Self {
+        let token = NEXT_TOKEN.with(|next| next.replace(next.get() + 1));
+        SAVED.with(|stack| stack.borrow_mut().push(Saved {
+            token,
+            #[cfg(users_passthrough)]
+            ids,
+            groups: None,
+        }));
+
+        Self {
+            token: Some(token),
+            #[cfg(all(windows, feature = "windows-native"))]
+            impersonation: None,
+            _thread: PhantomData,
+        }
+    }
+
+    /// Makes the guard switch the supplementary groups back to the given
+    /// ones, too.
+    fn save_groups(&self, groups: Vec<gid_t>) {
+        SAVED.with(|stack| {
+            if let Some(saved) = stack.borrow_mut().iter_mut().find(|s| Some(s.token) == self.token) {
+                saved.groups = Some(groups);
+            }
+        });
+    }
+
+    /// Switches back now, rather than when the guard is dropped, returning
+    /// the error if that fails.
+    ///
+    /// It stops at the first thing it fails to switch back, as what comes
+    /// after likely needs it.
+    pub fn restore(mut self) -> io::Result<()> {
+        self.switch_back()
+    }
+
+    /// Switches back, unless it has been already, or a later guard is still
+    /// around.
+    fn switch_back(&mut self) -> io::Result<()> {
+        #[cfg(all(windows, feature = "windows-native"))]
+        {
+            if let Some(impersonation) = self.impersonation.take() {
+                impersonation.revert()?;
+            }
+        }
+
+        let token = match self.token.take() {
+            Some(token) => token,
+            None        => return Ok(()),
+        };
+
+        let saved = SAVED.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            let saved = stack.iter().position(|s| s.token == token).map(|pos| (pos, stack.remove(pos)))?;
+
+            match stack.get_mut(saved.0) {
+                Some(later) => {
+                    #[cfg(users_passthrough)]
+                    { later.ids = saved.1.ids; }
+
+                    if saved.1.groups.is_some() {
+                        later.groups = saved.1.groups;
+                    }
+
+                    None
+                }
+                None => Some(saved.1),
+            }
+        });
+
+        if let Some(saved) = saved {
+            // the user first, as changing the groups back needs its privileges
+            #[cfg(users_passthrough)]
+            {
+                set_effective_uid(saved.ids.0)?;
+                set_effective_gid(saved.ids.1)?;
+            }
+
+            if let Some(groups) = saved.groups {
+                set_supplementary_groups(&groups)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for SwitchUserGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.switch_back() {
+            RESTORE_ERROR.with(|error| *error.borrow_mut() = Some(e));
+        }
+    }
+}
+
+thread_local! {
+    static RESTORE_ERROR: RefCell<Option<io::Error>> = const { RefCell::new(None) };
+}
+
+/// Returns the error a `SwitchUserGuard` last failed to switch back with
+/// when it was dropped on this thread, if any, and forgets it.
+pub fn take_restore_error() -> Option<io::Error> {
+    RESTORE_ERROR.with(|error| error.borrow_mut().take())
+}
+
+/// Sets the effective user and group IDs until the returned guard is
+/// dropped, leaving the supplementary groups as they are.
+///
+/// In a process with more than one thread, what happens then depends on
+/// the [`ThreadPolicy`](enum.ThreadPolicy.html).
+///
+/// Errors on Windows and Fuchsia unless unchanged (see module docs),
+/// const Ok with the stub, where the guard is nop on drop, too.
+#[track_caller]
 pub fn switch_user_group(uid: uid_t, gid: gid_t) -> io::Result<SwitchUserGuard> {
-    Ok(SwitchUserGuard {})
+    check_threads()?;
+    switch_ids(uid, gid)
+}
+
+/// Switches the effective IDs, without looking at the threads.
+#[track_caller]
+fn switch_ids(uid: uid_t, gid: gid_t) -> io::Result<SwitchUserGuard> {
+    #[cfg(users_passthrough)]
+    {
+        let guard = SwitchUserGuard::push((::users_orig::get_effective_uid(), ::users_orig::get_effective_gid()));
+
+        set_effective_gid(gid)?;
+        set_effective_uid(uid)?;
+        Ok(guard)
+    }
+
+    #[cfg(not(users_passthrough))]
+    {
+        stub_used("switch::switch_user_group");
+
+        Current::switch_user_group(uid, gid)?;
+
+        Ok(SwitchUserGuard::push())
+    }
+}
+
+/// What `switch_user_group` does when the process has more than one thread.
+///
+/// Linux keeps the user and group IDs per thread. Switching them with the
+/// system call only switches the calling thread, and the C library’s
+/// wrappers instead switch every thread at once, behind the backs of the
+/// others. Either way, a temporary switch rarely does what was meant, so a
+/// service may want to know about it, or refuse outright.
+///
+/// The threads are only counted on Linux and Android, from
+/// `/proc/self/task`; elsewhere, the process is taken to have one.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ThreadPolicy {
+
+    /// Switch regardless, as `switch_user_group` always used to.
+    #[default]
+    Allow,
+
+    /// Call the given function with the number of threads, then switch.
+    Warn(fn(usize)),
+
+    /// Fail with an error instead of switching.
+    Refuse,
+}
+
+static THREAD_POLICY: RwLock<ThreadPolicy> = RwLock::new(ThreadPolicy::Allow);
+
+/// Sets what `switch_user_group` and `switch_user_group_with_groups` do in a
+/// process with more than one thread, for the whole process.
+///
+/// # Examples
+///
+/// ```
+/// use users::switch::{set_thread_policy, ThreadPolicy};
+///
+/// set_thread_policy(ThreadPolicy::Warn(|threads| {
+///     eprintln!("warning: switching user with {} threads running", threads);
+/// }));
+/// ```
+pub fn set_thread_policy(policy: ThreadPolicy) {
+    *THREAD_POLICY.write().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+/// Applies the thread policy, if there are other threads.
+fn check_threads() -> io::Result<()> {
+    let policy = *THREAD_POLICY.read().unwrap_or_else(|e| e.into_inner());
+    if let ThreadPolicy::Allow = policy {
+        return Ok(());
+    }
+
+    apply_policy(policy, thread_count())
+}
+
+fn apply_policy(policy: ThreadPolicy, threads: usize) -> io::Result<()> {
+    match policy {
+        _ if threads <= 1        => Ok(()),
+        ThreadPolicy::Allow      => Ok(()),
+        ThreadPolicy::Warn(warn) => { warn(threads); Ok(()) }
+        ThreadPolicy::Refuse     => Err(io::Error::new(io::ErrorKind::Other, format!("refusing to switch user with {} threads running", threads))),
+    }
+}
+
+/// The number of threads in the process, or 1 if that can’t be told.
+fn thread_count() -> usize {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        if let Ok(tasks) = ::std::fs::read_dir("/proc/self/task") {
+            return tasks.count();
+        }
+    }
+
+    1
+}
+
+/// Like `switch_user_group`, but also sets the supplementary groups to the
+/// given ones until the guard is dropped, as otherwise the process keeps
+/// those it started with, such as root’s. Pass no groups to clear them.
+///
+/// The groups are set first, while the process still may.
+#[track_caller]
+pub fn switch_user_group_with_groups(uid: uid_t, gid: gid_t, groups: &[gid_t]) -> io::Result<SwitchUserGuard> {
+    check_threads()?;
+
+    let previous = supplementary_groups()?;
+    set_supplementary_groups(groups)?;
+
+    match switch_ids(uid, gid) {
+        Ok(guard) => {
+            guard.save_groups(previous);
+            Ok(guard)
+        }
+        Err(e) => {
+            let _ = set_supplementary_groups(&previous);
+            Err(e)
+        }
+    }
+}
+
+/// Runs the given closure with the effective user and group IDs switched
+/// as `switch_user_group` does, and switches back afterwards, returning
+/// what the closure did.
+///
+/// If the closure panics, the IDs are switched back before the panic
+/// continues, so nothing that catches it further up runs as the wrong
+/// user. If switching back fails, that is the error returned, as the
+/// process is left running as the wrong user.
+///
+/// # Examples
+///
+/// ```no_run
+/// use users::switch::switch_user_group_scoped;
+///
+/// let contents = switch_user_group_scoped(1000, 100, || {
+///     std::fs::read_to_string("/home/alice/.config/app.toml")
+/// }).expect("Failed to switch user");
+/// ```
+#[track_caller]
+pub fn switch_user_group_scoped<T, F: FnOnce() -> T>(uid: uid_t, gid: gid_t, f: F) -> io::Result<T> {
+    let guard = switch_user_group(uid, gid)?;
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    let restored = guard.restore();
+
+    match result {
+        Ok(value)  => restored.map(|()| value),
+        Err(cause) => panic::resume_unwind(cause),
+    }
+}
+
+/// Sets the supplementary groups of the process, with `setgroups`.
+///
+/// Errors on Windows and Fuchsia unless unchanged (see module docs), const
+/// Ok with the stub.
+#[track_caller]
+pub fn set_supplementary_groups(gids: &[gid_t]) -> io::Result<()> {
+    #[cfg(users_passthrough)]
+    return native::set_supplementary_groups(gids);
+
+    #[cfg(not(users_passthrough))]
+    {
+        stub_used("switch::set_supplementary_groups");
+        Current::set_supplementary_groups(gids)
+    }
+}
+
+/// Sets the supplementary groups of the process to those the given user is
+/// a member of, plus the given group, with `initgroups`.
+///
+/// Errors on Windows and Fuchsia unless unchanged (see module docs), const
+/// Ok with the stub.
+#[track_caller]
+pub fn init_groups<S: AsRef<OsStr> + ?Sized>(username: &S, gid: gid_t) -> io::Result<()> {
+    #[cfg(users_passthrough)]
+    return native::init_groups(username.as_ref(), gid);
+
+    #[cfg(not(users_passthrough))]
+    {
+        stub_used("switch::init_groups");
+        Current::init_groups(username.as_ref(), gid)
+    }
+}
+
+/// The supplementary group IDs of the process, unlike `group_access_list`
+/// including those that can’t be looked up.
+fn supplementary_groups() -> io::Result<Vec<gid_t>> {
+    #[cfg(users_passthrough)]
+    return native::supplementary_groups();
+
+    #[cfg(not(users_passthrough))]
+    Current::supplementary_groups()
+}
+
+/// Permanently drops the privileges of the process to those of the given
+/// user: sets the supplementary groups to the user’s, then the real,
+/// effective, and saved group IDs to their primary group, then the user
+/// IDs to theirs, and checks that none of it can be undone.
+///
+/// The order matters: once the user ID is no longer root, the process may
+/// not change its groups, and would keep root’s. Setting the saved IDs too
+/// means the process can’t switch back to them, as it could after
+/// `set_effective_uid`. To check, it looks at all the IDs again and, if the
+/// user isn’t root, tries to regain root, which must fail.
+///
+/// The error says which step failed. As each step changes what the process
+/// may do, a failure leaves it somewhere in between, and it is best to exit.
+///
+/// With the stub, nothing changes, so there is nothing to check either.
+///
+/// # Examples
+///
+/// ```no_run
+/// use users::get_user_by_name;
+/// use users::switch::drop_privileges;
+///
+/// let nobody = get_user_by_name("nobody").expect("No nobody user");
+/// if let Err(e) = drop_privileges(&nobody) {
+///     eprintln!("{}", e);
+///     std::process::exit(1);
+/// }
+/// ```
+#[track_caller]
+pub fn drop_privileges(user: &User) -> Result<(), DropPrivilegesError> {
+    let (uid, gid) = (user.uid(), user.primary_group_id());
+    let fail = |step| move |error| DropPrivilegesError { step, error };
+
+    init_groups(user.name(), gid).map_err(fail(DropStep::SetGroups))?;
+    set_all_gid(gid, gid, gid).map_err(fail(DropStep::SetGid))?;
+    set_all_uid(uid, uid, uid).map_err(fail(DropStep::SetUid))?;
+
+    #[cfg(not(users_passthrough))]
+    {
+        if Current::IS_STUB {
+            return Ok(());
+        }
+    }
+
+    verify_dropped(uid, gid).map_err(fail(DropStep::Verify))
+}
+
+/// Drops the privileges of the process to those of the given user, as
+/// `drop_privileges` does, but keeps the given capabilities, so that a
+/// daemon can, say, still bind to port 80 as `CAP_NET_BIND_SERVICE` allows.
+///
+/// It has the kernel keep the capabilities across the user switch with
+/// `PR_SET_KEEPCAPS`, then makes the given ones the only ones the process
+/// has, and raises them as ambient capabilities, so that programs it runs
+/// have them too.
+///
+/// Only on Linux and Android.
+///
+/// # Examples
+///
+/// ```no_run
+/// use users::get_user_by_name;
+/// use users::switch::{drop_privileges_keeping, Capability};
+///
+/// let www = get_user_by_name("www-data").expect("No www-data user");
+/// drop_privileges_keeping(&www, &[ Capability::NET_BIND_SERVICE ]).expect("Failed to drop privileges");
+/// ```
+#[cfg(all(users_passthrough, any(target_os = "linux", target_os = "android")))]
+pub fn drop_privileges_keeping(user: &User, caps: &[Capability]) -> Result<(), DropPrivilegesError> {
+    let fail = |step| move |error| DropPrivilegesError { step, error };
+    let masks = native::cap_masks(caps).map_err(fail(DropStep::SetCapabilities))?;
+
+    native::keep_caps(true).map_err(fail(DropStep::KeepCapabilities))?;
+    let dropped = drop_privileges(user);
+    let reset = native::keep_caps(false);
+    dropped?;
+    reset.map_err(fail(DropStep::KeepCapabilities))?;
+
+    native::set_caps(masks, caps).map_err(fail(DropStep::SetCapabilities))
+}
+
+/// A Linux capability, by its number in `<linux/capability.h>`.
+///
+/// The common ones are available as constants, named as in the header
+/// without the `CAP_` prefix.
+#[cfg(all(users_passthrough, any(target_os = "linux", target_os = "android")))]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Capability(pub u32);
+
+#[cfg(all(users_passthrough, any(target_os = "linux", target_os = "android")))]
+impl Capability {
+    pub const CHOWN: Self            = Capability(0);
+    pub const DAC_OVERRIDE: Self     = Capability(1);
+    pub const DAC_READ_SEARCH: Self  = Capability(2);
+    pub const FOWNER: Self           = Capability(3);
+    pub const KILL: Self             = Capability(5);
+    pub const SETGID: Self           = Capability(6);
+    pub const SETUID: Self           = Capability(7);
+    pub const NET_BIND_SERVICE: Self = Capability(10);
+    pub const NET_BROADCAST: Self    = Capability(11);
+    pub const NET_ADMIN: Self        = Capability(12);
+    pub const NET_RAW: Self          = Capability(13);
+    pub const IPC_LOCK: Self         = Capability(14);
+    pub const SYS_CHROOT: Self       = Capability(18);
+    pub const SYS_PTRACE: Self       = Capability(19);
+    pub const SYS_ADMIN: Self        = Capability(21);
+    pub const SYS_NICE: Self         = Capability(23);
+    pub const SYS_RESOURCE: Self     = Capability(24);
+    pub const SYS_TIME: Self         = Capability(25);
+}
+
+/// Checks that all the IDs are the given ones, and that root can’t be
+/// regained.
+fn verify_dropped(uid: uid_t, gid: gid_t) -> io::Result<()> {
+    #[cfg(users_passthrough)]
+    let (uids, gids) = (::passthrough::native_resuid(), ::passthrough::native_resgid());
+
+    #[cfg(not(users_passthrough))]
+    let (uids, gids) = (Current::get_resuid(), Current::get_resgid());
+
+    if uids != (uid, uid, uid) || gids != (gid, gid, gid) {
+        let message = format!("IDs are {:?} and {:?}, not {} and {}", uids, gids, uid, gid);
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, message));
+    }
+
+    if uid != 0 && set_effective_uid(0).is_ok() {
+        let _ = set_effective_uid(uid);
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "root privileges could be regained"));
+    }
+
+    Ok(())
+}
+
+/// The step of `drop_privileges` that failed.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum DropStep {
+
+    /// Setting the supplementary groups, with `initgroups`.
+    SetGroups,
+
+    /// Setting the group IDs, with `setresgid`.
+    SetGid,
+
+    /// Setting the user IDs, with `setresuid`.
+    SetUid,
+
+    /// Checking that the privileges are gone for good.
+    Verify,
+
+    /// Having the kernel keep capabilities across the user switch, or stop
+    /// doing so, with `PR_SET_KEEPCAPS`. Only on Linux.
+    KeepCapabilities,
+
+    /// Setting the capabilities to keep. Only on Linux.
+    SetCapabilities,
+}
+
+impl fmt::Display for DropStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DropStep::SetGroups        => write!(f, "set the supplementary groups"),
+            DropStep::SetGid           => write!(f, "set the group IDs"),
+            DropStep::SetUid           => write!(f, "set the user IDs"),
+            DropStep::Verify           => write!(f, "verify that privileges were dropped"),
+            DropStep::KeepCapabilities => write!(f, "keep capabilities across the user switch"),
+            DropStep::SetCapabilities  => write!(f, "set the capabilities to keep"),
+        }
+    }
+}
+
+/// The error returned when `drop_privileges` fails.
+#[derive(Debug)]
+pub struct DropPrivilegesError {
+    step: DropStep,
+    error: io::Error,
+}
+
+impl DropPrivilegesError {
+
+    /// Returns the step that failed.
+    pub fn step(&self) -> DropStep {
+        self.step
+    }
+
+    /// Returns the error the step failed with.
+    pub fn io_error(&self) -> &io::Error {
+        &self.error
+    }
+}
+
+impl fmt::Display for DropPrivilegesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to {}: {}", self.step, self.error)
+    }
+}
+
+impl Error for DropPrivilegesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<DropPrivilegesError> for io::Error {
+    fn from(error: DropPrivilegesError) -> Self {
+        io::Error::new(error.error.kind(), error)
+    }
+}
+
+/// Logs the given user on and makes the current thread impersonate them,
+/// until the returned guard is dropped and `RevertToSelf` is called.
+///
+/// The username can be given as `DOMAIN\user`, as `.\user` for a local
+/// account, or as a user principal name such as `user@example.com`.
+///
+/// Unlike `setuid` on Unix, impersonation only affects the calling thread.
+///
+/// # Windows functions used
+///
+/// - [`LogonUserW`](https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-logonuserw)
+/// - [`ImpersonateLoggedOnUser`](https://docs.microsoft.com/en-us/windows/win32/api/securitybaseapi/nf-securitybaseapi-impersonateloggedonuser)
+/// - [`RevertToSelf`](https://docs.microsoft.com/en-us/windows/win32/api/securitybaseapi/nf-securitybaseapi-reverttoself)
+///
+/// # Examples
+///
+/// ```no_run
+/// use users::switch::switch_user_with_password;
+///
+/// {
+///     let _guard = switch_user_with_password(r".\backup", "hunter2").expect("Failed to log on");
+///     // current thread now acts as the local backup user
+/// }
+/// // back to the original identity
+/// ```
+#[cfg(all(windows, feature = "windows-native"))]
+pub fn switch_user_with_password<U, P>(username: &U, password: &P) -> io::Result<SwitchUserGuard>
+where U: AsRef<OsStr> + ?Sized,
+      P: AsRef<OsStr> + ?Sized,
+{
+    let impersonation = windows::impersonate(username.as_ref(), password.as_ref())?;
+    Ok(SwitchUserGuard { token: None, impersonation: Some(impersonation), _thread: PhantomData })
+}
+
+
+/// The functions the real crate lacks, called directly.
+#[cfg(users_passthrough)]
+mod native {
+    use std::ffi::{CString, OsStr};
+    use std::io;
+    use std::os::raw::c_int;
+    use std::os::unix::ffi::OsStrExt;
+    use std::ptr;
+
+    use libc;
+    use {uid_t, gid_t};
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    use std::os::raw::c_ulong;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    use super::Capability;
+
+    fn check(status: c_int) -> io::Result<()> {
+        if status == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+    }
+
+    /// Turns `PR_SET_KEEPCAPS` on or off.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn keep_caps(keep: bool) -> io::Result<()> {
+        check(unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, c_ulong::from(keep), 0, 0, 0) })
+    }
+
+    /// Returns the two 32-bit halves of the capability set with the given
+    /// capabilities.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn cap_masks(caps: &[Capability]) -> io::Result<[u32; 2]> {
+        let mut masks = [0; 2];
+        for &Capability(cap) in caps {
+            if cap >= 64 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("no such capability {}", cap)));
+            }
+
+            masks[cap as usize / 32] |= 1 << (cap % 32);
+        }
+
+        Ok(masks)
+    }
+
+    /// Makes the given capabilities the only effective, permitted, and
+    /// inheritable ones, and raises them as ambient ones.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn set_caps(masks: [u32; 2], caps: &[Capability]) -> io::Result<()> {
+        #[repr(C)]
+        struct Header { version: u32, pid: c_int }
+
+        #[repr(C)]
+        struct Data { effective: u32, permitted: u32, inheritable: u32 }
+
+        // _LINUX_CAPABILITY_VERSION_3, which takes two sets of data
+        let header = Header { version: 0x2008_0522, pid: 0 };
+        let data = masks.map(|mask| Data { effective: mask, permitted: mask, inheritable: mask });
+        if unsafe { libc::syscall(libc::SYS_capset, &header, data.as_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        for &Capability(cap) in caps {
+            check(unsafe { libc::prctl(libc::PR_CAP_AMBIENT, libc::PR_CAP_AMBIENT_RAISE as c_ulong, c_ulong::from(cap), 0, 0) })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn supplementary_groups() -> io::Result<Vec<gid_t>> {
+        let count = unsafe { libc::getgroups(0, ptr::null_mut()) };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buf = vec![0 as gid_t; count as usize];
+        let count = unsafe { libc::getgroups(count, buf.as_mut_ptr()) };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        buf.truncate(count as usize);
+        Ok(buf)
+    }
+
+    pub fn set_supplementary_groups(gids: &[gid_t]) -> io::Result<()> {
+        check(unsafe { libc::setgroups(gids.len() as _, gids.as_ptr()) })
+    }
+
+    pub fn init_groups(username: &OsStr, gid: gid_t) -> io::Result<()> {
+        let username = CString::new(username.as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        check(unsafe { libc::initgroups(username.as_ptr(), gid as _) })
+    }
+
+    /// Checks the filesystem ID read back by passing an invalid one, which
+    /// changes nothing.
+    #[cfg(all(feature = "fsuid", any(target_os = "linux", target_os = "android")))]
+    pub fn check_fs_id(requested: u32, current: u32) -> io::Result<()> {
+        if requested == current {
+            Ok(())
+        }
+        else {
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("filesystem ID is still {}, not {}", current, requested)))
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "dragonfly", target_os = "openbsd"))]
+    pub fn set_all_uid(ruid: uid_t, euid: uid_t, suid: uid_t) -> io::Result<()> {
+        check(unsafe { libc::setresuid(ruid, euid, suid) })
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "dragonfly", target_os = "openbsd"))]
+    pub fn set_all_gid(rgid: gid_t, egid: gid_t, sgid: gid_t) -> io::Result<()> {
+        check(unsafe { libc::setresgid(rgid, egid, sgid) })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "dragonfly", target_os = "openbsd")))]
+    pub fn set_all_uid(ruid: uid_t, euid: uid_t, suid: uid_t) -> io::Result<()> {
+        if suid != euid {
+            return Err(::platform::saved_id_unsupported("set_all_uid"));
+        }
+
+        check(unsafe { libc::setreuid(ruid, euid) })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "dragonfly", target_os = "openbsd")))]
+    pub fn set_all_gid(rgid: gid_t, egid: gid_t, sgid: gid_t) -> io::Result<()> {
+        if sgid != egid {
+            return Err(::platform::saved_id_unsupported("set_all_gid"));
+        }
+
+        check(unsafe { libc::setregid(rgid, egid) })
+    }
+}
+
+
+
+#[cfg(all(test, not(feature = "deny-stub-at-runtime"), not(feature = "strict-stub")))]
+mod test {
+    use super::*;
+    use {get_resuid, get_resgid};
+
+    #[test]
+    fn all_unchanged() {
+        let (ruid, euid, suid) = get_resuid();
+        let (rgid, egid, sgid) = get_resgid();
+        set_all_gid(rgid, egid, sgid).unwrap();
+        set_all_uid(ruid, euid, suid).unwrap();
+        assert_eq!(get_resuid(), (ruid, euid, suid));
+    }
+
+    #[test]
+    fn groups_unchanged() {
+        let ((_, euid, _), (_, egid, _)) = (get_resuid(), get_resgid());
+        let groups = supplementary_groups().unwrap();
+
+        {
+            let _guard = switch_user_group_with_groups(euid, egid, &groups).unwrap();
+            assert_eq!(supplementary_groups().unwrap(), groups);
+        }
+
+        assert_eq!(supplementary_groups().unwrap(), groups);
+    }
+
+    #[test]
+    #[cfg(all(feature = "fsuid", users_passthrough, any(target_os = "linux", target_os = "android")))]
+    fn fs_unchanged() {
+        let (_, euid, _) = get_resuid();
+        assert_eq!(set_fs_uid(euid).unwrap(), euid);
+        if euid != 0 {
+            assert_eq!(set_fs_uid(euid + 1).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        }
+    }
+
+    #[test]
+    fn drop_error() {
+        let error = DropPrivilegesError { step: DropStep::SetUid, error: io::Error::from(io::ErrorKind::PermissionDenied) };
+        assert_eq!(error.step(), DropStep::SetUid);
+        assert!(error.to_string().starts_with("failed to set the user IDs: "));
+        assert_eq!(io::Error::from(error).kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    #[cfg(not(users_passthrough))]
+    fn drop_with_stub() {
+        if Current::IS_STUB {
+            assert!(drop_privileges(&User::new(1000, "alice", 100)).is_ok());
+        }
+    }
+
+    #[test]
+    fn scoped() {
+        let ((_, euid, _), (_, egid, _)) = (get_resuid(), get_resgid());
+        assert_eq!(switch_user_group_scoped(euid, egid, || 42).unwrap(), 42);
+
+        let result = panic::catch_unwind(|| switch_user_group_scoped(euid, egid, || panic!("oops")));
+        assert!(result.is_err());
+        assert_eq!(get_resuid().1, euid);
+    }
+
+    #[test]
+    fn restore() {
+        let ((_, euid, _), (_, egid, _)) = (get_resuid(), get_resgid());
+        let guard = switch_user_group(euid, egid).unwrap();
+        guard.restore().unwrap();
+        assert!(take_restore_error().is_none());
+    }
+
+    #[test]
+    fn thread_policy() {
+        assert!(thread_count() >= 1);
+        assert!(apply_policy(ThreadPolicy::Refuse, 1).is_ok());
+        assert!(apply_policy(ThreadPolicy::Refuse, 2).is_err());
+        assert!(apply_policy(ThreadPolicy::Allow, 2).is_ok());
+        assert!(apply_policy(ThreadPolicy::Warn(|threads| assert_eq!(threads, 3)), 3).is_ok());
+    }
+
+    #[test]
+    fn nested() {
+        let ((_, euid, _), (_, egid, _)) = (get_resuid(), get_resgid());
+        let outer = switch_user_group(euid, egid).unwrap();
+        let inner = switch_user_group_with_groups(euid, egid, &supplementary_groups().unwrap()).unwrap();
+        assert_eq!(SAVED.with(|stack| stack.borrow().len()), 2);
+
+        drop(outer);
+        assert!(SAVED.with(|stack| stack.borrow()[0].groups.is_some()));
+
+        inner.restore().unwrap();
+        assert_eq!(SAVED.with(|stack| stack.borrow().len()), 0);
+    }
+
+    #[test]
+    #[cfg(all(users_passthrough, any(target_os = "linux", target_os = "android")))]
+    fn capabilities() {
+        assert_eq!(native::cap_masks(&[ Capability::NET_BIND_SERVICE, Capability(33) ]).unwrap(), [ 1 << 10, 1 << 1 ]);
+        assert!(native::cap_masks(&[ Capability(64) ]).is_err());
+    }
 }
@@ -0,0 +1,315 @@
+//! Switching users through a trait, so that it can be mocked.
+//!
+//! Daemons that start as root usually drop their privileges early on, and
+//! the order matters: groups before the user, or the group change fails.
+//! Testing that logic with the functions in [`switch`](../switch/index.html)
+//! requires running the tests as root. Code written against the `Switch`
+//! trait instead can be given a `MockSwitch` in tests, which records the
+//! transitions it was asked for without changing anything:
+//!
+//! ```
+//! use users::switcher::{Switch, MockSwitch, Transition};
+//!
+//! fn drop_privileges<S: Switch>(switch: &S) -> std::io::Result<()> {
+//!     switch.set_current_gid(100)?;
+//!     switch.set_current_uid(1000)
+//! }
+//!
+//! let switch = MockSwitch::new(0, 0);
+//! drop_privileges(&switch).unwrap();
+//! assert_eq!(switch.transitions(), vec![ Transition::CurrentGid(100), Transition::CurrentUid(1000) ]);
+//! assert_eq!(switch.effective_uid(), 1000);
+//! ```
+//!
+//! In production, `System` does the same as the functions in `switch`,
+//! and `StubSwitch` does what the stub does on platforms without a backend.
+
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use platform::Platform;
+use stub::Stub;
+use switch;
+use {uid_t, gid_t};
+
+
+/// The operations of the [`switch`](../switch/index.html) module.
+pub trait Switch {
+
+    /// What `switch_user_group` returns, which switches back when dropped.
+    type Guard;
+
+    /// Sets the current user ID, like `switch::set_current_uid`.
+    fn set_current_uid(&self, uid: uid_t) -> io::Result<()>;
+
+    /// Sets the current group ID, like `switch::set_current_gid`.
+    fn set_current_gid(&self, gid: gid_t) -> io::Result<()>;
+
+    /// Sets the effective user ID, like `switch::set_effective_uid`.
+    fn set_effective_uid(&self, uid: uid_t) -> io::Result<()>;
+
+    /// Sets the effective group ID, like `switch::set_effective_gid`.
+    fn set_effective_gid(&self, gid: gid_t) -> io::Result<()>;
+
+    /// Sets the real and effective user IDs, like `switch::set_both_uid`.
+    fn set_both_uid(&self, ruid: uid_t, euid: uid_t) -> io::Result<()>;
+
+    /// Sets the real and effective group IDs, like `switch::set_both_gid`.
+    fn set_both_gid(&self, rgid: gid_t, egid: gid_t) -> io::Result<()>;
+
+    /// Sets the effective user and group IDs until the returned guard is
+    /// dropped, like `switch::switch_user_group`.
+    fn switch_user_group(&self, uid: uid_t, gid: gid_t) -> io::Result<Self::Guard>;
+}
+
+
+/// Switches by calling the functions in `switch`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct System;
+
+impl Switch for System {
+    type Guard = switch::SwitchUserGuard;
+
+    fn set_current_uid(&self, uid: uid_t) -> io::Result<()> {
+        switch::set_current_uid(uid)
+    }
+
+    fn set_current_gid(&self, gid: gid_t) -> io::Result<()> {
+        switch::set_current_gid(gid)
+    }
+
+    fn set_effective_uid(&self, uid: uid_t) -> io::Result<()> {
+        switch::set_effective_uid(uid)
+    }
+
+    fn set_effective_gid(&self, gid: gid_t) -> io::Result<()> {
+        switch::set_effective_gid(gid)
+    }
+
+    fn set_both_uid(&self, ruid: uid_t, euid: uid_t) -> io::Result<()> {
+        switch::set_both_uid(ruid, euid)
+    }
+
+    fn set_both_gid(&self, rgid: gid_t, egid: gid_t) -> io::Result<()> {
+        switch::set_both_gid(rgid, egid)
+    }
+
+    fn switch_user_group(&self, uid: uid_t, gid: gid_t) -> io::Result<Self::Guard> {
+        switch::switch_user_group(uid, gid)
+    }
+}
+
+
+/// Switches the way the stub does on platforms without a backend: by
+/// succeeding without doing anything, or failing with the `strict-stub`
+/// feature.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StubSwitch;
+
+impl Switch for StubSwitch {
+    type Guard = ();
+
+    fn set_current_uid(&self, uid: uid_t) -> io::Result<()> {
+        Stub::set_current_uid(uid)
+    }
+
+    fn set_current_gid(&self, gid: gid_t) -> io::Result<()> {
+        Stub::set_current_gid(gid)
+    }
+
+    fn set_effective_uid(&self, uid: uid_t) -> io::Result<()> {
+        Stub::set_effective_uid(uid)
+    }
+
+    fn set_effective_gid(&self, gid: gid_t) -> io::Result<()> {
+        Stub::set_effective_gid(gid)
+    }
+
+    fn set_both_uid(&self, ruid: uid_t, euid: uid_t) -> io::Result<()> {
+        Stub::set_both_uid(ruid, euid)
+    }
+
+    fn set_both_gid(&self, rgid: gid_t, egid: gid_t) -> io::Result<()> {
+        Stub::set_both_gid(rgid, egid)
+    }
+
+    fn switch_user_group(&self, uid: uid_t, gid: gid_t) -> io::Result<Self::Guard> {
+        Stub::switch_user_group(uid, gid)
+    }
+}
+
+
+/// A transition a `MockSwitch` was asked to make.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Transition {
+
+    /// `set_current_uid`.
+    CurrentUid(uid_t),
+
+    /// `set_current_gid`.
+    CurrentGid(gid_t),
+
+    /// `set_effective_uid`.
+    EffectiveUid(uid_t),
+
+    /// `set_effective_gid`.
+    EffectiveGid(gid_t),
+
+    /// `set_both_uid`, with the real and effective user IDs.
+    BothUid(uid_t, uid_t),
+
+    /// `set_both_gid`, with the real and effective group IDs.
+    BothGid(gid_t, gid_t),
+
+    /// `switch_user_group`, with the user and group IDs.
+    SwitchUserGroup(uid_t, gid_t),
+
+    /// The guard from `switch_user_group` being dropped, with the user and
+    /// group IDs switched back to.
+    Restore(uid_t, gid_t),
+}
+
+#[derive(Debug)]
+struct MockState {
+    uid: uid_t,
+    gid: gid_t,
+    euid: uid_t,
+    egid: gid_t,
+    transitions: Vec<Transition>,
+}
+
+/// A switch that records the transitions it is asked for, and keeps track
+/// of the IDs they would result in, without changing anything.
+///
+/// Clones share their transitions and IDs.
+#[derive(Debug, Clone)]
+pub struct MockSwitch {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockSwitch {
+
+    /// Create a new mock switch for a process running as the given user
+    /// and group.
+    pub fn new(uid: uid_t, gid: gid_t) -> Self {
+        let state = MockState { uid, gid, euid: uid, egid: gid, transitions: Vec::new() };
+        Self { state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Returns the transitions asked for so far, oldest first.
+    pub fn transitions(&self) -> Vec<Transition> {
+        self.with_state(|s| s.transitions.clone())
+    }
+
+    /// Returns the current user ID, after the transitions so far.
+    pub fn current_uid(&self) -> uid_t {
+        self.with_state(|s| s.uid)
+    }
+
+    /// Returns the current group ID, after the transitions so far.
+    pub fn current_gid(&self) -> gid_t {
+        self.with_state(|s| s.gid)
+    }
+
+    /// Returns the effective user ID, after the transitions so far.
+    pub fn effective_uid(&self) -> uid_t {
+        self.with_state(|s| s.euid)
+    }
+
+    /// Returns the effective group ID, after the transitions so far.
+    pub fn effective_gid(&self) -> gid_t {
+        self.with_state(|s| s.egid)
+    }
+
+    fn with_state<T, F: FnOnce(&mut MockState) -> T>(&self, f: F) -> T {
+        f(&mut self.state.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Records a transition and applies it.
+    fn transition<F: FnOnce(&mut MockState)>(&self, transition: Transition, apply: F) -> io::Result<()> {
+        self.with_state(|s| {
+            s.transitions.push(transition);
+            apply(s);
+        });
+
+        Ok(())
+    }
+}
+
+impl Switch for MockSwitch {
+    type Guard = MockSwitchGuard;
+
+    fn set_current_uid(&self, uid: uid_t) -> io::Result<()> {
+        self.transition(Transition::CurrentUid(uid), |s| { s.uid = uid; s.euid = uid; })
+    }
+
+    fn set_current_gid(&self, gid: gid_t) -> io::Result<()> {
+        self.transition(Transition::CurrentGid(gid), |s| { s.gid = gid; s.egid = gid; })
+    }
+
+    fn set_effective_uid(&self, uid: uid_t) -> io::Result<()> {
+        self.transition(Transition::EffectiveUid(uid), |s| s.euid = uid)
+    }
+
+    fn set_effective_gid(&self, gid: gid_t) -> io::Result<()> {
+        self.transition(Transition::EffectiveGid(gid), |s| s.egid = gid)
+    }
+
+    fn set_both_uid(&self, ruid: uid_t, euid: uid_t) -> io::Result<()> {
+        self.transition(Transition::BothUid(ruid, euid), |s| { s.uid = ruid; s.euid = euid; })
+    }
+
+    fn set_both_gid(&self, rgid: gid_t, egid: gid_t) -> io::Result<()> {
+        self.transition(Transition::BothGid(rgid, egid), |s| { s.gid = rgid; s.egid = egid; })
+    }
+
+    fn switch_user_group(&self, uid: uid_t, gid: gid_t) -> io::Result<Self::Guard> {
+        let (euid, egid) = (self.effective_uid(), self.effective_gid());
+        self.transition(Transition::SwitchUserGroup(uid, gid), |s| { s.euid = uid; s.egid = gid; })?;
+
+        Ok(MockSwitchGuard { switch: self.clone(), uid: euid, gid: egid })
+    }
+}
+
+/// Guard returned from a `MockSwitch`’s `switch_user_group`, which records
+/// switching back when it is dropped.
+#[derive(Debug)]
+pub struct MockSwitchGuard {
+    switch: MockSwitch,
+    uid: uid_t,
+    gid: gid_t,
+}
+
+impl Drop for MockSwitchGuard {
+    fn drop(&mut self) {
+        let (uid, gid) = (self.uid, self.gid);
+        let _ = self.switch.transition(Transition::Restore(uid, gid), |s| { s.euid = uid; s.egid = gid; });
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn switch_and_restore() {
+        let switch = MockSwitch::new(0, 0);
+
+        {
+            let _guard = switch.switch_user_group(1000, 100).unwrap();
+            assert_eq!(switch.effective_uid(), 1000);
+            assert_eq!(switch.current_uid(), 0);
+        }
+
+        assert_eq!(switch.effective_uid(), 0);
+        assert_eq!(switch.transitions(), vec![ Transition::SwitchUserGroup(1000, 100), Transition::Restore(0, 0) ]);
+    }
+
+    #[test]
+    fn both() {
+        let switch = MockSwitch::new(0, 0);
+        switch.set_both_gid(100, 10).unwrap();
+        assert_eq!((switch.current_gid(), switch.effective_gid()), (100, 10));
+    }
+}
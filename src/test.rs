@@ -0,0 +1,230 @@
+//! Helpers for testing code that calls the functions in this crate.
+//!
+//! A backend set with [`set_backend`](../fn.set_backend.html) applies to the
+//! whole process, so tests that each want their own users can’t use it
+//! while running in parallel. `override_backend` instead only applies to the
+//! thread it was called on, which is the thread the test runs on, and only
+//! until the guard it returns is dropped.
+//!
+//! # Examples
+//!
+//! ```
+//! use users::{User, get_current_username};
+//! use users::mock::MockUsers;
+//! use users::test::override_backend;
+//!
+//! let mut users = MockUsers::with_current_uid(1000);
+//! users.add_user(User::new(1000, "alice", 100));
+//!
+//! let _guard = override_backend(users);
+//! assert_eq!(get_current_username(), Some("alice".into()));
+//! ```
+//!
+//! For benchmarks, and tests that need many users, `generate_users` makes up
+//! a whole users database, the same one every time for the same seed.
+
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
+use backend::{Lookup, swap_override};
+use super::{Users, Groups, User, Group, uid_t, gid_t};
+
+
+/// Makes the user and group lookups, and the current and effective IDs and
+/// names, come from the given backend on the current thread, until the
+/// returned guard is dropped.
+///
+/// Overrides can be nested, with the previous one coming back when the
+/// inner guard is dropped.
+pub fn override_backend<B: Users + Groups + 'static>(backend: B) -> OverrideGuard {
+    OverrideGuard {
+        previous: swap_override(Some(Box::new(backend))),
+        thread: PhantomData,
+    }
+}
+
+
+/// The users most of this crate’s own tests look up: `alice`, with the ID
+/// 1000, who is the current user, and their primary group `users`.
+#[cfg(test)]
+#[cfg(feature = "mock")]
+pub(crate) fn mock_alice() -> ::mock::MockUsers {
+    let mut users = ::mock::MockUsers::with_current_uid(1000);
+    users.add_user(User::new(1000, "alice", 100));
+    users.add_group(Group::new(100, "users"));
+    users
+}
+
+
+/// Guard returned from an `override_backend` call.
+#[must_use = "the backend is only overridden until the guard is dropped"]
+pub struct OverrideGuard {
+    previous: Option<Box<dyn Lookup>>,
+
+    // The override belongs to the thread it was made on, so it must be
+    // undone there too.
+    thread: PhantomData<*const ()>,
+}
+
+impl Drop for OverrideGuard {
+    fn drop(&mut self) {
+        swap_override(self.previous.take());
+    }
+}
+
+
+/// The system accounts most Unix systems have, each with its own group.
+static SYSTEM_ACCOUNTS: &[(uid_t, &str)] = &[
+    (0, "root"), (1, "daemon"), (2, "bin"), (3, "sys"), (65534, "nobody"),
+];
+
+/// Groups that regular users share.
+static SHARED_GROUPS: &[(gid_t, &str)] = &[
+    (10, "wheel"), (50, "staff"), (100, "users"),
+];
+
+/// Names for regular users, which get a number added once they run out.
+static NAMES: &[&str] = &[
+    "alice", "bob", "carol", "dave", "erin", "frank", "grace", "heidi",
+    "ivan", "judy", "mallory", "niaj", "olivia", "peggy", "rupert", "sybil",
+    "trent", "victor", "walter", "yolanda",
+];
+
+/// The SplitMix64 generator, which is plenty for making up users.
+struct SplitMix(u64);
+
+impl SplitMix {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Makes up `count` users, along with their groups, in the same way every
+/// time for the same seed. Both are sorted by ID.
+///
+/// The first few users are system accounts, such as `root` and `nobody`.
+/// The rest are regular users with IDs from 1000, with gaps between them,
+/// skipping those of the system accounts, each of whom has either their own primary group or the shared `users`
+/// group. The `wheel` and `staff` groups are there too, as are all the
+/// users’ primary groups.
+pub fn generate_users(seed: u64, count: usize) -> (Vec<User>, Vec<Group>) {
+    let mut rng = SplitMix(seed);
+    let mut users = Vec::with_capacity(count);
+    let mut groups = SHARED_GROUPS.iter().map(|&(gid, name)| Group::new(gid, name)).collect::<Vec<_>>();
+    let mut taken = HashSet::new();
+
+    for &(id, name) in SYSTEM_ACCOUNTS.iter().take(count) {
+        users.push(User::new(id, name, id));
+        groups.push(Group::new(id, name));
+        taken.insert(name.to_owned());
+    }
+
+    let mut uid = 999;
+    while users.len() < count {
+        uid += 1 + rng.below(10) as uid_t;
+        while SYSTEM_ACCOUNTS.iter().any(|&(id, _)| id == uid) {
+            uid += 1;
+        }
+
+        let mut name = NAMES[rng.below(NAMES.len())].to_owned();
+        if taken.contains(&name) {
+            name = format!("{}{}", name, uid);
+        }
+
+        if rng.below(4) == 0 {
+            users.push(User::new(uid, &name, 100));
+        }
+        else {
+            users.push(User::new(uid, &name, uid));
+            groups.push(Group::new(uid, &name));
+        }
+
+        taken.insert(name);
+    }
+
+    users.sort_by_key(|u| u.uid());
+    groups.sort_by_key(|g| g.gid());
+    (users, groups)
+}
+
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "mock")]
+    use std::thread;
+
+    #[cfg(feature = "mock")]
+    use mock::MockUsers;
+
+    #[cfg(feature = "mock")]
+    use {Backend, backend_info, get_current_uid};
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn nested() {
+        let _outer = override_backend(MockUsers::with_current_uid(1000));
+        assert_eq!(get_current_uid(), 1000);
+
+        {
+            let _inner = override_backend(MockUsers::with_current_uid(1001));
+            assert_eq!(get_current_uid(), 1001);
+        }
+
+        assert_eq!(get_current_uid(), 1000);
+    }
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn other_threads() {
+        let _guard = override_backend(MockUsers::with_current_uid(1000));
+        assert_eq!(backend_info().backend(), Backend::Custom);
+
+        let other = thread::spawn(|| backend_info().backend()).join().unwrap();
+        assert_ne!(other, Backend::Custom);
+    }
+
+    #[test]
+    fn deterministic() {
+        let names = |seed| generate_users(seed, 50).0.iter().map(|u| u.name().to_owned()).collect::<Vec<_>>();
+        assert_eq!(names(1), names(1));
+        assert_ne!(names(1), names(2));
+    }
+
+    #[test]
+    fn unique() {
+        let (users, groups) = generate_users(7, 200);
+        assert_eq!(users.len(), 200);
+        assert_eq!(users[0].name(), "root");
+
+        let uids = users.iter().map(|u| u.uid()).collect::<HashSet<_>>();
+        let names = users.iter().map(|u| u.name().to_owned()).collect::<HashSet<_>>();
+        assert_eq!(uids.len(), 200);
+        assert_eq!(names.len(), 200);
+
+        let gids = groups.iter().map(|g| g.gid()).collect::<HashSet<_>>();
+        assert!(users.iter().all(|u| gids.contains(&u.primary_group_id())));
+
+        // Enough users for the IDs to go past nobody’s.
+        let (users, groups) = generate_users(1, 20_000);
+        assert!(users.last().unwrap().uid() > 65534);
+        assert_eq!(users.iter().map(|u| u.uid()).collect::<HashSet<_>>().len(), 20_000);
+        assert_eq!(groups.iter().map(|g| g.gid()).collect::<HashSet<_>>().len(), groups.len());
+    }
+
+    #[test]
+    fn few() {
+        assert_eq!(generate_users(0, 2).0.len(), 2);
+        assert!(generate_users(0, 0).0.is_empty());
+    }
+}
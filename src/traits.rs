@@ -1,3 +1,7 @@
+// These have to match the real crate’s traits method for method, or generic
+// code written on one platform won’t compile on another. The tests in
+// `parity.rs` check that they do.
+
 use std::ffi::OsStr;
 use std::sync::Arc;
 
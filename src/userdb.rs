@@ -0,0 +1,379 @@
+//! Looking users and groups up through systemd’s user database.
+//!
+//! On systems running systemd, users can come from places NSS doesn’t know
+//! about without the `systemd` module: home directories managed by
+//! `systemd-homed`, or the dynamic users services run as. All of them can
+//! be asked for through the Varlink sockets in `/run/systemd/userdb`, which
+//! is what a `UserdbBackend` does, so they resolve even for programs that
+//! bypass the C library:
+//!
+//! ```no_run
+//! use users::Users;
+//! use users::os::unix::UserExt;
+//! use users::userdb::UserdbBackend;
+//!
+//! let userdb = UserdbBackend::new();
+//! if let Some(user) = userdb.get_user_by_name("alice") {
+//!     println!("alice’s home is {:?}", user.home_dir());
+//! }
+//! ```
+//!
+//! If `systemd-userdbd` is running, only its multiplexer socket is asked,
+//! as it asks all the others. Otherwise, each socket in the directory is
+//! asked in turn, in the order of their names. A socket that can’t be
+//! connected to, or that answers with an error, counts as having no
+//! record.
+//!
+//! User records are translated the way systemd’s NSS module does it: the
+//! real name becomes the GECOS field, and the password is only there if
+//! the record has its privileged section. Group members are the ones in
+//! the group record, along with those the database lists memberships for.
+//!
+//! This is only available with the `userdb` feature, on Unix.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{self, json, Value};
+
+use files::process_ids;
+use {User, Group, Users, Groups, UserBuilder, GroupBuilder, uid_t, gid_t};
+
+
+/// The directory the user database’s sockets are in.
+pub const USERDB_DIR: &str = "/run/systemd/userdb";
+
+/// The socket of `systemd-userdbd`, which asks every other one.
+const MULTIPLEXER: &str = "io.systemd.Multiplexer";
+
+/// How long to wait for a socket to answer before giving up on it.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+
+/// Users and groups from systemd’s user database.
+#[derive(Clone, Debug)]
+pub struct UserdbBackend {
+    dir: PathBuf,
+    uid: uid_t,
+    gid: gid_t,
+    euid: uid_t,
+    egid: gid_t,
+}
+
+impl UserdbBackend {
+
+    /// Creates a backend asking the sockets in `/run/systemd/userdb`.
+    pub fn new() -> Self {
+        Self::in_dir(USERDB_DIR)
+    }
+
+    /// Creates a backend asking the sockets in the given directory.
+    pub fn in_dir<P: AsRef<Path>>(dir: P) -> Self {
+        let (uid, gid, euid, egid) = process_ids();
+        Self { dir: dir.as_ref().to_owned(), uid, gid, euid, egid }
+    }
+
+    /// Returns the names of the sockets to ask, which are also the names
+    /// of the services behind them.
+    fn services(&self) -> Vec<String> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_)      => return Vec::new(),
+        };
+
+        let mut services: Vec<String> = entries.filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .collect();
+
+        if services.iter().any(|s| s == MULTIPLEXER) {
+            return vec![ MULTIPLEXER.to_owned() ];
+        }
+
+        services.sort();
+        services
+    }
+
+    /// Calls a method on each service in turn, returning the first record
+    /// one has.
+    fn find_record(&self, method: &str, mut parameters: Value) -> Option<Value> {
+        self.services().into_iter().find_map(|service| {
+            parameters["service"] = Value::from(&*service);
+            let reply = call(&self.dir.join(&service), method, &parameters, false).ok()?.pop()?;
+            reply.get("record").cloned()
+        })
+    }
+
+    /// Returns the names of the users every service lists as members of
+    /// the given group.
+    fn memberships(&self, group_name: &str) -> Vec<String> {
+        let mut members = Vec::new();
+
+        for service in self.services() {
+            let parameters = json!({ "groupName": group_name, "service": service });
+            let replies = call(&self.dir.join(&service), "io.systemd.UserDatabase.GetMemberships", &parameters, true);
+
+            for reply in replies.unwrap_or_default() {
+                if let Some(name) = reply.get("userName").and_then(Value::as_str) {
+                    if ! members.iter().any(|m| m == name) {
+                        members.push(name.to_owned());
+                    }
+                }
+            }
+        }
+
+        members
+    }
+
+    fn find_user(&self, parameters: Value) -> Option<Arc<User>> {
+        let record = self.find_record("io.systemd.UserDatabase.GetUserRecord", parameters)?;
+        user_from_record(&record).map(Arc::new)
+    }
+
+    fn find_group(&self, parameters: Value) -> Option<Arc<Group>> {
+        let record = self.find_record("io.systemd.UserDatabase.GetGroupRecord", parameters)?;
+        let name = record.get("groupName")?.as_str()?;
+        let memberships = self.memberships(name);
+        group_from_record(&record, &memberships).map(Arc::new)
+    }
+}
+
+impl Default for UserdbBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Sends a method call to the socket at the given path, returning the
+/// parameters of each reply. With `more`, the service may answer more
+/// than once, until it says it is done.
+fn call(socket: &Path, method: &str, parameters: &Value, more: bool) -> io::Result<Vec<Value>> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut message = json!({ "method": method, "parameters": parameters });
+    if more {
+        message["more"] = Value::Bool(true);
+    }
+
+    let mut bytes = serde_json::to_vec(&message)?;
+    bytes.push(0);
+    stream.write_all(&bytes)?;
+
+    let mut reader = BufReader::new(stream);
+    let mut replies = Vec::new();
+
+    loop {
+        let mut bytes = Vec::new();
+        reader.read_until(0, &mut bytes)?;
+        if bytes.pop() != Some(0) {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed mid-reply"));
+        }
+
+        let mut reply: Value = serde_json::from_slice(&bytes)?;
+        if let Some(error) = reply.get("error").and_then(Value::as_str) {
+            // Having no record, or no more of them, is an answer like any
+            // other.
+            if error == "io.systemd.UserDatabase.NoRecordFound" {
+                return Ok(replies);
+            }
+
+            return Err(io::Error::new(io::ErrorKind::Other, error.to_owned()));
+        }
+
+        replies.push(reply["parameters"].take());
+
+        if ! reply.get("continues").and_then(Value::as_bool).unwrap_or(false) {
+            return Ok(replies);
+        }
+    }
+}
+
+/// Translates a JSON user record into a `User`.
+fn user_from_record(record: &Value) -> Option<User> {
+    let name = record.get("userName")?.as_str()?;
+    let uid = record.get("uid")?.as_u64()? as uid_t;
+
+    // A user without a GID of their own has a group with the same ID.
+    let gid = record.get("gid").and_then(Value::as_u64).map_or(uid as gid_t, |gid| gid as gid_t);
+
+    let mut builder = UserBuilder::new(uid, name, gid);
+    if let Some(home_dir) = record.get("homeDirectory").and_then(Value::as_str) {
+        builder = builder.home_dir(home_dir);
+    }
+    if let Some(shell) = record.get("shell").and_then(Value::as_str) {
+        builder = builder.shell(shell);
+    }
+    if let Some(real_name) = record.get("realName").and_then(Value::as_str) {
+        builder = builder.gecos(real_name);
+    }
+    if let Some(password) = record.pointer("/privileged/hashedPassword/0").and_then(Value::as_str) {
+        builder = builder.password(password);
+    }
+
+    Some(builder.build())
+}
+
+/// Translates a JSON group record into a `Group`, with the given members
+/// as well as those the record lists.
+fn group_from_record(record: &Value, memberships: &[String]) -> Option<Group> {
+    let name = record.get("groupName")?.as_str()?;
+    let gid = record.get("gid")?.as_u64()? as gid_t;
+
+    let mut members: Vec<&str> = record.get("members").and_then(Value::as_array)
+        .map(|members| members.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    for member in memberships {
+        if ! members.contains(&&**member) {
+            members.push(member);
+        }
+    }
+
+    Some(GroupBuilder::new(gid, name).members(members).build())
+}
+
+
+impl Users for UserdbBackend {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        self.find_user(json!({ "uid": uid }))
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(&self, username: &S) -> Option<Arc<User>> {
+        self.find_user(json!({ "userName": username.as_ref().to_str()? }))
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        self.uid
+    }
+
+    fn get_current_username(&self) -> Option<Arc<OsStr>> {
+        self.get_user_by_uid(self.uid).map(|u| Arc::from(u.name()))
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        self.euid
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<OsStr>> {
+        self.get_user_by_uid(self.euid).map(|u| Arc::from(u.name()))
+    }
+}
+
+impl Groups for UserdbBackend {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        self.find_group(json!({ "gid": gid }))
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(&self, group_name: &S) -> Option<Arc<Group>> {
+        self.find_group(json!({ "groupName": group_name.as_ref().to_str()? }))
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        self.gid
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<OsStr>> {
+        self.get_group_by_gid(self.gid).map(|g| Arc::from(g.name()))
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        self.egid
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<OsStr>> {
+        self.get_group_by_gid(self.egid).map(|g| Arc::from(g.name()))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::os::unix::net::UnixListener;
+    use std::process;
+    use std::thread;
+    use os::unix::{UserExt, GroupExt};
+
+    #[test]
+    fn records() {
+        let user = user_from_record(&json!({
+            "userName": "alice", "uid": 1000, "realName": "Alice",
+            "homeDirectory": "/home/alice", "shell": "/bin/zsh",
+            "privileged": { "hashedPassword": [ "$6$hash" ] },
+        })).unwrap();
+
+        assert_eq!(user.primary_group_id(), 1000);
+        assert_eq!(user.home_dir(), Path::new("/home/alice"));
+        assert_eq!(user.password(), "$6$hash");
+
+        let group = group_from_record(&json!({ "groupName": "wheel", "gid": 10, "members": [ "root" ] }), &[ "alice".into() ]).unwrap();
+        assert_eq!(group.members(), &[ "root".into(), "alice".into() ][..] as &[::std::ffi::OsString]);
+
+        assert!(user_from_record(&json!({ "uid": 1000 })).is_none());
+    }
+
+    /// Answers one call on the socket, as a service with a single user in
+    /// a single group would.
+    fn answer(listener: &UnixListener) {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut bytes = Vec::new();
+        reader.read_until(0, &mut bytes).unwrap();
+        bytes.pop();
+
+        let call: Value = serde_json::from_slice(&bytes).unwrap();
+        let parameters = &call["parameters"];
+        let reply = match call["method"].as_str().unwrap() {
+            "io.systemd.UserDatabase.GetUserRecord" if parameters["uid"] == 1000 || parameters["userName"] == "alice" => {
+                json!({ "parameters": { "record": { "userName": "alice", "uid": 1000, "gid": 100 } } })
+            }
+            "io.systemd.UserDatabase.GetGroupRecord" if parameters["gid"] == 100 => {
+                json!({ "parameters": { "record": { "groupName": "users", "gid": 100 } } })
+            }
+            "io.systemd.UserDatabase.GetMemberships" => {
+                json!({ "parameters": { "userName": "alice", "groupName": "users" } })
+            }
+            _ => json!({ "error": "io.systemd.UserDatabase.NoRecordFound" }),
+        };
+
+        let mut bytes = serde_json::to_vec(&reply).unwrap();
+        bytes.push(0);
+        (&stream).write_all(&bytes).unwrap();
+    }
+
+    #[test]
+    fn service() {
+        let dir = env::temp_dir().join(format!("users-userdb-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let listener = UnixListener::bind(dir.join("io.example.Users")).unwrap();
+
+        let server = thread::spawn(move || {
+            for _ in 0 .. 4 {
+                answer(&listener);
+            }
+        });
+
+        let userdb = UserdbBackend::in_dir(&dir);
+        assert_eq!(userdb.get_user_by_uid(1000).unwrap().name(), "alice");
+        assert!(userdb.get_user_by_name("bob").is_none());
+        assert_eq!(userdb.get_group_by_gid(100).unwrap().members(), &[ "alice".into() ][..] as &[::std::ffi::OsString]);
+
+        server.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn no_sockets() {
+        let userdb = UserdbBackend::in_dir("/nonexistent");
+        assert!(userdb.get_user_by_uid(0).is_none());
+        assert!(userdb.get_group_by_name("root").is_none());
+    }
+}
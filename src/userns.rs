@@ -0,0 +1,165 @@
+//! Translating IDs between user namespaces.
+//!
+//! A process in a user namespace, such as one in a rootless container, sees
+//! IDs that differ from those the host sees: its root may well be UID
+//! 100000 outside. On Linux, `/proc/<pid>/uid_map` and `gid_map` say how
+//! they correspond, one range per line, as the first ID inside the
+//! namespace, the first ID outside it, and the number of IDs. The IDs
+//! outside are the ones the process reading the file sees.
+//!
+//! ```no_run
+//! use users::userns::map_uid_out_of_ns;
+//!
+//! let pid = 4242;
+//! match map_uid_out_of_ns(pid, 0).unwrap() {
+//!     Some(uid) => println!("Root in process {} is UID {} here", pid, uid),
+//!     None      => println!("Root in process {} has no UID here", pid),
+//! }
+//! ```
+//!
+//! IDs that no range covers have no counterpart, and show up as the
+//! overflow ID, usually 65534, on the other side.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use {uid_t, gid_t};
+
+
+/// One line of an ID map.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct IdMapRange {
+
+    /// The first ID inside the namespace.
+    pub inside: u32,
+
+    /// The first ID outside the namespace.
+    pub outside: u32,
+
+    /// The number of IDs in the range.
+    pub count: u32,
+}
+
+/// The contents of a `uid_map` or `gid_map` file.
+#[derive(PartialEq, Eq, Clone, Default, Debug)]
+pub struct IdMap {
+    ranges: Vec<IdMapRange>,
+}
+
+impl IdMap {
+
+    /// The map of a process in the initial namespace, where every ID is
+    /// the same inside and out.
+    pub fn identity() -> Self {
+        Self { ranges: vec![ IdMapRange { inside: 0, outside: 0, count: u32::MAX } ] }
+    }
+
+    /// Parses the contents of a `uid_map` or `gid_map` file, skipping any
+    /// lines that don’t parse.
+    pub fn parse(contents: &str) -> Self {
+        let ranges = contents.lines().filter_map(|line| {
+            let mut fields = line.split_whitespace().map(str::parse);
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(Ok(inside)), Some(Ok(outside)), Some(Ok(count)), None) => Some(IdMapRange { inside, outside, count }),
+                _                                                            => None,
+            }
+        }).collect();
+
+        Self { ranges }
+    }
+
+    /// Reads a `uid_map` or `gid_map` file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Returns the ranges in the map, in the order listed.
+    pub fn ranges(&self) -> &[IdMapRange] {
+        &self.ranges
+    }
+
+    /// Translates an ID inside the namespace to the one outside it.
+    pub fn to_outside(&self, id: u32) -> Option<u32> {
+        self.ranges.iter()
+            .find(|r| id >= r.inside && id - r.inside < r.count)
+            .map(|r| r.outside + (id - r.inside))
+    }
+
+    /// Translates an ID outside the namespace to the one inside it.
+    pub fn to_inside(&self, id: u32) -> Option<u32> {
+        self.ranges.iter()
+            .find(|r| id >= r.outside && id - r.outside < r.count)
+            .map(|r| r.inside + (id - r.outside))
+    }
+}
+
+
+/// Reads the user ID map of the process with the given ID.
+pub fn read_uid_map(pid: u32) -> io::Result<IdMap> {
+    IdMap::from_file(format!("/proc/{}/uid_map", pid))
+}
+
+/// Reads the group ID map of the process with the given ID.
+pub fn read_gid_map(pid: u32) -> io::Result<IdMap> {
+    IdMap::from_file(format!("/proc/{}/gid_map", pid))
+}
+
+/// Translates a user ID as seen here to the one the process with the
+/// given ID sees, if it has one.
+pub fn map_uid_into_ns(pid: u32, uid: uid_t) -> io::Result<Option<uid_t>> {
+    Ok(read_uid_map(pid)?.to_inside(uid))
+}
+
+/// Translates a user ID as the process with the given ID sees it to the
+/// one seen here, if it has one.
+pub fn map_uid_out_of_ns(pid: u32, uid: uid_t) -> io::Result<Option<uid_t>> {
+    Ok(read_uid_map(pid)?.to_outside(uid))
+}
+
+/// Translates a group ID as seen here to the one the process with the
+/// given ID sees, if it has one.
+pub fn map_gid_into_ns(pid: u32, gid: gid_t) -> io::Result<Option<gid_t>> {
+    Ok(read_gid_map(pid)?.to_inside(gid))
+}
+
+/// Translates a group ID as the process with the given ID sees it to the
+/// one seen here, if it has one.
+pub fn map_gid_out_of_ns(pid: u32, gid: gid_t) -> io::Result<Option<gid_t>> {
+    Ok(read_gid_map(pid)?.to_outside(gid))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rootless() {
+        let map = IdMap::parse("         0       1000          1\n         1     100000      65536\n");
+        assert_eq!(map.ranges().len(), 2);
+        assert_eq!(map.to_outside(0), Some(1000));
+        assert_eq!(map.to_outside(1), Some(100000));
+        assert_eq!(map.to_outside(65536), Some(165535));
+        assert_eq!(map.to_outside(65537), None);
+        assert_eq!(map.to_inside(1000), Some(0));
+        assert_eq!(map.to_inside(100005), Some(6));
+        assert_eq!(map.to_inside(0), None);
+    }
+
+    #[test]
+    fn identity() {
+        let map = IdMap::parse("         0          0 4294967295\n");
+        assert_eq!(map, IdMap::identity());
+        assert_eq!(map.to_outside(1000), Some(1000));
+        assert_eq!(map.to_inside(u32::MAX - 1), Some(u32::MAX - 1));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn own_process() {
+        let (uid, ..) = ::files::process_ids();
+        let map = read_uid_map(::std::process::id()).unwrap();
+        assert!(map.to_outside(uid).is_some());
+    }
+}
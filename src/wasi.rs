@@ -0,0 +1,90 @@
+//! The WASI backend, which reads `/etc/passwd` and `/etc/group` through
+//! whatever directories the host has preopened.
+//!
+//! A module only sees these files if the host maps a directory onto `/etc`
+//! (with `wasmtime --dir /etc`, for example). When it doesn’t, every lookup
+//! falls back to the stub’s answer, the same as on any other platform
+//! without a backend.
+//!
+//! WASI has no notion of a current user, so the current and effective IDs
+//! stay at the stub’s 0.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+
+use backend::Backend;
+use base::{User, Group, uid_t, gid_t};
+use etc::{passwd_entries, group_entries};
+use platform::Platform;
+
+
+static PASSWD: &str = "/etc/passwd";
+static GROUP: &str = "/etc/group";
+
+
+/// Reads a file, treating a missing or unreadable one as empty.
+fn read(path: &str) -> String {
+    fs::read_to_string(path).unwrap_or_default()
+}
+
+
+/// The backend reading the `/etc` files.
+pub struct Wasi;
+
+impl Platform for Wasi {
+    const BACKEND: Backend = Backend::Files;
+
+    fn get_user_by_uid(uid: uid_t) -> Option<User> {
+        passwd_entries(&read(PASSWD)).find(|e| e.uid == uid)
+                                     .map(|e| e.to_user())
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(username: &S) -> Option<User> {
+        let username = username.as_ref();
+        passwd_entries(&read(PASSWD)).find(|e| OsStr::new(e.name) == username)
+                                     .map(|e| e.to_user())
+    }
+
+    fn get_group_by_gid(gid: gid_t) -> Option<Group> {
+        group_entries(&read(GROUP)).find(|e| e.gid == gid)
+                                   .map(|e| e.to_group())
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(groupname: &S) -> Option<Group> {
+        let groupname = groupname.as_ref();
+        group_entries(&read(GROUP)).find(|e| OsStr::new(e.name) == groupname)
+                                   .map(|e| e.to_group())
+    }
+
+    fn get_user_groups<S: AsRef<OsStr> + ?Sized>(username: &S, gid: gid_t) -> Option<Vec<Group>> {
+        let contents = fs::read_to_string(GROUP).ok()?;
+        let username = username.as_ref();
+
+        // Like `getgrouplist`, the primary group comes first.
+        let mut groups = Vec::new();
+        for entry in group_entries(&contents) {
+            if entry.gid == gid {
+                groups.insert(0, entry.to_group());
+            }
+            else if entry.members.iter().any(|m| OsStr::new(m) == username) {
+                groups.push(entry.to_group());
+            }
+        }
+
+        Some(groups)
+    }
+
+    fn all_users() -> Vec<User> {
+        passwd_entries(&read(PASSWD)).map(|e| e.to_user()).collect()
+    }
+
+    fn try_all_users() -> io::Result<Vec<io::Result<User>>> {
+        let passwd = fs::read_to_string(PASSWD)?;
+        Ok(passwd_entries(&passwd).map(|e| Ok(e.to_user())).collect())
+    }
+
+    fn all_groups() -> Vec<Group> {
+        group_entries(&read(GROUP)).map(|e| e.to_group()).collect()
+    }
+}
@@ -0,0 +1,263 @@
+//! Noticing when the users and groups files change, so that the caches can
+//! forget what they looked up before, and so that `watch` can tell what
+//! changed.
+//!
+//! This polls the files’ modification times and sizes, at most once a
+//! second, which works the same on every platform and needs no background
+//! thread. On platforms without the files, nothing ever changes.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use {User, Group};
+use snapshot::{UsersSnapshot, Modified, UserField, GroupField};
+
+
+/// The files the system’s users and groups come from.
+static FILES: &[&str] = &[ "/etc/passwd", "/etc/group" ];
+
+/// How long to trust the files not to have changed after checking.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+
+/// Watches a set of files for changes.
+pub(crate) struct Watch {
+    files: Vec<PathBuf>,
+    interval: Duration,
+    state: Mutex<State>,
+}
+
+struct State {
+    checked: Option<Instant>,
+    stamps: Vec<Option<Stamp>>,
+}
+
+/// What a file looked like when it was last checked.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl Default for Watch {
+    fn default() -> Self {
+        Self::new(FILES.iter().map(PathBuf::from).collect(), INTERVAL)
+    }
+}
+
+impl Watch {
+
+    /// Starts watching the given files, checking them at most once per
+    /// interval.
+    pub(crate) fn new(files: Vec<PathBuf>, interval: Duration) -> Self {
+        let stamps = stamps(&files);
+        Self { files, interval, state: Mutex::new(State { checked: None, stamps }) }
+    }
+
+    /// Returns whether any of the files changed since this last returned
+    /// true, or since watching started.
+    pub(crate) fn changed(&self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(checked) = state.checked {
+            if checked.elapsed() < self.interval {
+                return false;
+            }
+        }
+
+        let stamps = stamps(&self.files);
+        state.checked = Some(Instant::now());
+        if stamps == state.stamps {
+            return false;
+        }
+
+        state.stamps = stamps;
+        true
+    }
+}
+
+fn stamps(files: &[PathBuf]) -> Vec<Option<Stamp>> {
+    files.iter()
+         .map(|file| fs::metadata(file).ok())
+         .map(|m| m.map(|m| Stamp { modified: m.modified().ok(), len: m.len() }))
+         .collect()
+}
+
+
+/// A change to the users or groups, reported by `watch`.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum AccountEvent {
+
+    /// A user that wasn’t there before.
+    UserAdded(Arc<User>),
+
+    /// A user that isn’t there any more.
+    UserRemoved(Arc<User>),
+
+    /// A user whose details changed.
+    UserModified(Modified<User, UserField>),
+
+    /// A group that wasn’t there before.
+    GroupAdded(Arc<Group>),
+
+    /// A group that isn’t there any more.
+    GroupRemoved(Arc<Group>),
+
+    /// A group whose details changed.
+    GroupModified(Modified<Group, GroupField>),
+}
+
+/// Calls the given function with every change to the users and groups,
+/// checking for changes once per interval on a background thread, until
+/// the returned `Watcher` is dropped.
+///
+/// Where the users and groups come from `/etc/passwd` and `/etc/group`,
+/// they are only listed again once one of those files changed. Elsewhere,
+/// such as with the stub, they are listed again every interval and compared
+/// with what was there before.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use users::AccountEvent;
+///
+/// let watcher = unsafe {
+///     users::watch(Duration::from_secs(5), |event| {
+///         if let AccountEvent::UserAdded(user) = event {
+///             println!("Welcome, {:?}!", user.name());
+///         }
+///     })
+/// };
+/// # drop(watcher);
+/// ```
+///
+/// # Safety
+///
+/// The background thread lists users and groups the same way as
+/// `UsersSnapshot::capture`, so while the `Watcher` is alive, no other
+/// thread may call `all_users` or `all_groups` directly. Everything else in
+/// this crate that lists them waits its turn.
+pub unsafe fn watch<F>(interval: Duration, callback: F) -> Watcher
+where F: FnMut(AccountEvent) + Send + 'static
+{
+    let reads_files = cfg!(any(users_passthrough, target_os = "wasi", target_os = "illumos", target_os = "aix"))
+        && FILES.iter().any(|f| fs::metadata(f).is_ok());
+    let files = if reads_files { Some(Watch::new(FILES.iter().map(PathBuf::from).collect(), Duration::from_secs(0))) }
+                          else { None };
+
+    Watcher::spawn(interval, files, || UsersSnapshot::capture(), callback)
+}
+
+/// Keeps watching for changes to the users and groups, until dropped.
+///
+/// Returned by `watch`.
+pub struct Watcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    fn spawn<L, F>(interval: Duration, files: Option<Watch>, mut list: L, mut callback: F) -> Self
+    where L: FnMut() -> UsersSnapshot + Send + 'static,
+          F: FnMut(AccountEvent) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+
+        let mut before = list();
+        let thread = thread::spawn(move || {
+            loop {
+                thread::park_timeout(interval);
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let changed = match files {
+                    Some(ref files) => files.changed(),
+                    None            => true,
+                };
+
+                if changed {
+                    let after = list();
+                    for event in events(&before, &after) {
+                        callback(event);
+                    }
+                    before = after;
+                }
+            }
+        });
+
+        Self { stop, thread: Some(thread) }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn events(before: &UsersSnapshot, after: &UsersSnapshot) -> Vec<AccountEvent> {
+    let diff = before.diff(after);
+
+    diff.removed_users.into_iter().map(AccountEvent::UserRemoved)
+        .chain(diff.added_users.into_iter().map(AccountEvent::UserAdded))
+        .chain(diff.modified_users.into_iter().map(AccountEvent::UserModified))
+        .chain(diff.removed_groups.into_iter().map(AccountEvent::GroupRemoved))
+        .chain(diff.added_groups.into_iter().map(AccountEvent::GroupAdded))
+        .chain(diff.modified_groups.into_iter().map(AccountEvent::GroupModified))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn notices_changes() {
+        let file = env::temp_dir().join(format!("users-watch-{}", process::id()));
+        fs::write(&file, "root:x:0:0::/root:/bin/sh\n").unwrap();
+
+        let watch = Watch::new(vec![ file.clone() ], Duration::from_secs(0));
+        assert!(! watch.changed());
+
+        fs::write(&file, "root:x:0:0::/root:/bin/sh\nalice:x:1000:100::/home/alice:/bin/sh\n").unwrap();
+        assert!(watch.changed());
+        assert!(! watch.changed());
+
+        fs::remove_file(&file).unwrap();
+        assert!(watch.changed());
+    }
+
+    #[test]
+    fn reports_events() {
+        use std::sync::mpsc;
+
+        let users = Arc::new(Mutex::new(vec![ User::new(1000, "alice", 100) ]));
+        let listed = Arc::clone(&users);
+        let (sender, receiver) = mpsc::channel();
+
+        let watcher = Watcher::spawn(Duration::from_millis(10), None,
+            move || UsersSnapshot::new(listed.lock().unwrap().clone(), vec![]),
+            move |event| { let _ = sender.send(event); });
+
+        users.lock().unwrap().push(User::new(1001, "bob", 100));
+        match receiver.recv_timeout(Duration::from_secs(10)).unwrap() {
+            AccountEvent::UserAdded(user) => assert_eq!(user.name(), "bob"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        drop(watcher);
+        assert!(receiver.recv().is_err());
+    }
+}
@@ -0,0 +1,854 @@
+//! Native user lookups on Windows.
+//!
+//! Windows has no `passwd` database: accounts are identified by security
+//! identifiers (SIDs), and names are resolved through the LSA lookup
+//! functions. This module translates between the two worlds so that the
+//! functions in `base` can return real names instead of dummy values.
+//!
+//! SIDs are turned into user and group IDs with the Cygwin-compatible
+//! mapping from the [`sid` module](../sid/index.html), relative to the local
+//! machine’s account domain and, on a domain-joined machine, the primary
+//! domain.
+//!
+//! Names can be given in any form `LookupAccountNameW` understands: a bare
+//! name, `DOMAIN\user`, or a user principal name such as
+//! `user@example.com`. As a shorthand, `.\user` names a local account.
+
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::ptr;
+use std::slice;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use winapi::shared::lmcons::{MAX_PREFERRED_LENGTH, NERR_Success, UNLEN};
+use winapi::shared::basetsd::DWORD_PTR;
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE, HLOCAL, LPBYTE, LPVOID};
+use winapi::shared::sddl::{ConvertSidToStringSidW, ConvertStringSidToSidW};
+use winapi::shared::winerror::{ERROR_INSUFFICIENT_BUFFER, ERROR_MORE_DATA, ERROR_SUCCESS};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::lmaccess::{FILTER_NORMAL_ACCOUNT, NetUserEnum, NetUserGetInfo, USER_INFO_0, USER_INFO_1, USER_INFO_2, UF_ACCOUNTDISABLE};
+use winapi::um::lmaccess::{NetLocalGroupGetMembers, LOCALGROUP_MEMBERS_INFO_3};
+use winapi::um::lmapibuf::NetApiBufferFree;
+use winapi::um::lmjoin::{NetGetJoinInformation, NetSetupDomainName, NETSETUP_JOIN_STATUS};
+use winapi::um::processthreadsapi::{GetCurrentProcess, OpenProcessToken};
+use winapi::um::securitybaseapi::{GetTokenInformation, ImpersonateLoggedOnUser, RevertToSelf};
+use winapi::um::winbase::{GetComputerNameW, GetUserNameW, LocalFree, LookupAccountNameW, LookupAccountSidW, MAX_COMPUTERNAME_LENGTH};
+use winapi::um::winbase::{LogonUserW, LOGON32_LOGON_INTERACTIVE, LOGON32_PROVIDER_DEFAULT};
+use winapi::um::winnt::{HANDLE, LONG, LPWSTR, PSID, PVOID, SE_GROUP_USE_FOR_DENY_ONLY, SID_NAME_USE};
+use winapi::um::winnt::{SidTypeAlias, SidTypeDomain, SidTypeGroup, SidTypeLabel, SidTypeUser, SidTypeWellKnownGroup};
+use winapi::um::winnt::{TOKEN_ELEVATION, TOKEN_GROUPS, TOKEN_INFORMATION_CLASS, TOKEN_PRIMARY_GROUP, TOKEN_QUERY, TOKEN_USER};
+use winapi::um::winnt::{TokenElevation, TokenGroups, TokenPrimaryGroup, TokenUser};
+use winapi::um::winreg::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_SZ};
+
+use backend::Backend;
+use current::{self, Impersonating};
+use base::{User, Group, uid_t, gid_t};
+use platform::{Platform, same_groups};
+use sessions::{LastLogin, Session};
+use base::os::unix::{GroupExt, UserExt as UnixUserExt};
+use base::os::windows::UserExt;
+use sid::{Sid, SidMapping};
+
+
+/// The RID of the “None” group (“Domain Users” on a domain controller),
+/// which Windows assigns as the primary group of every new account.
+const DOMAIN_GROUP_RID_USERS: u32 = 513;
+
+/// The registry key holding a subkey for every profile on this machine,
+/// named after the SID of the account it belongs to.
+const PROFILE_LIST: &str = "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\ProfileList";
+
+/// The server handle that stands for the local machine.
+const WTS_CURRENT_SERVER_HANDLE: HANDLE = 0 as HANDLE;
+
+/// The information class `WTSQuerySessionInformationW` returns the name of
+/// the client of a remote session for.
+const WTS_CLIENT_NAME: i32 = 10;
+
+/// The information class `WTSQuerySessionInformationW` returns a `WTSINFOW`
+/// for.
+const WTS_SESSION_INFO: i32 = 24;
+
+/// The number of 100-nanosecond intervals between the `FILETIME` epoch,
+/// 1601, and the Unix one.
+const FILETIME_UNIX_EPOCH: i64 = 116_444_736_000_000_000;
+
+// NOTE: winapi lacks the session information functions and structures
+#[allow(non_camel_case_types, non_snake_case)]
+#[repr(C)]
+struct WTS_SESSION_INFOW {
+    SessionId: DWORD,
+    pWinStationName: LPWSTR,
+    State: i32,
+}
+
+#[allow(non_camel_case_types, non_snake_case)]
+#[repr(C)]
+struct WTSINFOW {
+    State: i32,
+    SessionId: DWORD,
+    IncomingBytes: DWORD,
+    OutgoingBytes: DWORD,
+    IncomingFrames: DWORD,
+    OutgoingFrames: DWORD,
+    IncomingCompressedBytes: DWORD,
+    OutgoingCompressedBytes: DWORD,
+    WinStationName: [u16; 32],
+    Domain: [u16; 17],
+    UserName: [u16; 21],
+    ConnectTime: i64,
+    DisconnectTime: i64,
+    LastInputTime: i64,
+    LogonTime: i64,
+    CurrentTime: i64,
+}
+
+#[allow(non_snake_case)]
+#[link(name = "wtsapi32")]
+extern "system" {
+    fn WTSEnumerateSessionsW(hServer: HANDLE, Reserved: DWORD, Version: DWORD, ppSessionInfo: *mut *mut WTS_SESSION_INFOW, pCount: *mut DWORD) -> BOOL;
+    fn WTSQuerySessionInformationW(hServer: HANDLE, SessionId: DWORD, WTSInfoClass: i32, ppBuffer: *mut LPWSTR, pBytesReturned: *mut DWORD) -> BOOL;
+    fn WTSFreeMemory(pMemory: PVOID);
+}
+
+
+/// An account, as resolved by the LSA lookup functions.
+struct Account {
+    name: OsString,
+    domain: OsString,
+    sid: Sid,
+    kind: SID_NAME_USE,
+}
+
+/// A SID allocated by `ConvertStringSidToSidW`, which has to be released
+/// with `LocalFree` again.
+struct LocalSid(PSID);
+
+impl Drop for LocalSid {
+    fn drop(&mut self) {
+        unsafe { LocalFree(self.0 as HLOCAL) };
+    }
+}
+
+/// An access token, closed on drop.
+struct Token(HANDLE);
+
+impl Token {
+
+    /// Opens the current process’s token for querying.
+    fn current_process() -> Option<Self> {
+        let mut handle = ptr::null_mut();
+
+        if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut handle) } == FALSE {
+            return None;
+        }
+
+        Some(Token(handle))
+    }
+
+    /// Reads one of the variable-length information blocks of this token.
+    ///
+    /// The buffer is made of `u64`s so that the structures the block is cast
+    /// to are suitably aligned.
+    fn information(&self, class: TOKEN_INFORMATION_CLASS) -> Option<Vec<u64>> {
+        let mut len: DWORD = 0;
+
+        // The first call only reports the buffer size it needs.
+        unsafe { GetTokenInformation(self.0, class, ptr::null_mut(), 0, &mut len) };
+        if unsafe { GetLastError() } != ERROR_INSUFFICIENT_BUFFER {
+            return None;
+        }
+
+        let mut buf = vec![0_u64; (len as usize + 7) / 8];
+        if unsafe { GetTokenInformation(self.0, class, buf.as_mut_ptr() as LPVOID, len, &mut len) } == FALSE {
+            return None;
+        }
+
+        Some(buf)
+    }
+
+    /// Returns the SID of the user this token belongs to.
+    fn user(&self) -> Option<Sid> {
+        let buf = self.information(TokenUser)?;
+        unsafe {
+            let user = &*(buf.as_ptr() as *const TOKEN_USER);
+            sid_to_string(user.User.Sid)?.parse().ok()
+        }
+    }
+
+    /// Returns the SID of the primary group of this token.
+    fn primary_group(&self) -> Option<Sid> {
+        let buf = self.information(TokenPrimaryGroup)?;
+        unsafe {
+            let group = &*(buf.as_ptr() as *const TOKEN_PRIMARY_GROUP);
+            sid_to_string(group.PrimaryGroup)?.parse().ok()
+        }
+    }
+
+    /// Returns whether this token is elevated.
+    fn is_elevated(&self) -> Option<bool> {
+        let buf = self.information(TokenElevation)?;
+        let elevation = unsafe { &*(buf.as_ptr() as *const TOKEN_ELEVATION) };
+        Some(elevation.TokenIsElevated != 0)
+    }
+
+    /// Returns the SIDs of the groups this token is a member of, leaving
+    /// out groups that are only used to deny access.
+    fn groups(&self) -> Option<Vec<Sid>> {
+        let buf = self.information(TokenGroups)?;
+        unsafe {
+            let groups = &*(buf.as_ptr() as *const TOKEN_GROUPS);
+            let entries = slice::from_raw_parts(groups.Groups.as_ptr(), groups.GroupCount as usize);
+
+            let sids = entries.iter()
+                              .filter(|entry| entry.Attributes & SE_GROUP_USE_FOR_DENY_ONLY == 0)
+                              .filter_map(|entry| sid_to_string(entry.Sid)?.parse().ok())
+                              .collect();
+            Some(sids)
+        }
+    }
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+/// The current thread impersonating another user, until this is dropped.
+pub struct Impersonation(Token, Impersonating);
+
+impl Impersonation {
+
+    /// Stops impersonating, returning the error if that fails.
+    pub fn revert(self) -> io::Result<()> {
+        // dropping it afterwards reverts again, which does nothing
+        if unsafe { RevertToSelf() } == FALSE {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Impersonation {
+    fn drop(&mut self) {
+        unsafe { RevertToSelf() };
+    }
+}
+
+
+/// Encodes a string as the null-terminated UTF-16 the `W` functions expect.
+fn to_wide(s: &OsStr) -> Vec<u16> {
+    s.encode_wide().chain(Some(0)).collect()
+}
+
+/// Splits a `DOMAIN\user` name into its domain and user parts, each
+/// encoded for the `W` functions.
+fn split_domain(name: &OsStr) -> (Option<Vec<u16>>, Vec<u16>) {
+    let wide = name.encode_wide().collect::<Vec<_>>();
+    let terminated = |part: &[u16]| part.iter().cloned().chain(Some(0)).collect();
+
+    match wide.iter().position(|&c| c == u16::from(b'\\')) {
+        Some(i) => (Some(terminated(&wide[..i])), terminated(&wide[i + 1 ..])),
+        None    => (None, terminated(&wide)),
+    }
+}
+
+/// Decodes a UTF-16 buffer, stopping at the first null character.
+fn from_wide(buf: &[u16]) -> OsString {
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    OsString::from_wide(&buf[..len])
+}
+
+/// Decodes a null-terminated UTF-16 string owned by the system.
+unsafe fn from_wide_ptr(p: *const u16) -> OsString {
+    let len = (0..).take_while(|&i| *p.offset(i) != 0).count();
+    OsString::from_wide(slice::from_raw_parts(p, len))
+}
+
+/// Renders a SID in its `S-1-5-21-…` string form.
+unsafe fn sid_to_string(sid: PSID) -> Option<String> {
+    let mut raw: LPWSTR = ptr::null_mut();
+    if ConvertSidToStringSidW(sid, &mut raw) == FALSE {
+        return None;
+    }
+
+    let string = from_wide_ptr(raw).into_string().ok();
+    LocalFree(raw as HLOCAL);
+    string
+}
+
+/// Converts a SID back into the binary form the Windows APIs take.
+fn to_local_sid(sid: &Sid) -> Option<LocalSid> {
+    let wide = to_wide(OsStr::new(&sid.to_string()));
+    let mut raw: PSID = ptr::null_mut();
+
+    if unsafe { ConvertStringSidToSidW(wide.as_ptr(), &mut raw) } == FALSE {
+        return None;
+    }
+
+    Some(LocalSid(raw))
+}
+
+/// Resolves a SID to the account it belongs to.
+unsafe fn lookup_sid(sid: PSID) -> Option<Account> {
+    let mut name_len: DWORD = 0;
+    let mut domain_len: DWORD = 0;
+    let mut kind: SID_NAME_USE = 0;
+
+    // The first call only reports the buffer sizes it needs.
+    LookupAccountSidW(ptr::null(), sid, ptr::null_mut(), &mut name_len, ptr::null_mut(), &mut domain_len, &mut kind);
+    if GetLastError() != ERROR_INSUFFICIENT_BUFFER {
+        return None;
+    }
+
+    let mut name = vec![0; name_len as usize];
+    let mut domain = vec![0; domain_len as usize];
+    if LookupAccountSidW(ptr::null(), sid, name.as_mut_ptr(), &mut name_len, domain.as_mut_ptr(), &mut domain_len, &mut kind) == FALSE {
+        return None;
+    }
+
+    Some(Account {
+        name:   from_wide(&name),
+        domain: from_wide(&domain),
+        sid:    sid_to_string(sid)?.parse().ok()?,
+        kind,
+    })
+}
+
+/// Resolves an account name to its SID, then back to the account, so that
+/// the returned name is spelled the way Windows spells it.
+fn lookup_name(name: &OsStr) -> Option<Account> {
+    let wide = to_wide(name);
+    let mut sid_len: DWORD = 0;
+    let mut domain_len: DWORD = 0;
+    let mut kind: SID_NAME_USE = 0;
+
+    unsafe {
+        LookupAccountNameW(ptr::null(), wide.as_ptr(), ptr::null_mut(), &mut sid_len, ptr::null_mut(), &mut domain_len, &mut kind);
+        if GetLastError() != ERROR_INSUFFICIENT_BUFFER {
+            return None;
+        }
+
+        let mut sid = vec![0_u8; sid_len as usize];
+        let mut domain = vec![0; domain_len as usize];
+        if LookupAccountNameW(ptr::null(), wide.as_ptr(), sid.as_mut_ptr() as PSID, &mut sid_len, domain.as_mut_ptr(), &mut domain_len, &mut kind) == FALSE {
+            return None;
+        }
+
+        lookup_sid(sid.as_mut_ptr() as PSID)
+    }
+}
+
+/// Returns the NetBIOS name of the local machine, which is also the name
+/// of its account domain.
+fn computer_name() -> Option<OsString> {
+    let mut buf = vec![0; MAX_COMPUTERNAME_LENGTH as usize + 1];
+    let mut len = buf.len() as DWORD;
+
+    if unsafe { GetComputerNameW(buf.as_mut_ptr(), &mut len) } == FALSE {
+        return None;
+    }
+
+    Some(from_wide(&buf))
+}
+
+/// Returns the SID of the local machine’s account domain.
+fn machine_domain_sid() -> Option<Sid> {
+    lookup_name(&computer_name()?)
+        .filter(|account| account.kind == SidTypeDomain)
+        .map(|account| account.sid)
+}
+
+/// Returns the SID of the domain this machine is joined to, if any.
+fn primary_domain_sid() -> Option<Sid> {
+    let mut buf: LPWSTR = ptr::null_mut();
+    let mut status: NETSETUP_JOIN_STATUS = 0;
+
+    if unsafe { NetGetJoinInformation(ptr::null(), &mut buf, &mut status) } != NERR_Success {
+        return None;
+    }
+
+    let name = unsafe { from_wide_ptr(buf) };
+    unsafe { NetApiBufferFree(buf as LPVOID) };
+
+    if status != NetSetupDomainName {
+        return None;
+    }
+
+    lookup_name(&name)
+        .filter(|account| account.kind == SidTypeDomain)
+        .map(|account| account.sid)
+}
+
+/// Lists the names of the normal accounts in the local account database.
+fn local_account_names() -> Vec<OsString> {
+    let mut names = Vec::new();
+    let mut resume: DWORD = 0;
+
+    loop {
+        let mut buf: LPBYTE = ptr::null_mut();
+        let mut read: DWORD = 0;
+        let mut total: DWORD = 0;
+
+        let status = unsafe {
+            NetUserEnum(ptr::null(), 0, FILTER_NORMAL_ACCOUNT, &mut buf, MAX_PREFERRED_LENGTH, &mut read, &mut total, &mut resume)
+        };
+
+        if !buf.is_null() {
+            if status == NERR_Success || status == ERROR_MORE_DATA {
+                let entries = unsafe { slice::from_raw_parts(buf as *const USER_INFO_0, read as usize) };
+                names.extend(entries.iter().map(|entry| unsafe { from_wide_ptr(entry.usri0_name) }));
+            }
+
+            unsafe { NetApiBufferFree(buf as LPVOID) };
+        }
+
+        if status != ERROR_MORE_DATA {
+            break;
+        }
+    }
+
+    names
+}
+
+/// Lists the members of a local group, such as `BUILTIN\Administrators`,
+/// as `DOMAIN\name` names that `LookupAccountNameW` understands. Groups
+/// that aren’t local, or whose members can’t be read, have none.
+fn local_group_members(group: &OsStr) -> Vec<OsString> {
+    let group = to_wide(group);
+    let mut members = Vec::new();
+    let mut resume: DWORD_PTR = 0;
+
+    loop {
+        let mut buf: LPBYTE = ptr::null_mut();
+        let mut read: DWORD = 0;
+        let mut total: DWORD = 0;
+
+        let status = unsafe {
+            NetLocalGroupGetMembers(ptr::null(), group.as_ptr(), 3, &mut buf, MAX_PREFERRED_LENGTH, &mut read, &mut total, &mut resume)
+        };
+
+        if !buf.is_null() {
+            if status == NERR_Success || status == ERROR_MORE_DATA {
+                let entries = unsafe { slice::from_raw_parts(buf as *const LOCALGROUP_MEMBERS_INFO_3, read as usize) };
+                members.extend(entries.iter().map(|entry| unsafe { from_wide_ptr(entry.lgrmi3_domainandname) }));
+            }
+
+            unsafe { NetApiBufferFree(buf as LPVOID) };
+        }
+
+        if status != ERROR_MORE_DATA {
+            break;
+        }
+    }
+
+    members
+}
+
+/// Looks up the profile directory of the account with the given SID.
+/// Accounts that have never logged on don’t have one yet.
+fn profile_dir(sid: &Sid) -> Option<OsString> {
+    let key = to_wide(OsStr::new(&format!("{}\\{}", PROFILE_LIST, sid)));
+    let value = to_wide(OsStr::new("ProfileImagePath"));
+    let mut len: DWORD = 0;
+
+    unsafe {
+        // The first call only reports the buffer size it needs, in bytes.
+        // The path is stored with environment variables in it, which
+        // `RRF_RT_REG_SZ` has expanded.
+        if RegGetValueW(HKEY_LOCAL_MACHINE, key.as_ptr(), value.as_ptr(), RRF_RT_REG_SZ, ptr::null_mut(), ptr::null_mut(), &mut len) != ERROR_SUCCESS as LONG {
+            return None;
+        }
+
+        let mut buf = vec![0_u16; len as usize / 2 + 1];
+        len = (buf.len() * 2) as DWORD;
+        if RegGetValueW(HKEY_LOCAL_MACHINE, key.as_ptr(), value.as_ptr(), RRF_RT_REG_SZ, ptr::null_mut(), buf.as_mut_ptr() as PVOID, &mut len) != ERROR_SUCCESS as LONG {
+            return None;
+        }
+
+        Some(from_wide(&buf))
+    }
+}
+
+/// Returns whether a local account is disabled. Domain accounts live on a
+/// domain controller, so they are never taken to be.
+fn is_disabled(account: &Account) -> bool {
+    if ! computer_name().map_or(false, |name| name.eq_ignore_ascii_case(&account.domain)) {
+        return false;
+    }
+
+    let name = to_wide(&account.name);
+    let mut buf: LPBYTE = ptr::null_mut();
+    let status = unsafe { NetUserGetInfo(ptr::null(), name.as_ptr(), 1, &mut buf) };
+    if buf.is_null() {
+        return false;
+    }
+
+    let disabled = status == NERR_Success && unsafe { (*(buf as *const USER_INFO_1)).usri1_flags } & UF_ACCOUNTDISABLE != 0;
+    unsafe { NetApiBufferFree(buf as LPVOID) };
+    disabled
+}
+
+/// Returns the SID mapping for this machine.
+fn mapping() -> SidMapping {
+    let mut mapping = SidMapping::new();
+
+    if let Some(machine) = machine_domain_sid() {
+        mapping = mapping.with_machine_domain(machine);
+    }
+
+    if let Some(domain) = primary_domain_sid() {
+        mapping = mapping.with_primary_domain(domain);
+    }
+
+    mapping
+}
+
+/// Prefixes a name with the machine’s name, so that a local account is
+/// found rather than a domain account of the same name.
+fn qualify_local(name: &OsStr) -> Option<OsString> {
+    let mut qualified = computer_name()?;
+    qualified.push("\\");
+    qualified.push(name);
+    Some(qualified)
+}
+
+/// Turns a resolved account into a `User`, if it is one.
+fn account_to_user(account: Account, mapping: &SidMapping) -> Option<User> {
+    if account.kind != SidTypeUser {
+        return None;
+    }
+
+    let uid = mapping.sid_to_id(&account.sid)?;
+    let primary_group = account.sid.domain()
+                                   .and_then(|domain| mapping.sid_to_id(&domain.with_rid(DOMAIN_GROUP_RID_USERS)))
+                                   .unwrap_or(DOMAIN_GROUP_RID_USERS);
+
+    // The profile directory is the closest thing Windows has to a home
+    // directory, so it stands in for one.
+    let user = match profile_dir(&account.sid) {
+        Some(dir) => User::new(uid, &account.name, primary_group).with_profile_dir(&dir).with_home_dir(&dir),
+        None      => User::new(uid, &account.name, primary_group),
+    };
+
+    let disabled = is_disabled(&account);
+    Some(user.with_sid(account.sid).with_domain(&account.domain).with_disabled(disabled))
+}
+
+/// Turns a resolved account into a `Group`, if it is one. Besides actual
+/// groups, aliases such as `BUILTIN\Administrators`, well-known groups
+/// such as `Everyone`, and integrity labels all count as groups.
+///
+/// Only aliases have their members filled in: the members of a domain
+/// group live on a domain controller, and the other kinds have none.
+fn account_to_group(account: Account, mapping: &SidMapping) -> Option<Group> {
+    match account.kind {
+        SidTypeGroup | SidTypeAlias | SidTypeWellKnownGroup | SidTypeLabel => {},
+        _ => return None,
+    }
+
+    let gid = mapping.sid_to_id(&account.sid)?;
+    let mut group = Group::new(gid, &account.name);
+    if account.kind == SidTypeAlias {
+        for member in local_group_members(&account.name) {
+            group = group.add_member(&member);
+        }
+    }
+
+    Some(group)
+}
+
+/// Resolves a SID to the group it belongs to.
+fn sid_to_group(sid: &Sid, mapping: &SidMapping) -> Option<Group> {
+    let sid = to_local_sid(sid)?;
+    let account = unsafe { lookup_sid(sid.0) }?;
+    account_to_group(account, mapping)
+}
+
+
+/// Maps one of the current process token’s SIDs to an ID, or 0 if the
+/// token can’t be read.
+fn current_id(which: fn(&Token) -> Option<Sid>) -> u32 {
+    Token::current_process().and_then(|token| which(&token))
+                            .and_then(|sid| mapping().sid_to_id(&sid))
+                            .unwrap_or(0)
+}
+
+
+/// The backend using the Windows security APIs.
+pub struct Windows;
+
+impl Platform for Windows {
+    const BACKEND: Backend = Backend::Windows;
+    const SWITCHES: bool = true;
+
+    fn get_user_by_uid(uid: uid_t) -> Option<User> {
+        let mapping = mapping();
+        let sid = to_local_sid(&mapping.id_to_sid(uid)?)?;
+        let account = unsafe { lookup_sid(sid.0) }?;
+        account_to_user(account, &mapping)
+    }
+
+    fn get_user_by_name<S: AsRef<OsStr> + ?Sized>(username: &S) -> Option<User> {
+        let username = username.as_ref();
+
+        let account = match username.to_str() {
+            Some(name) if name.starts_with(".\\") => lookup_name(&qualify_local(OsStr::new(&name[2..]))?)?,
+            _                                      => lookup_name(username)?,
+        };
+
+        account_to_user(account, &mapping())
+    }
+
+    fn all_users() -> Vec<User> {
+        let mapping = mapping();
+
+        local_account_names().into_iter()
+            .filter_map(|name| qualify_local(&name))
+            .filter_map(|name| lookup_name(&name))
+            .filter_map(|account| account_to_user(account, &mapping))
+            .collect()
+    }
+
+    fn get_group_by_gid(gid: gid_t) -> Option<Group> {
+        let mapping = mapping();
+        sid_to_group(&mapping.id_to_sid(gid)?, &mapping)
+    }
+
+    fn get_group_by_name<S: AsRef<OsStr> + ?Sized>(groupname: &S) -> Option<Group> {
+        let account = lookup_name(groupname.as_ref())?;
+        account_to_group(account, &mapping())
+    }
+
+    fn group_access_list() -> io::Result<Vec<Group>> {
+        let token = Token::current_process().ok_or_else(io::Error::last_os_error)?;
+        let sids = token.groups().ok_or_else(io::Error::last_os_error)?;
+        let mapping = mapping();
+
+        // Logon session SIDs and the like can’t be looked up, and don’t have
+        // IDs anyway, so they are silently skipped.
+        let mut groups = sids.iter()
+                             .filter_map(|sid| sid_to_group(sid, &mapping))
+                             .collect::<Vec<_>>();
+        groups.sort_by_key(|group| group.gid());
+        groups.dedup_by_key(|group| group.gid());
+        Ok(groups)
+    }
+
+    fn get_current_uid() -> uid_t {
+        current_id(Token::user)
+    }
+
+    fn get_effective_uid() -> uid_t {
+        current_id(Token::user)
+    }
+
+    fn get_current_gid() -> gid_t {
+        current_id(Token::primary_group)
+    }
+
+    fn get_effective_gid() -> gid_t {
+        current_id(Token::primary_group)
+    }
+
+    fn get_current_username() -> Option<OsString> {
+        let mut buf = vec![0; UNLEN as usize + 1];
+        let mut len = buf.len() as DWORD;
+
+        if unsafe { GetUserNameW(buf.as_mut_ptr(), &mut len) } == FALSE {
+            return None;
+        }
+
+        Some(from_wide(&buf))
+    }
+
+    fn set_current_uid(uid: uid_t) -> io::Result<()> {
+        unchanged(uid, Self::get_effective_uid())
+    }
+
+    fn set_current_gid(gid: gid_t) -> io::Result<()> {
+        unchanged(gid, Self::get_effective_gid())
+    }
+
+    fn set_effective_uid(uid: uid_t) -> io::Result<()> {
+        unchanged(uid, Self::get_effective_uid())
+    }
+
+    fn set_effective_gid(gid: gid_t) -> io::Result<()> {
+        unchanged(gid, Self::get_effective_gid())
+    }
+
+    fn set_both_uid(ruid: uid_t, euid: uid_t) -> io::Result<()> {
+        unchanged(ruid, Self::get_effective_uid()).and(unchanged(euid, Self::get_effective_uid()))
+    }
+
+    fn set_both_gid(rgid: gid_t, egid: gid_t) -> io::Result<()> {
+        unchanged(rgid, Self::get_effective_gid()).and(unchanged(egid, Self::get_effective_gid()))
+    }
+
+    fn set_all_uid(ruid: uid_t, euid: uid_t, suid: uid_t) -> io::Result<()> {
+        Self::set_both_uid(ruid, euid).and(unchanged(suid, Self::get_effective_uid()))
+    }
+
+    fn set_all_gid(rgid: gid_t, egid: gid_t, sgid: gid_t) -> io::Result<()> {
+        Self::set_both_gid(rgid, egid).and(unchanged(sgid, Self::get_effective_gid()))
+    }
+
+    fn set_supplementary_groups(gids: &[gid_t]) -> io::Result<()> {
+        unchanged_groups(gids, &Self::supplementary_groups()?)
+    }
+
+    fn init_groups(username: &OsStr, gid: gid_t) -> io::Result<()> {
+        let gids = Self::get_user_group_ids(username, gid).unwrap_or_else(|| vec![ gid ]);
+        unchanged_groups(&gids, &Self::supplementary_groups()?)
+    }
+
+    fn switch_user_group(uid: uid_t, gid: gid_t) -> io::Result<()> {
+        unchanged(uid, Self::get_effective_uid()).and(unchanged(gid, Self::get_effective_gid()))
+    }
+}
+
+/// Succeeds if the requested ID is the one the process already has, and
+/// fails otherwise, as there is no way to switch to it by ID alone.
+fn unchanged(requested: u32, current: u32) -> io::Result<()> {
+    if requested == current {
+        Ok(())
+    }
+    else {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "switching identity on Windows requires credentials, see switch_user_with_password"))
+    }
+}
+
+fn unchanged_groups(requested: &[gid_t], current: &[gid_t]) -> io::Result<()> {
+    if same_groups(requested, current) {
+        Ok(())
+    }
+    else {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "switching identity on Windows requires credentials, see switch_user_with_password"))
+    }
+}
+
+pub fn is_elevated() -> bool {
+    Token::current_process().and_then(|token| token.is_elevated()).unwrap_or(false)
+}
+
+pub fn impersonate(username: &OsStr, password: &OsStr) -> io::Result<Impersonation> {
+    // A `.` domain restricts the logon to local accounts, and a user
+    // principal name comes without a domain; both are passed on as they are.
+    let (domain, user) = split_domain(username);
+    let domain = domain.as_ref().map_or(ptr::null(), |domain| domain.as_ptr());
+    let password = to_wide(password);
+    let mut handle = ptr::null_mut();
+
+    let ok = unsafe {
+        LogonUserW(user.as_ptr(), domain, password.as_ptr(), LOGON32_LOGON_INTERACTIVE, LOGON32_PROVIDER_DEFAULT, &mut handle)
+    };
+
+    if ok == FALSE {
+        return Err(io::Error::last_os_error());
+    }
+
+    let token = Token(handle);
+    if unsafe { ImpersonateLoggedOnUser(token.0) } == FALSE {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(Impersonation(token, current::impersonating()))
+}
+
+pub fn sessions() -> io::Result<Vec<Session>> {
+    let mut infos = ptr::null_mut();
+    let mut count = 0;
+    if unsafe { WTSEnumerateSessionsW(WTS_CURRENT_SERVER_HANDLE, 0, 1, &mut infos, &mut count) } == FALSE {
+        return Err(io::Error::last_os_error());
+    }
+
+    let entries = unsafe { slice::from_raw_parts(infos, count as usize) };
+    let sessions = entries.iter().filter_map(|entry| {
+        let info = query_session(entry.SessionId, WTS_SESSION_INFO)?;
+        let info = unsafe { &*(info.0 as *const WTSINFOW) };
+        let username = from_wide(&info.UserName);
+        if username.is_empty() {
+            return None;
+        }
+
+        let host = query_session(entry.SessionId, WTS_CLIENT_NAME)
+            .map(|name| unsafe { from_wide_ptr(name.0) })
+            .filter(|name| ! name.is_empty());
+
+        Some(Session {
+            username,
+            tty: unsafe { from_wide_ptr(entry.pWinStationName) },
+            host,
+            login_time: filetime(info.LogonTime),
+        })
+    }).collect();
+
+    unsafe { WTSFreeMemory(infos as PVOID) };
+    Ok(sessions)
+}
+
+pub fn last_login_for(uid: uid_t) -> io::Result<Option<LastLogin>> {
+    let mapping = mapping();
+    let account = mapping.id_to_sid(uid)
+                         .and_then(|sid| to_local_sid(&sid))
+                         .and_then(|sid| unsafe { lookup_sid(sid.0) });
+
+    let account = match account {
+        Some(account) if account.kind == SidTypeUser => account,
+        _                                            => return Ok(None),
+    };
+
+    if ! computer_name().map_or(false, |name| name.eq_ignore_ascii_case(&account.domain)) {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "the last logon of a domain account is kept by each domain controller"));
+    }
+
+    let name = to_wide(&account.name);
+    let mut buf: LPBYTE = ptr::null_mut();
+    let status = unsafe { NetUserGetInfo(ptr::null(), name.as_ptr(), 2, &mut buf) };
+    if status != NERR_Success {
+        if ! buf.is_null() {
+            unsafe { NetApiBufferFree(buf as LPVOID) };
+        }
+        return Err(io::Error::from_raw_os_error(status as i32));
+    }
+
+    // The time is in seconds since 1970, and zero if it isn’t known.
+    let seconds = unsafe { (*(buf as *const USER_INFO_2)).usri2_last_logon };
+    unsafe { NetApiBufferFree(buf as LPVOID) };
+
+    if seconds == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(LastLogin { time: UNIX_EPOCH + Duration::from_secs(u64::from(seconds)), tty: None, host: None }))
+}
+
+/// Memory allocated by `WTSQuerySessionInformationW`, freed on drop.
+struct WtsBuffer(LPWSTR);
+
+impl Drop for WtsBuffer {
+    fn drop(&mut self) {
+        unsafe { WTSFreeMemory(self.0 as PVOID) };
+    }
+}
+
+fn query_session(id: DWORD, class: i32) -> Option<WtsBuffer> {
+    let mut buf = ptr::null_mut();
+    let mut len = 0;
+    if unsafe { WTSQuerySessionInformationW(WTS_CURRENT_SERVER_HANDLE, id, class, &mut buf, &mut len) } == FALSE || buf.is_null() {
+        return None;
+    }
+
+    Some(WtsBuffer(buf))
+}
+
+/// Converts a `FILETIME`, as a count of 100-nanosecond intervals since 1601.
+fn filetime(intervals: i64) -> SystemTime {
+    let intervals = intervals.saturating_sub(FILETIME_UNIX_EPOCH).max(0) as u64;
+    UNIX_EPOCH + Duration::new(intervals / 10_000_000, (intervals % 10_000_000) as u32 * 100)
+}